@@ -1,4 +1,5 @@
-use std::time::{Duration, Instant};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
 use reqwest::{Client, Method, StatusCode};
 use serde_json::{Value, json};
@@ -6,17 +7,53 @@ use tokio::time::sleep;
 use tokio::sync::mpsc;
 use futures::StreamExt;
 
+use crate::cassette::{
+    Cassette, Interaction, RecordedBody, RecordedEvent, redact_authorization, redact_json,
+};
+use crate::config::store_profile_tokens;
 use crate::errors::{CliError, redact_secret, with_debug_hint};
+use crate::retry::{DEFAULT_RETRY_BUDGET, jittered_backoff, parse_retry_after};
+use crate::sse::{SseDecoder, SseEvent};
 
 #[derive(Debug, Clone)]
 pub struct ApiClient {
     client: Client,
+    stream_client: Client,
     base_url: String,
-    token: Option<String>,
+    credentials: Arc<RwLock<Credentials>>,
+    refresh_lock: Arc<tokio::sync::Mutex<()>>,
+    profile: Option<String>,
+    cassette: Option<Arc<Cassette>>,
     retries: u32,
+    retry_budget: Duration,
+    verbose: bool,
     debug: bool,
 }
 
+/// Tokens shared by every clone of a client, so a refresh performed by one
+/// request (or one TUI background task) is seen by all of them.
+#[derive(Debug, Default)]
+struct Credentials {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+}
+
+/// A response read in full, from the network or from a cassette.
+struct RawResponse {
+    status: StatusCode,
+    request_id: Option<String>,
+    /// Parsed `Retry-After`, if the server sent one.
+    retry_after: Option<Duration>,
+    text: String,
+}
+
+enum SendError {
+    /// The request never got a response (connect, timeout, ...).
+    Transport(reqwest::Error),
+    /// Replay has no recorded answer, or the recording could not be saved.
+    Cassette(CliError),
+}
+
 #[derive(Debug, Clone)]
 pub struct ApiResponse {
     pub request_id: Option<String>,
@@ -34,15 +71,158 @@ impl ApiClient {
     ) -> Result<Self, CliError> {
         let timeout = Duration::from_millis(timeout_ms.max(1));
         let client = Client::builder().timeout(timeout).build()?;
+        let stream_client = Client::builder()
+            .connect_timeout(timeout)
+            .read_timeout(timeout.max(STREAM_IDLE_TIMEOUT))
+            .build()?;
         Ok(Self {
             client,
+            stream_client,
             base_url,
-            token,
+            credentials: Arc::new(RwLock::new(Credentials {
+                access_token: token,
+                refresh_token: None,
+            })),
+            refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
+            profile: None,
+            cassette: None,
             retries,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            verbose: false,
             debug,
         })
     }
 
+    /// Cap the total time one request may spend retrying, waits included.
+    pub fn with_retry_budget(mut self, budget: Duration) -> Self {
+        self.retry_budget = budget;
+        self
+    }
+
+    /// Report idempotency keys and retries on stderr.
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Record every exchange to, or replay every exchange from, `cassette`.
+    pub fn with_cassette(mut self, cassette: Option<Arc<Cassette>>) -> Self {
+        self.cassette = cassette;
+        self
+    }
+
+    fn replaying(&self) -> bool {
+        self.cassette.as_ref().is_some_and(|c| c.is_replay())
+    }
+
+    fn recorder(&self) -> Option<&Cassette> {
+        self.cassette.as_deref().filter(|c| !c.is_replay())
+    }
+
+    /// Enable automatic access-token refresh. Rotated tokens are written back
+    /// to `profile` in the config file.
+    pub fn with_refresh_token(mut self, refresh_token: Option<String>, profile: String) -> Self {
+        if let Ok(mut creds) = self.credentials.write() {
+            creds.refresh_token = refresh_token;
+        }
+        self.profile = Some(profile);
+        self
+    }
+
+    fn access_token(&self) -> Option<String> {
+        self.credentials
+            .read()
+            .ok()
+            .and_then(|c| c.access_token.clone())
+    }
+
+    fn can_refresh(&self) -> bool {
+        self.credentials
+            .read()
+            .map(|c| c.refresh_token.is_some())
+            .unwrap_or(false)
+    }
+
+    fn bearer_token(&self, auth_required: bool) -> Result<Option<String>, CliError> {
+        let token = self.access_token();
+        // A replayed session needs no credentials; the cassette has the answers.
+        if auth_required && token.is_none() && !self.replaying() {
+            return Err(CliError::auth("Missing token.")
+                .with_reason("not_logged_in")
+                .with_hint("Run `starbott auth login` first."));
+        }
+        Ok(token)
+    }
+
+    /// Exchange the stored refresh token for a new access token.
+    ///
+    /// Needs a server that issues refresh tokens and serves
+    /// `POST /v1/auth/refresh`. The Starbot API's device flow does neither
+    /// yet, so against it there is never a refresh token and a 401 asks the
+    /// user to log in again.
+    ///
+    /// `stale` is the access token the caller was rejected with; if another
+    /// request already replaced it, no second exchange is made. Returns
+    /// `Ok(false)` when no refresh was possible so the caller can surface the
+    /// original 401.
+    async fn refresh_access_token(&self, stale: Option<&str>) -> Result<bool, CliError> {
+        let _guard = self.refresh_lock.lock().await;
+
+        if self.access_token().as_deref() != stale {
+            return Ok(true);
+        }
+
+        let stored = self
+            .credentials
+            .read()
+            .ok()
+            .and_then(|c| c.refresh_token.clone());
+        let Some(refresh_token) = stored.or_else(|| self.replaying().then(String::new)) else {
+            return Ok(false);
+        };
+
+        let body = json!({ "refresh_token": refresh_token });
+        let response = match self
+            .send(&Method::POST, "/v1/auth/refresh", None, Some(&body), None, None)
+            .await
+        {
+            Ok(resp) => resp,
+            // A replayed session without a recorded refresh surfaces the 401.
+            Err(SendError::Cassette(_)) if self.replaying() => return Ok(false),
+            Err(SendError::Cassette(err)) => return Err(err),
+            Err(SendError::Transport(_)) => return Ok(false),
+        };
+        if !response.status.is_success() {
+            return Ok(false);
+        }
+
+        let payload = serde_json::from_str::<Value>(&response.text).unwrap_or_else(|_| json!({}));
+        let Some(access_token) = payload
+            .get("access_token")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+        else {
+            return Ok(false);
+        };
+        let rotated = payload
+            .get("refresh_token")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .unwrap_or(refresh_token);
+
+        if let Ok(mut creds) = self.credentials.write() {
+            creds.access_token = Some(access_token.clone());
+            creds.refresh_token = Some(rotated.clone());
+        }
+
+        // Replayed tokens are redacted placeholders; never persist them.
+        if let Some(profile) = self.profile.as_ref().filter(|_| !self.replaying()) {
+            store_profile_tokens(profile, access_token, Some(rotated))?;
+        }
+
+        Ok(true)
+    }
+
     pub async fn get_json(
         &self,
         path: &str,
@@ -73,6 +253,19 @@ impl ApiClient {
             .await
     }
 
+    /// POST with a caller-chosen `Idempotency-Key`, for operations that have
+    /// a natural identity of their own. Other mutating calls get a random key.
+    pub async fn post_json_idempotent(
+        &self,
+        path: &str,
+        body: Option<Value>,
+        auth_required: bool,
+        idempotency_key: &str,
+    ) -> Result<ApiResponse, CliError> {
+        self.execute(Method::POST, path, None, body, auth_required, false, Some(idempotency_key))
+            .await
+    }
+
     pub async fn request_json(
         &self,
         method: Method,
@@ -82,51 +275,78 @@ impl ApiClient {
         auth_required: bool,
         idempotent: bool,
     ) -> Result<ApiResponse, CliError> {
-        let token = if auth_required {
-            Some(self.token.clone().ok_or_else(|| {
-                CliError::Auth("Missing token. Run `starbott auth login` first.".to_string())
-            })?)
-        } else {
-            self.token.clone()
-        };
-
-        let url = join_url(&self.base_url, path);
-        let max_attempts = if idempotent {
-            self.retries.saturating_add(1)
-        } else {
-            1
-        };
-
-        for attempt in 0..max_attempts {
-            let started = Instant::now();
-            let mut request = self.client.request(method.clone(), url.clone());
-
-            if let Some(query_items) = query {
-                request = request.query(query_items);
-            }
+        self.execute(method, path, query, body, auth_required, idempotent, None)
+            .await
+    }
 
-            if let Some(ref bearer) = token {
-                request = request.bearer_auth(bearer);
+    /// Send a request, retrying transient failures while the retry budget
+    /// lasts. POST, PATCH and DELETE always carry an `Idempotency-Key` (a
+    /// fresh UUID unless the caller supplied one), reused across retries.
+    /// The Starbot API does not deduplicate by it yet, so unless `idempotent`
+    /// they are only retried when the server provably never handled them:
+    /// the connection failed, or a 429/503 came with `Retry-After`.
+    #[allow(clippy::too_many_arguments)]
+    async fn execute(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<&[(String, String)]>,
+        body: Option<Value>,
+        auth_required: bool,
+        idempotent: bool,
+        idempotency_key: Option<&str>,
+    ) -> Result<ApiResponse, CliError> {
+        let mut token = self.bearer_token(auth_required)?;
+
+        let generated_key;
+        let idempotency_key = match idempotency_key {
+            Some(key) => Some(key),
+            None if is_mutating(&method) => {
+                generated_key = uuid::Uuid::new_v4().to_string();
+                Some(generated_key.as_str())
             }
+            None => None,
+        };
+        if let Some(key) = idempotency_key {
+            self.log_verbose(&format!("{method} {path} idempotency_key={key}"));
+        }
 
-            if let Some(ref payload) = body {
-                request = request.json(payload);
-            }
+        let max_attempts = self.retries.saturating_add(1);
+        let first_attempt = Instant::now();
+        let mut refreshed = false;
+        let mut attempt = 0;
 
-            let response = request.send().await;
+        while attempt < max_attempts {
+            let started = Instant::now();
+            let response = self
+                .send(&method, path, query, body.as_ref(), token.as_deref(), idempotency_key)
+                .await;
             match response {
-                Ok(resp) => {
-                    let status = resp.status();
-                    let request_id = resp
-                        .headers()
-                        .get("x-request-id")
-                        .and_then(|v| v.to_str().ok())
-                        .map(|s| s.to_string());
-
-                    let text = resp.text().await.unwrap_or_default();
-                    if is_retryable_status(status) && idempotent && attempt + 1 < max_attempts {
-                        sleep(backoff_delay_ms(attempt)).await;
-                        continue;
+                Ok(RawResponse { status, request_id, retry_after, text }) => {
+
+                    // A rejected token means the request was never processed, so it is
+                    // safe to replay once with a fresh token regardless of idempotency.
+                    if status == StatusCode::UNAUTHORIZED && !refreshed && self.may_refresh(&token) {
+                        refreshed = true;
+                        if self.refresh_access_token(token.as_deref()).await? {
+                            token = self.access_token();
+                            continue;
+                        }
+                    }
+
+                    let rejected_unseen = retry_after.is_some()
+                        && matches!(status, StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE);
+                    if is_retryable_status(status)
+                        && (idempotent || rejected_unseen)
+                        && attempt + 1 < max_attempts
+                    {
+                        let delay = retry_after.unwrap_or_else(|| jittered_backoff(attempt));
+                        if self.within_budget(first_attempt, delay) {
+                            self.log_retry(&method, path, status.as_str(), delay, attempt, max_attempts, idempotency_key);
+                            sleep(delay).await;
+                            attempt += 1;
+                            continue;
+                        }
                     }
 
                     let parsed = if text.trim().is_empty() {
@@ -144,65 +364,238 @@ impl ApiClient {
                         });
                     }
 
-                    return Err(self.http_error(status, request_id, parsed));
+                    return Err(self.http_error(status, request_id, retry_after, parsed));
                 }
-                Err(err) => {
+                Err(SendError::Cassette(err)) => return Err(err),
+                Err(SendError::Transport(err)) => {
                     let transient = err.is_timeout() || err.is_connect() || err.is_request();
-                    if transient && idempotent && attempt + 1 < max_attempts {
-                        sleep(backoff_delay_ms(attempt)).await;
+                    let delay = jittered_backoff(attempt);
+                    // A timeout may come after the server acted on the request.
+                    let unseen = if idempotent { transient } else { err.is_connect() };
+                    if unseen
+                        && attempt + 1 < max_attempts
+                        && self.within_budget(first_attempt, delay)
+                    {
+                        let reason = if err.is_timeout() { "timeout" } else { "network error" };
+                        self.log_retry(&method, path, reason, delay, attempt, max_attempts, idempotency_key);
+                        sleep(delay).await;
+                        attempt += 1;
                         continue;
                     }
 
-                    let message = if err.is_timeout() {
-                        "Request timed out.".to_string()
+                    let (message, reason) = if err.is_timeout() {
+                        ("Request timed out.".to_string(), "timeout")
                     } else {
-                        format!("Network request failed: {err}")
+                        (format!("Network request failed: {err}"), "network_error")
                     };
-                    return Err(CliError::Network(with_debug_hint(&message, self.debug)));
+                    return Err(CliError::network(with_debug_hint(&message, self.debug))
+                        .with_reason(reason)
+                        .with_hint(NETWORK_HINT));
                 }
             }
         }
 
-        Err(CliError::Network(with_debug_hint(
+        Err(CliError::network(with_debug_hint(
             "Request failed after retries.",
             self.debug,
-        )))
+        ))
+        .with_hint(NETWORK_HINT))
+    }
+
+    fn log_verbose(&self, message: &str) {
+        if self.verbose {
+            eprintln!("{message}");
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn log_retry(
+        &self,
+        method: &Method,
+        path: &str,
+        reason: &str,
+        delay: Duration,
+        attempt: u32,
+        max_attempts: u32,
+        idempotency_key: Option<&str>,
+    ) {
+        let mut message = format!(
+            "retrying {method} {path} after {reason} in {}ms (attempt {}/{max_attempts})",
+            delay.as_millis(),
+            attempt + 2,
+        );
+        if let Some(key) = idempotency_key {
+            message.push_str(&format!(" idempotency_key={key}"));
+        }
+        self.log_verbose(&message);
+    }
+
+    /// Whether sleeping `delay` keeps the request inside `--retry-budget`.
+    fn within_budget(&self, first_attempt: Instant, delay: Duration) -> bool {
+        first_attempt.elapsed() + delay <= self.retry_budget
+    }
+
+    /// Whether a 401 for a request sent with `token` is worth a refresh.
+    fn may_refresh(&self, token: &Option<String>) -> bool {
+        self.replaying() || (token.is_some() && self.can_refresh())
+    }
+
+    /// Perform one HTTP exchange, or answer it from the replay cassette.
+    /// Recorded exchanges are appended to the cassette when recording.
+    async fn send(
+        &self,
+        method: &Method,
+        path: &str,
+        query: Option<&[(String, String)]>,
+        body: Option<&Value>,
+        token: Option<&str>,
+        idempotency_key: Option<&str>,
+    ) -> Result<RawResponse, SendError> {
+        if let Some(cassette) = self.cassette.as_ref().filter(|c| c.is_replay()) {
+            let interaction = cassette
+                .next(method.as_str(), path)
+                .map_err(SendError::Cassette)?;
+            return Ok(RawResponse {
+                status: StatusCode::from_u16(interaction.status)
+                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                request_id: interaction.request_id,
+                retry_after: replayed_retry_after(&interaction.response_headers),
+                text: interaction.response.to_text(),
+            });
+        }
+
+        let mut request = self
+            .client
+            .request(method.clone(), join_url(&self.base_url, path));
+        if let Some(query_items) = query {
+            request = request.query(query_items);
+        }
+        if let Some(bearer) = token {
+            request = request.bearer_auth(bearer);
+        }
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+        if let Some(payload) = body {
+            request = request.json(payload);
+        }
+
+        let resp = request.send().await.map_err(SendError::Transport)?;
+        let status = resp.status();
+        let request_id = header_request_id(&resp);
+        let retry_after_header = header_retry_after(&resp);
+        let text = resp.text().await.unwrap_or_default();
+
+        if let Some(cassette) = self.recorder() {
+            let mut interaction = record_interaction(
+                method.as_str(),
+                path,
+                query,
+                body,
+                token,
+                status,
+                request_id.clone(),
+                RecordedBody::from_text(&text),
+            );
+            if let Some(key) = idempotency_key {
+                interaction
+                    .request_headers
+                    .insert("idempotency-key".to_string(), key.to_string());
+            }
+            if let Some(value) = &retry_after_header {
+                interaction
+                    .response_headers
+                    .insert("retry-after".to_string(), value.clone());
+            }
+            cassette.push(interaction).map_err(SendError::Cassette)?;
+        }
+
+        Ok(RawResponse {
+            status,
+            request_id,
+            retry_after: retry_after_header
+                .as_deref()
+                .and_then(|v| parse_retry_after(v, SystemTime::now())),
+            text,
+        })
     }
 
     fn http_error(
         &self,
         status: StatusCode,
         request_id: Option<String>,
+        retry_after: Option<Duration>,
         payload: Value,
     ) -> CliError {
-        let message = payload
-            .get("error")
-            .and_then(|v| v.as_str())
-            .or_else(|| payload.get("message").and_then(|v| v.as_str()))
+        // Errors are `{ error }`, `{ error: <code>, message }`, or the same
+        // nested under `error`.
+        let body = payload.get("error").filter(|v| v.is_object()).unwrap_or(&payload);
+        let text = |key: &str| body.get(key).and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+        let server_code = text("code")
+            .or_else(|| text("error_code"))
+            .or_else(|| text("error").filter(|e| is_reason_code(e) && text("message").is_some()));
+        let message = text("message")
+            .or_else(|| text("error"))
             .map(|s| s.to_string())
             .unwrap_or_else(|| format!("Request failed with status {}", status.as_u16()));
 
         let mut details = message;
-        if let Some(id) = request_id {
-            details.push_str(&format!(" (request_id: {id})"));
+        if let Some(wait) = retry_after.filter(|_| status == StatusCode::TOO_MANY_REQUESTS) {
+            details.push_str(&format!(" Server asked to retry after {}.", format_wait(wait)));
         }
         if self.debug {
             let mut payload_text = payload.to_string();
-            if let Some(token) = &self.token {
-                payload_text = payload_text.replace(token, &redact_secret(token));
+            if let Some(token) = self.access_token() {
+                payload_text = payload_text.replace(&token, &redact_secret(&token));
             }
             details.push_str(&format!(" payload={payload_text}"));
         } else {
             details = with_debug_hint(&details, false);
         }
 
+        let error = match status.as_u16() {
+            400 => CliError::usage(details),
+            401 | 403 => CliError::auth(details),
+            429 => CliError::rate_limited(details),
+            500..=599 => CliError::server(details),
+            _ => CliError::generic(details),
+        };
+        let reason = match server_code {
+            Some(code) => code.to_string(),
+            None => self.status_reason(status),
+        };
+        let error = error
+            .with_status(status.as_u16())
+            .with_request_id(request_id)
+            .with_reason(reason)
+            // The statuses this client retries itself, plus a plain 500.
+            .with_retryable(is_retryable_status(status) || status == StatusCode::INTERNAL_SERVER_ERROR);
+        match status_hint(status) {
+            Some(hint) => error.with_hint(hint),
+            None => error,
+        }
+    }
+
+    /// A reason for statuses the server did not explain. A 401 is reported
+    /// as `token_expired` when the JWT we sent says so.
+    fn status_reason(&self, status: StatusCode) -> String {
         match status.as_u16() {
-            400 => CliError::Usage(details),
-            401 | 403 => CliError::Auth(details),
-            429 => CliError::RateLimited(details),
-            500..=599 => CliError::Server(details),
-            _ => CliError::Generic(details),
+            401 => match self.access_token() {
+                Some(token) if jwt_expired(&token, SystemTime::now()) => "token_expired",
+                Some(_) => "token_invalid",
+                None => "not_logged_in",
+            },
+            400 => "bad_request",
+            403 => "forbidden",
+            404 => "not_found",
+            409 => "conflict",
+            410 => "gone",
+            422 => "unprocessable",
+            429 => "rate_limited",
+            500..=599 => "server_error",
+            other => return format!("http_{other}"),
         }
+        .to_string()
     }
 
     pub async fn put_json(&self, path: &str, body: Option<Value>, auth_required: bool) -> Result<ApiResponse, CliError> {
@@ -220,104 +613,291 @@ impl ApiClient {
             .await
     }
 
-    /// Start a streaming POST request (SSE format)
-    /// Returns a receiver channel that yields (event_type, data) tuples
+    /// Start a streaming POST request (SSE format).
+    /// Returns a receiver channel that yields decoded events; see [`StreamEvent`].
     pub async fn post_stream(
         &self,
         path: &str,
         body: Option<Value>,
         auth_required: bool,
     ) -> Result<mpsc::UnboundedReceiver<StreamEvent>, CliError> {
-        let token = if auth_required {
-            Some(self.token.clone().ok_or_else(|| {
-                CliError::Auth("Missing token. Run `starbott auth login` first.".to_string())
-            })?)
-        } else {
-            self.token.clone()
-        };
-
-        let url = join_url(&self.base_url, path);
-        let mut request = self.client.post(url);
-
-        if let Some(token_str) = token {
-            request = request.header("Authorization", format!("Bearer {}", token_str));
-        }
+        self.post_stream_with_options(path, body, auth_required, StreamOptions::default())
+            .await
+    }
 
-        if let Some(body_val) = body {
-            request = request.json(&body_val);
+    /// Like [`ApiClient::post_stream`], with control over automatic resume.
+    ///
+    /// When the transport fails mid-stream and the server has sent an `id:`,
+    /// the request is re-sent with `Last-Event-ID` after the server's `retry:`
+    /// delay. Without an event ID there is nothing to resume from, so the
+    /// stream ends with [`StreamEvent::Disconnected`] instead.
+    pub async fn post_stream_with_options(
+        &self,
+        path: &str,
+        body: Option<Value>,
+        auth_required: bool,
+        options: StreamOptions,
+    ) -> Result<mpsc::UnboundedReceiver<StreamEvent>, CliError> {
+        if self.replaying() {
+            return self.replay_stream(path, auth_required).await;
         }
 
-        let response = request.send().await.map_err(|e| {
-            CliError::Network(format!("Stream request failed: {}", e))
-        })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let payload = response.json::<Value>().await.unwrap_or_else(|_| json!({}));
-            return Err(self.http_error(status, None, payload));
-        }
+        // One key for the request and every resume of it.
+        let idempotency_key = uuid::Uuid::new_v4().to_string();
+        self.log_verbose(&format!("POST {path} idempotency_key={idempotency_key}"));
+        let response = self
+            .open_stream(path, body.as_ref(), auth_required, &idempotency_key, None)
+            .await?;
 
         let (tx, rx) = mpsc::unbounded_channel();
+        let client = self.clone();
+        let path = path.to_string();
+        let request_id = header_request_id(&response);
 
         // Spawn a task to process the stream
         tokio::spawn(async move {
-            let mut stream = response.bytes_stream();
-            let mut buffer = String::new();
-            let mut current_event = String::new();
-            let mut current_data_lines: Vec<String> = Vec::new();
-
-            let flush_event = |event_type: &mut String, data_lines: &mut Vec<String>| {
-                if data_lines.is_empty() {
-                    event_type.clear();
-                    return;
-                }
-                let kind = if event_type.trim().is_empty() {
-                    "message".to_string()
-                } else {
-                    event_type.trim().to_string()
-                };
-                let data = data_lines.join("\n");
-                let _ = tx.send(StreamEvent {
-                    event_type: kind,
-                    data,
-                });
-                data_lines.clear();
-                event_type.clear();
-            };
-
-            while let Some(chunk_result) = stream.next().await {
-                match chunk_result {
-                    Ok(chunk) => {
-                        if let Ok(text) = std::str::from_utf8(&chunk) {
-                            buffer.push_str(text);
-
-                            // Process complete lines
-                            while let Some(newline_pos) = buffer.find('\n') {
-                                let line = buffer[..newline_pos].trim_end_matches('\r').to_string();
-                                buffer = buffer[newline_pos + 1..].to_string();
-
-                                if line.is_empty() {
-                                    // Empty line signals end of event.
-                                    flush_event(&mut current_event, &mut current_data_lines);
-                                } else if let Some(event_type) = line.strip_prefix("event:") {
-                                    current_event = event_type.trim().to_string();
-                                } else if let Some(data) = line.strip_prefix("data:") {
-                                    current_data_lines.push(data.trim_start().to_string());
+            let mut decoder = SseDecoder::new();
+            let mut response = response;
+            let mut reconnects = 0;
+            // Events are recorded as one interaction, written when the stream ends.
+            let mut recording = client.recorder().map(|_| StreamRecording {
+                token: client.access_token(),
+                request_id,
+                events: Vec::new(),
+            });
+
+            loop {
+                let mut stream = response.bytes_stream();
+                let mut failure = None;
+
+                while let Some(chunk_result) = stream.next().await {
+                    match chunk_result {
+                        Ok(chunk) => {
+                            for event in decoder.push(&chunk) {
+                                if let Some(recording) = recording.as_mut() {
+                                    recording.events.push(RecordedEvent::from(&event));
+                                }
+                                if tx.send(StreamEvent::from(event)).is_err() {
+                                    client.finish_recording(&path, body.as_ref(), recording);
+                                    return;
                                 }
                             }
                         }
+                        Err(err) => {
+                            failure = Some(describe_stream_error(&err));
+                            break;
+                        }
                     }
-                    Err(_) => break,
                 }
-            }
 
-            // Flush trailing buffered event if the server closed without a blank line.
-            flush_event(&mut current_event, &mut current_data_lines);
+                let Some(mut error) = failure else {
+                    // Clean EOF. Flush a trailing event if the server closed without a blank line.
+                    if let Some(event) = decoder.finish() {
+                        if let Some(recording) = recording.as_mut() {
+                            recording.events.push(RecordedEvent::from(&event));
+                        }
+                        let _ = tx.send(StreamEvent::from(event));
+                    }
+                    client.finish_recording(&path, body.as_ref(), recording);
+                    return;
+                };
+
+                decoder.reset_pending();
+                let Some(last_event_id) = decoder.last_event_id().map(|s| s.to_string()) else {
+                    client.finish_recording(&path, body.as_ref(), recording);
+                    let _ = tx.send(StreamEvent::Disconnected { error });
+                    return;
+                };
+
+                let resumed = loop {
+                    if !options.resume || reconnects >= options.max_reconnects {
+                        break None;
+                    }
+                    reconnects += 1;
+                    let notice = StreamEvent::Reconnecting {
+                        attempt: reconnects,
+                        last_event_id: last_event_id.clone(),
+                        error: error.clone(),
+                    };
+                    if tx.send(notice).is_err() {
+                        client.finish_recording(&path, body.as_ref(), recording);
+                        return;
+                    }
+
+                    sleep(decoder.retry().unwrap_or(DEFAULT_STREAM_RETRY)).await;
+                    match client
+                        .open_stream(
+                            &path,
+                            body.as_ref(),
+                            auth_required,
+                            &idempotency_key,
+                            Some(&last_event_id),
+                        )
+                        .await
+                    {
+                        Ok(resp) => break Some(resp),
+                        Err(err) => error = err.to_string(),
+                    }
+                };
+
+                match resumed {
+                    Some(resp) => response = resp,
+                    None => {
+                        client.finish_recording(&path, body.as_ref(), recording);
+                        let _ = tx.send(StreamEvent::Disconnected { error });
+                        return;
+                    }
+                }
+            }
         });
 
         Ok(rx)
     }
 
+    /// Send the streaming request and return the response once headers arrive,
+    /// refreshing the access token once on a 401.
+    async fn open_stream(
+        &self,
+        path: &str,
+        body: Option<&Value>,
+        auth_required: bool,
+        idempotency_key: &str,
+        last_event_id: Option<&str>,
+    ) -> Result<reqwest::Response, CliError> {
+        let mut token = self.bearer_token(auth_required)?;
+
+        let url = join_url(&self.base_url, path);
+        let mut refreshed = false;
+
+        let response = loop {
+            let mut request = self
+                .stream_client
+                .post(url.clone())
+                .header("Accept", "text/event-stream")
+                .header("Cache-Control", "no-cache");
+
+            if let Some(ref token_str) = token {
+                request = request.header("Authorization", format!("Bearer {}", token_str));
+            }
+
+            if let Some(id) = last_event_id {
+                request = request.header("Last-Event-ID", id);
+            }
+            request = request.header("Idempotency-Key", idempotency_key);
+
+            if let Some(body_val) = body {
+                request = request.json(body_val);
+            }
+
+            let response = request.send().await.map_err(|e| {
+                CliError::network(format!("Stream request failed: {}", e))
+            })?;
+
+            if response.status() == StatusCode::UNAUTHORIZED
+                && !refreshed
+                && self.may_refresh(&token)
+            {
+                refreshed = true;
+                if self.refresh_access_token(token.as_deref()).await? {
+                    token = self.access_token();
+                    continue;
+                }
+            }
+
+            break response;
+        };
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let request_id = header_request_id(&response);
+            let retry_after = header_retry_after(&response);
+            let text = response.text().await.unwrap_or_default();
+            if let Some(cassette) = self.recorder() {
+                let mut interaction = record_interaction(
+                    "POST",
+                    path,
+                    None,
+                    body,
+                    token.as_deref(),
+                    status,
+                    request_id.clone(),
+                    RecordedBody::from_text(&text),
+                );
+                if let Some(value) = &retry_after {
+                    interaction
+                        .response_headers
+                        .insert("retry-after".to_string(), value.clone());
+                }
+                cassette.push(interaction)?;
+            }
+            let payload = serde_json::from_str::<Value>(&text).unwrap_or_else(|_| json!({}));
+            let wait = retry_after
+                .as_deref()
+                .and_then(|v| parse_retry_after(v, SystemTime::now()));
+            return Err(self.http_error(status, request_id, wait, payload));
+        }
+
+        Ok(response)
+    }
+
+    /// Serve a stream from the replay cassette.
+    async fn replay_stream(
+        &self,
+        path: &str,
+        auth_required: bool,
+    ) -> Result<mpsc::UnboundedReceiver<StreamEvent>, CliError> {
+        let token = self.bearer_token(auth_required)?;
+        let Some(cassette) = self.cassette.as_ref() else {
+            return Err(CliError::generic("No cassette to replay from.".to_string()));
+        };
+
+        let mut interaction = cassette.next("POST", path)?;
+        if interaction.status == StatusCode::UNAUTHORIZED.as_u16()
+            && self.refresh_access_token(token.as_deref()).await?
+        {
+            interaction = cassette.next("POST", path)?;
+        }
+
+        let status =
+            StatusCode::from_u16(interaction.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let events = match &interaction.response {
+            RecordedBody::Events(events) if status.is_success() => events.clone(),
+            other => {
+                let payload = serde_json::from_str::<Value>(&other.to_text())
+                    .unwrap_or_else(|_| json!({}));
+                let wait = replayed_retry_after(&interaction.response_headers);
+                return Err(self.http_error(status, interaction.request_id, wait, payload));
+            }
+        };
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        for event in events {
+            let _ = tx.send(StreamEvent::from(SseEvent::from(event)));
+        }
+        Ok(rx)
+    }
+
+    /// Write a finished stream to the recording cassette, if any. Failures are
+    /// reported but do not interrupt the stream consumer.
+    fn finish_recording(&self, path: &str, body: Option<&Value>, recording: Option<StreamRecording>) {
+        let (Some(cassette), Some(recording)) = (self.recorder(), recording) else {
+            return;
+        };
+        let interaction = record_interaction(
+            "POST",
+            path,
+            None,
+            body,
+            recording.token.as_deref(),
+            StatusCode::OK,
+            recording.request_id,
+            RecordedBody::Events(recording.events),
+        );
+        if let Err(err) = cassette.push(interaction) {
+            eprintln!("Warning: failed to write cassette: {err}");
+        }
+    }
+
     // Task management operations
     pub async fn list_tasks(&self, status: Option<String>, limit: i32) -> Result<Vec<Task>, CliError> {
         let mut query = Vec::new();
@@ -330,37 +910,37 @@ impl ApiClient {
 
         let res = self.get_json("/v1/tasks", Some(&query), true).await?;
         let tasks = serde_json::from_value::<Vec<Task>>(res.json.get("data").cloned().unwrap_or(json!([])))
-            .map_err(|e| CliError::Generic(format!("Failed to parse tasks: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to parse tasks: {}", e)))?;
         Ok(tasks)
     }
 
     pub async fn get_task(&self, task_id: &str) -> Result<Task, CliError> {
         let res = self.get_json(&format!("/v1/tasks/{}", task_id), None, true).await?;
         let task = serde_json::from_value::<Task>(res.json.get("task").cloned().unwrap_or(json!({})))
-            .map_err(|e| CliError::Generic(format!("Failed to parse task: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to parse task: {}", e)))?;
         Ok(task)
     }
 
     pub async fn create_task(&self, task: CreateTaskRequest) -> Result<Task, CliError> {
-        let body = serde_json::to_value(task).map_err(|e| CliError::Generic(format!("Failed to serialize task: {}", e)))?;
+        let body = serde_json::to_value(task).map_err(|e| CliError::generic(format!("Failed to serialize task: {}", e)))?;
         let res = self.post_json("/v1/tasks", Some(body), true).await?;
         let created_task = serde_json::from_value::<Task>(res.json.get("task").cloned().unwrap_or(json!({})))
-            .map_err(|e| CliError::Generic(format!("Failed to parse created task: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to parse created task: {}", e)))?;
         Ok(created_task)
     }
 
     pub async fn update_task(&self, task_id: String, task: UpdateTaskRequest) -> Result<Task, CliError> {
-        let body = serde_json::to_value(task).map_err(|e| CliError::Generic(format!("Failed to serialize task: {}", e)))?;
+        let body = serde_json::to_value(task).map_err(|e| CliError::generic(format!("Failed to serialize task: {}", e)))?;
         let res = self.put_json(&format!("/v1/tasks/{}", task_id), Some(body), true).await?;
         let updated_task = serde_json::from_value::<Task>(res.json.get("task").cloned().unwrap_or(json!({})))
-            .map_err(|e| CliError::Generic(format!("Failed to parse updated task: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to parse updated task: {}", e)))?;
         Ok(updated_task)
     }
 
     pub async fn complete_task(&self, task_id: &str) -> Result<Task, CliError> {
         let res = self.post_json(&format!("/v1/tasks/{}/complete", task_id), None, true).await?;
         let completed_task = serde_json::from_value::<Task>(res.json.get("task").cloned().unwrap_or(json!({})))
-            .map_err(|e| CliError::Generic(format!("Failed to parse completed task: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to parse completed task: {}", e)))?;
         Ok(completed_task)
     }
 
@@ -372,17 +952,17 @@ impl ApiClient {
     pub async fn start_task(&self, task_id: &str) -> Result<Task, CliError> {
         let res = self.post_json(&format!("/v1/tasks/{}/start", task_id), None, true).await?;
         let started_task = serde_json::from_value::<Task>(res.json.get("task").cloned().unwrap_or(json!({})))
-            .map_err(|e| CliError::Generic(format!("Failed to parse started task: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to parse started task: {}", e)))?;
         Ok(started_task)
     }
 
     /// Chat operations
     pub async fn create_chat(&self, request: CreateChatRequest) -> Result<ChatResponse, CliError> {
-        let body = serde_json::to_value(request).map_err(|e| CliError::Generic(format!("Failed to serialize chat request: {}", e)))?;
+        let body = serde_json::to_value(request).map_err(|e| CliError::generic(format!("Failed to serialize chat request: {}", e)))?;
         let res = self.post_json("/v1/inference/chat", Some(body), true).await?;
 
         let chat_response = serde_json::from_value::<ChatResponse>(res.json)
-            .map_err(|e| CliError::Generic(format!("Failed to parse chat response: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to parse chat response: {}", e)))?;
         Ok(chat_response)
     }
 }
@@ -444,10 +1024,353 @@ pub struct UpdateTaskRequest {
     pub metadata: Option<serde_json::Value>,
 }
 
+/// Item yielded by [`ApiClient::post_stream`].
 #[derive(Debug, Clone)]
-pub struct StreamEvent {
-    pub event_type: String,
-    pub data: String,
+pub enum StreamEvent {
+    /// An event dispatched by the server.
+    Event(GenerationEvent),
+    /// The connection dropped and is being resumed from `last_event_id`.
+    Reconnecting {
+        attempt: u32,
+        last_event_id: String,
+        error: String,
+    },
+    /// The stream ended abnormally; no further events will arrive.
+    Disconnected { error: String },
+}
+
+impl From<SseEvent> for StreamEvent {
+    fn from(event: SseEvent) -> Self {
+        StreamEvent::Event(GenerationEvent::decode(&event.event_type, &event.data))
+    }
+}
+
+/// Events emitted by the generation endpoint (`POST /v1/chats/:chatId/run`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenerationEvent {
+    /// `token.delta`: a chunk of assistant text.
+    TokenDelta { text: String },
+    /// `status`: a human-readable pipeline update (routing, searching, ...).
+    Status { message: String },
+    /// `tool.start`: the server began running a tool.
+    ToolStart {
+        tool_call_id: String,
+        tool_name: String,
+        arguments: Value,
+    },
+    /// `tool.arguments`: the parsed arguments of an upcoming tool call.
+    ToolArguments {
+        tool_call_id: String,
+        name: String,
+        arguments: Value,
+    },
+    /// `tool.end`: a tool finished, successfully or not.
+    ToolEnd {
+        tool_call_id: String,
+        tool_name: String,
+        success: bool,
+        duration_ms: Option<u64>,
+        preview: Option<String>,
+        error: Option<String>,
+    },
+    /// `memory.injected`: how much memory context was added to the prompt.
+    MemoryInjected { identity_chunks: u64, chat_chunks: u64 },
+    /// `interpreter.debug`: the intent classifier's verdict.
+    InterpreterDebug {
+        primary_intent: String,
+        confidence: f64,
+    },
+    /// `message.final`: the saved assistant message.
+    MessageFinal(MessageFinal),
+    /// `chat.updated`: the chat's title or timestamp changed.
+    ChatUpdated {
+        id: String,
+        title: Option<String>,
+        updated_at: Option<String>,
+    },
+    /// `error`: fatal errors end the run; non-fatal ones (provider fallback)
+    /// are followed by more events.
+    Error {
+        message: String,
+        fatal: bool,
+        provider: Option<String>,
+    },
+    /// An event type this client does not know, or a known type whose payload
+    /// did not match the expected shape.
+    Unknown { event_type: String, data: String },
+}
+
+/// Payload of a `message.final` event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageFinal {
+    pub id: Option<String>,
+    pub content: String,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub model_display_name: Option<String>,
+    pub usage: Option<Usage>,
+    /// The full payload, for fields not modelled above (lane, codex, ...).
+    pub raw: Value,
+}
+
+/// Token usage reported with `message.final`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl GenerationEvent {
+    /// Decode one SSE event. Never fails: anything unrecognised or malformed
+    /// becomes [`GenerationEvent::Unknown`].
+    pub fn decode(event_type: &str, data: &str) -> Self {
+        Self::try_decode(event_type, data).unwrap_or_else(|| GenerationEvent::Unknown {
+            event_type: event_type.to_string(),
+            data: data.to_string(),
+        })
+    }
+
+    fn try_decode(event_type: &str, data: &str) -> Option<Self> {
+        let payload: Value = serde_json::from_str(data).ok()?;
+        let payload = payload.as_object()?;
+        let str_field = |key: &str| payload.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let u64_field = |key: &str| payload.get(key).and_then(|v| v.as_u64());
+
+        let event = match event_type {
+            "token.delta" => GenerationEvent::TokenDelta {
+                text: str_field("text")?,
+            },
+            "status" => GenerationEvent::Status {
+                message: str_field("message")?,
+            },
+            "tool.start" => GenerationEvent::ToolStart {
+                tool_call_id: str_field("tool_call_id").unwrap_or_default(),
+                tool_name: str_field("tool_name")?,
+                arguments: payload.get("arguments").cloned().unwrap_or(Value::Null),
+            },
+            "tool.arguments" => GenerationEvent::ToolArguments {
+                tool_call_id: str_field("tool_call_id").unwrap_or_default(),
+                name: str_field("name")?,
+                arguments: payload.get("arguments").cloned().unwrap_or(Value::Null),
+            },
+            "tool.end" => GenerationEvent::ToolEnd {
+                tool_call_id: str_field("tool_call_id").unwrap_or_default(),
+                tool_name: str_field("tool_name")?,
+                success: payload.get("success").and_then(|v| v.as_bool()).unwrap_or(false),
+                duration_ms: u64_field("duration_ms"),
+                preview: str_field("preview").filter(|s| !s.is_empty()),
+                error: str_field("error"),
+            },
+            "memory.injected" => GenerationEvent::MemoryInjected {
+                identity_chunks: u64_field("identity_chunks").unwrap_or(0),
+                chat_chunks: u64_field("chat_chunks").unwrap_or(0),
+            },
+            "interpreter.debug" => GenerationEvent::InterpreterDebug {
+                primary_intent: str_field("primary_intent")?,
+                confidence: payload.get("confidence").and_then(|v| v.as_f64()).unwrap_or(0.0),
+            },
+            "message.final" => GenerationEvent::MessageFinal(MessageFinal {
+                id: str_field("id"),
+                content: str_field("content").unwrap_or_default(),
+                provider: str_field("provider"),
+                model: str_field("model"),
+                model_display_name: str_field("modelDisplayName"),
+                usage: payload
+                    .get("usage")
+                    .and_then(|v| serde_json::from_value(v.clone()).ok()),
+                raw: Value::Object(payload.clone()),
+            }),
+            "chat.updated" => GenerationEvent::ChatUpdated {
+                id: str_field("id")?,
+                title: str_field("title"),
+                updated_at: str_field("updatedAt"),
+            },
+            "error" => GenerationEvent::Error {
+                message: str_field("message")
+                    .or_else(|| str_field("error_message"))
+                    .unwrap_or_else(|| "Unknown error".to_string()),
+                // Provider failures carry `retrying`; treat them as fatal only
+                // once no fallback is left.
+                fatal: payload
+                    .get("fatal")
+                    .and_then(|v| v.as_bool())
+                    .or_else(|| payload.get("retrying").and_then(|v| v.as_bool()).map(|r| !r))
+                    .unwrap_or(true),
+                provider: str_field("provider"),
+            },
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    /// The event as one NDJSON object: its wire payload plus a `type` field.
+    pub fn to_json(&self) -> Value {
+        match self {
+            GenerationEvent::TokenDelta { text } => json!({ "type": "token.delta", "text": text }),
+            GenerationEvent::Status { message } => json!({ "type": "status", "message": message }),
+            GenerationEvent::ToolStart { tool_call_id, tool_name, arguments } => json!({
+                "type": "tool.start",
+                "tool_call_id": tool_call_id,
+                "tool_name": tool_name,
+                "arguments": arguments,
+            }),
+            GenerationEvent::ToolArguments { tool_call_id, name, arguments } => json!({
+                "type": "tool.arguments",
+                "tool_call_id": tool_call_id,
+                "name": name,
+                "arguments": arguments,
+            }),
+            GenerationEvent::ToolEnd { tool_call_id, tool_name, success, duration_ms, preview, error } => json!({
+                "type": "tool.end",
+                "tool_call_id": tool_call_id,
+                "tool_name": tool_name,
+                "success": success,
+                "duration_ms": duration_ms,
+                "preview": preview,
+                "error": error,
+            }),
+            GenerationEvent::MemoryInjected { identity_chunks, chat_chunks } => json!({
+                "type": "memory.injected",
+                "identity_chunks": identity_chunks,
+                "chat_chunks": chat_chunks,
+            }),
+            GenerationEvent::InterpreterDebug { primary_intent, confidence } => json!({
+                "type": "interpreter.debug",
+                "primary_intent": primary_intent,
+                "confidence": confidence,
+            }),
+            GenerationEvent::MessageFinal(message) => {
+                let mut payload = match &message.raw {
+                    Value::Object(_) => message.raw.clone(),
+                    _ => json!({ "content": message.content }),
+                };
+                payload["type"] = json!("message.final");
+                payload
+            }
+            GenerationEvent::ChatUpdated { id, title, updated_at } => json!({
+                "type": "chat.updated",
+                "id": id,
+                "title": title,
+                "updatedAt": updated_at,
+            }),
+            GenerationEvent::Error { message, fatal, provider } => json!({
+                "type": "error",
+                "message": message,
+                "fatal": fatal,
+                "provider": provider,
+            }),
+            GenerationEvent::Unknown { event_type, data } => json!({
+                "type": event_type,
+                "data": serde_json::from_str::<Value>(data).unwrap_or_else(|_| json!(data)),
+            }),
+        }
+    }
+}
+
+/// Options for [`ApiClient::post_stream_with_options`].
+#[derive(Debug, Clone)]
+pub struct StreamOptions {
+    /// Reconnect with `Last-Event-ID` after a transport error.
+    pub resume: bool,
+    /// Maximum reconnection attempts over the lifetime of the stream.
+    pub max_reconnects: u32,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            resume: true,
+            max_reconnects: 3,
+        }
+    }
+}
+
+const DEFAULT_STREAM_RETRY: Duration = Duration::from_secs(1);
+
+/// Streams have no overall deadline; instead they fail after this long without
+/// any bytes (events or heartbeats), or after `--timeout` if that is longer.
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+fn describe_stream_error(err: &reqwest::Error) -> String {
+    if err.is_timeout() {
+        "stream idle timeout".to_string()
+    } else {
+        format!("connection lost: {err}")
+    }
+}
+
+/// A stream being captured for `--record`.
+struct StreamRecording {
+    token: Option<String>,
+    request_id: Option<String>,
+    events: Vec<RecordedEvent>,
+}
+
+fn header_request_id(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+}
+
+fn header_retry_after(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+}
+
+fn replayed_retry_after(headers: &std::collections::BTreeMap<String, String>) -> Option<Duration> {
+    headers
+        .get("retry-after")
+        .and_then(|v| parse_retry_after(v, SystemTime::now()))
+}
+
+/// `90s`, `2m30s`: how long the server asked us to wait.
+fn format_wait(wait: Duration) -> String {
+    let secs = wait.as_secs_f64().ceil() as u64;
+    match (secs / 60, secs % 60) {
+        (0, s) => format!("{s}s"),
+        (m, 0) => format!("{m}m"),
+        (m, s) => format!("{m}m{s}s"),
+    }
+}
+
+/// Build a cassette entry with credentials redacted.
+#[allow(clippy::too_many_arguments)]
+fn record_interaction(
+    method: &str,
+    path: &str,
+    query: Option<&[(String, String)]>,
+    body: Option<&Value>,
+    token: Option<&str>,
+    status: StatusCode,
+    request_id: Option<String>,
+    response: RecordedBody,
+) -> Interaction {
+    let mut request_headers = std::collections::BTreeMap::new();
+    if let Some(token) = token {
+        request_headers.insert(
+            "authorization".to_string(),
+            redact_authorization(&format!("Bearer {token}")),
+        );
+    }
+
+    Interaction {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.map(|q| q.to_vec()).unwrap_or_default(),
+        request_headers,
+        request_body: body.cloned().map(redact_json),
+        response_headers: std::collections::BTreeMap::new(),
+        status: status.as_u16(),
+        request_id,
+        response,
+    }
 }
 
 fn join_url(base_url: &str, path: &str) -> String {
@@ -467,6 +1390,48 @@ fn join_url(base_url: &str, path: &str) -> String {
     format!("{base}/{trimmed_path}")
 }
 
+fn is_mutating(method: &Method) -> bool {
+    matches!(*method, Method::POST | Method::PATCH | Method::DELETE)
+}
+
+const NETWORK_HINT: &str = "Check --api-url and that the Starbot API is reachable.";
+
+fn status_hint(status: StatusCode) -> Option<&'static str> {
+    match status.as_u16() {
+        401 => Some("Run `starbott auth login` to sign in again."),
+        403 => Some("Check that the active profile and workspace have access to this resource."),
+        429 => Some("Wait before retrying, or allow more time with --retry-budget."),
+        500..=599 => Some("Try again later; include the request_id when reporting the problem."),
+        _ => None,
+    }
+}
+
+/// `snake_case` identifiers such as `rate_limited`, as opposed to prose.
+fn is_reason_code(value: &str) -> bool {
+    value
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+        && value.starts_with(|c: char| c.is_ascii_lowercase())
+}
+
+/// Whether a JWT's `exp` claim lies before `now`. Tokens that are not JWTs,
+/// or carry no `exp`, are not considered expired.
+fn jwt_expired(token: &str, now: SystemTime) -> bool {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    let exp = token
+        .split('.')
+        .nth(1)
+        .and_then(|claims| URL_SAFE_NO_PAD.decode(claims.trim_end_matches('=')).ok())
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+        .and_then(|claims| claims.get("exp").and_then(|v| v.as_u64()));
+    match (exp, now.duration_since(std::time::UNIX_EPOCH)) {
+        (Some(exp), Ok(elapsed)) => exp <= elapsed.as_secs(),
+        _ => false,
+    }
+}
+
 fn is_retryable_status(status: StatusCode) -> bool {
     matches!(
         status,
@@ -477,8 +1442,94 @@ fn is_retryable_status(status: StatusCode) -> bool {
     )
 }
 
-fn backoff_delay_ms(attempt: u32) -> Duration {
-    let pow = attempt.min(6);
-    let factor = 1u64 << pow;
-    Duration::from_millis(200 * factor)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jwt_expiry_is_read_from_the_exp_claim() {
+        use base64::Engine;
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+        let jwt = |claims: Value| format!("h.{}.sig", URL_SAFE_NO_PAD.encode(claims.to_string()));
+        let now = std::time::UNIX_EPOCH + Duration::from_secs(2_000);
+        assert!(jwt_expired(&jwt(json!({ "exp": 1_000 })), now));
+        assert!(!jwt_expired(&jwt(json!({ "exp": 3_000 })), now));
+        assert!(!jwt_expired(&jwt(json!({ "sub": "u1" })), now));
+        assert!(!jwt_expired("opaque-token", now));
+    }
+
+    #[test]
+    fn decodes_token_delta_and_final_message() {
+        assert_eq!(
+            GenerationEvent::decode("token.delta", r#"{"text":"hi"}"#),
+            GenerationEvent::TokenDelta { text: "hi".to_string() }
+        );
+
+        let data = r#"{"id":"m1","content":"done","provider":"azure","modelDisplayName":"GPT","usage":{"promptTokens":3,"completionTokens":4,"totalTokens":7},"lane":"quick"}"#;
+        let GenerationEvent::MessageFinal(message) = GenerationEvent::decode("message.final", data) else {
+            panic!("expected message.final");
+        };
+        assert_eq!(message.content, "done");
+        assert_eq!(message.model_display_name.as_deref(), Some("GPT"));
+        assert_eq!(message.usage.map(|u| u.total_tokens), Some(7));
+        assert_eq!(message.raw["lane"], "quick");
+    }
+
+    #[test]
+    fn decodes_tool_lifecycle() {
+        let end = GenerationEvent::decode(
+            "tool.end",
+            r#"{"tool_call_id":"c1","tool_name":"web_search","success":false,"error":"Tool not found"}"#,
+        );
+        assert_eq!(
+            end,
+            GenerationEvent::ToolEnd {
+                tool_call_id: "c1".to_string(),
+                tool_name: "web_search".to_string(),
+                success: false,
+                duration_ms: None,
+                preview: None,
+                error: Some("Tool not found".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn error_fatality_follows_fatal_or_retrying() {
+        let fatal = GenerationEvent::decode("error", r#"{"message":"boom","fatal":true}"#);
+        assert!(matches!(fatal, GenerationEvent::Error { fatal: true, .. }));
+
+        let fallback = GenerationEvent::decode(
+            "error",
+            r#"{"type":"provider_failure","provider":"vertex","error_message":"503","retrying":true}"#,
+        );
+        assert_eq!(
+            fallback,
+            GenerationEvent::Error {
+                message: "503".to_string(),
+                fatal: false,
+                provider: Some("vertex".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_and_malformed_events_are_preserved() {
+        assert_eq!(
+            GenerationEvent::decode("codex.classification", r#"{"intent":"code"}"#),
+            GenerationEvent::Unknown {
+                event_type: "codex.classification".to_string(),
+                data: r#"{"intent":"code"}"#.to_string(),
+            }
+        );
+        assert!(matches!(
+            GenerationEvent::decode("token.delta", "not json"),
+            GenerationEvent::Unknown { .. }
+        ));
+        assert!(matches!(
+            GenerationEvent::decode("tool.start", r#"{"tool_call_id":"c1"}"#),
+            GenerationEvent::Unknown { .. }
+        ));
+    }
 }
@@ -1,7 +1,13 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::api::ApiClient;
-use crate::config::{CliConfig, active_profile_name, resolve_api_url, resolve_token};
+use crate::cassette::Cassette;
+use crate::config::{
+    CliConfig, active_profile_name, profile_ref, resolve_api_url, resolve_refresh_token,
+    resolve_token,
+};
 use crate::errors::CliError;
 use crate::output::OutputMode;
 
@@ -14,6 +20,9 @@ pub struct Runtime {
     pub api_url_override: Option<String>,
     pub timeout_ms: u64,
     pub retries: u32,
+    pub retry_budget: Duration,
+    /// `--record` / `--replay` cassette shared by every API client.
+    pub cassette: Option<Arc<Cassette>>,
 }
 
 impl Runtime {
@@ -33,13 +42,26 @@ impl Runtime {
         resolve_token(&self.config, &self.active_profile())
     }
 
+    pub fn resolved_refresh_token(&self) -> Option<String> {
+        resolve_refresh_token(&self.config, &self.active_profile())
+    }
+
+    /// The workspace selected for the active profile, if any.
+    pub fn workspace_id(&self) -> Option<String> {
+        profile_ref(&self.config, &self.active_profile()).and_then(|p| p.workspace_id.clone())
+    }
+
     pub fn api_client(&self) -> Result<ApiClient, CliError> {
-        ApiClient::new(
+        Ok(ApiClient::new(
             self.resolved_api_url()?,
             self.resolved_token(),
             self.timeout_ms,
             self.retries,
             self.output.debug,
-        )
+        )?
+        .with_refresh_token(self.resolved_refresh_token(), self.active_profile())
+        .with_retry_budget(self.retry_budget)
+        .with_verbose(self.output.verbose && !self.output.json && !self.output.quiet)
+        .with_cassette(self.cassette.clone()))
     }
 }
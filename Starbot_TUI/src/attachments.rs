@@ -0,0 +1,330 @@
+//! Local files attached to chat and agent prompts
+//!
+//! `--file <path>` (repeatable, `-` for stdin) and `--glob <pattern>` read
+//! files from disk. Text goes into the prompt as labelled `<file>` blocks so
+//! any model can see it; binary files travel as base64 message attachments.
+//! Each file is held to the server's 100KB `file.read` cap: text beyond it is
+//! truncated with a marker, binary files beyond it are refused.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use serde_json::{Value, json};
+
+use crate::app::Runtime;
+use crate::errors::CliError;
+use crate::parse::glob::Glob;
+
+/// Per-file cap, matching the server's `file.read` limit.
+pub const MAX_FILE_BYTES: usize = 100 * 1024;
+/// More matches than this almost always means a pattern that is too broad.
+const MAX_GLOB_MATCHES: usize = 50;
+/// Directories never descended into while expanding a glob.
+const SKIPPED_DIRS: [&str; 3] = [".git", "node_modules", "target"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Text { text: String, truncated: bool },
+    Binary { mime: &'static str, data: Vec<u8> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    /// The path as given (or `stdin`).
+    pub label: String,
+    /// Size on disk, before any truncation.
+    pub size: usize,
+    pub content: Content,
+}
+
+impl Attachment {
+    pub fn from_bytes(label: &str, bytes: Vec<u8>) -> Result<Self, CliError> {
+        let size = bytes.len();
+        let content = if is_binary(&bytes) {
+            if size > MAX_FILE_BYTES {
+                return Err(CliError::usage(format!(
+                    "{label} is a {} binary file; attachments are limited to {}.",
+                    human_size(size),
+                    human_size(MAX_FILE_BYTES)
+                )));
+            }
+            Content::Binary {
+                mime: mime_type(label),
+                data: bytes,
+            }
+        } else {
+            let mut text = String::from_utf8(bytes)
+                .map_err(|_| CliError::generic(format!("{label} is not valid UTF-8.")))?;
+            let truncated = text.len() > MAX_FILE_BYTES;
+            if truncated {
+                let mut end = MAX_FILE_BYTES;
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                text.truncate(end);
+            }
+            Content::Text { text, truncated }
+        };
+        Ok(Self {
+            label: label.to_string(),
+            size,
+            content,
+        })
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        matches!(
+            self.content,
+            Content::Text {
+                truncated: true,
+                ..
+            }
+        )
+    }
+}
+
+/// Read every `--file` and `--glob` in order, skipping files named twice.
+pub fn collect(files: &[PathBuf], globs: &[String]) -> Result<Vec<Attachment>, CliError> {
+    let mut seen = HashSet::new();
+    let mut attachments = Vec::new();
+
+    for path in files {
+        if path.as_os_str() == "-" {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|e| CliError::generic(format!("Failed reading stdin: {e}")))?;
+            attachments.push(Attachment::from_bytes("stdin", bytes)?);
+            continue;
+        }
+        if seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone())) {
+            attachments.push(read_file(path)?);
+        }
+    }
+
+    for pattern in globs {
+        let matches = expand_glob(pattern)?;
+        for path in matches {
+            if seen.insert(fs::canonicalize(&path).unwrap_or_else(|_| path.clone())) {
+                attachments.push(read_file(&path)?);
+            }
+        }
+    }
+
+    Ok(attachments)
+}
+
+/// [`collect`], warning on stderr about each truncated file.
+pub fn load(
+    runtime: &Runtime,
+    files: &[PathBuf],
+    globs: &[String],
+) -> Result<Vec<Attachment>, CliError> {
+    let attachments = collect(files, globs)?;
+    for attachment in attachments.iter().filter(|a| a.is_truncated()) {
+        runtime.output.print_stderr(&format!(
+            "Warning: {} is {}; only the first {} is attached.",
+            attachment.label,
+            human_size(attachment.size),
+            human_size(MAX_FILE_BYTES)
+        ));
+    }
+    Ok(attachments)
+}
+
+fn read_file(path: &Path) -> Result<Attachment, CliError> {
+    let label = path.display().to_string();
+    if path.is_dir() {
+        return Err(CliError::usage(format!("{label} is a directory."))
+            .with_hint("Use --glob '<dir>/**/*' to attach its files."));
+    }
+    let bytes =
+        fs::read(path).map_err(|err| CliError::usage(format!("Could not read {label}: {err}")))?;
+    Attachment::from_bytes(&label, bytes)
+}
+
+/// Files under the glob's base directory whose path matches, sorted.
+pub fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>, CliError> {
+    let glob = Glob::new(pattern).map_err(CliError::usage)?;
+    let base = match glob.base_dir() {
+        "" => PathBuf::from("."),
+        dir => PathBuf::from(dir),
+    };
+
+    let mut found = Vec::new();
+    let mut pending = vec![base];
+    while let Some(dir) = pending.pop() {
+        if dir.is_file() {
+            found.push(dir);
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(kind) = entry.file_type() else {
+                continue;
+            };
+            if kind.is_dir() {
+                let name = entry.file_name();
+                if !SKIPPED_DIRS.iter().any(|skip| name == *skip) {
+                    pending.push(path);
+                }
+            } else if kind.is_file() {
+                found.push(path);
+            }
+        }
+    }
+
+    let mut matches: Vec<PathBuf> = found
+        .into_iter()
+        .filter(|path| {
+            let text = path.to_string_lossy();
+            glob.is_match(text.strip_prefix("./").unwrap_or(&text))
+        })
+        .collect();
+    matches.sort();
+
+    if matches.is_empty() {
+        return Err(CliError::usage(format!("No files match `{pattern}`.")));
+    }
+    if matches.len() > MAX_GLOB_MATCHES {
+        return Err(CliError::usage(format!(
+            "`{pattern}` matches {} files (at most {MAX_GLOB_MATCHES} can be attached).",
+            matches.len()
+        ))
+        .with_hint("Narrow the pattern, or pass the files you need with --file."));
+    }
+    Ok(matches)
+}
+
+/// The prompt with text attachments appended as `<file>` blocks, plus the
+/// binary ones as message attachments.
+pub fn apply(prompt: &str, attachments: &[Attachment]) -> (String, Vec<Value>) {
+    let mut content = prompt.to_string();
+    let mut binary = Vec::new();
+
+    for attachment in attachments {
+        match &attachment.content {
+            Content::Text { text, truncated } => {
+                content.push_str(&format!("\n\n<file path=\"{}\"", attachment.label));
+                if *truncated {
+                    content.push_str(&format!(
+                        " truncated=\"first {} of {}\"",
+                        human_size(MAX_FILE_BYTES),
+                        human_size(attachment.size)
+                    ));
+                }
+                content.push_str(">\n");
+                content.push_str(text);
+                if !text.ends_with('\n') {
+                    content.push('\n');
+                }
+                content.push_str("</file>");
+            }
+            Content::Binary { mime, data } => {
+                content.push_str(&format!(
+                    "\n\n<file path=\"{}\" type=\"{mime}\" size=\"{}\">(attached as binary)</file>",
+                    attachment.label,
+                    human_size(attachment.size)
+                ));
+                binary.push(json!({
+                    "name": attachment.label,
+                    "mimeType": mime,
+                    "size": attachment.size,
+                    "encoding": "base64",
+                    "data": base64::engine::general_purpose::STANDARD.encode(data),
+                }));
+            }
+        }
+    }
+
+    (content, binary)
+}
+
+/// NUL bytes or invalid UTF-8 in the first 8KB mean binary.
+fn is_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(8192)];
+    if head.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => false,
+        // A multi-byte character cut off by the 8KB window is still text.
+        Err(err) => err.error_len().is_some(),
+    }
+}
+
+fn mime_type(label: &str) -> &'static str {
+    let ext = Path::new(label)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match ext.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+pub fn human_size(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{bytes}B")
+    } else {
+        format!("{:.0}KB", bytes as f64 / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_binary_content() {
+        assert!(!is_binary(b"fn main() {}\n"));
+        assert!(!is_binary("héllo wörld".as_bytes()));
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(is_binary(&[0xff, 0xfe, 0x41]));
+    }
+
+    #[test]
+    fn truncates_text_and_refuses_large_binaries() {
+        let big = "é".repeat(MAX_FILE_BYTES);
+        let attachment = Attachment::from_bytes("big.txt", big.into_bytes()).unwrap();
+        assert!(attachment.is_truncated());
+        assert_eq!(attachment.size, MAX_FILE_BYTES * 2);
+        let Content::Text { text, .. } = &attachment.content else {
+            panic!("expected text");
+        };
+        assert_eq!(text.len(), MAX_FILE_BYTES);
+
+        let mut blob = vec![0u8; MAX_FILE_BYTES + 1];
+        blob[0] = 0x89;
+        assert!(Attachment::from_bytes("blob.bin", blob).is_err());
+    }
+
+    #[test]
+    fn applies_text_blocks_and_binary_attachments() {
+        let attachments = vec![
+            Attachment::from_bytes("trace.log", b"panicked at main.rs:3".to_vec()).unwrap(),
+            Attachment::from_bytes("shot.png", vec![0x89, b'P', b'N', b'G', 0]).unwrap(),
+        ];
+        let (prompt, binary) = apply("explain this", &attachments);
+        assert_eq!(
+            prompt,
+            "explain this\n\n<file path=\"trace.log\">\npanicked at main.rs:3\n</file>\n\n<file path=\"shot.png\" type=\"image/png\" size=\"5B\">(attached as binary)</file>"
+        );
+        assert_eq!(binary.len(), 1);
+        assert_eq!(binary[0]["mimeType"], "image/png");
+        assert_eq!(binary[0]["data"], "iVBORwA=");
+    }
+}
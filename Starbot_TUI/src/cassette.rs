@@ -0,0 +1,302 @@
+//! HTTP record/replay
+//!
+//! `--record <file>` captures every request `ApiClient` makes together with the
+//! server's answer, SSE streams included, in a JSON "cassette". Tokens are
+//! redacted before anything is written. `--replay <file>` serves those answers
+//! back without touching the network, so a cassette attached to a bug report
+//! reproduces the session offline.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::errors::{CliError, redact_secret};
+use crate::sse::SseEvent;
+
+const CASSETTE_VERSION: u32 = 1;
+
+/// One request and the response it received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub query: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub request_headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<Value>,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub response_headers: BTreeMap<String, String>,
+    pub response: RecordedBody,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedBody {
+    Json(Value),
+    Text(String),
+    Events(Vec<RecordedEvent>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub event: String,
+    pub data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+impl From<&SseEvent> for RecordedEvent {
+    fn from(event: &SseEvent) -> Self {
+        Self {
+            event: event.event_type.clone(),
+            data: redact_text(&event.data),
+            id: event.id.clone(),
+        }
+    }
+}
+
+impl From<RecordedEvent> for SseEvent {
+    fn from(event: RecordedEvent) -> Self {
+        SseEvent {
+            event_type: event.event,
+            data: event.data,
+            id: event.id,
+        }
+    }
+}
+
+impl RecordedBody {
+    /// Store a response body, keeping JSON structured so the cassette stays
+    /// readable and its token fields can be redacted.
+    pub fn from_text(text: &str) -> Self {
+        match serde_json::from_str::<Value>(text) {
+            Ok(json) => RecordedBody::Json(redact_json(json)),
+            Err(_) => RecordedBody::Text(text.to_string()),
+        }
+    }
+
+    /// The body as the server sent it (modulo redaction).
+    pub fn to_text(&self) -> String {
+        match self {
+            RecordedBody::Json(json) => json.to_string(),
+            RecordedBody::Text(text) => text.clone(),
+            RecordedBody::Events(_) => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    version: u32,
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+/// A cassette shared by every clone of an `ApiClient`.
+#[derive(Debug)]
+pub struct Cassette {
+    mode: Mode,
+    path: PathBuf,
+    interactions: Mutex<Vec<Interaction>>,
+    /// Replay only: which interactions have been served.
+    used: Mutex<Vec<bool>>,
+}
+
+impl Cassette {
+    /// Start a new recording at `path`, replacing any existing file.
+    pub fn record(path: &Path) -> Result<Self, CliError> {
+        let cassette = Self {
+            mode: Mode::Record,
+            path: path.to_path_buf(),
+            interactions: Mutex::new(Vec::new()),
+            used: Mutex::new(Vec::new()),
+        };
+        cassette.save(&[])?;
+        Ok(cassette)
+    }
+
+    /// Load a cassette for replay.
+    pub fn replay(path: &Path) -> Result<Self, CliError> {
+        let text = fs::read_to_string(path).map_err(|err| {
+            CliError::usage(format!("Could not read cassette {}: {err}", path.display()))
+        })?;
+        let file: CassetteFile = serde_json::from_str(&text).map_err(|err| {
+            CliError::usage(format!("Invalid cassette {}: {err}", path.display()))
+        })?;
+        if file.version != CASSETTE_VERSION {
+            return Err(CliError::usage(format!(
+                "Unsupported cassette version {} (expected {CASSETTE_VERSION}).",
+                file.version
+            )));
+        }
+
+        let used = vec![false; file.interactions.len()];
+        Ok(Self {
+            mode: Mode::Replay,
+            path: path.to_path_buf(),
+            interactions: Mutex::new(file.interactions),
+            used: Mutex::new(used),
+        })
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self.mode, Mode::Replay)
+    }
+
+    /// Append an interaction and rewrite the file, so the cassette is complete
+    /// even if the command exits with an error right after.
+    pub fn push(&self, interaction: Interaction) -> Result<(), CliError> {
+        if self.is_replay() {
+            return Ok(());
+        }
+        let mut interactions = self
+            .interactions
+            .lock()
+            .map_err(|_| CliError::generic("Cassette lock poisoned.".to_string()))?;
+        interactions.push(interaction);
+        self.save(&interactions)
+    }
+
+    /// The next unplayed interaction for `method path`, in recording order.
+    pub fn next(&self, method: &str, path: &str) -> Result<Interaction, CliError> {
+        let interactions = self
+            .interactions
+            .lock()
+            .map_err(|_| CliError::generic("Cassette lock poisoned.".to_string()))?;
+        let mut used = self
+            .used
+            .lock()
+            .map_err(|_| CliError::generic("Cassette lock poisoned.".to_string()))?;
+
+        let index = interactions
+            .iter()
+            .enumerate()
+            .position(|(idx, i)| !used[idx] && i.method == method && i.path == path)
+            .ok_or_else(|| {
+                CliError::network(format!(
+                    "No recorded response for {method} {path} in cassette {}.",
+                    self.path.display()
+                ))
+            })?;
+        used[index] = true;
+        Ok(interactions[index].clone())
+    }
+
+    fn save(&self, interactions: &[Interaction]) -> Result<(), CliError> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = CassetteFile {
+            version: CASSETTE_VERSION,
+            interactions: interactions.to_vec(),
+        };
+        fs::write(&self.path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+}
+
+/// Redact credentials in a JSON document: any string under a key that looks
+/// like a token, secret, password, or device code.
+pub fn redact_json(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        Value::String(s) if is_secret_key(&key) => Value::String(redact_secret(&s)),
+                        other => redact_json(other),
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact_json).collect()),
+        other => other,
+    }
+}
+
+/// Redact a raw payload if it is JSON; other text is kept as-is.
+fn redact_text(text: &str) -> String {
+    match serde_json::from_str::<Value>(text) {
+        Ok(json @ (Value::Object(_) | Value::Array(_))) => redact_json(json).to_string(),
+        _ => text.to_string(),
+    }
+}
+
+/// `Authorization` values keep their scheme; the credential is redacted.
+pub fn redact_authorization(value: &str) -> String {
+    match value.split_once(' ') {
+        Some((scheme, credential)) => format!("{scheme} {}", redact_secret(credential)),
+        None => redact_secret(value),
+    }
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    ["token", "secret", "password", "device_code", "api_key", "apikey"]
+        .iter()
+        .any(|needle| key.contains(needle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redacts_nested_token_fields() {
+        let redacted = redact_json(json!({
+            "access_token": "abcdef123456",
+            "user": { "refreshToken": "zzzzzzzzzz", "name": "ada" },
+            "totalTokens": 42,
+        }));
+        assert_eq!(redacted["access_token"], "abc******456");
+        assert_eq!(redacted["user"]["refreshToken"], "zzz****zzz");
+        assert_eq!(redacted["user"]["name"], "ada");
+        assert_eq!(redacted["totalTokens"], 42);
+    }
+
+    #[test]
+    fn replays_matching_interactions_in_order() {
+        let path = std::env::temp_dir().join(format!("starbott-cassette-{}.json", std::process::id()));
+        let recorder = Cassette::record(&path).unwrap();
+        for n in 1..=2 {
+            recorder
+                .push(Interaction {
+                    method: "GET".to_string(),
+                    path: "/v1/health".to_string(),
+                    query: Vec::new(),
+                    request_headers: BTreeMap::new(),
+                    request_body: None,
+                    status: 200,
+                    request_id: None,
+                    response_headers: BTreeMap::new(),
+                    response: RecordedBody::Json(json!({ "n": n })),
+                })
+                .unwrap();
+        }
+
+        let player = Cassette::replay(&path).unwrap();
+        assert!(player.next("POST", "/v1/health").is_err());
+        for n in 1..=2 {
+            let interaction = player.next("GET", "/v1/health").unwrap();
+            assert_eq!(interaction.response.to_text(), json!({ "n": n }).to_string());
+        }
+        assert!(player.next("GET", "/v1/health").is_err());
+        let _ = fs::remove_file(path);
+    }
+}
@@ -3,52 +3,59 @@
 //! This module implements an agent that creates chats, calls the generation
 //! endpoint (`POST /v1/chats/:chatId/run`) via SSE streaming, handles tool
 //! calls, and manages tasks.
+//!
+//! Server-side tools run on the server and are only reported here. Local
+//! tools are requested by the model with the `<tool>`/`<args>` tags described
+//! in `agent_system_prompt.md`; the agent runs them through
+//! [`EnhancedToolExecutor`], posts the results back as a `<tool_result>`
+//! message and runs the chat again, until a reply asks for no more tools or
+//! `max_iterations` is reached.
 
 use std::collections::HashMap;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
 
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::api::ApiClient;
+use crate::api::{ApiClient, GenerationEvent, StreamEvent};
 use crate::app::Runtime;
+use crate::attachments::{self, Attachment};
+use crate::commands::enhanced_tools::{self, EnhancedToolExecutor, ToolConfig, ToolMode};
+use crate::commands::pty::{PtyConfig, PtyManager};
+use crate::conversations;
 use crate::errors::CliError;
+use crate::journal::Journal;
+use crate::policy::{Approval, ToolPolicy};
 
 // ---------------------------------------------------------------------------
 // Types
 // ---------------------------------------------------------------------------
 
-/// Agent state machine
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum AgentState {
-    Idle,
-    Reasoning,
-    Executing,
-    Processing,
-    Error,
-}
-
 /// Agent configuration
 #[derive(Debug, Clone)]
 pub struct AgentConfig {
     pub model: String,
-    pub max_tokens: u32,
-    pub temperature: f32,
     pub system_prompt: String,
     pub max_iterations: u32,
-    pub enable_tasks: bool,
+    /// Gate for local tool calls; see [`crate::policy`].
+    pub tool_policy: ToolPolicy,
+    pub approval: Approval,
+    /// Workspace whose root file tools are confined to.
+    pub workspace_id: Option<String>,
 }
 
 impl Default for AgentConfig {
     fn default() -> Self {
         Self {
             model: "auto".to_string(),
-            max_tokens: 4096,
-            temperature: 0.7,
             system_prompt: include_str!("agent_system_prompt.md").to_string(),
             max_iterations: 50,
-            enable_tasks: true,
+            tool_policy: ToolPolicy::default(),
+            approval: Approval::default(),
+            workspace_id: None,
         }
     }
 }
@@ -88,10 +95,181 @@ impl ToolResult {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Local tool calls
+// ---------------------------------------------------------------------------
+
+/// `<tool>name</tool>` with optional `<args>{...}</args>`, as the agent
+/// system prompt asks the model to write them.
+static TOOL_CALL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<tool>\s*([A-Za-z0-9_.-]+)\s*</tool>\s*(?:<args>(.*?)</args>)?")
+        .expect("valid regex")
+});
+
+/// Tool output beyond this is cut before it is posted back to the chat.
+const MAX_TOOL_OUTPUT: usize = attachments::MAX_FILE_BYTES;
+
+/// Tool calls in an assistant reply, in order. Calls whose arguments are not
+/// a JSON object come back as `Err` so the model can be told to retry.
+pub fn parse_tool_calls(text: &str, iteration: u32) -> Vec<Result<ToolCall, (ToolCall, String)>> {
+    TOOL_CALL
+        .captures_iter(text)
+        .enumerate()
+        .map(|(idx, captures)| {
+            let mut call = ToolCall {
+                id: format!("call-{iteration}-{}", idx + 1),
+                name: captures[1].to_string(),
+                arguments: HashMap::new(),
+            };
+            let raw = captures.get(2).map_or("", |m| m.as_str()).trim();
+            if raw.is_empty() {
+                return Ok(call);
+            }
+            match serde_json::from_str::<serde_json::Value>(raw) {
+                Ok(serde_json::Value::Object(map)) => {
+                    call.arguments = map.into_iter().collect();
+                    Ok(call)
+                }
+                _ => Err((call, "arguments must be a JSON object".to_string())),
+            }
+        })
+        .collect()
+}
+
+/// The executor local tool calls run through: direct execution only, so
+/// nothing is proposed to the server behind the user's back, and every call
+/// checked against `policy` first.
+pub fn local_executor(api: &ApiClient, policy: ToolPolicy, approval: Approval) -> EnhancedToolExecutor {
+    EnhancedToolExecutor::new(
+        api.clone(),
+        ToolConfig {
+            mode: ToolMode::Direct,
+            ..Default::default()
+        },
+    )
+    .with_policy(policy, approval)
+    .with_shells(shell_manager())
+}
+
+/// Where `shell_open` sessions live. They are recorded, as an audit trail
+/// of what the agent ran.
+pub fn shell_manager() -> Arc<PtyManager> {
+    Arc::new(PtyManager::new(PtyConfig {
+        record: true,
+        ..Default::default()
+    }))
+}
+
+/// Run one call; tool failures become failed results for the model to see.
+pub async fn execute_tool_call(executor: &EnhancedToolExecutor, call: &ToolCall) -> ToolResult {
+    let started = std::time::Instant::now();
+    let result = match executor.execute_tool(&call.name, &call.arguments).await {
+        Ok(enhanced_tools::ToolResult { success: true, output, .. }) => ToolResult::success(output),
+        Ok(enhanced_tools::ToolResult { output, error, .. }) => ToolResult {
+            error: error.or_else(|| Some(output.clone())),
+            ..ToolResult::error(output)
+        },
+        Err(err) => ToolResult {
+            error: Some(err.to_string()),
+            ..ToolResult::error(String::new())
+        },
+    };
+    ToolResult {
+        metadata: Some(HashMap::from([(
+            "duration_ms".to_string(),
+            started.elapsed().as_millis().to_string(),
+        )])),
+        ..result
+    }
+}
+
+/// Run every tool call in `reply`, in order; empty when it asks for none.
+/// `report` sees each call before it runs and again with its result.
+pub async fn run_tool_calls(
+    executor: &EnhancedToolExecutor,
+    reply: &str,
+    iteration: u32,
+    mut report: impl FnMut(&ToolCall, Option<&ToolResult>),
+) -> Vec<(ToolCall, ToolResult)> {
+    let mut results = Vec::new();
+    for parsed in parse_tool_calls(reply, iteration) {
+        let (call, result) = match parsed {
+            Ok(call) => {
+                report(&call, None);
+                let result = execute_tool_call(executor, &call).await;
+                (call, result)
+            }
+            Err((call, reason)) => {
+                report(&call, None);
+                let result = ToolResult {
+                    error: Some(reason),
+                    ..ToolResult::error(String::new())
+                };
+                (call, result)
+            }
+        };
+        report(&call, Some(&result));
+        results.push((call, result));
+    }
+    results
+}
+
+/// The follow-up user message carrying a round of tool results.
+pub fn tool_results_message(results: &[(ToolCall, ToolResult)]) -> String {
+    results
+        .iter()
+        .map(|(call, result)| {
+            // A failure's error is a summary; any output follows it.
+            let mut body = match (&result.error, result.success) {
+                (Some(error), false) if result.output.is_empty() => error.clone(),
+                (Some(error), false) => format!("{error}\n{}", result.output),
+                _ => result.output.clone(),
+            };
+            if body.len() > MAX_TOOL_OUTPUT {
+                let mut end = MAX_TOOL_OUTPUT;
+                while !body.is_char_boundary(end) {
+                    end -= 1;
+                }
+                body.truncate(end);
+                body.push_str("\n[output truncated]");
+            }
+            if !body.ends_with('\n') {
+                body.push('\n');
+            }
+            format!(
+                "<tool_result id=\"{}\" tool=\"{}\" success=\"{}\">\n{body}</tool_result>",
+                call.id, call.name, result.success
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
 // ---------------------------------------------------------------------------
 // Agent run – the real entry point
 // ---------------------------------------------------------------------------
 
+/// Everything `agent run` (and `chat --stream`) sends besides the prompt.
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    pub project_id: Option<String>,
+    /// Chat to continue; a new one is created when unset.
+    pub chat_id: Option<String>,
+    pub model_prefs: Option<String>,
+    pub attachments: Vec<Attachment>,
+    /// The messages API has no system role, so this is sent as an
+    /// `<instructions>` block ahead of the user's message.
+    pub system_prompt: Option<String>,
+    /// quick, standard or deep
+    pub lane: Option<String>,
+    /// Run `<tool>` calls from the reply locally and feed the results back.
+    pub local_tools: bool,
+    /// Model turns allowed with local tools; [`AgentConfig`]'s default when unset.
+    pub max_iterations: Option<u32>,
+    /// Run local tool calls the policy would ask about without asking.
+    pub yolo: bool,
+}
+
 /// Handle `starbott agent run "prompt"`.
 ///
 /// Flow:
@@ -100,34 +278,51 @@ impl ToolResult {
 ///   3. Add the user message.
 ///   4. Stream generation via `POST /v1/chats/:chatId/run`.
 ///   5. Print tokens as they arrive, report tool calls.
+///   6. With local tools, run the reply's tool calls, post their results and
+///      go back to 4.
 pub async fn handle_run(
     api: &ApiClient,
     runtime: &Runtime,
     prompt: String,
-    project_id: Option<String>,
-    chat_id: Option<String>,
-    model_prefs: Option<String>,
+    options: RunOptions,
 ) -> Result<(), CliError> {
-    // 1. Resolve project
-    let pid = match project_id {
-        Some(id) => id,
-        None => resolve_or_create_project(api).await?,
-    };
+    if options.local_tools {
+        runtime.config.tool_policy.validate()?;
+    }
 
-    // 2. Resolve chat
-    let cid = match chat_id {
-        Some(id) => id,
-        None => create_chat(api, &pid).await?,
+    // 1–2. Reuse the chat, or create one in the given (or first) project
+    let (pid, cid) = match options.chat_id {
+        Some(id) => (options.project_id, id),
+        None => {
+            let pid = match options.project_id {
+                Some(id) => id,
+                None => resolve_or_create_project(api).await?,
+            };
+            let cid = create_chat(api, &pid).await?;
+            (Some(pid), cid)
+        }
     };
-
-    // 3. Post user message to the chat
-    add_message(api, &cid, "user", &prompt).await?;
+    conversations::remember(runtime, &cid, pid.as_deref(), None);
+
+    // 3. Post user message to the chat, files included
+    let config = AgentConfig::default();
+    let system_prompt = match (options.local_tools, options.system_prompt) {
+        (true, Some(system)) => Some(format!("{}\n\n{system}", config.system_prompt)),
+        (true, None) => Some(config.system_prompt),
+        (false, system) => system,
+    };
+    let prompt = match &system_prompt {
+        Some(system) => format!("<instructions>\n{system}\n</instructions>\n\n{prompt}"),
+        None => prompt,
+    };
+    let (content, binary) = attachments::apply(&prompt, &options.attachments);
+    add_message(api, &cid, "user", &content, &binary).await?;
 
     // 4. Stream generation
     let body = json!({
-        "mode": "standard",
+        "mode": options.lane.as_deref().unwrap_or("standard"),
         "auto": true,
-        "model_prefs": model_prefs.as_deref().unwrap_or("auto"),
+        "model_prefs": options.model_prefs.as_deref().unwrap_or("auto"),
         "client_context": {
             "working_dir": std::env::current_dir()
                 .unwrap_or_else(|_| PathBuf::from("/"))
@@ -136,193 +331,329 @@ pub async fn handle_run(
         },
     });
 
-    let rx = api.post_stream(
-        &format!("/v1/chats/{}/run", cid),
-        Some(body),
-        true,
-    ).await?;
+    let approval = if options.yolo { Approval::Granted } else { Approval::Prompt };
+    let executor = options
+        .local_tools
+        .then(|| {
+            local_executor(api, runtime.config.tool_policy.clone(), approval)
+                .with_workspace(runtime.workspace_id())
+        });
+    let max_iterations = options.max_iterations.unwrap_or(config.max_iterations);
+    let mut iteration = 1;
+    let outcome = loop {
+        let streamed = match api.post_stream(
+            &format!("/v1/chats/{}/run", cid),
+            Some(body.clone()),
+            true,
+        ).await {
+            Ok(rx) => stream_to_terminal(rx, runtime).await,
+            Err(err) => Err(err),
+        };
+        let (title, reply) = match streamed {
+            Ok(streamed) => streamed,
+            Err(err) => break Err(err),
+        };
+        if title.is_some() {
+            conversations::remember(runtime, &cid, pid.as_deref(), title.as_deref());
+        }
 
-    stream_to_terminal(rx, runtime).await?;
+        // 6. Local tool round
+        let Some(executor) = &executor else {
+            break Ok(());
+        };
+        let results = run_tool_calls(executor, &reply, iteration, |call, result| {
+            report_local_tool(runtime, call, result);
+        })
+        .await;
+        if results.is_empty() {
+            break Ok(());
+        }
+        if iteration >= max_iterations {
+            break Err(CliError::generic(format!(
+                "Agent stopped after {max_iterations} iterations without a final answer."
+            ))
+            .with_hint("Raise --max-iterations, or continue with --chat-id."));
+        }
+        iteration += 1;
+        if let Err(err) = add_message(api, &cid, "user", &tool_results_message(&results), &[]).await {
+            break Err(err);
+        }
+    };
 
-    Ok(())
+    if let Some(executor) = &executor {
+        let closed = executor.close_shells().await;
+        if closed > 0 {
+            runtime.output.print_verbose(&format!("Closed {closed} shell session(s)."));
+        }
+    }
+    if let Some(journal) = executor.as_ref().map(|e| e.journal()).filter(|j| j.changed() > 0) {
+        if runtime.output.json {
+            let _ = runtime.output.print_event(&json!({
+                "type": "files.changed",
+                "files": journal.changed(),
+                "undoSession": journal.id(),
+            }));
+        }
+        report_changes(runtime, journal);
+    }
+    outcome
+}
+
+/// Point at `starbott undo` when the run changed files.
+pub fn report_changes(runtime: &Runtime, journal: &Journal) {
+    let files = journal.changed();
+    if files == 0 {
+        return;
+    }
+    runtime.output.print_stderr(&format!(
+        "Changed {files} file{}; undo with `starbott undo --session {}`.",
+        if files == 1 { "" } else { "s" },
+        journal.id()
+    ));
+}
+
+/// Print a local tool call the way server tool events are printed.
+fn report_local_tool(runtime: &Runtime, call: &ToolCall, result: Option<&ToolResult>) {
+    let event = match result {
+        None => GenerationEvent::ToolStart {
+            tool_call_id: call.id.clone(),
+            tool_name: call.name.clone(),
+            arguments: json!(call.arguments),
+        },
+        Some(result) => GenerationEvent::ToolEnd {
+            tool_call_id: call.id.clone(),
+            tool_name: call.name.clone(),
+            success: result.success,
+            duration_ms: result
+                .metadata
+                .as_ref()
+                .and_then(|m| m.get("duration_ms"))
+                .and_then(|ms| ms.parse().ok()),
+            preview: (!result.output.is_empty())
+                .then(|| result.output.chars().take(200).collect()),
+            error: result.error.clone(),
+        },
+    };
+    if runtime.output.json {
+        let mut line = event.to_json();
+        line["local"] = json!(true);
+        let _ = runtime.output.print_event(&line);
+        return;
+    }
+    match event {
+        GenerationEvent::ToolStart { tool_name, arguments, .. } => {
+            eprintln!("\x1b[33m[tool] executing locally: {}\x1b[0m", tool_name);
+            if runtime.output.verbose || runtime.output.debug {
+                eprintln!("\x1b[90m[tool.args] {} {}\x1b[0m", tool_name, arguments);
+            }
+        }
+        GenerationEvent::ToolEnd { tool_name, success, duration_ms, preview, error, .. } => {
+            let icon = if success { "\x1b[32m✓\x1b[0m" } else { "\x1b[31m✗\x1b[0m" };
+            eprintln!(
+                "{} \x1b[33m[tool] {} ({}ms)\x1b[0m",
+                icon,
+                tool_name,
+                duration_ms.unwrap_or(0)
+            );
+            if runtime.output.verbose || !success {
+                if let Some(detail) = error.or(preview) {
+                    eprintln!("\x1b[90m  {}\x1b[0m", detail);
+                }
+            }
+        }
+        _ => {}
+    }
 }
 
 // ---------------------------------------------------------------------------
 // SSE stream consumer
 // ---------------------------------------------------------------------------
 
+/// Print a generation stream; returns the chat title if the server set one,
+/// and the final reply.
 async fn stream_to_terminal(
-    mut rx: tokio::sync::mpsc::UnboundedReceiver<crate::api::StreamEvent>,
+    mut rx: tokio::sync::mpsc::UnboundedReceiver<StreamEvent>,
     runtime: &Runtime,
-) -> Result<(), CliError> {
+) -> Result<(Option<String>, String), CliError> {
     let mut full_response = String::new();
+    let mut chat_title = None;
+    let mut reply = String::new();
     let mut _tool_active = false;
 
     while let Some(event) = rx.recv().await {
-        match event.event_type.as_str() {
-            // Streaming tokens from the model
-            "token.delta" => {
-                if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                    if let Some(text) = parsed.get("text").and_then(|t| t.as_str()) {
-                        full_response.push_str(text);
-                        // Print token immediately for streaming UX
-                        print!("{}", text);
-                        let _ = std::io::stdout().flush();
-                    }
+        let event = match event {
+            StreamEvent::Event(event) => event,
+            StreamEvent::Reconnecting { attempt, last_event_id, error } => {
+                if runtime.output.json {
+                    runtime.output.print_event(&json!({
+                        "type": "stream.reconnecting",
+                        "attempt": attempt,
+                        "last_event_id": last_event_id,
+                        "error": error,
+                    }))?;
+                } else {
+                    eprintln!(
+                        "\x1b[33m[stream] {}; resuming after event {} (attempt {})\x1b[0m",
+                        error, last_event_id, attempt
+                    );
                 }
+                continue;
+            }
+            StreamEvent::Disconnected { error } => {
+                if !full_response.is_empty() && !runtime.output.json {
+                    println!();
+                }
+                return Err(CliError::network(format!("Stream ended unexpectedly: {error}")));
+            }
+        };
+
+        // A fatal error ends the run; it is reported once, as the command's error.
+        if let GenerationEvent::Error { message, fatal: true, .. } = &event {
+            if !full_response.is_empty() && !runtime.output.json {
+                println!();
+            }
+            return Err(CliError::server(message.clone()).with_reason("generation_failed"));
+        }
+
+        if let GenerationEvent::ChatUpdated { title: Some(title), .. } = &event {
+            chat_title = Some(title.clone());
+        }
+        if let GenerationEvent::MessageFinal(message) = &event {
+            reply = message.content.clone();
+        }
+
+        // `--json` streams every event as one NDJSON line on stdout.
+        if runtime.output.json {
+            runtime.output.print_event(&event.to_json())?;
+            continue;
+        }
+
+        match event {
+            // Streaming tokens from the model
+            GenerationEvent::TokenDelta { text } => {
+                full_response.push_str(&text);
+                // Print token immediately for streaming UX
+                print!("{}", text);
+                let _ = std::io::stdout().flush();
             }
 
             // Status updates from the backend pipeline
-            "status" => {
+            GenerationEvent::Status { message } => {
                 if runtime.output.verbose {
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                        if let Some(msg) = parsed.get("message").and_then(|m| m.as_str()) {
-                            eprintln!("\x1b[90m[status] {}\x1b[0m", msg);
-                        }
-                    }
+                    eprintln!("\x1b[90m[status] {}\x1b[0m", message);
                 }
             }
 
             // Tool execution start
-            "tool.start" => {
+            GenerationEvent::ToolStart { tool_name, .. } => {
                 _tool_active = true;
-                if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                    let name = parsed.get("tool_name").and_then(|n| n.as_str()).unwrap_or("?");
-                    eprintln!("\x1b[33m[tool] executing: {}\x1b[0m", name);
-                }
+                eprintln!("\x1b[33m[tool] executing: {}\x1b[0m", tool_name);
             }
 
             // Tool arguments (debug)
-            "tool.arguments" => {
+            GenerationEvent::ToolArguments { name, arguments, .. } => {
                 if runtime.output.verbose || runtime.output.debug {
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                        let name = parsed.get("name").and_then(|n| n.as_str()).unwrap_or("?");
-                        let empty = json!({});
-                        let args = parsed.get("arguments").unwrap_or(&empty);
-                        eprintln!("\x1b[90m[tool.args] {} {}\x1b[0m", name, args);
-                    }
+                    eprintln!("\x1b[90m[tool.args] {} {}\x1b[0m", name, arguments);
                 }
             }
 
             // Tool execution end
-            "tool.end" => {
+            GenerationEvent::ToolEnd { tool_name, success, duration_ms, preview, error, .. } => {
                 _tool_active = false;
-                if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                    let name = parsed.get("tool_name").and_then(|n| n.as_str()).unwrap_or("?");
-                    let success = parsed.get("success").and_then(|s| s.as_bool()).unwrap_or(false);
-                    let icon = if success { "\x1b[32m✓\x1b[0m" } else { "\x1b[31m✗\x1b[0m" };
-                    let duration = parsed.get("duration_ms").and_then(|d| d.as_u64()).unwrap_or(0);
-                    eprintln!("{} \x1b[33m[tool] {} ({}ms)\x1b[0m", icon, name, duration);
-
-                    // Show preview if available
-                    if let Some(preview) = parsed.get("preview").and_then(|p| p.as_str()) {
-                        if !preview.is_empty() && runtime.output.verbose {
-                            eprintln!("\x1b[90m  {}\x1b[0m", preview);
-                        }
+                let icon = if success { "\x1b[32m✓\x1b[0m" } else { "\x1b[31m✗\x1b[0m" };
+                eprintln!(
+                    "{} \x1b[33m[tool] {} ({}ms)\x1b[0m",
+                    icon,
+                    tool_name,
+                    duration_ms.unwrap_or(0)
+                );
+
+                // Show preview (or the failure reason) if available
+                if runtime.output.verbose {
+                    if let Some(detail) = error.or(preview) {
+                        eprintln!("\x1b[90m  {}\x1b[0m", detail);
                     }
                 }
             }
 
             // Memory injection debug
-            "memory.injected" => {
+            GenerationEvent::MemoryInjected { identity_chunks, chat_chunks } => {
                 if runtime.output.verbose {
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                        let identity = parsed.get("identity_chunks").and_then(|c| c.as_u64()).unwrap_or(0);
-                        let chat = parsed.get("chat_chunks").and_then(|c| c.as_u64()).unwrap_or(0);
-                        eprintln!(
-                            "\x1b[90m[memory] identity={} chat={}\x1b[0m",
-                            identity, chat
-                        );
-                    }
+                    eprintln!(
+                        "\x1b[90m[memory] identity={} chat={}\x1b[0m",
+                        identity_chunks, chat_chunks
+                    );
                 }
             }
 
             // Interpreter debug
-            "interpreter.debug" => {
+            GenerationEvent::InterpreterDebug { primary_intent, confidence } => {
                 if runtime.output.debug {
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                        let intent = parsed.get("primary_intent").and_then(|i| i.as_str()).unwrap_or("?");
-                        let confidence = parsed.get("confidence").and_then(|c| c.as_f64()).unwrap_or(0.0);
-                        eprintln!(
-                            "\x1b[90m[interpreter] intent={} confidence={:.2}\x1b[0m",
-                            intent, confidence
-                        );
-                    }
+                    eprintln!(
+                        "\x1b[90m[interpreter] intent={} confidence={:.2}\x1b[0m",
+                        primary_intent, confidence
+                    );
                 }
             }
 
             // Final message with metadata
-            "message.final" => {
-                if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                    // If we haven't been streaming tokens (e.g. clarification), print content now
-                    if full_response.is_empty() {
-                        if let Some(content) = parsed.get("content").and_then(|c| c.as_str()) {
-                            println!("{}", content);
-                        }
-                    } else {
-                        // Ensure final newline after streamed tokens
-                        println!();
-                    }
+            GenerationEvent::MessageFinal(message) => {
+                // If we haven't been streaming tokens (e.g. clarification), print content now
+                if full_response.is_empty() {
+                    println!("{}", message.content);
+                } else {
+                    // Ensure final newline after streamed tokens
+                    println!();
+                }
 
-                    // Print usage/model info if verbose
-                    if runtime.output.verbose {
-                        let provider = parsed.get("provider").and_then(|p| p.as_str()).unwrap_or("?");
-                        let model = parsed.get("modelDisplayName").and_then(|m| m.as_str()).unwrap_or("?");
-                        let usage = parsed.get("usage");
-                        let prompt_tokens = usage.and_then(|u| u.get("promptTokens")).and_then(|t| t.as_u64()).unwrap_or(0);
-                        let completion_tokens = usage.and_then(|u| u.get("completionTokens")).and_then(|t| t.as_u64()).unwrap_or(0);
-                        eprintln!(
-                            "\x1b[90m[model] {} ({}) prompt={} completion={}\x1b[0m",
-                            model, provider, prompt_tokens, completion_tokens
-                        );
-                    }
+                // Print usage/model info if verbose
+                if runtime.output.verbose {
+                    let usage = message.usage.unwrap_or_default();
+                    eprintln!(
+                        "\x1b[90m[model] {} ({}) prompt={} completion={}\x1b[0m",
+                        message.model_display_name.as_deref().unwrap_or("?"),
+                        message.provider.as_deref().unwrap_or("?"),
+                        usage.prompt_tokens,
+                        usage.completion_tokens
+                    );
                 }
             }
 
             // Chat updated
-            "chat.updated" => {
+            GenerationEvent::ChatUpdated { id, title, .. } => {
                 if runtime.output.verbose {
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                        let id = parsed.get("id").and_then(|i| i.as_str()).unwrap_or("?");
-                        let title = parsed.get("title").and_then(|t| t.as_str()).unwrap_or("?");
-                        eprintln!("\x1b[90m[chat] id={} title=\"{}\"\x1b[0m", id, title);
-                    }
+                    eprintln!(
+                        "\x1b[90m[chat] id={} title=\"{}\"\x1b[0m",
+                        id,
+                        title.as_deref().unwrap_or("?")
+                    );
                 }
             }
 
             // Errors
-            "error" => {
-                if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                    let msg = parsed.get("message").and_then(|m| m.as_str())
-                        .or_else(|| parsed.get("error_message").and_then(|m| m.as_str()))
-                        .unwrap_or("Unknown error");
-                    let fatal = parsed.get("fatal").and_then(|f| f.as_bool()).unwrap_or(false);
-
-                    if fatal {
-                        eprintln!("\x1b[31m[error] {}\x1b[0m", msg);
-                    } else {
-                        // Non-fatal (e.g. provider fallback)
-                        if runtime.output.verbose {
-                            let provider = parsed.get("provider").and_then(|p| p.as_str()).unwrap_or("?");
-                            eprintln!(
-                                "\x1b[33m[warn] {} failed: {}\x1b[0m",
-                                provider, msg
-                            );
-                        }
-                    }
+            GenerationEvent::Error { message, provider, .. } => {
+                // Only non-fatal errors (e.g. provider fallback) get here.
+                if runtime.output.verbose {
+                    eprintln!(
+                        "\x1b[33m[warn] {} failed: {}\x1b[0m",
+                        provider.as_deref().unwrap_or("?"),
+                        message
+                    );
                 }
             }
 
-            _ => {
-                // Unknown event type
+            GenerationEvent::Unknown { event_type, data } => {
                 if runtime.output.debug {
-                    eprintln!("\x1b[90m[event:{}] {}\x1b[0m", event.event_type, event.data);
+                    eprintln!("\x1b[90m[event:{}] {}\x1b[0m", event_type, data);
                 }
             }
         }
     }
 
-    Ok(())
+    if reply.is_empty() {
+        reply = full_response;
+    }
+    Ok((chat_title, reply))
 }
 
 // ---------------------------------------------------------------------------
@@ -356,7 +687,7 @@ async fn resolve_or_create_project(api: &ApiClient) -> Result<String, CliError>
     let id = res.json.get("id")
         .or_else(|| res.json.get("project").and_then(|p| p.get("id")))
         .and_then(|i| i.as_str())
-        .ok_or_else(|| CliError::Generic("Failed to create project".to_string()))?;
+        .ok_or_else(|| CliError::generic("Failed to create project".to_string()))?;
     Ok(id.to_string())
 }
 
@@ -372,13 +703,22 @@ async fn create_chat(api: &ApiClient, project_id: &str) -> Result<String, CliErr
     let id = res.json.get("id")
         .or_else(|| res.json.get("chat").and_then(|c| c.get("id")))
         .and_then(|i| i.as_str())
-        .ok_or_else(|| CliError::Generic("Failed to create chat".to_string()))?;
+        .ok_or_else(|| CliError::generic("Failed to create chat".to_string()))?;
     Ok(id.to_string())
 }
 
 /// Add a message to a chat.
-async fn add_message(api: &ApiClient, chat_id: &str, role: &str, content: &str) -> Result<(), CliError> {
-    let body = json!({ "role": role, "content": content });
+async fn add_message(
+    api: &ApiClient,
+    chat_id: &str,
+    role: &str,
+    content: &str,
+    attachments: &[serde_json::Value],
+) -> Result<(), CliError> {
+    let mut body = json!({ "role": role, "content": content });
+    if !attachments.is_empty() {
+        body["attachments"] = json!(attachments);
+    }
     api.post_json(
         &format!("/v1/chats/{}/messages", chat_id),
         Some(body),
@@ -387,15 +727,44 @@ async fn add_message(api: &ApiClient, chat_id: &str, role: &str, content: &str)
     Ok(())
 }
 
+/// Collect a generation stream's final reply without printing anything.
+async fn collect_reply(
+    mut rx: tokio::sync::mpsc::UnboundedReceiver<StreamEvent>,
+) -> Result<String, CliError> {
+    let mut full_response = String::new();
+    while let Some(event) = rx.recv().await {
+        let event = match event {
+            StreamEvent::Event(event) => event,
+            StreamEvent::Reconnecting { .. } => continue,
+            StreamEvent::Disconnected { error } => {
+                return Err(CliError::network(format!("Stream ended unexpectedly: {error}")));
+            }
+        };
+
+        match event {
+            GenerationEvent::TokenDelta { text } => full_response.push_str(&text),
+            GenerationEvent::MessageFinal(message) => full_response = message.content,
+            GenerationEvent::Error { message, fatal: true, .. } => {
+                return Err(CliError::server(message).with_reason("generation_failed"));
+            }
+            _ => {}
+        }
+    }
+    Ok(full_response)
+}
+
 // ---------------------------------------------------------------------------
 // CLIAgent – higher-level wrapper (kept for backward compat)
 // ---------------------------------------------------------------------------
 
 /// Agent stats
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AgentStats {
+    /// Generation runs, one per loop iteration.
     pub total_requests: u64,
     pub total_tool_calls: u64,
+    pub failed_tool_calls: u64,
     pub successful_tasks: u64,
     pub failed_tasks: u64,
 }
@@ -412,18 +781,19 @@ pub struct AgentContext {
 pub struct CLIAgent {
     config: AgentConfig,
     api_client: ApiClient,
+    executor: EnhancedToolExecutor,
     context: Option<AgentContext>,
-    state: AgentState,
     stats: AgentStats,
 }
 
 impl CLIAgent {
     pub fn new(config: AgentConfig, api_client: ApiClient, _session_id: String) -> Self {
         Self {
+            executor: local_executor(&api_client, config.tool_policy.clone(), config.approval)
+                .with_workspace(config.workspace_id.clone()),
             config,
             api_client,
             context: None,
-            state: AgentState::Idle,
             stats: AgentStats::default(),
         }
     }
@@ -438,13 +808,13 @@ impl CLIAgent {
             messages: Vec::new(),
             current_task: None,
         });
-        self.state = AgentState::Idle;
         Ok(())
     }
 
+    /// Run `user_input` in a fresh chat, executing the reply's tool calls
+    /// locally and posting their results back until the model answers
+    /// without asking for tools.
     pub async fn process(&mut self, user_input: String) -> Result<String, CliError> {
-        self.state = AgentState::Reasoning;
-
         if let Some(ref mut ctx) = self.context {
             ctx.messages.push(Message {
                 role: "user".to_string(),
@@ -454,10 +824,18 @@ impl CLIAgent {
             });
         }
 
+        self.run_loop(&user_input).await
+    }
+
+    async fn run_loop(&mut self, user_input: &str) -> Result<String, CliError> {
         // Use the real API: resolve project, create chat, run generation
         let pid = resolve_or_create_project(&self.api_client).await?;
         let cid = create_chat(&self.api_client, &pid).await?;
-        add_message(&self.api_client, &cid, "user", &user_input).await?;
+        let first = format!(
+            "<instructions>\n{}\n</instructions>\n\n{user_input}",
+            self.config.system_prompt
+        );
+        add_message(&self.api_client, &cid, "user", &first, &[]).await?;
 
         let body = json!({
             "mode": "standard",
@@ -470,54 +848,45 @@ impl CLIAgent {
             },
         });
 
-        let rx = self.api_client.post_stream(
-            &format!("/v1/chats/{}/run", cid),
-            Some(body),
-            true,
-        ).await?;
-
-        // Collect full response from stream
-        let mut full_response = String::new();
-        let mut rx = rx;
-        while let Some(event) = rx.recv().await {
-            match event.event_type.as_str() {
-                "token.delta" => {
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                        if let Some(text) = parsed.get("text").and_then(|t| t.as_str()) {
-                            full_response.push_str(text);
-                        }
-                    }
-                }
-                "message.final" => {
-                    if full_response.is_empty() {
-                        if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&event.data) {
-                            if let Some(content) = parsed.get("content").and_then(|c| c.as_str()) {
-                                full_response = content.to_string();
-                            }
-                        }
-                    }
-                }
-                _ => {}
+        for iteration in 1..=self.config.max_iterations {
+            let rx = self.api_client.post_stream(
+                &format!("/v1/chats/{}/run", cid),
+                Some(body.clone()),
+                true,
+            ).await?;
+            let reply = collect_reply(rx).await?;
+            self.stats.total_requests += 1;
+
+            let results = run_tool_calls(&self.executor, &reply, iteration, |_, _| {}).await;
+            self.stats.total_tool_calls += results.len() as u64;
+            self.stats.failed_tool_calls +=
+                results.iter().filter(|(_, result)| !result.success).count() as u64;
+
+            if let Some(ref mut ctx) = self.context {
+                ctx.messages.push(Message {
+                    role: "assistant".to_string(),
+                    content: reply.clone(),
+                    tool_calls: (!results.is_empty())
+                        .then(|| results.iter().map(|(call, _)| call.clone()).collect()),
+                    tool_results: (!results.is_empty())
+                        .then(|| results.iter().map(|(_, result)| result.clone()).collect()),
+                });
             }
+            if results.is_empty() {
+                return Ok(reply);
+            }
+            add_message(&self.api_client, &cid, "user", &tool_results_message(&results), &[]).await?;
         }
 
-        self.stats.total_requests += 1;
-        self.state = AgentState::Idle;
-
-        if let Some(ref mut ctx) = self.context {
-            ctx.messages.push(Message {
-                role: "assistant".to_string(),
-                content: full_response.clone(),
-                tool_calls: None,
-                tool_results: None,
-            });
-        }
-
-        Ok(full_response)
+        Err(CliError::generic(format!(
+            "Agent stopped after {} iterations without a final answer.",
+            self.config.max_iterations
+        )))
     }
 
-    pub fn state(&self) -> &AgentState { &self.state }
     pub fn stats(&self) -> &AgentStats { &self.stats }
+    pub fn journal(&self) -> &Journal { self.executor.journal() }
+    pub async fn close_shells(&self) -> usize { self.executor.close_shells().await }
 
     pub fn set_current_task(&mut self, task_id: Option<String>) {
         if let Some(ref mut ctx) = self.context {
@@ -551,7 +920,13 @@ impl CLIAgentCommands {
             task.priority,
         );
 
-        let result = agent.process(prompt).await?;
+        let result = match agent.process(prompt).await {
+            Ok(result) => result,
+            Err(err) => {
+                agent.stats.failed_tasks += 1;
+                return Err(err);
+            }
+        };
 
         // Mark task started
         let _ = agent.api_client.start_task(task_id).await;
@@ -564,3 +939,62 @@ impl CLIAgentCommands {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tool_calls_in_order() {
+        let reply = "Let me look.\n<tool>read_file</tool>\n<args>{\"path\": \"src/main.rs\"}</args>\nthen\n<tool>list_tasks</tool>\n<tool>write_file</tool><args>not json</args>";
+        let calls = parse_tool_calls(reply, 2);
+        assert_eq!(calls.len(), 3);
+
+        let first = calls[0].as_ref().unwrap();
+        assert_eq!(first.id, "call-2-1");
+        assert_eq!(first.name, "read_file");
+        assert_eq!(first.arguments["path"], "src/main.rs");
+
+        let second = calls[1].as_ref().unwrap();
+        assert_eq!(second.name, "list_tasks");
+        assert!(second.arguments.is_empty());
+
+        let (third, reason) = calls[2].as_ref().unwrap_err();
+        assert_eq!(third.name, "write_file");
+        assert_eq!(reason, "arguments must be a JSON object");
+
+        assert!(parse_tool_calls("No tools needed.", 1).is_empty());
+    }
+
+    #[test]
+    fn formats_tool_results_for_the_model() {
+        let call = |name: &str| ToolCall {
+            id: format!("call-1-{name}"),
+            name: name.to_string(),
+            arguments: HashMap::new(),
+        };
+        let failed = ToolResult {
+            error: Some("Failed to read file: not found".to_string()),
+            ..ToolResult::error(String::new())
+        };
+        let message = tool_results_message(&[
+            (call("read_file"), ToolResult::success("fn main() {}".to_string())),
+            (call("list_tasks"), failed),
+        ]);
+        assert_eq!(
+            message,
+            "<tool_result id=\"call-1-read_file\" tool=\"read_file\" success=\"true\">\nfn main() {}\n</tool_result>\n\n<tool_result id=\"call-1-list_tasks\" tool=\"list_tasks\" success=\"false\">\nFailed to read file: not found\n</tool_result>"
+        );
+
+        let exited = ToolResult {
+            error: Some("Command exited with code 1".to_string()),
+            ..ToolResult::error("{\"exit_code\": 1}".to_string())
+        };
+        let message = tool_results_message(&[(call("interactive_shell"), exited)]);
+        assert!(message.contains(">\nCommand exited with code 1\n{\"exit_code\": 1}\n</tool_result>"), "{message}");
+
+        let big = ToolResult::success("x".repeat(MAX_TOOL_OUTPUT + 10));
+        let message = tool_results_message(&[(call("read_file"), big)]);
+        assert!(message.contains("\n[output truncated]\n</tool_result>"));
+    }
+}
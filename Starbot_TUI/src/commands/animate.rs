@@ -30,14 +30,14 @@ pub async fn handle(_runtime: &Runtime, _args: AnimateArgs) -> Result<(), CliErr
 fn setup_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>, CliError> {
     let stdout = stdout();
     let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend).map_err(|e| CliError::Generic(e.to_string()))?;
+    let mut terminal = Terminal::new(backend).map_err(|e| CliError::generic(e.to_string()))?;
 
-    enable_raw_mode().map_err(|e| CliError::Generic(e.to_string()))?;
+    enable_raw_mode().map_err(|e| CliError::generic(e.to_string()))?;
     execute!(
         terminal.backend_mut(),
         EnterAlternateScreen,
     )
-    .map_err(|e| CliError::Generic(e.to_string()))?;
+    .map_err(|e| CliError::generic(e.to_string()))?;
 
     Ok(terminal)
 }
@@ -45,13 +45,13 @@ fn setup_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>, CliError>
 fn teardown_terminal(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
 ) -> Result<(), CliError> {
-    disable_raw_mode().map_err(|e| CliError::Generic(e.to_string()))?;
+    disable_raw_mode().map_err(|e| CliError::generic(e.to_string()))?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
     )
-    .map_err(|e| CliError::Generic(e.to_string()))?;
-    terminal.show_cursor().map_err(|e| CliError::Generic(e.to_string()))?;
+    .map_err(|e| CliError::generic(e.to_string()))?;
+    terminal.show_cursor().map_err(|e| CliError::generic(e.to_string()))?;
     Ok(())
 }
 
@@ -180,7 +180,7 @@ fn run_animation_builder(
                     .style(Style::default().fg(Color::Yellow));
                 f.render_widget(info, chunks[2]);
             })
-            .map_err(|e| CliError::Generic(e.to_string()))?;
+            .map_err(|e| CliError::generic(e.to_string()))?;
 
         // Update animation frame if playing
         if let Some(idx) = playing_idx {
@@ -194,8 +194,8 @@ fn run_animation_builder(
         }
 
         // Handle input
-        if event::poll(Duration::from_millis(50)).map_err(|e| CliError::Generic(e.to_string()))? {
-            if let Event::Key(key) = event::read().map_err(|e| CliError::Generic(e.to_string()))? {
+        if event::poll(Duration::from_millis(50)).map_err(|e| CliError::generic(e.to_string()))? {
+            if let Event::Key(key) = event::read().map_err(|e| CliError::generic(e.to_string()))? {
                 match mode {
                     AppMode::Browse => {
                         match key.code {
@@ -213,9 +213,9 @@ fn run_animation_builder(
                                 }
 
                                 // Re-enable TUI
-                                enable_raw_mode().map_err(|e| CliError::Generic(e.to_string()))?;
+                                enable_raw_mode().map_err(|e| CliError::generic(e.to_string()))?;
                                 execute!(terminal.backend_mut(), EnterAlternateScreen)
-                                    .map_err(|e| CliError::Generic(e.to_string()))?;
+                                    .map_err(|e| CliError::generic(e.to_string()))?;
                                 terminal.clear().ok();
                                 mode = AppMode::Browse;
                             }
@@ -250,9 +250,9 @@ fn run_animation_builder(
                                     }
 
                                     // Re-enable TUI
-                                    enable_raw_mode().map_err(|e| CliError::Generic(e.to_string()))?;
+                                    enable_raw_mode().map_err(|e| CliError::generic(e.to_string()))?;
                                     execute!(terminal.backend_mut(), EnterAlternateScreen)
-                                        .map_err(|e| CliError::Generic(e.to_string()))?;
+                                        .map_err(|e| CliError::generic(e.to_string()))?;
                                     terminal.clear().ok();
                                 }
                             }
@@ -284,7 +284,7 @@ fn prompt_for_frames() -> Result<Vec<String>, CliError> {
     let mut input = String::new();
     io::stdin()
         .read_line(&mut input)
-        .map_err(|e| CliError::Generic(e.to_string()))?;
+        .map_err(|e| CliError::generic(e.to_string()))?;
 
     let frames: Vec<String> = input
         .split_whitespace()
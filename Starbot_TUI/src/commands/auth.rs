@@ -2,7 +2,7 @@ use clap::Subcommand;
 use serde_json::json;
 
 use crate::app::Runtime;
-use crate::config::{ensure_profile, profile_mut, save_config};
+use crate::config::{clear_profile_tokens, ensure_profile, save_config, set_profile_tokens};
 use crate::errors::CliError;
 
 #[derive(Debug, Subcommand)]
@@ -29,13 +29,13 @@ async fn login(runtime: &mut Runtime, token: Option<String>) -> Result<(), CliEr
     if let Some(t) = token {
         let t = t.trim().to_string();
         if t.is_empty() {
-            return Err(CliError::Usage("Token cannot be empty.".to_string()));
+            return Err(CliError::usage("Token cannot be empty.".to_string()));
         }
         return save_token(runtime, t, None);
     }
 
     if is_ci() {
-        return Err(CliError::Usage(
+        return Err(CliError::usage(
             "CI mode detected. Pass `--token` explicitly.".to_string(),
         ));
     }
@@ -71,21 +71,21 @@ async fn device_code_flow(runtime: &mut Runtime) -> Result<(), CliError> {
         .json
         .get("device_code")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| CliError::Server("Missing device_code in response".to_string()))?
+        .ok_or_else(|| CliError::server("Missing device_code in response".to_string()))?
         .to_string();
 
     let user_code = res
         .json
         .get("user_code")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| CliError::Server("Missing user_code in response".to_string()))?
+        .ok_or_else(|| CliError::server("Missing user_code in response".to_string()))?
         .to_string();
 
     let verification_url = res
         .json
         .get("verification_url")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| CliError::Server("Missing verification_url in response".to_string()))?
+        .ok_or_else(|| CliError::server("Missing verification_url in response".to_string()))?
         .to_string();
 
     let interval = res
@@ -153,7 +153,7 @@ async fn device_code_flow(runtime: &mut Runtime) -> Result<(), CliError> {
                 return Ok(());
             }
             "expired" => {
-                return Err(CliError::Auth(
+                return Err(CliError::auth(
                     "Device code expired. Please try again.".to_string(),
                 ));
             }
@@ -163,7 +163,7 @@ async fn device_code_flow(runtime: &mut Runtime) -> Result<(), CliError> {
         }
     }
 
-    Err(CliError::Auth(
+    Err(CliError::auth(
         "Authorization timed out. Please try again.".to_string(),
     ))
 }
@@ -197,13 +197,7 @@ fn save_token(
     refresh_token: Option<String>,
 ) -> Result<(), CliError> {
     let profile_name = runtime.active_profile();
-    ensure_profile(&mut runtime.config, &profile_name);
-    let profile = profile_mut(&mut runtime.config, &profile_name)
-        .ok_or_else(|| CliError::Generic("Failed to load active profile.".to_string()))?;
-    profile.token = Some(access_token);
-    if let Some(rt) = refresh_token {
-        profile.refresh_token = Some(rt);
-    }
+    set_profile_tokens(&mut runtime.config, &profile_name, access_token, refresh_token)?;
     save_config(&runtime.config)?;
     Ok(())
 }
@@ -211,10 +205,7 @@ fn save_token(
 async fn logout(runtime: &mut Runtime) -> Result<(), CliError> {
     let profile_name = runtime.active_profile();
     ensure_profile(&mut runtime.config, &profile_name);
-    let profile = profile_mut(&mut runtime.config, &profile_name)
-        .ok_or_else(|| CliError::Generic("Failed to load active profile.".to_string()))?;
-    profile.token = None;
-    profile.refresh_token = None;
+    clear_profile_tokens(&mut runtime.config, &profile_name)?;
     save_config(&runtime.config)?;
 
     if runtime.output.json {
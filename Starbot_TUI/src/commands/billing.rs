@@ -67,7 +67,7 @@ async fn portal(runtime: &Runtime, args: PortalArgs) -> Result<(), CliError> {
         .get("url")
         .and_then(|v| v.as_str())
         .ok_or_else(|| {
-            CliError::Server("Billing portal URL was not returned by the server.".to_string())
+            CliError::server("Billing portal URL was not returned by the server.".to_string())
         })?;
 
     if runtime.output.json {
@@ -78,7 +78,7 @@ async fn portal(runtime: &Runtime, args: PortalArgs) -> Result<(), CliError> {
 
     if args.open {
         open::that(url).map_err(|e| {
-            CliError::Generic(format!("Failed to open browser for portal URL: {e}"))
+            CliError::generic(format!("Failed to open browser for portal URL: {e}"))
         })?;
     }
 
@@ -1,89 +1,186 @@
-use std::io::{self, Read};
+use std::io::{self, IsTerminal, Read};
+use std::path::PathBuf;
 
-use clap::Args;
+use clap::{Args, Subcommand};
 use serde_json::{Value, json};
 
+use crate::api::ApiClient;
 use crate::app::Runtime;
+use crate::attachments;
+use crate::commands::agent::RunOptions;
+use crate::conversations::{self, ConversationIndex, age, current_dir_key, now_secs};
 use crate::errors::CliError;
+use crate::parse::intent::{TaskDraft, detect_task_intent, parse_task};
 use crate::parse::response::{extract_reply, extract_provider_model, extract_usage_line};
+use crate::templates;
 
 #[derive(Debug, Args)]
 pub struct ChatArgs {
+    #[command(subcommand)]
+    pub command: Option<ChatCommand>,
     /// Prompt text
     pub prompt: Option<String>,
     /// Model or provider selector. Examples: "azure:gpt-5.2-chat" or "auto"
     #[arg(short = 'm', long = "model")]
     pub model: Option<String>,
-    /// Optional conversation id
-    #[arg(short = 'c', long = "conversation")]
+    /// Conversation to continue: an ID, a unique ID prefix, or a title from `chat sessions list`
+    #[arg(short = 'c', long = "conversation", conflicts_with_all = ["continue_last", "new"])]
     pub conversation: Option<String>,
+    /// Continue the last conversation used from this directory
+    #[arg(long = "continue", conflicts_with = "new")]
+    pub continue_last: bool,
+    /// Start a new conversation (the default without --continue or --conversation)
+    #[arg(long)]
+    pub new: bool,
     /// Read prompt from stdin
     #[arg(long)]
     pub stdin: bool,
-    /// Request streaming response (falls back to non-streaming if unsupported)
+    /// Attach a file (repeatable; `-` reads stdin). Text over 100KB is truncated
+    #[arg(long = "file", value_name = "PATH")]
+    pub files: Vec<PathBuf>,
+    /// Attach every file matching a glob such as `src/**/*.rs` (repeatable)
+    #[arg(long = "glob", value_name = "PATTERN")]
+    pub globs: Vec<String>,
+    /// Stream the reply as it is generated (with --json: one NDJSON event per line)
     #[arg(long)]
     pub stream: bool,
     /// Optional max output tokens passthrough
     #[arg(long = "max-tokens")]
     pub max_tokens: Option<u32>,
+    /// Start from a prompt template (see `starbott templates list`)
+    #[arg(long, value_name = "NAME")]
+    pub template: Option<String>,
+    /// Template variable (repeatable): --var file=src/api.rs
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = templates::parse_var)]
+    pub vars: Vec<(String, String)>,
+    /// System prompt to chat with (front-matter and {{variables}} supported)
+    #[arg(long, value_name = "PATH")]
+    pub system_prompt_file: Option<PathBuf>,
+    /// Create a task from the prompt: `<title> [description: ...] [priority: high|medium|low|0-10]`
+    #[arg(long, conflicts_with_all = ["stream", "no_intents", "template"])]
+    pub task: bool,
+    /// Always chat, even if the prompt reads like `create task: ...`
+    #[arg(long = "no-intents")]
+    pub no_intents: bool,
+    /// Create the task without asking for confirmation
+    #[arg(short = 'y', long, requires = "task")]
+    pub yes: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ChatCommand {
+    /// Conversations remembered for the active profile
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionsCommand {
+    /// List conversations, most recently used first
+    List {
+        /// Only conversations last used from the current directory
+        #[arg(long)]
+        here: bool,
+    },
+    /// Show one conversation
+    Show {
+        /// ID, unique ID prefix, or title
+        conversation: String,
+    },
+    /// Forget a conversation locally (the chat itself stays on the server)
+    Rm {
+        /// ID, unique ID prefix, or title
+        conversation: String,
+    },
 }
 
 pub async fn handle(runtime: &Runtime, args: ChatArgs) -> Result<(), CliError> {
-    let prompt = resolve_prompt(&args)?;
-    let api = runtime.api_client()?;
+    if let Some(ChatCommand::Sessions { command }) = args.command {
+        return handle_sessions(runtime, command);
+    }
 
-    // Check if user wants to create a task
-    if prompt.to_lowercase().contains("create task") || prompt.to_lowercase().contains("make task") {
-        let task_title = extract_task_title(&prompt);
-        let task_description = extract_task_description(&prompt);
-
-        let task_data = json!({
-            "title": task_title,
-            "description": task_description,
-            "priority": extract_task_priority(&prompt),
-        });
-
-        let res = api.post_json("/v1/tasks", Some(task_data), true).await?;
-
-        if runtime.output.json {
-            runtime.output.print_json(&res.json)?;
-        } else {
-            let task_id = res.json.get("task")
-                .and_then(|t| t.get("id"))
-                .and_then(|i| i.as_str())
-                .unwrap_or("unknown");
-            let title = res.json.get("task")
-                .and_then(|t| t.get("title"))
-                .and_then(|t| t.as_str())
-                .unwrap_or("Untitled");
-            runtime.output.print_human(&format!("✓ Created task: {} (ID: {})", title, task_id));
-        }
+    let prepared = templates::prepare(
+        resolve_prompt(&args)?,
+        args.template.as_deref(),
+        args.system_prompt_file.as_deref(),
+        &args.vars,
+    )?;
+    if let Some(notice) = prepared.temperature_notice() {
+        runtime.output.print_stderr(&notice);
+    }
+    let prompt = prepared.prompt;
+    let model = args.model.clone().or(prepared.front.model);
+    let api = runtime.api_client()?;
 
+    let draft = if args.task {
+        Some(parse_task(&prompt))
+    } else if args.no_intents || args.template.is_some() {
+        None
+    } else {
+        detect_task_intent(&prompt)
+    };
+    if let Some(draft) = draft
+        && create_task(runtime, &api, &draft, &args).await?
+    {
         return Ok(());
     }
 
+    if args.stdin && args.files.iter().any(|f| f.as_os_str() == "-") {
+        return Err(CliError::usage("--stdin and --file - both read stdin; use one."));
+    }
+    let attachments = attachments::load(runtime, &args.files, &args.globs)?;
+
+    let (conversation_id, project_id) = match resolve_conversation(runtime, &args)? {
+        Some((id, project_id)) => (Some(id), project_id),
+        None => (None, None),
+    };
+
     if args.stream {
-        runtime.output.print_verbose(
-            "Requested --stream. Using standard response (CLI streaming output is not implemented yet).",
-        );
+        if args.max_tokens.is_some() {
+            runtime.output.print_verbose("--max-tokens is ignored when streaming.");
+        }
+        let options = RunOptions {
+            project_id,
+            chat_id: conversation_id,
+            model_prefs: model,
+            attachments,
+            system_prompt: prepared.system,
+            lane: prepared.front.lane,
+            ..Default::default()
+        };
+        return crate::commands::agent::handle_run(&api, runtime, prompt, options).await;
+    }
+    if prepared.front.lane.is_some() {
+        runtime.output.print_verbose("The template's lane only applies to --stream runs.");
     }
 
+    let (content, binary) = attachments::apply(&prompt, &attachments);
+    let mut user_message = json!({ "role": "user", "content": content });
+    if !binary.is_empty() {
+        user_message["attachments"] = json!(binary);
+    }
+    let mut messages = Vec::new();
+    if let Some(system) = prepared.system {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+    messages.push(user_message);
+
     let mut body = json!({
-        "messages": [
-            { "role": "user", "content": prompt }
-        ],
+        "messages": messages,
         "client": "cli",
         "provider": "auto"
     });
 
-    if let Some(conversation_id) = &args.conversation {
+    if let Some(conversation_id) = &conversation_id {
         body["conversationId"] = json!(conversation_id);
     }
     if let Some(max_tokens) = args.max_tokens {
         body["max_tokens"] = json!(max_tokens);
     }
 
-    if let Some(model_or_provider) = &args.model {
+    if let Some(model_or_provider) = &model {
         apply_model_selector(&mut body, model_or_provider);
     }
 
@@ -93,6 +190,17 @@ pub async fn handle(runtime: &Runtime, args: ChatArgs) -> Result<(), CliError> {
         res.request_id, res.elapsed_ms
     ));
 
+    let used_id = ["conversation_id", "conversationId", "chatId"]
+        .iter()
+        .find_map(|key| res.json.get(*key))
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .or(conversation_id);
+    if let Some(id) = &used_id {
+        conversations::remember(runtime, id, project_id.as_deref(), None);
+        runtime.output.print_verbose(&format!("conversation={id}"));
+    }
+
     if runtime.output.json {
         runtime.output.print_json(&res.json)?;
         return Ok(());
@@ -115,84 +223,179 @@ pub async fn handle(runtime: &Runtime, args: ChatArgs) -> Result<(), CliError> {
     Ok(())
 }
 
-/// Extract task title from prompt
-fn extract_task_title(prompt: &str) -> String {
-    // Simple extraction - look for quotes or use first reasonable phrase
-    if let Some(start) = prompt.find('"') {
-        if let Some(end) = prompt[start + 1..].find('"') {
-            return prompt[start + 1..start + 1 + end].to_string();
+/// Offer `draft` for creation. Returns `false` when the prompt should go to
+/// chat after all: a detected intent that was declined or can't be confirmed.
+async fn create_task(
+    runtime: &Runtime,
+    api: &ApiClient,
+    draft: &TaskDraft,
+    args: &ChatArgs,
+) -> Result<bool, CliError> {
+    let can_ask = !args.stdin && io::stdin().is_terminal();
+    if !args.yes {
+        if !can_ask {
+            if args.task {
+                return Err(CliError::usage("Creating a task needs confirmation.")
+                    .with_hint("Pass --yes to create it without asking."));
+            }
+            runtime.output.print_verbose(
+                "The prompt reads like a task request; sending it to chat. Use --task to create it.",
+            );
+            return Ok(false);
         }
-    }
 
-    // Fallback: use the first few words after "create task" or "make task"
-    let words: Vec<&str> = prompt.split_whitespace().collect();
-    if let Some(idx) = words.iter().position(|w| w.to_lowercase() == "task") {
-        if idx + 1 < words.len() {
-            return words[idx + 1..].join(" ").trim_matches('.').to_string();
+        eprintln!("Task");
+        eprintln!("  Title:       {}", draft.title);
+        eprintln!("  Description: {}", draft.description.as_deref().unwrap_or("-"));
+        eprintln!("  Priority:    {}", draft.priority);
+        if !confirm("Create this task?")? {
+            if args.task {
+                runtime.output.print_human("Cancelled.");
+                return Ok(true);
+            }
+            return Ok(false);
         }
     }
 
-    "Untitled Task".to_string()
+    let task_data = json!({
+        "title": draft.title,
+        "description": draft.description,
+        "priority": draft.priority,
+    });
+    let res = api.post_json("/v1/tasks", Some(task_data), true).await?;
+
+    if runtime.output.json {
+        runtime.output.print_json(&res.json)?;
+    } else {
+        let task_id = res.json.get("task")
+            .and_then(|t| t.get("id"))
+            .and_then(|i| i.as_str())
+            .unwrap_or("unknown");
+        let title = res.json.get("task")
+            .and_then(|t| t.get("title"))
+            .and_then(|t| t.as_str())
+            .unwrap_or("Untitled");
+        runtime.output.print_human(&format!("✓ Created task: {} (ID: {})", title, task_id));
+    }
+    Ok(true)
 }
 
-/// Extract task description from prompt
-fn extract_task_description(prompt: &str) -> Option<String> {
-    // Look for description or details in the prompt
-    if let Some(desc_start) = prompt.to_lowercase().find("description:") {
-        let desc = prompt[desc_start + 12..].trim();
-        if !desc.is_empty() {
-            return Some(desc.to_string());
-        }
+pub(crate) fn confirm(question: &str) -> Result<bool, CliError> {
+    eprint!("{question} [y/N] ");
+    let _ = io::Write::flush(&mut io::stderr());
+    let mut line = String::new();
+    io::stdin()
+        .read_line(&mut line)
+        .map_err(|e| CliError::generic(format!("Failed reading input: {e}")))?;
+    let s = line.trim().to_ascii_lowercase();
+    Ok(s == "y" || s == "yes")
+}
+
+/// The conversation this invocation continues, as `(id, project id)`.
+fn resolve_conversation(
+    runtime: &Runtime,
+    args: &ChatArgs,
+) -> Result<Option<(String, Option<String>)>, CliError> {
+    if args.continue_last {
+        let index = ConversationIndex::load()?;
+        let last = index
+            .last_for(&runtime.active_profile(), &current_dir_key())
+            .ok_or_else(|| {
+                CliError::usage("No earlier conversation from this directory.")
+                    .with_hint("Start one with `starbott chat \"...\"`, or pick one from `starbott chat sessions list`.")
+            })?;
+        return Ok(Some((last.id.clone(), last.project_id.clone())));
     }
 
-    // Look for anything after the task title
-    let title = extract_task_title(prompt);
-    if let Some(title_pos) = prompt.find(&title) {
-        if title_pos + title.len() < prompt.len() {
-            let desc = prompt[title_pos + title.len()..].trim();
-            if !desc.is_empty() && !desc.to_lowercase().contains("priority:") {
-                return Some(desc.to_string());
+    args.conversation
+        .as_deref()
+        .map(|query| conversations::resolve_chat(runtime, query))
+        .transpose()
+}
+
+fn handle_sessions(runtime: &Runtime, command: SessionsCommand) -> Result<(), CliError> {
+    let profile = runtime.active_profile();
+    let mut index = ConversationIndex::load()?;
+
+    match command {
+        SessionsCommand::List { here } => {
+            let cwd = current_dir_key();
+            let now = now_secs();
+            let rows: Vec<Value> = index
+                .list(&profile)
+                .into_iter()
+                .filter(|c| !here || c.cwd == cwd)
+                .map(|c| {
+                    let mut row = serde_json::to_value(c).unwrap_or_default();
+                    row["lastUsed"] = json!(age(c.last_used_at, now));
+                    row
+                })
+                .collect();
+            runtime.output.print_list(
+                &json!({ "conversations": rows }),
+                &rows,
+                &[("ID", "id"), ("TITLE", "title"), ("LAST USED", "lastUsed"), ("DIRECTORY", "cwd")],
+                "No conversations yet.",
+            )
+        }
+        SessionsCommand::Show { conversation } => {
+            let found = find_conversation(&index, &profile, &conversation)?;
+            if runtime.output.json {
+                return runtime.output.print_json(&serde_json::to_value(found)?);
+            }
+            runtime.output.print_human(&format!("ID: {}", found.id));
+            runtime.output.print_human(&format!("Title: {}", found.title.as_deref().unwrap_or("-")));
+            runtime.output.print_human(&format!("Project: {}", found.project_id.as_deref().unwrap_or("-")));
+            runtime.output.print_human(&format!("Directory: {}", found.cwd));
+            runtime.output.print_human(&format!("Last used: {}", age(found.last_used_at, now_secs())));
+            Ok(())
+        }
+        SessionsCommand::Rm { conversation } => {
+            let id = find_conversation(&index, &profile, &conversation)?.id.clone();
+            index.remove(&profile, &id);
+            index.save()?;
+            if runtime.output.json {
+                return runtime.output.print_json(&json!({ "ok": true, "removed": id }));
             }
+            runtime.output.print_human(&format!("Forgot conversation {id}"));
+            Ok(())
         }
     }
-
-    None
 }
 
-/// Extract task priority from prompt
-fn extract_task_priority(prompt: &str) -> i32 {
-    if prompt.to_lowercase().contains("priority: high") || prompt.to_lowercase().contains("high priority") {
-        8
-    } else if prompt.to_lowercase().contains("priority: low") || prompt.to_lowercase().contains("low priority") {
-        2
-    } else if prompt.to_lowercase().contains("priority: medium") || prompt.to_lowercase().contains("medium priority") {
-        5
-    } else {
-        0
-    }
+fn find_conversation<'a>(
+    index: &'a ConversationIndex,
+    profile: &str,
+    query: &str,
+) -> Result<&'a conversations::Conversation, CliError> {
+    index.resolve(profile, query)?.ok_or_else(|| {
+        CliError::usage(format!("No conversation matching `{query}`."))
+            .with_hint("`starbott chat sessions list` shows the remembered conversations.")
+    })
 }
 
-fn resolve_prompt(args: &ChatArgs) -> Result<String, CliError> {
+/// The typed prompt, if any; templates can stand in for a missing one.
+fn resolve_prompt(args: &ChatArgs) -> Result<Option<String>, CliError> {
     if args.stdin {
         let mut input = String::new();
         io::stdin()
             .read_to_string(&mut input)
-            .map_err(|e| CliError::Generic(format!("Failed reading stdin: {e}")))?;
+            .map_err(|e| CliError::generic(format!("Failed reading stdin: {e}")))?;
         let trimmed = input.trim().to_string();
         if trimmed.is_empty() {
-            return Err(CliError::Usage(
+            return Err(CliError::usage(
                 "No prompt provided via stdin. Pipe text or pass a prompt argument.".to_string(),
             ));
         }
-        return Ok(trimmed);
+        return Ok(Some(trimmed));
     }
 
-    match &args.prompt {
-        Some(value) if !value.trim().is_empty() => Ok(value.trim().to_string()),
-        _ => Err(CliError::Usage(
-            "Missing prompt. Use `starbott chat \"...\"` or pass `--stdin`.".to_string(),
-        )),
-    }
+    Ok(args
+        .prompt
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string))
 }
 
 fn apply_model_selector(body: &mut Value, selector: &str) {
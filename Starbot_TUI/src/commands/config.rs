@@ -2,8 +2,13 @@ use clap::{Subcommand, ValueEnum};
 use serde_json::json;
 
 use crate::app::Runtime;
-use crate::config::{ensure_profile, profile_mut, profile_ref, save_config, validate_url};
+use crate::config::{
+    CliConfig, credential_store, ensure_profile, profile_mut, profile_ref, save_config,
+    set_profile_tokens, validate_url,
+};
+use crate::credentials::{self, Secrets, StoreKind};
 use crate::errors::{CliError, redact_secret};
+use crate::policy::Mode;
 
 #[derive(Debug, Subcommand)]
 pub enum ConfigCommand {
@@ -26,6 +31,12 @@ pub enum ConfigCommand {
     Profiles,
     /// Switch active profile
     Use { profile: String },
+    /// Move plaintext tokens from config.json into a credential store
+    MigrateSecrets {
+        /// Target store (default: keyring if available, else encrypted-file)
+        #[arg(long, value_enum)]
+        to: Option<StoreKind>,
+    },
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -34,6 +45,11 @@ pub enum ConfigKey {
     ApiUrl,
     #[value(name = "token")]
     Token,
+    #[value(name = "credentialStore")]
+    CredentialStore,
+    /// The tool policy mode; rules are edited in config.json.
+    #[value(name = "toolPolicy")]
+    ToolPolicy,
 }
 
 pub async fn handle(runtime: &mut Runtime, command: ConfigCommand) -> Result<(), CliError> {
@@ -43,6 +59,7 @@ pub async fn handle(runtime: &mut Runtime, command: ConfigCommand) -> Result<(),
         ConfigCommand::Set { key, value } => set(runtime, key, value).await,
         ConfigCommand::Profiles => profiles(runtime).await,
         ConfigCommand::Use { profile } => use_profile(runtime, profile).await,
+        ConfigCommand::MigrateSecrets { to } => migrate_secrets(runtime, to).await,
     }
 }
 
@@ -53,21 +70,24 @@ async fn init(
 ) -> Result<(), CliError> {
     let profile_name = runtime.active_profile();
     ensure_profile(&mut runtime.config, &profile_name);
-    if let Some(profile) = profile_mut(&mut runtime.config, &profile_name) {
-        if let Some(url) = api_url {
-            validate_url(&url)?;
-            profile.api_url = url;
-        }
+    if let Some(profile) = profile_mut(&mut runtime.config, &profile_name)
+        && let Some(url) = api_url
+    {
+        validate_url(&url)?;
+        profile.api_url = url;
+    }
 
-        if let Some(value) = token {
-            profile.token = Some(value);
-        } else if !is_ci() && !runtime.output.json && !runtime.output.quiet {
+    let token = match token {
+        Some(value) => Some(value),
+        None if !is_ci() && !runtime.output.json && !runtime.output.quiet => {
             let maybe_token = rpassword::prompt_password("Token (optional, Enter to skip): ")
-                .map_err(|e| CliError::Generic(format!("Failed reading token: {e}")))?;
-            if !maybe_token.trim().is_empty() {
-                profile.token = Some(maybe_token.trim().to_string());
-            }
+                .map_err(|e| CliError::generic(format!("Failed reading token: {e}")))?;
+            Some(maybe_token.trim().to_string()).filter(|t| !t.is_empty())
         }
+        None => None,
+    };
+    if let Some(value) = token {
+        set_profile_tokens(&mut runtime.config, &profile_name, value, None)?;
     }
 
     runtime.config.profile = profile_name;
@@ -89,7 +109,7 @@ async fn init(
 async fn get(runtime: &mut Runtime, key: ConfigKey, show_token: bool) -> Result<(), CliError> {
     let profile_name = runtime.active_profile();
     let profile = profile_ref(&runtime.config, &profile_name).ok_or_else(|| {
-        CliError::Usage(format!(
+        CliError::usage(format!(
             "Profile '{profile_name}' not found. Run `starbott config init` first."
         ))
     })?;
@@ -126,6 +146,33 @@ async fn get(runtime: &mut Runtime, key: ConfigKey, show_token: bool) -> Result<
                 runtime.output.print_human("(not set)");
             }
         }
+        ConfigKey::CredentialStore => {
+            let store = credential_store(&runtime.config).as_str();
+            if runtime.output.json {
+                runtime.output.print_json(&json!({
+                    "key": "credentialStore",
+                    "value": store
+                }))?;
+            } else {
+                runtime.output.print_human(store);
+            }
+        }
+        ConfigKey::ToolPolicy => {
+            let policy = &runtime.config.tool_policy;
+            if runtime.output.json {
+                runtime.output.print_json(&json!({
+                    "key": "toolPolicy",
+                    "value": policy.mode.as_str(),
+                    "rules": policy.rules,
+                }))?;
+            } else {
+                runtime.output.print_human(&format!(
+                    "{} ({} rules)",
+                    policy.mode.as_str(),
+                    policy.rules.len()
+                ));
+            }
+        }
     }
 
     Ok(())
@@ -134,19 +181,34 @@ async fn get(runtime: &mut Runtime, key: ConfigKey, show_token: bool) -> Result<
 async fn set(runtime: &mut Runtime, key: ConfigKey, value: String) -> Result<(), CliError> {
     let profile_name = runtime.active_profile();
     ensure_profile(&mut runtime.config, &profile_name);
-    let profile = profile_mut(&mut runtime.config, &profile_name).ok_or_else(|| {
-        CliError::Generic(format!(
-            "Failed to resolve profile '{profile_name}' while setting config."
-        ))
-    })?;
 
     match key {
         ConfigKey::ApiUrl => {
             validate_url(&value)?;
+            let profile = profile_mut(&mut runtime.config, &profile_name).ok_or_else(|| {
+                CliError::generic(format!(
+                    "Failed to resolve profile '{profile_name}' while setting config."
+                ))
+            })?;
             profile.api_url = value;
         }
         ConfigKey::Token => {
-            profile.token = Some(value);
+            set_profile_tokens(&mut runtime.config, &profile_name, value, None)?;
+        }
+        ConfigKey::CredentialStore => {
+            let store = StoreKind::from_str(&value, true).map_err(|_| {
+                CliError::usage(format!(
+                    "Unknown credential store '{value}' (use keyring, encrypted-file, or plaintext)."
+                ))
+            })?;
+            runtime.config.credential_store = Some(store);
+        }
+        ConfigKey::ToolPolicy => {
+            runtime.config.tool_policy.mode = Mode::from_str(&value, true).map_err(|_| {
+                CliError::usage(format!(
+                    "Unknown tool policy '{value}' (use ask-writes, ask-all, read-only, or allow-all)."
+                ))
+            })?;
         }
     }
 
@@ -180,6 +242,7 @@ async fn profiles(runtime: &mut Runtime) -> Result<(), CliError> {
                         .and_then(|p| p.token.as_ref())
                         .map(|t| !t.is_empty())
                         .unwrap_or(false)
+                        || has_stored_token(&runtime.config, name)
                 })
             })
             .collect::<Vec<_>>();
@@ -213,6 +276,76 @@ async fn use_profile(runtime: &mut Runtime, profile_name: String) -> Result<(),
     Ok(())
 }
 
+/// Whether `profile_name` has a token in the credential store. Unlike
+/// `resolve_token` this ignores `STARBOTT_TOKEN`.
+fn has_stored_token(config: &CliConfig, profile_name: &str) -> bool {
+    let kind = credential_store(config);
+    kind != StoreKind::Plaintext
+        && credentials::load(kind, profile_name)
+            .map(|s| s.access_token.is_some())
+            .unwrap_or(false)
+}
+
+async fn migrate_secrets(runtime: &mut Runtime, to: Option<StoreKind>) -> Result<(), CliError> {
+    let target = to.unwrap_or_else(StoreKind::preferred);
+    if target == StoreKind::Plaintext {
+        return Err(CliError::usage(
+            "migrate-secrets moves tokens out of config.json; use --to keyring or --to encrypted-file."
+                .to_string(),
+        ));
+    }
+
+    let mut names: Vec<String> = runtime
+        .config
+        .profiles
+        .iter()
+        .filter(|(_, p)| p.token.is_some() || p.refresh_token.is_some())
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+
+    // Write everything to the store before touching config.json, so a failure
+    // part-way leaves the plaintext copies in place.
+    for name in &names {
+        let profile = &runtime.config.profiles[name];
+        let secrets = Secrets {
+            access_token: profile.token.clone(),
+            refresh_token: profile.refresh_token.clone(),
+        };
+        credentials::save(target, name, &secrets)?;
+    }
+    for name in &names {
+        if let Some(profile) = profile_mut(&mut runtime.config, name) {
+            profile.token = None;
+            profile.refresh_token = None;
+        }
+    }
+    runtime.config.credential_store = Some(target);
+    let path = save_config(&runtime.config)?;
+    runtime.config_path = path;
+
+    if runtime.output.json {
+        runtime.output.print_json(&json!({
+            "ok": true,
+            "store": target.as_str(),
+            "profiles": names
+        }))?;
+    } else if names.is_empty() {
+        runtime.output.print_human(&format!(
+            "No plaintext tokens found. Credential store set to {}.",
+            target.as_str()
+        ));
+    } else {
+        runtime.output.print_human(&format!(
+            "Moved tokens for {} profile(s) into the {} store: {}",
+            names.len(),
+            target.as_str(),
+            names.join(", ")
+        ));
+    }
+    Ok(())
+}
+
 fn is_ci() -> bool {
     std::env::var("CI")
         .ok()
@@ -4,15 +4,22 @@
 //! with both local tools (direct execution) and remote tools (API proposals).
 //! It includes validation, security, and retry mechanisms.
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
 use tokio::time::timeout;
 
 use crate::api::ApiClient;
+use crate::attachments::human_size;
 use crate::errors::CliError;
-use crate::commands::pty::{PtyConfig, PtyManager, PtySession};
+use crate::journal::Journal;
+use crate::patch;
+use crate::commands::pty::{CommandOutput, CommandStatus, PtyConfig, PtyManager, PtySession};
+use crate::policy::{self, Action, Approval, ToolPolicy, Verdict};
+use crate::sandbox::{MAX_READ_BYTES, Sandbox};
 
 /// Tool execution mode
 #[derive(Debug, Clone, PartialEq)]
@@ -36,8 +43,11 @@ pub struct ToolConfig {
     pub enable_validation: bool,
     /// Execution mode
     pub mode: ToolMode,
-    /// Workspace ID for tool execution
+    /// Workspace ID for tool execution; file tools are confined to its root
     pub workspace_id: Option<String>,
+    /// Directory file tools are confined to, ahead of `workspace_id`'s root;
+    /// the current directory when neither is set
+    pub workspace_root: Option<PathBuf>,
     /// Enable PTY for interactive commands
     pub enable_pty: bool,
     /// PTY configuration
@@ -52,6 +62,7 @@ impl Default for ToolConfig {
             enable_validation: true,
             mode: ToolMode::Hybrid,
             workspace_id: None,
+            workspace_root: None,
             enable_pty: false,
             pty_config: None,
         }
@@ -120,6 +131,7 @@ pub struct ToolDefinition {
     pub description: String,
     pub parameters: Vec<ToolParameter>,
     pub category: String,
+    /// Read-only: the default tool policy runs these without asking.
     pub safe: bool,
     pub file_operations: bool,
     pub network_operations: bool,
@@ -149,7 +161,7 @@ impl ToolValidator {
         // Check required parameters
         for param in &tool_def.parameters {
             if param.required && !args.contains_key(&param.name) {
-                return Err(CliError::Usage(format!(
+                return Err(CliError::usage(format!(
                     "Required parameter '{}' missing for tool '{}'",
                     param.name, tool_def.name
                 )));
@@ -172,7 +184,7 @@ impl ToolValidator {
                 // Check enum values
                 if let Some(enum_values) = &param.enum_values {
                     if !enum_values.contains(&s) {
-                        return Err(CliError::Usage(format!(
+                        return Err(CliError::usage(format!(
                             "Invalid value '{}' for parameter '{}'. Must be one of: {:?}",
                             s, param.name, enum_values
                         )));
@@ -182,9 +194,9 @@ impl ToolValidator {
                 // Check regex validation
                 if let Some(regex_str) = &param.validation_regex {
                     let regex = regex::Regex::new(regex_str)
-                        .map_err(|e| CliError::Usage(format!("Invalid regex pattern: {}", e)))?;
+                        .map_err(|e| CliError::usage(format!("Invalid regex pattern: {}", e)))?;
                     if !regex.is_match(s) {
-                        return Err(CliError::Usage(format!(
+                        return Err(CliError::usage(format!(
                             "Value '{}' for parameter '{}' doesn't match required pattern",
                             s, param.name
                         )));
@@ -198,7 +210,7 @@ impl ToolValidator {
                 // TODO: Add boolean validation if needed
             }
             _ => {
-                return Err(CliError::Usage(format!(
+                return Err(CliError::usage(format!(
                     "Invalid type for parameter '{}'",
                     param.name
                 )));
@@ -214,7 +226,12 @@ pub struct EnhancedToolExecutor {
     api_client: ApiClient,
     config: ToolConfig,
     tool_definitions: HashMap<String, ToolDefinition>,
-    pty_manager: Arc<Mutex<PtyManager>>,
+    pty_manager: Arc<PtyManager>,
+    policy: ToolPolicy,
+    approval: Approval,
+    journal: Arc<Journal>,
+    /// `workspace_id`'s root, once the server has told us where it is.
+    resolved_root: Arc<OnceCell<PathBuf>>,
 }
 
 impl EnhancedToolExecutor {
@@ -226,12 +243,85 @@ impl EnhancedToolExecutor {
             api_client,
             config,
             tool_definitions: HashMap::new(),
-            pty_manager: Arc::new(Mutex::new(pty_manager)),
+            pty_manager: Arc::new(pty_manager),
+            policy: ToolPolicy::default(),
+            approval: Approval::default(),
+            journal: Arc::new(Journal::new()),
+            resolved_root: Arc::default(),
         };
         executor.register_default_tools();
         executor
     }
 
+    /// Check calls against `policy` before running them, settling `ask`
+    /// verdicts as `approval` says.
+    pub fn with_policy(mut self, policy: ToolPolicy, approval: Approval) -> Self {
+        self.policy = policy;
+        self.approval = approval;
+        self
+    }
+
+    /// Record file writes in `journal` instead of a journal of their own,
+    /// so several executors share one undo session.
+    pub fn with_journal(mut self, journal: Arc<Journal>) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// The undo journal file writes are recorded in.
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Keep `shell_open` sessions in `shells`, so they outlive this executor.
+    pub fn with_shells(mut self, shells: Arc<PtyManager>) -> Self {
+        self.pty_manager = shells;
+        self
+    }
+
+    /// Confine file tools to the root of `workspace_id` rather than the
+    /// current directory.
+    pub fn with_workspace(mut self, workspace_id: Option<String>) -> Self {
+        self.config.workspace_id = workspace_id;
+        self.resolved_root = Arc::default();
+        self
+    }
+
+    /// The directory file tools are confined to. `Err` says why the
+    /// workspace's root could not be found; file tools report it rather
+    /// than falling back to the current directory.
+    async fn workspace_root(&self) -> Result<PathBuf, String> {
+        if let Some(root) = &self.config.workspace_root {
+            return Ok(root.clone());
+        }
+        let workspace_id = self.config.workspace_id.as_deref().map(str::trim);
+        let Some(id) = workspace_id.filter(|id| !id.is_empty()) else {
+            return std::env::current_dir()
+                .map_err(|err| format!("No usable working directory: {err}"));
+        };
+        self.resolved_root
+            .get_or_try_init(|| lookup_workspace_root(&self.api_client, id))
+            .await
+            .cloned()
+    }
+
+    /// Close every shell the agent opened; returns how many there were.
+    pub async fn close_shells(&self) -> usize {
+        self.pty_manager.close_all().await
+    }
+
+    /// What the policy says about a call. Tools without a definition are
+    /// never treated as read-only.
+    pub fn verdict(&self, tool_name: &str, args: &HashMap<String, serde_json::Value>) -> Verdict {
+        let read_only = self.tool_definitions.get(tool_name).is_some_and(|t| t.safe);
+        self.policy.decide(tool_name, read_only, args)
+    }
+
+    /// Whether `tool_name` is one of the tools this executor can run.
+    pub fn has_tool(&self, tool_name: &str) -> bool {
+        self.tool_definitions.contains_key(tool_name)
+    }
+
     /// Register a tool definition
     pub fn register_tool(&mut self, tool: ToolDefinition) {
         self.tool_definitions.insert(tool.name.clone(), tool);
@@ -281,7 +371,35 @@ impl EnhancedToolExecutor {
                     },
                 ],
                 category: "filesystem".to_string(),
-                safe: true,
+                safe: false,
+                file_operations: true,
+                network_operations: false,
+            },
+            ToolDefinition {
+                name: "apply_patch".to_string(),
+                description: "Apply a unified diff or search/replace blocks to files".to_string(),
+                parameters: vec![
+                    ToolParameter {
+                        name: "patch".to_string(),
+                        r#type: "string".to_string(),
+                        description: "Unified diff, or search/replace blocks".to_string(),
+                        required: true,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                    ToolParameter {
+                        name: "path".to_string(),
+                        r#type: "string".to_string(),
+                        description: "File for search/replace blocks that do not name one".to_string(),
+                        required: false,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                ],
+                category: "filesystem".to_string(),
+                safe: false,
                 file_operations: true,
                 network_operations: false,
             },
@@ -326,13 +444,13 @@ impl EnhancedToolExecutor {
                     validation_regex: None,
                 }],
                 category: "system".to_string(),
-                safe: true,
+                safe: false,
                 file_operations: false,
                 network_operations: false,
             },
             ToolDefinition {
                 name: "interactive_shell".to_string(),
-                description: "Execute command in interactive shell with PTY".to_string(),
+                description: "Execute command in interactive shell with PTY; reports its exit code, or the prompt it is waiting at".to_string(),
                 parameters: vec![
                     ToolParameter {
                         name: "command".to_string(),
@@ -345,8 +463,17 @@ impl EnhancedToolExecutor {
                     },
                     ToolParameter {
                         name: "input".to_string(),
-                        r#type: "string".to_string(),
-                        description: "Optional input to send".to_string(),
+                        r#type: "array".to_string(),
+                        description: "Answers to send, in order, each time the command stops at an input prompt".to_string(),
+                        required: false,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                    ToolParameter {
+                        name: "expect".to_string(),
+                        r#type: "array".to_string(),
+                        description: "Extra regexes for input prompts, matched against the last line of output, e.g. `\\?\\s*$` for any line ending in a question mark".to_string(),
                         required: false,
                         default_value: None,
                         enum_values: None,
@@ -355,7 +482,7 @@ impl EnhancedToolExecutor {
                     ToolParameter {
                         name: "timeout".to_string(),
                         r#type: "number".to_string(),
-                        description: "Timeout in seconds".to_string(),
+                        description: "Seconds to wait for the command to finish or ask for input".to_string(),
                         required: false,
                         default_value: Some(serde_json::Value::Number(30.into())),
                         enum_values: None,
@@ -363,6 +490,127 @@ impl EnhancedToolExecutor {
                     },
                 ],
                 category: "system".to_string(),
+                safe: false,
+                file_operations: false,
+                network_operations: false,
+            },
+            ToolDefinition {
+                name: "shell_open".to_string(),
+                description: "Open a persistent shell; working directory, variables and activated environments carry over between shell_exec calls".to_string(),
+                parameters: vec![
+                    ToolParameter {
+                        name: "cwd".to_string(),
+                        r#type: "string".to_string(),
+                        description: "Directory to start in, inside the workspace".to_string(),
+                        required: false,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                ],
+                category: "system".to_string(),
+                safe: true,
+                file_operations: false,
+                network_operations: false,
+            },
+            ToolDefinition {
+                name: "shell_exec".to_string(),
+                description: "Run a command in a shell from shell_open; reports its exit code, or the prompt it is waiting at".to_string(),
+                parameters: vec![
+                    ToolParameter {
+                        name: "session".to_string(),
+                        r#type: "string".to_string(),
+                        description: "Session ID from shell_open".to_string(),
+                        required: true,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                    ToolParameter {
+                        name: "command".to_string(),
+                        r#type: "string".to_string(),
+                        description: "Command to run".to_string(),
+                        required: true,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                    ToolParameter {
+                        name: "timeout".to_string(),
+                        r#type: "number".to_string(),
+                        description: "Seconds to wait for the command to finish or ask for input (default 20, at most 25)".to_string(),
+                        required: false,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                ],
+                category: "system".to_string(),
+                safe: false,
+                file_operations: false,
+                network_operations: false,
+            },
+            ToolDefinition {
+                name: "shell_send_input".to_string(),
+                description: "Answer the prompt a shell_exec command is waiting at, interrupt it, or (with neither) keep waiting for it".to_string(),
+                parameters: vec![
+                    ToolParameter {
+                        name: "session".to_string(),
+                        r#type: "string".to_string(),
+                        description: "Session ID from shell_open".to_string(),
+                        required: true,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                    ToolParameter {
+                        name: "input".to_string(),
+                        r#type: "string".to_string(),
+                        description: "Line to send".to_string(),
+                        required: false,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                    ToolParameter {
+                        name: "interrupt".to_string(),
+                        r#type: "boolean".to_string(),
+                        description: "Send Ctrl-C instead".to_string(),
+                        required: false,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                    ToolParameter {
+                        name: "timeout".to_string(),
+                        r#type: "number".to_string(),
+                        description: "Seconds to wait for the command to finish or ask for input (default 20, at most 25)".to_string(),
+                        required: false,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                ],
+                category: "system".to_string(),
+                safe: false,
+                file_operations: false,
+                network_operations: false,
+            },
+            ToolDefinition {
+                name: "shell_close".to_string(),
+                description: "Close a shell from shell_open".to_string(),
+                parameters: vec![
+                    ToolParameter {
+                        name: "session".to_string(),
+                        r#type: "string".to_string(),
+                        description: "Session ID from shell_open".to_string(),
+                        required: true,
+                        default_value: None,
+                        enum_values: None,
+                        validation_regex: None,
+                    },
+                ],
+                category: "system".to_string(),
                 safe: true,
                 file_operations: false,
                 network_operations: false,
@@ -400,7 +648,7 @@ impl EnhancedToolExecutor {
                     },
                 ],
                 category: "tasks".to_string(),
-                safe: true,
+                safe: false,
                 file_operations: false,
                 network_operations: true,
             },
@@ -442,7 +690,7 @@ impl EnhancedToolExecutor {
                     },
                 ],
                 category: "tasks".to_string(),
-                safe: true,
+                safe: false,
                 file_operations: false,
                 network_operations: true,
             },
@@ -492,7 +740,7 @@ impl EnhancedToolExecutor {
                     validation_regex: None,
                 }],
                 category: "tasks".to_string(),
-                safe: true,
+                safe: false,
                 file_operations: false,
                 network_operations: true,
             },
@@ -503,12 +751,34 @@ impl EnhancedToolExecutor {
         }
     }
 
-    /// Execute a tool with enhanced error handling and retries
+    /// Execute a tool with enhanced error handling and retries. Calls the
+    /// policy refuses, or the user declines, come back as failed results.
     pub async fn execute_tool(
         &self,
         tool_name: &str,
         args: &HashMap<String, serde_json::Value>,
     ) -> Result<ToolResult, CliError> {
+        let verdict = self.verdict(tool_name, args);
+        match verdict.action {
+            Action::Allow => {}
+            Action::Deny => {
+                return Ok(ToolResult::error(format!(
+                    "{tool_name} is not allowed ({}).",
+                    verdict.reason
+                )));
+            }
+            Action::Ask if self.approval == Approval::Granted => {}
+            Action::Ask => match policy::ask(tool_name, args, &verdict) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Ok(ToolResult::error(format!(
+                        "The user declined to run {tool_name}."
+                    )));
+                }
+                Err(reason) => return Ok(ToolResult::error(reason)),
+            },
+        }
+
         // If tool definition exists, validate; otherwise try direct execution
         if let Some(tool_def) = self.tool_definitions.get(tool_name) {
             if self.config.enable_validation {
@@ -531,7 +801,12 @@ impl EnhancedToolExecutor {
         }
 
         // Fallback: try direct execution even without definition
-        let local_executor = LocalToolExecutor::new(self.api_client.clone());
+        let local_executor = LocalToolExecutor::new(
+            self.api_client.clone(),
+            self.workspace_root().await,
+            self.journal.clone(),
+            self.pty_manager.clone(),
+        );
         local_executor.execute(tool_name.to_string(), args.clone()).await
     }
 
@@ -544,7 +819,12 @@ impl EnhancedToolExecutor {
         let start_time = std::time::Instant::now();
 
         // Create local tool executor
-        let local_executor = LocalToolExecutor::new(self.api_client.clone());
+        let local_executor = LocalToolExecutor::new(
+            self.api_client.clone(),
+            self.workspace_root().await,
+            self.journal.clone(),
+            self.pty_manager.clone(),
+        );
 
         // Execute with timeout
         let result = timeout(
@@ -552,7 +832,7 @@ impl EnhancedToolExecutor {
             local_executor.execute(tool_def.name.clone(), args.clone()),
         ).await
         .map_err(|_| {
-            CliError::Generic(format!("Tool '{}' timed out after {} seconds", tool_def.name, self.config.timeout_seconds))
+            CliError::generic(format!("Tool '{}' timed out after {} seconds", tool_def.name, self.config.timeout_seconds))
         })??;
 
         let duration_ms = start_time.elapsed().as_millis() as u64;
@@ -576,7 +856,7 @@ impl EnhancedToolExecutor {
 
         // Convert args to JSON string
         let _args_json = serde_json::to_string(args)
-            .map_err(|e| CliError::Generic(format!("Failed to serialize arguments: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to serialize arguments: {}", e)))?;
 
         // Build proposal request
         let proposal_request = serde_json::json!({
@@ -622,7 +902,7 @@ impl EnhancedToolExecutor {
             }))
         } else {
             // Require manual approval
-            Err(CliError::Usage(format!(
+            Err(CliError::usage(format!(
                 "Tool '{}' requires manual approval. Please use the tools command to approve or deny.",
                 tool_def.name
             )))
@@ -630,23 +910,68 @@ impl EnhancedToolExecutor {
     }
 }
 
+/// Ask the server where workspace `id` lives on this machine.
+async fn lookup_workspace_root(api: &ApiClient, id: &str) -> Result<PathBuf, String> {
+    let res = api
+        .get_json(&format!("/v1/workspaces/{id}"), None, true)
+        .await
+        .map_err(|err| format!("Could not find the root of workspace {id}: {err}"))?;
+    res.json
+        .get("workspace")
+        .and_then(|ws| ws.get("rootPath"))
+        .and_then(serde_json::Value::as_str)
+        .filter(|root| !root.trim().is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| {
+            format!("Workspace {id} has no rootPath, so file tools have nowhere to work.")
+        })
+}
+
+/// One file `apply_patch` is about to change.
+struct PlannedWrite {
+    resolved: PathBuf,
+    /// As the patch named it, for messages.
+    path: String,
+    /// `None` when the patch creates the file.
+    original: Option<String>,
+    contents: String,
+}
+
 /// Local tool executor for direct tool execution
 struct LocalToolExecutor {
     api_client: ApiClient,
+    workspace_root: Result<PathBuf, String>,
+    journal: Arc<Journal>,
+    shells: Arc<PtyManager>,
 }
 
 impl LocalToolExecutor {
-    fn new(api_client: ApiClient) -> Self {
-        Self { api_client }
+    fn new(
+        api_client: ApiClient,
+        workspace_root: Result<PathBuf, String>,
+        journal: Arc<Journal>,
+        shells: Arc<PtyManager>,
+    ) -> Self {
+        Self { api_client, workspace_root, journal, shells }
+    }
+
+    /// The sandbox file tools resolve their paths through.
+    fn sandbox(&self) -> Result<Sandbox, String> {
+        Sandbox::new(self.workspace_root.as_ref()?)
     }
 
     async fn execute(&self, tool_name: String, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         match tool_name.as_str() {
             "read_file" => self.execute_read_file(args).await,
             "write_file" => self.execute_write_file(args).await,
+            "apply_patch" => self.execute_apply_patch(args).await,
             "search_files" => self.execute_search_files(args).await,
             "execute_command" => self.execute_command(args).await,
             "interactive_shell" => self.execute_interactive_shell(args).await,
+            "shell_open" => self.execute_shell_open(args).await,
+            "shell_exec" => self.execute_shell_exec(args).await,
+            "shell_send_input" => self.execute_shell_send_input(args).await,
+            "shell_close" => self.execute_shell_close(args).await,
             "create_task" => self.execute_create_task(args).await,
             "update_task" => self.execute_update_task(args).await,
             "list_tasks" => self.execute_list_tasks(args).await,
@@ -659,11 +984,29 @@ impl LocalToolExecutor {
     async fn execute_read_file(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         let path = args.get("path")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| CliError::Generic("Path is required".to_string()))?;
+            .ok_or_else(|| CliError::generic("Path is required".to_string()))?;
+
+        let resolved = match self.sandbox().and_then(|sandbox| sandbox.resolve_existing(path)) {
+            Ok(resolved) => resolved,
+            Err(reason) => return Ok(ToolResult::error(reason)),
+        };
+        let metadata = tokio::fs::metadata(&resolved)
+            .await
+            .map_err(|e| CliError::generic(format!("Failed to read file: {}", e)))?;
+        if metadata.is_dir() {
+            return Ok(ToolResult::error(format!("{path} is a directory; use search_files to list it.")));
+        }
+        if metadata.len() > MAX_READ_BYTES {
+            return Ok(ToolResult::error(format!(
+                "{path} is {}; read_file is limited to {}.",
+                human_size(metadata.len() as usize),
+                human_size(MAX_READ_BYTES as usize)
+            )));
+        }
 
-        let content = tokio::fs::read_to_string(path)
+        let content = tokio::fs::read_to_string(&resolved)
             .await
-            .map_err(|e| CliError::Generic(format!("Failed to read file: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to read file: {}", e)))?;
 
         Ok(ToolResult::success(content))
     }
@@ -671,18 +1014,135 @@ impl LocalToolExecutor {
     async fn execute_write_file(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         let path = args.get("path")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| CliError::Generic("Path is required".to_string()))?;
+            .ok_or_else(|| CliError::generic("Path is required".to_string()))?;
         let content = args.get("content")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| CliError::Generic("Content is required".to_string()))?;
+            .ok_or_else(|| CliError::generic("Content is required".to_string()))?;
 
-        tokio::fs::write(path, content)
-            .await
-            .map_err(|e| CliError::Generic(format!("Failed to write file: {}", e)))?;
+        let resolved = match self.sandbox().and_then(|sandbox| sandbox.resolve_for_write(path)) {
+            Ok(resolved) => resolved,
+            Err(reason) => return Ok(ToolResult::error(reason)),
+        };
+        // Atomic, and journaled for `starbott undo`.
+        self.journal
+            .write(&resolved, content.as_bytes())
+            .map_err(|e| CliError::generic(format!("Failed to write file: {}", e)))?;
 
         Ok(ToolResult::success(format!("File written to {}", path)))
     }
 
+    /// Apply every file's hunks, or none: if any hunk fails nothing is
+    /// written, and the result says which hunks failed and why. Sections for
+    /// the same file are applied as one, in order.
+    async fn execute_apply_patch(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
+        let files = match patch::from_args(&args) {
+            Some(Ok(files)) => files,
+            Some(Err(reason)) => return Ok(ToolResult::error(reason)),
+            None => return Err(CliError::generic("Patch is required".to_string())),
+        };
+        let sandbox = match self.sandbox() {
+            Ok(sandbox) => sandbox,
+            Err(reason) => return Ok(ToolResult::error(reason)),
+        };
+
+        let mut report = Vec::new();
+        let mut failed = false;
+        let mut targets: Vec<(PathBuf, patch::FilePatch)> = Vec::new();
+        for file in files {
+            match sandbox.resolve_for_write(&file.path) {
+                Ok(resolved) => match targets.iter_mut().find(|(path, _)| *path == resolved) {
+                    Some((_, merged)) => {
+                        merged.create |= file.create;
+                        merged.hunks.extend(file.hunks);
+                    }
+                    None => targets.push((resolved, file)),
+                },
+                Err(reason) => {
+                    report.push(reason);
+                    failed = true;
+                }
+            }
+        }
+
+        let mut planned = Vec::new();
+        for (resolved, file) in targets {
+            let original = match std::fs::read_to_string(&resolved) {
+                Ok(text) => Some(text),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    report.push(format!("{}: {}", file.path, e));
+                    failed = true;
+                    continue;
+                }
+            };
+            if original.is_none() && !file.creates() {
+                report.push(format!("{}: does not exist", file.path));
+                failed = true;
+                continue;
+            }
+            if original.is_some() && file.create {
+                report.push(format!("{}: already exists", file.path));
+                failed = true;
+                continue;
+            }
+
+            let outcome = patch::apply(original.as_deref(), &file);
+            report.extend(outcome.hunks.iter().map(|hunk| format!("{}: {}", file.path, hunk)));
+            match outcome.contents {
+                Some(contents) => planned.push(PlannedWrite {
+                    resolved,
+                    path: file.path,
+                    original,
+                    contents,
+                }),
+                None => failed = true,
+            }
+        }
+
+        if failed {
+            return Ok(ToolResult::error(format!(
+                "Patch not applied; no files were changed.\n{}",
+                report.join("\n")
+            )));
+        }
+        for (done, write) in planned.iter().enumerate() {
+            if let Err(err) = self.journal.write(&write.resolved, write.contents.as_bytes()) {
+                let reason = format!("{}: {err}", write.path);
+                return Ok(ToolResult::error(self.roll_back(&planned[..done], reason)));
+            }
+        }
+        Ok(ToolResult::success(format!(
+            "Patched {} file(s).\n{}",
+            planned.len(),
+            report.join("\n")
+        )))
+    }
+
+    /// Put back the files a patch already wrote before one of its writes
+    /// failed, newest first; says what happened for the tool result.
+    fn roll_back(&self, written: &[PlannedWrite], reason: String) -> String {
+        let stuck: Vec<&str> = written
+            .iter()
+            .rev()
+            .filter(|write| {
+                let restored = match &write.original {
+                    Some(text) => self.journal.write(&write.resolved, text.as_bytes()),
+                    None => self.journal.remove(&write.resolved),
+                };
+                restored.is_err()
+            })
+            .map(|write| write.path.as_str())
+            .collect();
+        if stuck.is_empty() {
+            format!("Patch not applied; no files were changed.\n{reason}")
+        } else {
+            format!(
+                "Patch partly applied: {reason}\nCould not put back {}; `starbott undo` can.",
+                stuck.join(", ")
+            )
+        }
+    }
+
     async fn execute_search_files(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         let pattern = args.get("pattern")
             .and_then(|v| v.as_str())
@@ -691,8 +1151,12 @@ impl LocalToolExecutor {
             .and_then(|v| v.as_str())
             .unwrap_or(".");
 
+        let dir = match self.sandbox().and_then(|sandbox| sandbox.resolve_existing(path)) {
+            Ok(dir) => dir,
+            Err(reason) => return Ok(ToolResult::error(reason)),
+        };
         let mut results = Vec::new();
-        let entries = match std::fs::read_dir(path) {
+        let entries = match std::fs::read_dir(&dir) {
             Ok(entries) => entries,
             Err(e) => return Ok(ToolResult::error(format!("Failed to read directory: {}", e))),
         };
@@ -713,13 +1177,13 @@ impl LocalToolExecutor {
     async fn execute_command(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         let command = args.get("command")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| CliError::Generic("Command is required".to_string()))?;
+            .ok_or_else(|| CliError::generic("Command is required".to_string()))?;
 
         let output = std::process::Command::new("sh")
             .arg("-c")
             .arg(command)
             .output()
-            .map_err(|e| CliError::Generic(format!("Failed to execute command: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to execute command: {}", e)))?;
 
         let output_str = String::from_utf8_lossy(&output.stdout);
         if output.status.success() {
@@ -733,93 +1197,141 @@ impl LocalToolExecutor {
     async fn execute_interactive_shell(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         let command = args.get("command")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| CliError::Generic("Command is required".to_string()))?;
-
-        let input = args.get("input")
-            .and_then(|v| v.as_str());
+            .ok_or_else(|| CliError::generic("Command is required".to_string()))?;
+
+        // Answers for the prompts the command stops at, in order.
+        let strings = |key: &str| -> Vec<String> {
+            match args.get(key) {
+                Some(serde_json::Value::String(s)) => vec![s.clone()],
+                Some(serde_json::Value::Array(items)) => items
+                    .iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+        let mut answers: VecDeque<String> = strings("input").into();
 
         let timeout_secs = args.get("timeout")
             .and_then(|v| v.as_u64())
             .unwrap_or(30);
 
-        // Create a PTY session
-        let pty_config = PtyConfig {
-            shell: "/bin/bash".to_string(),
-            term_type: "xterm-256color".to_string(),
-            columns: 80,
-            rows: 24,
+        let mut pty_config = PtyConfig {
             prompt_timeout: timeout_secs,
-            max_buffer_size: 1024 * 1024,
+            record: true,
+            ..Default::default()
         };
-
+        pty_config.input_patterns.extend(strings("expect"));
         let mut session = PtySession::new(pty_config);
 
-        // Spawn the PTY
-        match session.spawn().await {
-            Ok(_) => {},
-            Err(e) => return Ok(ToolResult::error(format!("Failed to spawn PTY: {}", e))),
+        if let Err(e) = session.spawn().await {
+            return Ok(ToolResult::error(format!("Failed to spawn PTY: {}", e)));
         }
 
-        // Send the command
-        match session.send_line(command).await {
-            Ok(_) => {},
-            Err(e) => return Ok(ToolResult::error(format!("Failed to send command: {}", e))),
+        let mut result = match session.execute(command).await {
+            Ok(result) => result,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        while matches!(result.status, CommandStatus::NeedsInput { .. })
+            && let Some(answer) = answers.pop_front()
+        {
+            result = match session.respond(&answer).await {
+                Ok(next) => result.then(next),
+                Err(e) => return Ok(ToolResult::error(e.to_string())),
+            };
         }
 
-        // If input is provided, send it
-        if let Some(input) = input {
-            match session.send_line(input).await {
-                Ok(_) => {},
-                Err(e) => return Ok(ToolResult::error(format!("Failed to send input: {}", e))),
+        let recording = session.recording_id().map(|id| ("recording", id.into()));
+        let _ = session.kill().await;
+        shell_result(&result, recording)
+    }
+
+    /// Open a shell that later shell_* calls reach by its session ID
+    async fn execute_shell_open(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
+        let cwd = args.get("cwd").and_then(|v| v.as_str()).unwrap_or(".");
+        let dir = match self.sandbox().and_then(|sandbox| sandbox.resolve_existing(cwd)) {
+            Ok(dir) if dir.is_dir() => dir,
+            Ok(dir) => return Ok(ToolResult::error(format!("{} is not a directory", dir.display()))),
+            Err(reason) => return Ok(ToolResult::error(reason)),
+        };
+
+        let session = match self.shells.create_session().await {
+            Ok(session) => session,
+            Err(e) => return Ok(ToolResult::error(format!("Failed to open a shell: {}", e))),
+        };
+        let quoted = format!("'{}'", dir.display().to_string().replace('\'', "'\\''"));
+        match self.shells.execute(&session, &format!("cd {quoted}")).await {
+            Ok(result) if result.success() => {}
+            outcome => {
+                let _ = self.shells.kill_session(&session).await;
+                let reason = match outcome {
+                    Ok(result) => result.status.to_string(),
+                    Err(e) => e.to_string(),
+                };
+                return Ok(ToolResult::error(format!(
+                    "Failed to open a shell in {}: {}",
+                    dir.display(),
+                    reason
+                )));
             }
         }
 
-        // Read output with timeout
-        let mut output = String::new();
-        let start = std::time::Instant::now();
+        let mut body = serde_json::json!({ "session": session, "cwd": dir.display().to_string() });
+        if let Some(info) = self.shells.list_sessions().into_iter().find(|info| info.id == session)
+            && let Some(recording) = info.recording
+        {
+            body["recording"] = recording.into();
+        }
+        Ok(ToolResult::success(serde_json::to_string_pretty(&body)?))
+    }
 
-        loop {
-            if start.elapsed().as_secs() > timeout_secs {
-                break;
-            }
+    async fn execute_shell_exec(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
+        let session = shell_session(&args)?;
+        let command = args.get("command")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| CliError::generic("Command is required".to_string()))?;
 
-            match tokio::time::timeout(Duration::from_millis(100), session.read()).await {
-                Ok(Ok(pty_output)) => {
-                    if !pty_output.content.is_empty() {
-                        output.push_str(&pty_output.content);
-                    }
-                    if pty_output.waiting_for_input {
-                        // Shell is waiting for input, assume command is done
-                        break;
-                    }
-                    if matches!(session.state(), crate::commands::pty::PtyState::Exited) {
-                        break;
-                    }
-                }
-                Ok(Err(e)) => {
-                    return Ok(ToolResult::error(format!("Failed to read from PTY: {}", e)));
-                }
-                Err(_) => {
-                    // Timeout, check if we have output
-                    if !output.is_empty() {
-                        break;
-                    }
-                    continue;
-                }
-            }
+        match self.shells.execute_for(session, command, Some(shell_wait(&args))).await {
+            Ok(result) => shell_result(&result, Some(("session", session.into()))),
+            Err(e) => Ok(ToolResult::error(e.to_string())),
         }
+    }
 
-        // Kill the session
-        let _ = session.kill().await;
+    async fn execute_shell_send_input(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
+        let session = shell_session(&args)?;
+        let wait = Some(shell_wait(&args));
+        let interrupt = args.get("interrupt").and_then(|v| v.as_bool()).unwrap_or(false);
+
+        let result = match args.get("input").and_then(|v| v.as_str()) {
+            _ if interrupt => match self.shells.interrupt(session).await {
+                Ok(()) => self.shells.wait_for_command(session, wait).await,
+                Err(e) => Err(e),
+            },
+            Some(input) => self.shells.respond(session, input, wait).await,
+            None => self.shells.wait_for_command(session, wait).await,
+        };
+        match result {
+            Ok(result) => shell_result(&result, Some(("session", session.into()))),
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
+    }
 
-        Ok(ToolResult::success(output))
+    async fn execute_shell_close(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
+        let session = shell_session(&args)?;
+        if !self.shells.list_sessions().iter().any(|info| info.id == session) {
+            return Ok(ToolResult::error(format!("No open shell {}", session)));
+        }
+        match self.shells.kill_session(session).await {
+            Ok(()) => Ok(ToolResult::success(format!("Closed shell {}", session))),
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
     }
 
     // Task-related tool implementations
     async fn execute_create_task(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         let title = args.get("title")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| CliError::Generic("Title is required".to_string()))?;
+            .ok_or_else(|| CliError::generic("Title is required".to_string()))?;
 
         let description = args.get("description")
             .and_then(|v| v.as_str())
@@ -850,7 +1362,7 @@ impl LocalToolExecutor {
     async fn execute_update_task(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         let task_id = args.get("task_id")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| CliError::Generic("Task ID is required".to_string()))?;
+            .ok_or_else(|| CliError::generic("Task ID is required".to_string()))?;
 
         let mut update_data = serde_json::json!({});
         if let Some(title) = args.get("title").and_then(|v| v.as_str()) {
@@ -873,7 +1385,7 @@ impl LocalToolExecutor {
         let _priority = args.get("priority").and_then(|v| v.as_u64()).map(|p| p as i32);
 
         let tasks = self.api_client.list_tasks(status.map(|s| s.to_string()), 20).await
-            .map_err(|e| CliError::Generic(format!("Failed to list tasks: {}", e)))?;
+            .map_err(|e| CliError::generic(format!("Failed to list tasks: {}", e)))?;
 
         let task_list = tasks.iter()
             .map(|task| format!("- [{}] {} (Priority: {})", task.status, task.title, task.priority))
@@ -886,7 +1398,7 @@ impl LocalToolExecutor {
     async fn execute_complete_task(&self, args: HashMap<String, serde_json::Value>) -> Result<ToolResult, CliError> {
         let task_id = args.get("task_id")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| CliError::Generic("Task ID is required".to_string()))?;
+            .ok_or_else(|| CliError::generic("Task ID is required".to_string()))?;
 
         match self.api_client.post_json(&format!("/v1/tasks/{}/complete", task_id), None, true).await {
             Ok(_res) => {
@@ -895,4 +1407,112 @@ impl LocalToolExecutor {
             Err(e) => Ok(ToolResult::error(format!("Failed to complete task: {}", e))),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Longest a shell tool waits, under the executor's default 30s tool timeout;
+/// a command still running after that keeps running.
+const MAX_SHELL_WAIT_SECS: u64 = 25;
+
+fn shell_session(args: &HashMap<String, serde_json::Value>) -> Result<&str, CliError> {
+    args.get("session")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| CliError::generic("Session is required".to_string()))
+}
+
+fn shell_wait(args: &HashMap<String, serde_json::Value>) -> u64 {
+    args.get("timeout")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(20)
+        .min(MAX_SHELL_WAIT_SECS)
+}
+
+/// A shell command's status, exit code or pending prompt, and output, as JSON
+/// for the agent to act on; it succeeded only if it exited with 0. A failure's
+/// error is the status on one line.
+fn shell_result(
+    result: &CommandOutput,
+    extra: Option<(&str, serde_json::Value)>,
+) -> Result<ToolResult, CliError> {
+    let mut body = serde_json::to_value(result)?;
+    if let Some((key, value)) = extra {
+        body[key] = value;
+    }
+    let body = serde_json::to_string_pretty(&body)
+        .map_err(|e| CliError::generic(format!("Failed to encode shell result: {}", e)))?;
+    if result.success() {
+        Ok(ToolResult::success(body))
+    } else {
+        Ok(ToolResult {
+            success: false,
+            output: body,
+            error: Some(format!("Command {}", result.status)),
+            metadata: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn args(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shells_keep_their_state_between_calls() {
+        let root = std::env::temp_dir().join(format!("starbott-shells-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        let api = ApiClient::new("http://127.0.0.1:9".to_string(), None, 1000, 0, false).unwrap();
+        let executor = EnhancedToolExecutor::new(
+            api,
+            ToolConfig {
+                mode: ToolMode::Direct,
+                workspace_root: Some(root.clone()),
+                pty_config: Some(PtyConfig {
+                    shell: "/bin/sh".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .with_policy(ToolPolicy::default(), Approval::Granted);
+        let run = |tool: &'static str, value: Value| {
+            let executor = &executor;
+            async move { executor.execute_tool(tool, &args(value)).await.unwrap() }
+        };
+        let body = |result: &ToolResult| serde_json::from_str::<Value>(&result.output).unwrap();
+
+        let opened = run("shell_open", json!({ "cwd": "sub" })).await;
+        assert!(opened.success, "{opened:?}");
+        let session = body(&opened)["session"].as_str().unwrap().to_string();
+
+        let set = run("shell_exec", json!({ "session": session, "command": "export GREETING=hi; cd .." })).await;
+        assert!(set.success, "{set:?}");
+        let echoed = run("shell_exec", json!({ "session": session, "command": "echo $GREETING $(basename \"$PWD\")" })).await;
+        let name = root.file_name().unwrap().to_string_lossy();
+        assert_eq!(body(&echoed)["output"], format!("hi {name}\n"));
+
+        let asked = run("shell_exec", json!({ "session": session, "command": "printf 'Continue? [y/N] '; read answer; echo got-$answer" })).await;
+        assert!(!asked.success);
+        assert_eq!(asked.error.as_deref(), Some("Command waiting for input at `Continue? [y/N]`"));
+        assert_eq!(body(&asked)["status"], "needs_input");
+        assert_eq!(body(&asked)["prompt"], "Continue? [y/N]");
+        let answered = run("shell_send_input", json!({ "session": session, "input": "y" })).await;
+        assert_eq!(body(&answered)["output"], "got-y\n");
+
+        let slow = run("shell_exec", json!({ "session": session, "command": "sleep 30", "timeout": 1 })).await;
+        assert_eq!(body(&slow)["status"], "timed_out");
+        let stopped = run("shell_send_input", json!({ "session": session, "interrupt": true })).await;
+        assert_eq!(body(&stopped)["exit_code"], 130);
+
+        assert!(run("shell_close", json!({ "session": session })).await.success);
+        assert!(!run("shell_exec", json!({ "session": session, "command": "true" })).await.success);
+        run("shell_open", json!({})).await;
+        assert_eq!(executor.close_shells().await, 1);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -9,9 +9,12 @@ pub mod enhanced_tools;
 pub mod health;
 #[allow(dead_code)]
 pub mod pty;
+pub mod sessions;
 pub mod tasks;
+pub mod templates;
 pub mod tools;
 pub mod tui;
+pub mod undo;
 pub mod usage;
 pub mod whoami;
 pub mod workspaces;
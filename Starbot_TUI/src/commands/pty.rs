@@ -2,20 +2,61 @@
 //!
 //! This module provides functionality to spawn and manage interactive shell sessions
 //! within the CLI agent, allowing for commands that require user interaction.
+//! Sessions run on a real pseudo-terminal (Unix only), so prompts from
+//! `npm init`, `git rebase -i` or `sudo` behave as they do in a terminal.
+//!
+//! `execute` frames each command: the shell's prompt is set to a marker
+//! unique to the session that carries `$?`, so the end of a command and its
+//! exit code are read off the output rather than guessed from silence. A
+//! command that stops at a line matching one of the input patterns
+//! (`Password:`, `[y/N]`, ...) comes back as needing input, to be answered
+//! with `respond`.
+//!
+//! With `record` set, a session also writes an asciicast recording of its
+//! terminal; see `crate::recording`.
 
 use std::collections::{HashMap, VecDeque};
-use std::process::Stdio;
+use std::fmt;
+use std::process::ExitStatus;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+#[cfg(unix)]
+use pty_process::{Pty, Size};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Child;
+use tokio::sync::{broadcast, mpsc, oneshot};
 
 use crate::errors::CliError;
+use crate::recording::Recorder;
+
+/// PTYs are Unix-only: elsewhere `spawn` fails, so no session ever holds one.
+#[cfg(not(unix))]
+type Pty = tokio::io::Empty;
+
+/// Prompts a framed command may stop at, matched against the last line of
+/// its output once the output pauses. Only explicit prompt shapes: a line
+/// that merely ends in `?` is left to callers to opt into.
+pub const DEFAULT_INPUT_PATTERNS: [&str; 5] = [
+    r"(?i)(password|passphrase|passcode)[^:\n]*:\s*$",
+    r"(?i)[\[(](y/n|yes/no)[^\])\n]*[\])]\s*[?:]?\s*$",
+    r"(?i)press (enter|return|any key)",
+    r"(?i)(continue|proceed|overwrite|are you sure)[^\n]*\?\s*$",
+    // `npm init` style: `package name: (demo) `
+    r":\s*\([^)\n]*\)\s*$",
+];
+
+/// How long output must pause before its last line is checked for a prompt.
+const INPUT_SETTLE: Duration = Duration::from_millis(250);
+
+/// Output chunks a slow subscriber may fall behind by before it skips ahead.
+const OUTPUT_CHANNEL: usize = 256;
 
 /// PTY session state
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PtyState {
     /// PTY is ready
     Ready,
@@ -40,10 +81,18 @@ pub struct PtyConfig {
     pub columns: u16,
     /// Number of rows
     pub rows: u16,
-    /// Timeout for waiting for prompts (in seconds)
+    /// How long `execute` waits for a command to finish or ask for input
+    /// (in seconds)
     pub prompt_timeout: u64,
     /// Maximum buffer size
     pub max_buffer_size: usize,
+    /// Regexes for input prompts; see `DEFAULT_INPUT_PATTERNS`
+    pub input_patterns: Vec<String>,
+    /// Seconds without input or output after which `PtyManager` closes a
+    /// session; never while a command is still running
+    pub idle_timeout: u64,
+    /// Record the session under the state directory
+    pub record: bool,
 }
 
 impl Default for PtyConfig {
@@ -55,6 +104,9 @@ impl Default for PtyConfig {
             rows: 24,
             prompt_timeout: 30,
             max_buffer_size: 1024 * 1024, // 1MB
+            input_patterns: DEFAULT_INPUT_PATTERNS.map(str::to_string).to_vec(),
+            idle_timeout: 30 * 60,
+            record: false,
         }
     }
 }
@@ -64,7 +116,7 @@ impl Default for PtyConfig {
 pub struct PtyOutput {
     /// The output content
     pub content: String,
-    /// Whether the output is stderr
+    /// Always false: stderr shares the terminal with stdout
     pub is_stderr: bool,
     /// Timestamp
     pub timestamp: u64,
@@ -72,16 +124,130 @@ pub struct PtyOutput {
     pub waiting_for_input: bool,
 }
 
-/// PTY session
+impl PtyOutput {
+    fn new(content: String, waiting_for_input: bool) -> Self {
+        Self {
+            content,
+            is_stderr: false,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            waiting_for_input,
+        }
+    }
+}
+
+/// How a PTY session's shell ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PtyExit {
+    /// Exit code, if the shell exited normally
+    pub code: Option<i32>,
+    /// Signal that killed the shell (Unix only)
+    pub signal: Option<i32>,
+}
+
+impl PtyExit {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+impl From<ExitStatus> for PtyExit {
+    fn from(status: ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+        Self {
+            code: status.code(),
+            signal,
+        }
+    }
+}
+
+impl fmt::Display for PtyExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.code, self.signal) {
+            (Some(code), _) => write!(f, "exit code {code}"),
+            (None, Some(signal)) => write!(f, "signal {signal}"),
+            (None, None) => write!(f, "unknown status"),
+        }
+    }
+}
+
+/// Where a framed command stopped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CommandStatus {
+    /// The command finished; the shell is ready for the next one
+    Exited { exit_code: i32 },
+    /// The command is waiting at `prompt`; answer it with `respond`
+    NeedsInput { prompt: String },
+    /// Neither happened within the prompt timeout; the command is still running
+    TimedOut,
+    /// The shell itself exited
+    ShellExited { exit: PtyExit },
+}
+
+impl fmt::Display for CommandStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandStatus::Exited { exit_code } => write!(f, "exited with code {exit_code}"),
+            CommandStatus::NeedsInput { prompt } => write!(f, "waiting for input at `{prompt}`"),
+            CommandStatus::TimedOut => write!(f, "still running after the timeout"),
+            CommandStatus::ShellExited { exit } => write!(f, "shell exited with {exit}"),
+        }
+    }
+}
+
+/// Output of a framed command, up to where it stopped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandOutput {
+    #[serde(flatten)]
+    pub status: CommandStatus,
+    pub output: String,
+}
+
+impl CommandOutput {
+    /// Whether the command finished with exit code 0
+    pub fn success(&self) -> bool {
+        self.status == CommandStatus::Exited { exit_code: 0 }
+    }
+
+    /// Append the output of a later step of the same command
+    pub fn then(mut self, next: CommandOutput) -> Self {
+        self.output.push_str(&next.output);
+        self.status = next.status;
+        self
+    }
+}
+
+/// PTY session: a shell attached to a real pseudo-terminal, so programs
+/// see a TTY (`isatty`, window size, job control) and stderr arrives
+/// interleaved with stdout, as it would on screen.
 pub struct PtySession {
     config: PtyConfig,
     state: PtyState,
     child: Option<Child>,
-    stdin: Option<ChildStdin>,
-    stdout: Option<ChildStdout>,
+    pty: Option<Pty>,
+    exit: Option<PtyExit>,
+    /// Unread output, bounded by `max_buffer_size`: complete lines, their
+    /// total size, and the line still being written
     buffer: VecDeque<u8>,
     output_lines: VecDeque<String>,
+    buffered: usize,
+    /// The start of a UTF-8 sequence the last read cut off
+    partial: Vec<u8>,
+    /// Every chunk of output, as it is read
+    output: broadcast::Sender<String>,
     last_activity: Instant,
+    /// The prompt marker, once the shell is framed
+    marker: Option<Regex>,
+    prompts: Vec<Regex>,
+    /// A framed command has not reached the marker yet
+    busy: bool,
+    recorder: Option<Recorder>,
 }
 
 impl PtySession {
@@ -91,67 +257,86 @@ impl PtySession {
             config,
             state: PtyState::Ready,
             child: None,
-            stdin: None,
-            stdout: None,
+            pty: None,
+            exit: None,
             buffer: VecDeque::new(),
             output_lines: VecDeque::new(),
+            buffered: 0,
+            partial: Vec::new(),
+            output: broadcast::channel(OUTPUT_CHANNEL).0,
             last_activity: Instant::now(),
+            marker: None,
+            prompts: Vec::new(),
+            busy: false,
+            recorder: None,
         }
     }
 
-    /// Spawn the PTY session
+    /// Spawn the shell on a new PTY sized to the configured rows and columns
+    #[cfg(unix)]
     pub async fn spawn(&mut self) -> Result<(), CliError> {
         let shell_path = std::path::Path::new(&self.config.shell);
         if !shell_path.exists() {
-            return Err(CliError::Generic(format!(
+            return Err(CliError::generic(format!(
                 "Shell not found: {}",
                 self.config.shell
             )));
         }
 
-        let mut cmd = Command::new(&self.config.shell);
-        cmd.env("TERM", &self.config.term_type)
-            .env("COLUMNS", self.config.columns.to_string())
-            .env("LINES", self.config.rows.to_string())
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        let pty = Pty::new().map_err(|e| CliError::generic(format!("Failed to open a PTY: {}", e)))?;
+        pty.resize(Size::new(self.config.rows, self.config.columns))
+            .map_err(|e| CliError::generic(format!("Failed to size the PTY: {}", e)))?;
+        let pts = pty
+            .pts()
+            .map_err(|e| CliError::generic(format!("Failed to open the PTY: {}", e)))?;
 
-        match cmd.spawn() {
-            Ok(mut child) => {
-                self.stdin = match child.stdin.take() {
-                    Some(stdin) => Some(stdin),
-                    None => return Err(CliError::Generic("Failed to capture stdin".to_string())),
-                };
-                self.stdout = match child.stdout.take() {
-                    Some(stdout) => Some(stdout),
-                    None => return Err(CliError::Generic("Failed to capture stdout".to_string())),
-                };
-                self.child = Some(child);
-                self.state = PtyState::Running;
-                self.last_activity = Instant::now();
-                Ok(())
-            }
-            Err(e) => Err(CliError::Generic(format!("Failed to spawn PTY: {}", e))),
+        let mut cmd = pty_process::Command::new(&self.config.shell);
+        cmd.env("TERM", &self.config.term_type);
+        let child = cmd
+            .spawn(&pts)
+            .map_err(|e| CliError::generic(format!("Failed to spawn PTY: {}", e)))?;
+        // Only the shell may hold the terminal end, or reads never see EOF.
+        drop(pts);
+
+        if self.config.record {
+            let env = [("SHELL", &self.config.shell), ("TERM", &self.config.term_type)]
+                .map(|(key, value)| (key.to_string(), value.clone()));
+            // A session that cannot be recorded still runs.
+            self.recorder =
+                Recorder::start(self.config.columns, self.config.rows, env.into()).ok();
         }
+
+        self.child = Some(child);
+        self.pty = Some(pty);
+        self.exit = None;
+        self.state = PtyState::Ready;
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// PTYs are Unix-only.
+    #[cfg(not(unix))]
+    pub async fn spawn(&mut self) -> Result<(), CliError> {
+        Err(CliError::generic(
+            "Interactive shells need a pseudo-terminal, which is only supported on Unix."
+                .to_string(),
+        ))
     }
 
     /// Send input to the PTY
     pub async fn send(&mut self, input: &str) -> Result<(), CliError> {
-        if let Some(ref mut stdin) = self.stdin {
-            stdin
-                .write_all(input.as_bytes())
-                .await
-                .map_err(|e| CliError::Generic(format!("Failed to write to PTY: {}", e)))?;
-            stdin
-                .flush()
-                .await
-                .map_err(|e| CliError::Generic(format!("Failed to flush PTY: {}", e)))?;
-            self.last_activity = Instant::now();
-            Ok(())
-        } else {
-            Err(CliError::Generic("PTY not initialized".to_string()))
+        let pty = self.pty.as_mut().ok_or_else(not_initialized)?;
+        pty.write_all(input.as_bytes())
+            .await
+            .map_err(|e| CliError::generic(format!("Failed to write to PTY: {}", e)))?;
+        pty.flush()
+            .await
+            .map_err(|e| CliError::generic(format!("Failed to flush PTY: {}", e)))?;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.input(input);
         }
+        self.last_activity = Instant::now();
+        Ok(())
     }
 
     /// Send a line followed by newline to the PTY
@@ -159,92 +344,59 @@ impl PtySession {
         self.send(&format!("{}\n", line)).await
     }
 
-    /// Read output from the PTY
+    /// Read output from the PTY. Once the shell has exited this reports its
+    /// status, then empty output.
     pub async fn read(&mut self) -> Result<PtyOutput, CliError> {
-        if let Some(ref mut stdout) = self.stdout {
-            let mut buf = [0u8; 4096];
-            let n = stdout
-                .read(&mut buf)
-                .await
-                .map_err(|e| CliError::Generic(format!("Failed to read from PTY: {}", e)))?;
-
-            if n > 0 {
-                let content = String::from_utf8_lossy(&buf[..n]).to_string();
-                self.buffer.extend(buf[..n].iter().cloned());
+        if self.exit.is_some() {
+            return Ok(PtyOutput::new(String::new(), false));
+        }
+        let pty = self.pty.as_mut().ok_or_else(not_initialized)?;
+        let mut buf = [0u8; 4096];
+        let n = match pty.read(&mut buf).await {
+            Ok(n) => n,
+            // Linux reports EIO instead of EOF once the terminal end is closed.
+            #[cfg(unix)]
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => 0,
+            Err(e) => return Err(CliError::generic(format!("Failed to read from PTY: {}", e))),
+        };
 
-                // Extract complete lines
-                while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
-                    let line_bytes: Vec<u8> = self.buffer.drain(..=pos).collect();
-                    if let Ok(line) = String::from_utf8(line_bytes) {
-                        self.output_lines.push_back(line);
-                    }
-                }
+        if n == 0 {
+            let exit = self.wait().await?;
+            let cut = String::from_utf8_lossy(&std::mem::take(&mut self.partial)).into_owned();
+            return Ok(PtyOutput::new(
+                format!("{cut}\n[Process exited with {}]", exit),
+                false,
+            ));
+        }
 
-                self.last_activity = Instant::now();
+        let content = decode_utf8(&mut self.partial, &buf[..n]);
+        self.buffer.extend(buf[..n].iter().cloned());
 
-                // Check if we're waiting for input (no activity in timeout)
-                let waiting_for_input = self.last_activity.elapsed()
-                    > Duration::from_secs(self.config.prompt_timeout);
+        // Extract complete lines
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line_bytes: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line_bytes).into_owned();
+            self.buffered += line.len();
+            self.output_lines.push_back(line);
+        }
+        // Drop the oldest output once over the limit.
+        let max = self.config.max_buffer_size;
+        while self.buffered + self.buffer.len() > max
+            && let Some(line) = self.output_lines.pop_front()
+        {
+            self.buffered -= line.len();
+        }
+        if self.buffer.len() > max {
+            self.buffer.drain(..self.buffer.len() - max);
+        }
 
-                Ok(PtyOutput {
-                    content,
-                    is_stderr: false,
-                    timestamp: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    waiting_for_input,
-                })
-            } else {
-                // Check if the child has exited
-                if let Some(ref mut child) = self.child {
-                    match child.try_wait() {
-                        Ok(Some(status)) => {
-                            self.state = PtyState::Exited;
-                            Ok(PtyOutput {
-                                content: format!("\n[Process exited with status: {}]", status),
-                                is_stderr: false,
-                                timestamp: std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs(),
-                                waiting_for_input: false,
-                            })
-                        }
-                        Ok(None) => {
-                            // Still running, check for timeout
-                            let waiting_for_input = self.last_activity.elapsed()
-                                > Duration::from_secs(self.config.prompt_timeout);
-                            if waiting_for_input {
-                                self.state = PtyState::Waiting;
-                            }
-                            Ok(PtyOutput {
-                                content: String::new(),
-                                is_stderr: false,
-                                timestamp: std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs(),
-                                waiting_for_input,
-                            })
-                        }
-                        Err(e) => Err(CliError::Generic(format!("Failed to check child status: {}", e))),
-                    }
-                } else {
-                    Ok(PtyOutput {
-                        content: String::new(),
-                        is_stderr: false,
-                        timestamp: std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs(),
-                        waiting_for_input: false,
-                    })
-                }
-            }
-        } else {
-            Err(CliError::Generic("PTY not initialized".to_string()))
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.output(&content);
         }
+        // Nobody listening is fine.
+        let _ = self.output.send(content.clone());
+        self.last_activity = Instant::now();
+        Ok(PtyOutput::new(content, false))
     }
 
     /// Read with a timeout
@@ -258,49 +410,191 @@ impl PtySession {
                 if waiting_for_input {
                     self.state = PtyState::Waiting;
                 }
-                Ok(PtyOutput {
-                    content: String::new(),
-                    is_stderr: false,
-                    timestamp: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    waiting_for_input,
-                })
+                Ok(PtyOutput::new(String::new(), waiting_for_input))
             }
         }
     }
 
-    /// Send a command and wait for completion
-    pub async fn execute(&mut self, command: &str) -> Result<String, CliError> {
+    /// Run `command` and wait until it finishes, stops at an input prompt,
+    /// or the prompt timeout passes. Several lines run as one command.
+    pub async fn execute(&mut self, command: &str) -> Result<CommandOutput, CliError> {
+        if self.marker.is_none() {
+            self.frame().await?;
+        }
+        if self.busy {
+            return Err(CliError::usage(
+                "The previous command is still running; answer it, wait for it, or interrupt it first."
+                    .to_string(),
+            ));
+        }
+        let line = if command.contains('\n') {
+            format!("{{\n{command}\n}}")
+        } else {
+            command.to_string()
+        };
+        self.busy = true;
         self.state = PtyState::Running;
-        self.send_line(command).await?;
+        self.send_line(&line).await?;
+        self.collect().await
+    }
 
-        let mut output = String::new();
-        let mut waiting_count = 0;
+    /// Answer a command waiting for input, then wait for it again
+    pub async fn respond(&mut self, input: &str) -> Result<CommandOutput, CliError> {
+        if !self.busy {
+            return Err(CliError::usage("No command is waiting for input.".to_string()));
+        }
+        self.state = PtyState::Running;
+        self.send_line(input).await?;
+        self.collect().await
+    }
 
-        loop {
-            let pty_output = self.read_timeout(100).await?;
+    /// Keep waiting for a command that timed out or stopped at a prompt
+    pub async fn wait_for_command(&mut self) -> Result<CommandOutput, CliError> {
+        if !self.busy {
+            return Err(CliError::usage("No command is running.".to_string()));
+        }
+        self.collect().await
+    }
 
-            if !pty_output.content.is_empty() {
-                output.push_str(&pty_output.content);
-                waiting_count = 0;
-            }
+    /// Use `timeout` seconds as the prompt timeout, if given; returns the
+    /// previous one
+    fn override_timeout(&mut self, timeout: Option<u64>) -> u64 {
+        let previous = self.config.prompt_timeout;
+        if let Some(timeout) = timeout {
+            self.config.prompt_timeout = timeout;
+        }
+        previous
+    }
 
-            if pty_output.waiting_for_input {
-                waiting_count += 1;
-                if waiting_count > 5 {
-                    // Consistently waiting for input, assume command is done
-                    break;
+    /// Switch the shell to framed commands: no echo, and a prompt made of a
+    /// per-session marker and `$?`.
+    async fn frame(&mut self) -> Result<(), CliError> {
+        self.prompts = self
+            .config
+            .input_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| {
+                    CliError::usage(format!("Invalid input pattern `{pattern}`: {e}"))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let nonce = &uuid::Uuid::new_v4().simple().to_string()[..12];
+        self.marker =
+            Some(Regex::new(&format!("__STARBOTT_{nonce}_([0-9]+)__")).expect("valid marker"));
+        self.busy = true;
+        self.send_line(&format!(
+            "stty -echo; bind 'set enable-bracketed-paste off' 2>/dev/null; \
+             unset PROMPT_COMMAND; PS2=''; PS1='__STARBOTT_{nonce}_$?__'"
+        ))
+        .await?;
+        match self.collect().await?.status {
+            CommandStatus::Exited { .. } => Ok(()),
+            status => Err(CliError::generic(format!(
+                "The shell did not take the command prompt: {status}"
+            ))),
+        }
+    }
+
+    /// Read until the marker, an input prompt, the timeout, or the shell's exit.
+    async fn collect(&mut self) -> Result<CommandOutput, CliError> {
+        let marker = self.marker.clone().ok_or_else(not_initialized)?;
+        let deadline = Instant::now() + Duration::from_secs(self.config.prompt_timeout);
+        let mut output = String::new();
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(self.stopped(output, CommandStatus::TimedOut));
+            }
+            let Ok(read) = tokio::time::timeout(left.min(INPUT_SETTLE), self.read()).await else {
+                if let Some(prompt) = self.input_prompt(&output) {
+                    self.state = PtyState::Waiting;
+                    return Ok(self.stopped(output, CommandStatus::NeedsInput { prompt }));
                 }
+                continue;
+            };
+            let read = read?;
+            if let Some(exit) = self.exit {
+                self.busy = false;
+                return Ok(self.stopped(output, CommandStatus::ShellExited { exit }));
             }
 
-            if self.state == PtyState::Exited {
-                break;
+            // Only the tail can hold a marker split across reads.
+            let mut from = output.len().saturating_sub(64);
+            while !output.is_char_boundary(from) {
+                from -= 1;
+            }
+            output.push_str(&read.content);
+            if let Some(found) = marker.captures_at(&output, from) {
+                let exit_code = found[1].parse().unwrap_or(-1);
+                // Anything after the marker is the idle shell.
+                output.truncate(found.get(0).map_or(output.len(), |m| m.start()));
+                self.busy = false;
+                self.state = PtyState::Ready;
+                return Ok(self.stopped(output, CommandStatus::Exited { exit_code }));
             }
         }
+    }
 
-        Ok(output)
+    /// The unfinished last line of `output`, if it looks like a prompt.
+    fn input_prompt(&self, output: &str) -> Option<String> {
+        let last = output.rsplit('\n').next().unwrap_or_default();
+        let last = last.rsplit('\r').find(|part| !part.is_empty()).unwrap_or_default();
+        (!last.trim().is_empty() && self.prompts.iter().any(|re| re.is_match(last)))
+            .then(|| last.trim().to_string())
+    }
+
+    /// Tidy terminal line endings and keep at most `max_buffer_size` bytes,
+    /// from the end.
+    fn stopped(&self, output: String, status: CommandStatus) -> CommandOutput {
+        let mut output = output
+            .replace("\r\n", "\n")
+            .replace("\x1b[?2004h", "")
+            .replace("\x1b[?2004l", "");
+        if output.len() > self.config.max_buffer_size {
+            let mut start = output.len() - self.config.max_buffer_size;
+            while !output.is_char_boundary(start) {
+                start += 1;
+            }
+            output.drain(..start);
+        }
+        CommandOutput { status, output }
+    }
+
+    /// Wait for the shell to exit
+    pub async fn wait(&mut self) -> Result<PtyExit, CliError> {
+        if let Some(exit) = self.exit {
+            return Ok(exit);
+        }
+        let child = self.child.as_mut().ok_or_else(not_initialized)?;
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| CliError::generic(format!("Failed to wait for PTY: {}", e)))?;
+        Ok(self.exited(status))
+    }
+
+    /// The shell's exit status, if it has exited
+    pub fn try_wait(&mut self) -> Result<Option<PtyExit>, CliError> {
+        if self.exit.is_some() {
+            return Ok(self.exit);
+        }
+        let Some(child) = self.child.as_mut() else {
+            return Ok(None);
+        };
+        match child.try_wait() {
+            Ok(Some(status)) => Ok(Some(self.exited(status))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(CliError::generic(format!("Failed to check child status: {}", e))),
+        }
+    }
+
+    fn exited(&mut self, status: ExitStatus) -> PtyExit {
+        let exit = PtyExit::from(status);
+        self.exit = Some(exit);
+        self.state = PtyState::Exited;
+        exit
     }
 
     /// Get the current state
@@ -310,7 +604,17 @@ impl PtySession {
 
     /// Check if the PTY is ready for input
     pub fn is_ready(&self) -> bool {
-        matches!(self.state, PtyState::Running | PtyState::Waiting)
+        self.pty.is_some() && !matches!(self.state, PtyState::Exited | PtyState::Error)
+    }
+
+    /// Whether a framed command is running or waiting for input
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    /// When the session last had input or output
+    pub fn last_activity(&self) -> Instant {
+        self.last_activity
     }
 
     /// Get all buffered output lines
@@ -318,43 +622,195 @@ impl PtySession {
         self.output_lines.iter().cloned().collect()
     }
 
+    /// Take the unread output, including an unfinished last line
+    pub fn take_output(&mut self) -> String {
+        let mut output: String = self.output_lines.drain(..).collect();
+        output.push_str(&String::from_utf8_lossy(self.buffer.make_contiguous()));
+        self.clear_output();
+        output
+    }
+
     /// Clear the output buffer
     pub fn clear_output(&mut self) {
         self.output_lines.clear();
         self.buffer.clear();
+        self.buffered = 0;
+    }
+
+    /// ID of the session's recording, if it is recorded
+    pub fn recording_id(&self) -> Option<&str> {
+        self.recorder.as_ref().map(Recorder::id)
     }
 
-    /// Resize the terminal
-    pub fn resize(&mut self, columns: u16, rows: u16) {
+    /// Output as it is read, from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.output.subscribe()
+    }
+
+    /// Resize the terminal; the foreground program gets SIGWINCH
+    pub fn resize(&mut self, columns: u16, rows: u16) -> Result<(), CliError> {
         self.config.columns = columns;
         self.config.rows = rows;
-        // In a real PTY implementation, we would use ioctl to resize
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.resize(columns, rows);
+        }
+        #[cfg(unix)]
+        if let Some(pty) = &self.pty {
+            pty.resize(Size::new(rows, columns))
+                .map_err(|e| CliError::generic(format!("Failed to resize PTY: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    /// Send `signal` to the terminal's foreground process group: the command
+    /// the shell is running, or the shell itself when idle.
+    #[cfg(unix)]
+    pub fn signal(&self, signal: i32) -> Result<(), CliError> {
+        use std::os::fd::AsRawFd;
+
+        let pty = self.pty.as_ref().ok_or_else(not_initialized)?;
+        // Safety: plain syscalls on a file descriptor we own.
+        let group = unsafe { libc::tcgetpgrp(pty.as_raw_fd()) };
+        if group <= 0 || unsafe { libc::kill(-group, signal) } == -1 {
+            return Err(CliError::generic(format!(
+                "Failed to signal PTY: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Ctrl-C: SIGINT to the foreground process group
+    #[cfg(unix)]
+    pub fn interrupt(&self) -> Result<(), CliError> {
+        self.signal(libc::SIGINT)
+    }
+
+    /// PTYs are Unix-only.
+    #[cfg(not(unix))]
+    pub fn interrupt(&self) -> Result<(), CliError> {
+        Err(not_initialized())
     }
 
-    /// Kill the PTY session
+    /// Kill the shell and close the PTY
     pub async fn kill(&mut self) -> Result<(), CliError> {
-        if let Some(mut child) = self.child.take() {
+        if self.exit.is_none()
+            && let Some(child) = self.child.as_mut()
+        {
             child
                 .kill()
                 .await
-                .map_err(|e| CliError::Generic(format!("Failed to kill PTY: {}", e)))?;
-            self.state = PtyState::Exited;
+                .map_err(|e| CliError::generic(format!("Failed to kill PTY: {}", e)))?;
+            if let Ok(Some(status)) = child.try_wait() {
+                self.exited(status);
+            }
         }
+        self.pty = None;
+        self.state = PtyState::Exited;
         Ok(())
     }
 }
 
 impl Drop for PtySession {
     fn drop(&mut self) {
-        if let Some(mut child) = self.child.take() {
-            let _ = child.kill();
+        // Closing the PTY hangs up the shell's session; the kill covers
+        // shells that ignore SIGHUP.
+        self.pty = None;
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.start_kill();
         }
     }
 }
 
-/// PTY manager for managing multiple sessions by ID
+fn not_initialized() -> CliError {
+    CliError::generic("PTY not initialized".to_string())
+}
+
+/// Decode `partial` followed by `chunk`, replacing invalid sequences like
+/// `from_utf8_lossy` but keeping an incomplete one at the end in `partial`
+/// for the next read.
+fn decode_utf8(partial: &mut Vec<u8>, chunk: &[u8]) -> String {
+    partial.extend_from_slice(chunk);
+    let bytes = std::mem::take(partial);
+    let mut text = String::with_capacity(bytes.len());
+    let mut rest = &bytes[..];
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                break;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).expect("valid prefix"));
+                match e.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[len..];
+                    }
+                    None => {
+                        partial.extend_from_slice(after);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    text
+}
+
+/// A request to a session's task, with the channel for its answer
+enum Request {
+    Send(String, oneshot::Sender<Result<(), CliError>>),
+    Read(oneshot::Sender<PtyOutput>),
+    /// Run a command, waiting up to the given seconds instead of the prompt timeout
+    Execute(String, Option<u64>, oneshot::Sender<Result<CommandOutput, CliError>>),
+    Respond(String, Option<u64>, oneshot::Sender<Result<CommandOutput, CliError>>),
+    Wait(Option<u64>, oneshot::Sender<Result<CommandOutput, CliError>>),
+    Resize(u16, u16, oneshot::Sender<Result<(), CliError>>),
+    Interrupt(oneshot::Sender<Result<(), CliError>>),
+    Kill(oneshot::Sender<Result<(), CliError>>),
+}
+
+/// A session as `list_sessions` reports it
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub state: PtyState,
+    /// Seconds since the session was created
+    pub age_secs: u64,
+    /// Seconds since its last input or output
+    pub idle_secs: u64,
+    /// ID of its recording, for `starbott sessions replay`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recording: Option<String>,
+}
+
+/// What a session's task last reported, readable without asking it
+#[derive(Debug)]
+struct Status {
+    state: PtyState,
+    created: Instant,
+    last_activity: Instant,
+    recording: Option<String>,
+}
+
+/// The manager's end of a session's task
+#[derive(Debug, Clone)]
+struct SessionHandle {
+    requests: mpsc::Sender<Request>,
+    output: broadcast::Sender<String>,
+    status: Arc<Mutex<Status>>,
+}
+
+type Sessions = Arc<Mutex<HashMap<String, SessionHandle>>>;
+
+/// PTY manager for managing multiple sessions by ID. Each session is a task
+/// that owns its PTY, so a long command in one shell never holds up another;
+/// requests to the same session are handled in order.
+#[derive(Debug)]
 pub struct PtyManager {
-    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    sessions: Sessions,
     default_config: PtyConfig,
 }
 
@@ -373,57 +829,269 @@ impl PtyManager {
         let mut session = PtySession::new(self.default_config.clone());
         session.spawn().await?;
 
-        let mut sessions = self.sessions.lock().unwrap();
-        sessions.insert(session_id.clone(), session);
+        let (requests, inbox) = mpsc::channel(16);
+        let now = Instant::now();
+        let status = Arc::new(Mutex::new(Status {
+            state: session.state,
+            created: now,
+            last_activity: now,
+            recording: session.recording_id().map(str::to_string),
+        }));
+        let handle = SessionHandle {
+            requests,
+            output: session.output.clone(),
+            status: status.clone(),
+        };
+        tokio::spawn(run_session(
+            session,
+            inbox,
+            status,
+            Arc::downgrade(&self.sessions),
+            session_id.clone(),
+        ));
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.clone(), handle);
 
         Ok(session_id)
     }
 
     /// Send input to a session
     pub async fn send(&self, session_id: &str, input: &str) -> Result<(), CliError> {
-        let mut sessions = self.sessions.lock().unwrap();
-        if let Some(session) = sessions.get_mut(session_id) {
-            session.send(input).await?;
-            Ok(())
-        } else {
-            Err(CliError::Generic(format!("PTY session not found: {}", session_id)))
-        }
+        let input = input.to_string();
+        self.request(session_id, |reply| Request::Send(input, reply)).await?
     }
 
-    /// Read from a session
+    /// Take a session's unread output
     pub async fn read(&self, session_id: &str) -> Result<PtyOutput, CliError> {
-        let mut sessions = self.sessions.lock().unwrap();
-        if let Some(session) = sessions.get_mut(session_id) {
-            session.read().await
-        } else {
-            Err(CliError::Generic(format!("PTY session not found: {}", session_id)))
-        }
+        self.request(session_id, Request::Read).await
     }
 
     /// Execute a command in a session
-    pub async fn execute(&self, session_id: &str, command: &str) -> Result<String, CliError> {
-        let mut sessions = self.sessions.lock().unwrap();
-        if let Some(session) = sessions.get_mut(session_id) {
-            session.execute(command).await
-        } else {
-            Err(CliError::Generic(format!("PTY session not found: {}", session_id)))
-        }
+    pub async fn execute(&self, session_id: &str, command: &str) -> Result<CommandOutput, CliError> {
+        self.execute_for(session_id, command, None).await
+    }
+
+    /// Execute a command, waiting up to `timeout` seconds instead of the
+    /// session's prompt timeout
+    pub async fn execute_for(
+        &self,
+        session_id: &str,
+        command: &str,
+        timeout: Option<u64>,
+    ) -> Result<CommandOutput, CliError> {
+        let command = command.to_string();
+        self.request(session_id, |reply| Request::Execute(command, timeout, reply)).await?
+    }
+
+    /// Answer a command waiting for input in a session
+    pub async fn respond(
+        &self,
+        session_id: &str,
+        input: &str,
+        timeout: Option<u64>,
+    ) -> Result<CommandOutput, CliError> {
+        let input = input.to_string();
+        self.request(session_id, |reply| Request::Respond(input, timeout, reply)).await?
+    }
+
+    /// Keep waiting for a session's command that timed out or stopped at a prompt
+    pub async fn wait_for_command(
+        &self,
+        session_id: &str,
+        timeout: Option<u64>,
+    ) -> Result<CommandOutput, CliError> {
+        self.request(session_id, |reply| Request::Wait(timeout, reply)).await?
+    }
+
+    /// Resize a session's terminal
+    pub async fn resize(&self, session_id: &str, columns: u16, rows: u16) -> Result<(), CliError> {
+        self.request(session_id, |reply| Request::Resize(columns, rows, reply)).await?
+    }
+
+    /// Ctrl-C to a session's running command, once its current request is done
+    pub async fn interrupt(&self, session_id: &str) -> Result<(), CliError> {
+        self.request(session_id, Request::Interrupt).await?
+    }
+
+    /// A session's output as it is read, from now on
+    pub fn subscribe(&self, session_id: &str) -> Result<broadcast::Receiver<String>, CliError> {
+        Ok(self.handle(session_id)?.output.subscribe())
     }
 
     /// Kill a session and remove it
     pub async fn kill_session(&self, session_id: &str) -> Result<(), CliError> {
-        let mut sessions = self.sessions.lock().unwrap();
-        if let Some(mut session) = sessions.remove(session_id) {
-            session.kill().await?;
+        let handle = self.sessions.lock().unwrap().remove(session_id);
+        if let Some(handle) = handle {
+            let (reply, answer) = oneshot::channel();
+            // A task that is already gone has closed its session.
+            if handle.requests.send(Request::Kill(reply)).await.is_ok() {
+                answer.await.unwrap_or(Ok(()))?;
+            }
         }
         Ok(())
     }
 
-    /// List active session IDs
-    pub fn list_sessions(&self) -> Vec<String> {
+    /// Kill every session; returns how many there were
+    pub async fn close_all(&self) -> usize {
+        let ids: Vec<String> = self.sessions.lock().unwrap().keys().cloned().collect();
+        for id in &ids {
+            let _ = self.kill_session(id).await;
+        }
+        ids.len()
+    }
+
+    /// List sessions with their state and age, oldest first
+    pub fn list_sessions(&self) -> Vec<SessionInfo> {
         let sessions = self.sessions.lock().unwrap();
-        sessions.keys().cloned().collect()
+        let mut list: Vec<(Instant, SessionInfo)> = sessions
+            .iter()
+            .map(|(id, handle)| {
+                let status = handle.status.lock().unwrap();
+                let info = SessionInfo {
+                    id: id.clone(),
+                    state: status.state,
+                    age_secs: status.created.elapsed().as_secs(),
+                    idle_secs: status.last_activity.elapsed().as_secs(),
+                    recording: status.recording.clone(),
+                };
+                (status.created, info)
+            })
+            .collect();
+        list.sort_by_key(|(created, _)| *created);
+        list.into_iter().map(|(_, info)| info).collect()
+    }
+
+    fn handle(&self, session_id: &str) -> Result<SessionHandle, CliError> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| CliError::generic(format!("PTY session not found: {}", session_id)))
+    }
+
+    /// Send a request to a session's task and wait for the answer. The
+    /// sessions lock is only held to look the handle up, never across an await.
+    async fn request<T>(
+        &self,
+        session_id: &str,
+        request: impl FnOnce(oneshot::Sender<T>) -> Request,
+    ) -> Result<T, CliError> {
+        let handle = self.handle(session_id)?;
+        let closed =
+            || CliError::generic(format!("PTY session {} has closed", session_id));
+        let (reply, answer) = oneshot::channel();
+        handle
+            .requests
+            .send(request(reply))
+            .await
+            .map_err(|_| closed())?;
+        answer.await.map_err(|_| closed())
+    }
+}
+
+/// A session's task: it answers requests in order, reads output between
+/// them, and closes the session once it has been idle for `idle_timeout`,
+/// is killed, or the manager is dropped. A command that outlived its
+/// timeout and is still running (a quiet build, say) keeps the session
+/// open; one stopped at a prompt does not.
+async fn run_session(
+    mut session: PtySession,
+    mut inbox: mpsc::Receiver<Request>,
+    status: Arc<Mutex<Status>>,
+    sessions: std::sync::Weak<Mutex<HashMap<String, SessionHandle>>>,
+    id: String,
+) {
+    let idle = Duration::from_secs(session.config.idle_timeout);
+    loop {
+        let reap_at = tokio::time::Instant::from_std(session.last_activity() + idle);
+        let reapable = !session.busy || session.state == PtyState::Waiting;
+        let open = tokio::select! {
+            request = inbox.recv() => match request {
+                Some(request) => answer(&mut session, request, &status).await,
+                None => false,
+            },
+            // A busy session's output belongs to the command's result.
+            read = session.read(), if session.exit.is_none() && !session.busy => {
+                if read.is_err() {
+                    session.state = PtyState::Error;
+                }
+                read.is_ok()
+            }
+            _ = tokio::time::sleep_until(reap_at), if reapable => {
+                if let Some(sessions) = sessions.upgrade() {
+                    sessions.lock().unwrap().remove(&id);
+                }
+                false
+            }
+        };
+
+        let mut status = status.lock().unwrap();
+        status.state = session.state;
+        status.last_activity = session.last_activity();
+        if !open {
+            break;
+        }
+    }
+    let _ = session.kill().await;
+    status.lock().unwrap().state = PtyState::Exited;
+}
+
+/// Handle one request; false once the session is killed.
+async fn answer(session: &mut PtySession, request: Request, status: &Mutex<Status>) -> bool {
+    if matches!(request, Request::Execute(..) | Request::Respond(..) | Request::Wait(..)) {
+        status.lock().unwrap().state = PtyState::Running;
+    }
+    match request {
+        Request::Send(input, reply) => {
+            let _ = reply.send(session.send(&input).await);
+        }
+        Request::Read(reply) => {
+            let waiting = session.state == PtyState::Waiting;
+            let _ = reply.send(PtyOutput::new(session.take_output(), waiting));
+        }
+        Request::Execute(command, timeout, reply) => {
+            let default = session.override_timeout(timeout);
+            let result = session.execute(&command).await;
+            session.config.prompt_timeout = default;
+            // The caller gets this output in the result.
+            session.clear_output();
+            let _ = reply.send(result);
+        }
+        Request::Respond(input, timeout, reply) => {
+            let default = session.override_timeout(timeout);
+            let result = session.respond(&input).await;
+            session.config.prompt_timeout = default;
+            session.clear_output();
+            let _ = reply.send(result);
+        }
+        Request::Wait(timeout, reply) => {
+            let default = session.override_timeout(timeout);
+            let result = session.wait_for_command().await;
+            session.config.prompt_timeout = default;
+            session.clear_output();
+            let _ = reply.send(result);
+        }
+        Request::Resize(columns, rows, reply) => {
+            let _ = reply.send(session.resize(columns, rows));
+        }
+        Request::Interrupt(reply) => {
+            // Interrupting an idle shell would print a stray prompt marker.
+            let result = if session.busy {
+                session.interrupt()
+            } else {
+                Err(CliError::usage("No command is running.".to_string()))
+            };
+            let _ = reply.send(result);
+        }
+        Request::Kill(reply) => {
+            let _ = reply.send(session.kill().await);
+            return false;
+        }
     }
+    true
 }
 
 /// Interactive shell helper
@@ -446,16 +1114,12 @@ impl InteractiveShell {
     /// Initialize the shell
     pub async fn initialize(&mut self) -> Result<(), CliError> {
         self.pty.spawn().await?;
-
-        // Set up the shell environment
-        self.pty.send_line("export HISTCONTROL=ignoreboth").await?;
-        self.pty.send_line("export PS1='\\$ '").await?;
-
+        self.pty.execute("export HISTCONTROL=ignoreboth").await?;
         Ok(())
     }
 
     /// Execute a command and capture output
-    pub async fn execute(&mut self, command: &str) -> Result<String, CliError> {
+    pub async fn execute(&mut self, command: &str) -> Result<CommandOutput, CliError> {
         // Add to history
         self.history.push(command.to_string());
         self.history_index = self.history.len();
@@ -520,9 +1184,275 @@ mod tests {
         assert_eq!(config.rows, 24);
     }
 
+    #[test]
+    fn test_decode_utf8_holds_back_a_split_character() {
+        let mut partial = Vec::new();
+        let bytes = "né✓".as_bytes();
+        assert_eq!(decode_utf8(&mut partial, &bytes[..2]), "n");
+        assert_eq!(decode_utf8(&mut partial, &bytes[2..4]), "é");
+        assert_eq!(partial, &bytes[3..4]);
+        assert_eq!(decode_utf8(&mut partial, &bytes[4..]), "✓");
+        assert!(partial.is_empty());
+
+        // Invalid bytes are still replaced rather than held.
+        assert_eq!(decode_utf8(&mut partial, b"a\xffb\xe2"), "a\u{FFFD}b");
+        assert_eq!(partial, b"\xe2");
+    }
+
     #[tokio::test]
     async fn test_pty_session_creation() {
         let session = PtySession::new(PtyConfig::default());
         assert_eq!(session.state(), &PtyState::Ready);
     }
+
+    #[cfg(unix)]
+    async fn sh() -> PtySession {
+        let mut session = PtySession::new(PtyConfig {
+            shell: "/bin/sh".to_string(),
+            ..Default::default()
+        });
+        session.spawn().await.unwrap();
+        session
+    }
+
+    /// Read until `needle` shows up. Tests compute it with `$((...))` so the
+    /// echoed command line does not match.
+    #[cfg(unix)]
+    async fn read_until(session: &mut PtySession, needle: &str) -> String {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut out = String::new();
+        while !out.contains(needle) {
+            assert!(Instant::now() < deadline, "no {needle:?} in {out:?}");
+            out.push_str(&session.read_timeout(200).await.unwrap().content);
+        }
+        out
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pty_is_a_terminal_with_merged_stderr() {
+        let mut session = sh().await;
+        session.send_line("tty -s && echo tty-$((1+1))").await.unwrap();
+        read_until(&mut session, "tty-2").await;
+        session.send_line("echo err-$((2+3)) >&2").await.unwrap();
+        read_until(&mut session, "err-5").await;
+
+        session.resize(120, 40).unwrap();
+        session.send_line("echo size-$((1+1)) $(stty size)").await.unwrap();
+        assert!(read_until(&mut session, "size-2 40 120").await.contains("size-2 40 120"));
+
+        session.send_line("exit 3").await.unwrap();
+        let exit = session.wait().await.unwrap();
+        assert_eq!(exit.code, Some(3));
+        assert_eq!(exit.to_string(), "exit code 3");
+        assert_eq!(session.state(), &PtyState::Exited);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pty_interrupt_reaches_the_foreground_command() {
+        let mut session = sh().await;
+        session.send_line("echo ready-$((1+1)); exec sleep 30").await.unwrap();
+        read_until(&mut session, "ready-2").await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        session.interrupt().unwrap();
+        let exit = tokio::time::timeout(Duration::from_secs(10), session.wait())
+            .await
+            .expect("sleep was interrupted")
+            .unwrap();
+        assert_eq!(exit.signal, Some(libc::SIGINT));
+        assert!(!exit.success());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_reports_exit_codes() {
+        for shell in ["/bin/sh", "/bin/bash"] {
+            if !std::path::Path::new(shell).exists() {
+                continue;
+            }
+            let mut session = PtySession::new(PtyConfig {
+                shell: shell.to_string(),
+                prompt_timeout: 10,
+                ..Default::default()
+            });
+            session.spawn().await.unwrap();
+
+            let ok = session.execute("echo hello; echo oops >&2").await.unwrap();
+            assert_eq!(ok.status, CommandStatus::Exited { exit_code: 0 }, "{shell}");
+            assert_eq!(ok.output, "hello\noops\n", "{shell}");
+            assert!(ok.success());
+
+            // Slow output is waited for, not cut off at a pause.
+            let slow = session.execute("sleep 1; echo done\nfalse").await.unwrap();
+            assert_eq!(slow.status, CommandStatus::Exited { exit_code: 1 }, "{shell}");
+            assert_eq!(slow.output, "done\n", "{shell}");
+
+            let exited = session.execute("exit 4").await.unwrap();
+            assert_eq!(
+                exited.status.to_string(),
+                "shell exited with exit code 4",
+                "{shell}"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_stops_at_input_prompts() {
+        let mut session = PtySession::new(PtyConfig {
+            shell: "/bin/sh".to_string(),
+            prompt_timeout: 10,
+            ..Default::default()
+        });
+        session.spawn().await.unwrap();
+
+        let asked = session
+            .execute("printf 'Password: '; read secret; echo got-$secret")
+            .await
+            .unwrap();
+        assert_eq!(
+            asked.status,
+            CommandStatus::NeedsInput {
+                prompt: "Password:".to_string()
+            }
+        );
+        assert!(session.execute("true").await.is_err());
+
+        let answered = session.respond("hunter2").await.unwrap();
+        assert_eq!(answered.status, CommandStatus::Exited { exit_code: 0 });
+        assert_eq!(answered.output, "got-hunter2\n");
+        let json = serde_json::to_value(asked.then(answered)).unwrap();
+        assert_eq!(json["status"], "exited");
+        assert_eq!(json["exit_code"], 0);
+
+        // Nothing prompt-like: the command runs into the timeout instead.
+        session.config.prompt_timeout = 1;
+        let silent = session.execute("read line; echo $line").await.unwrap();
+        assert_eq!(silent.status, CommandStatus::TimedOut);
+        let done = session.respond("late").await.unwrap();
+        assert_eq!(done.output, "late\n");
+
+        // A pause after a question mark is not a prompt unless asked for.
+        session.config.prompt_timeout = 10;
+        let question = "printf 'Compiling demo?'; sleep 1; echo";
+        let paused = session.execute(question).await.unwrap();
+        assert_eq!(paused.status, CommandStatus::Exited { exit_code: 0 });
+        assert_eq!(paused.output, "Compiling demo?\n");
+
+        let mut config = session.config.clone();
+        config.input_patterns.push(r"\?\s*$".to_string());
+        let mut eager = PtySession::new(config);
+        eager.spawn().await.unwrap();
+        let asked = eager.execute(question).await.unwrap();
+        assert_eq!(
+            asked.status,
+            CommandStatus::NeedsInput {
+                prompt: "Compiling demo?".to_string()
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    fn sh_config() -> PtyConfig {
+        PtyConfig {
+            shell: "/bin/sh".to_string(),
+            prompt_timeout: 10,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_manager_sessions_run_concurrently() {
+        let manager = Arc::new(PtyManager::new(sh_config()));
+        let slow = manager.create_session().await.unwrap();
+        let fast = manager.create_session().await.unwrap();
+
+        let started = Instant::now();
+        let slow_run = tokio::spawn({
+            let manager = manager.clone();
+            let slow = slow.clone();
+            async move { manager.execute(&slow, "sleep 2; echo slow").await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let result = manager.execute(&fast, "echo fast").await.unwrap();
+        assert_eq!(result.output, "fast\n");
+        assert!(started.elapsed() < Duration::from_millis(1500));
+
+        let listed = manager.list_sessions();
+        assert_eq!(listed.len(), 2);
+        assert_eq!((listed[0].id.as_str(), listed[0].state), (slow.as_str(), PtyState::Running));
+        assert_eq!((listed[1].id.as_str(), listed[1].state), (fast.as_str(), PtyState::Ready));
+
+        assert_eq!(slow_run.await.unwrap().unwrap().output, "slow\n");
+        manager.kill_session(&slow).await.unwrap();
+        assert_eq!(manager.list_sessions().len(), 1);
+        assert!(manager.execute(&slow, "true").await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_manager_reaps_idle_sessions() {
+        let manager = PtyManager::new(PtyConfig {
+            idle_timeout: 1,
+            ..sh_config()
+        });
+        let id = manager.create_session().await.unwrap();
+        let mut output = manager.subscribe(&id).unwrap();
+        manager.send(&id, "echo out-$((20+22))\n").await.unwrap();
+        let mut seen = String::new();
+        while !seen.contains("out-42") {
+            seen.push_str(&output.recv().await.unwrap());
+        }
+        assert!(manager.read(&id).await.unwrap().content.contains("out-42"));
+
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+        assert!(manager.list_sessions().is_empty());
+        assert!(manager.read(&id).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_manager_keeps_sessions_with_a_running_command() {
+        let manager = PtyManager::new(PtyConfig {
+            idle_timeout: 1,
+            ..sh_config()
+        });
+        let id = manager.create_session().await.unwrap();
+        let quiet = manager.execute_for(&id, "sleep 3; echo built", Some(1)).await.unwrap();
+        assert_eq!(quiet.status, CommandStatus::TimedOut);
+
+        // Silent for longer than the idle timeout, but not idle.
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(manager.list_sessions().len(), 1);
+        let done = manager.wait_for_command(&id, Some(10)).await.unwrap();
+        assert_eq!(done.status, CommandStatus::Exited { exit_code: 0 });
+        assert_eq!(done.output, "built\n");
+
+        // Once the command is over the session can be reaped again.
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+        assert!(manager.list_sessions().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unread_output_is_bounded() {
+        let mut session = PtySession::new(PtyConfig {
+            max_buffer_size: 64,
+            ..sh_config()
+        });
+        session.spawn().await.unwrap();
+        session
+            .send_line("for i in 1 2 3 4 5 6 7 8 9; do echo line-$i-padding-padding; done; echo end-$((1+1))")
+            .await
+            .unwrap();
+        read_until(&mut session, "end-2").await;
+        let unread = session.take_output();
+        assert!(unread.len() <= 64, "{unread:?}");
+        assert!(unread.contains("end-2"), "{unread:?}");
+        assert!(!unread.contains("line-1-"), "{unread:?}");
+        assert!(session.take_output().is_empty());
+    }
 }
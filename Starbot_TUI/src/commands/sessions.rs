@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use clap::Subcommand;
+use serde_json::{Value, json};
+
+use crate::app::Runtime;
+use crate::conversations::{age, now_secs};
+use crate::errors::CliError;
+use crate::recording::{self, Recording};
+
+#[derive(Debug, Subcommand)]
+pub enum SessionsCommand {
+    /// List recorded agent shell sessions.
+    List,
+    /// Play a recorded shell session back in the terminal.
+    Replay {
+        /// Recording ID or unique prefix
+        id: String,
+        /// Playback speed; 2 plays twice as fast
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Longest pause between events, in seconds, before the speed applies
+        #[arg(long, default_value_t = 2.0)]
+        max_idle: f64,
+    },
+}
+
+pub async fn handle(runtime: &Runtime, command: SessionsCommand) -> Result<(), CliError> {
+    match command {
+        SessionsCommand::List => list(runtime),
+        SessionsCommand::Replay {
+            id,
+            speed,
+            max_idle,
+        } => replay(runtime, &id, speed, max_idle).await,
+    }
+}
+
+fn list(runtime: &Runtime) -> Result<(), CliError> {
+    let recordings = recording::recordings()?;
+    let now = now_secs();
+    let rows: Vec<Value> = recordings
+        .iter()
+        .map(|r| {
+            json!({
+                "id": r.id,
+                "started": age(r.header.timestamp, now),
+                "duration": format!("{:.1}s", r.duration),
+                "cwd": r.header.title.clone().unwrap_or_default(),
+            })
+        })
+        .collect();
+    runtime.output.print_list(
+        &json!({ "recordings": recordings }),
+        &rows,
+        &[
+            ("ID", "id"),
+            ("STARTED", "started"),
+            ("DURATION", "duration"),
+            ("CWD", "cwd"),
+        ],
+        "No recorded sessions. Shells the agent runs are recorded here.",
+    )
+}
+
+async fn replay(runtime: &Runtime, id: &str, speed: f64, max_idle: f64) -> Result<(), CliError> {
+    if !(speed > 0.0 && speed.is_finite()) {
+        return Err(CliError::usage(
+            "--speed must be greater than 0.".to_string(),
+        ));
+    }
+    if !(max_idle >= 0.0 && max_idle.is_finite()) {
+        return Err(CliError::usage(
+            "--max-idle cannot be negative.".to_string(),
+        ));
+    }
+    let recording = recording::find(recording::recordings()?, id)?;
+    let events = recording.events()?;
+    if runtime.output.json {
+        return runtime
+            .output
+            .print_json(&json!({ "recording": recording, "events": events }));
+    }
+
+    runtime.output.print_stderr(&header(&recording));
+    let mut stdout = io::stdout();
+    let mut last = 0.0;
+    for event in events.iter().filter(|event| event.1 == "o") {
+        let pause = (event.0 - last).clamp(0.0, max_idle) / speed;
+        last = event.0;
+        if pause > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(pause)).await;
+        }
+        stdout.write_all(event.2.as_bytes())?;
+        stdout.flush()?;
+    }
+    runtime
+        .output
+        .print_stderr(&format!("\n[End of recording {}]", recording.id));
+    Ok(())
+}
+
+fn header(recording: &Recording) -> String {
+    let mut line = format!(
+        "[Recording {}, {}x{}, {:.1}s",
+        recording.id, recording.header.width, recording.header.height, recording.duration
+    );
+    if let Some(cwd) = &recording.header.title {
+        line.push_str(&format!(", in {cwd}"));
+    }
+    line.push(']');
+    line
+}
@@ -186,42 +186,17 @@ async fn handle_create_task(api: &crate::api::ApiClient, args: TaskCreateArgs, r
 
 async fn handle_list_tasks(api: &crate::api::ApiClient, args: TaskListArgs, runtime: &Runtime) -> Result<(), CliError> {
     let tasks = api.list_tasks(args.status, args.limit).await?;
-
-    if runtime.output.json {
-        runtime.output.print_json(&json!({ "tasks": tasks }))?;
-    } else {
-        if tasks.is_empty() {
-            runtime.output.print_human("No tasks found.");
-            return Ok(());
-        }
-
-        runtime.output.print_human("Tasks:");
-        for task in tasks {
-            let status_icon = match task.status.as_str() {
-                "PENDING" => "⏳",
-                "IN_PROGRESS" => "🔄",
-                "COMPLETED" => "✅",
-                "CANCELLED" => "❌",
-                _ => "❓",
-            };
-
-            let priority_indicator = "⋅".repeat((task.priority.min(10)).max(0) as usize);
-
-            runtime.output.print_human(&format!(
-                "{} [{}] {} {}",
-                status_icon,
-                task.priority,
-                priority_indicator,
-                task.title
-            ));
-
-            if let Some(ref desc) = task.description {
-                runtime.output.print_human(&format!("    {}", desc));
-            }
-        }
-    }
-
-    Ok(())
+    let rows: Vec<serde_json::Value> = tasks
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()?;
+
+    runtime.output.print_list(
+        &json!({ "tasks": rows }),
+        &rows,
+        &[("ID", "id"), ("STATUS", "status"), ("PRIORITY", "priority"), ("TITLE", "title")],
+        "No tasks found.",
+    )
 }
 
 async fn handle_get_task(api: &crate::api::ApiClient, args: TaskGetArgs, runtime: &Runtime) -> Result<(), CliError> {
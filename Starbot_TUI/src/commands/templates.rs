@@ -0,0 +1,135 @@
+use std::fs;
+use std::process::Command;
+
+use clap::Subcommand;
+use serde_json::{Value, json};
+
+use crate::app::Runtime;
+use crate::errors::CliError;
+use crate::templates::{self, Source, Template, template_dirs, user_template_dir};
+
+const SKELETON: &str = "---\n# description: What this template is for\n# model: auto\n# lane: standard\n# temperature: 0.7\n# role: user\n---\n{{prompt}}\n";
+
+#[derive(Debug, Subcommand)]
+pub enum TemplatesCommand {
+    /// List templates from ./.starbott/templates and the config directory.
+    List,
+    /// Print a template.
+    Show {
+        /// Template name (file name without `.md`)
+        name: String,
+    },
+    /// Open a template in $VISUAL or $EDITOR, creating it if needed.
+    Edit {
+        /// Template name (file name without `.md`)
+        name: String,
+        /// Create new templates in the project's .starbott/templates
+        #[arg(long)]
+        project: bool,
+    },
+}
+
+pub async fn handle(runtime: &Runtime, command: TemplatesCommand) -> Result<(), CliError> {
+    match command {
+        TemplatesCommand::List => list(runtime),
+        TemplatesCommand::Show { name } => show(runtime, &name),
+        TemplatesCommand::Edit { name, project } => edit(runtime, &name, project),
+    }
+}
+
+fn list(runtime: &Runtime) -> Result<(), CliError> {
+    let rows: Vec<Value> = templates::list()?
+        .iter()
+        .map(|t| {
+            let mut row = serde_json::to_value(t).unwrap_or_default();
+            if let Some(obj) = row.as_object_mut() {
+                obj.remove("body");
+            }
+            row["variables"] = json!(t.variables.join(","));
+            row
+        })
+        .collect();
+    runtime.output.print_list(
+        &json!({ "templates": rows }),
+        &rows,
+        &[
+            ("NAME", "name"),
+            ("SOURCE", "source"),
+            ("DESCRIPTION", "description"),
+            ("VARIABLES", "variables"),
+        ],
+        "No templates. Create one with `starbott templates edit <name>`.",
+    )
+}
+
+fn show(runtime: &Runtime, name: &str) -> Result<(), CliError> {
+    let template = templates::find(name)?;
+    if runtime.output.json {
+        return runtime.output.print_json(&serde_json::to_value(&template)?);
+    }
+    runtime
+        .output
+        .print_stderr(&format!("# {}", template.path.display()));
+    runtime
+        .output
+        .print_human(&fs::read_to_string(&template.path)?);
+    Ok(())
+}
+
+fn edit(runtime: &Runtime, name: &str, project: bool) -> Result<(), CliError> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(CliError::usage(format!("Invalid template name `{name}`.")));
+    }
+
+    let existing = templates::find(name).ok().map(|t| t.path);
+    let path = match existing {
+        Some(path) if !project => path,
+        _ => {
+            let dir = if project {
+                std::env::current_dir()?.join(".starbott").join("templates")
+            } else {
+                template_dirs()?
+                    .into_iter()
+                    .find(|(source, _)| *source == Source::Project)
+                    .map_or(user_template_dir()?, |(_, dir)| dir)
+            };
+            fs::create_dir_all(&dir)?;
+            let path = dir.join(format!("{name}.md"));
+            if !path.exists() {
+                fs::write(&path, SKELETON)?;
+            }
+            path
+        }
+    };
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(parts)
+        .arg(&path)
+        .status()
+        .map_err(|err| {
+            CliError::generic(format!("Could not start editor `{editor}`: {err}"))
+                .with_hint("Set $VISUAL or $EDITOR.")
+        })?;
+    if !status.success() {
+        return Err(CliError::generic(format!(
+            "Editor `{editor}` exited with {status}."
+        )));
+    }
+
+    // Catch front-matter mistakes now rather than at the next chat.
+    Template::load_file(&path)?;
+    if runtime.output.json {
+        return runtime
+            .output
+            .print_json(&json!({ "ok": true, "path": path }));
+    }
+    runtime
+        .output
+        .print_human(&format!("Saved {}", path.display()));
+    Ok(())
+}
@@ -91,7 +91,7 @@ async fn propose(
 
     let tool_name = tool_name.trim().to_string();
     if tool_name.is_empty() {
-        return Err(CliError::Usage("--tool-name must be non-empty.".to_string()));
+        return Err(CliError::usage("--tool-name must be non-empty.".to_string()));
     }
 
     let input_json = read_input_json(input, input_file, stdin)?;
@@ -133,7 +133,7 @@ async fn propose(
         .unwrap_or("")
         .to_string();
     if proposal_id.is_empty() {
-        return Err(CliError::Server("Missing proposalId in response".to_string()));
+        return Err(CliError::server("Missing proposalId in response".to_string()));
     }
 
     let expires_at = res.json.get("expiresAt").and_then(|v| v.as_str()).unwrap_or("-");
@@ -153,7 +153,12 @@ async fn propose(
 
     if approve {
         let commit_res = api
-            .post_json("/v1/tools/commit", Some(json!({ "proposalId": proposal_id })), true)
+            .post_json_idempotent(
+                "/v1/tools/commit",
+                Some(json!({ "proposalId": proposal_id })),
+                true,
+                &commit_key(&proposal_id),
+            )
             .await?;
         runtime.output.print_human("committed.");
         runtime.output.print_human(&format_json(&commit_res.json)?);
@@ -171,15 +176,26 @@ async fn propose(
     Ok(())
 }
 
+/// A proposal runs at most once, so its id makes a stable idempotency key and
+/// lets a failed commit be retried safely.
+fn commit_key(proposal_id: &str) -> String {
+    format!("tools-commit-{proposal_id}")
+}
+
 async fn commit(runtime: &Runtime, proposal_id: String) -> Result<(), CliError> {
     let api = runtime.api_client()?;
     let pid = proposal_id.trim();
     if pid.is_empty() {
-        return Err(CliError::Usage("--proposal-id is required.".to_string()));
+        return Err(CliError::usage("--proposal-id is required.".to_string()));
     }
 
     let res = api
-        .post_json("/v1/tools/commit", Some(json!({ "proposalId": pid })), true)
+        .post_json_idempotent(
+            "/v1/tools/commit",
+            Some(json!({ "proposalId": pid })),
+            true,
+            &commit_key(pid),
+        )
         .await?;
 
     if runtime.output.json {
@@ -195,7 +211,7 @@ async fn deny(runtime: &Runtime, proposal_id: String, reason: Option<String>) ->
     let api = runtime.api_client()?;
     let pid = proposal_id.trim();
     if pid.is_empty() {
-        return Err(CliError::Usage("--proposal-id is required.".to_string()));
+        return Err(CliError::usage("--proposal-id is required.".to_string()));
     }
 
     let body = if let Some(r) = reason.map(|s| s.trim().to_string()).filter(|s| !s.is_empty()) {
@@ -234,27 +250,14 @@ async fn list_runs(
 
     let query_ref = if query.is_empty() { None } else { Some(query.as_slice()) };
     let res = api.get_json("/v1/tools/runs", query_ref, true).await?;
-
-    if runtime.output.json {
-        runtime.output.print_json(&res.json)?;
-        return Ok(());
-    }
-
     let runs = res.json.get("runs").and_then(|v| v.as_array()).cloned().unwrap_or_default();
-    if runs.is_empty() {
-        runtime.output.print_human("No tool runs.");
-        return Ok(());
-    }
 
-    for r in runs {
-        let tool = r.get("toolName").and_then(|v| v.as_str()).unwrap_or("-");
-        let status = r.get("status").and_then(|v| v.as_str()).unwrap_or("-");
-        let created = r.get("createdAt").and_then(|v| v.as_str()).unwrap_or("-");
-        let id = r.get("id").and_then(|v| v.as_str()).unwrap_or("-");
-        runtime.output.print_human(&format!("- {created} {tool} {status} ({id})"));
-    }
-
-    Ok(())
+    runtime.output.print_list(
+        &res.json,
+        &runs,
+        &[("CREATED", "createdAt"), ("TOOL", "toolName"), ("STATUS", "status"), ("ID", "id")],
+        "No tool runs.",
+    )
 }
 
 fn read_input_json(input: Option<String>, input_file: Option<PathBuf>, stdin: bool) -> Result<Value, CliError> {
@@ -268,7 +271,7 @@ fn read_input_json(input: Option<String>, input_file: Option<PathBuf>, stdin: bo
     .collect::<Vec<_>>();
 
     if sources.len() > 1 {
-        return Err(CliError::Usage(
+        return Err(CliError::usage(
             "Pass only one of --input, --input-file, or --stdin.".to_string(),
         ));
     }
@@ -277,13 +280,13 @@ fn read_input_json(input: Option<String>, input_file: Option<PathBuf>, stdin: bo
         raw
     } else if let Some(path) = input_file {
         std::fs::read_to_string(&path).map_err(|e| {
-            CliError::Usage(format!("Failed to read {}: {e}", path.display()))
+            CliError::usage(format!("Failed to read {}: {e}", path.display()))
         })?
     } else if stdin {
         let mut buf = String::new();
         io::stdin()
             .read_to_string(&mut buf)
-            .map_err(|e| CliError::Generic(format!("Failed reading stdin: {e}")))?;
+            .map_err(|e| CliError::generic(format!("Failed reading stdin: {e}")))?;
         buf
     } else {
         "{}".to_string()
@@ -295,11 +298,11 @@ fn read_input_json(input: Option<String>, input_file: Option<PathBuf>, stdin: bo
     }
 
     let parsed = serde_json::from_str::<Value>(trimmed).map_err(|e| {
-        CliError::Usage(format!("Invalid JSON input: {e}"))
+        CliError::usage(format!("Invalid JSON input: {e}"))
     })?;
 
     if !parsed.is_object() {
-        return Err(CliError::Usage("Tool input must be a JSON object.".to_string()));
+        return Err(CliError::usage("Tool input must be a JSON object.".to_string()));
     }
 
     Ok(parsed)
@@ -311,7 +314,7 @@ fn prompt_approve() -> Result<bool, CliError> {
     let mut line = String::new();
     io::stdin()
         .read_line(&mut line)
-        .map_err(|e| CliError::Generic(format!("Failed reading input: {e}")))?;
+        .map_err(|e| CliError::generic(format!("Failed reading input: {e}")))?;
     let s = line.trim().to_ascii_lowercase();
     Ok(s == "y" || s == "yes")
 }
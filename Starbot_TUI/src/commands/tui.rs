@@ -1,4 +1,5 @@
 use std::io;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use clap::Args;
@@ -23,9 +24,11 @@ use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::api::{ApiClient, ApiResponse};
 use crate::app::Runtime;
+use crate::commands::agent::shell_manager;
 use crate::config::{CliConfig, profile_mut, profile_ref, save_config};
 use crate::cute::{CuteMode, load_cute_mode};
 use crate::errors::CliError;
+use crate::journal::Journal;
 
 // PHASE 3: Import handler modules and response parsing
 use crate::parse::response::{extract_reply, extract_provider_model, extract_usage_line};
@@ -47,10 +50,10 @@ struct TerminalGuard;
 impl TerminalGuard {
     fn enter() -> Result<Self, CliError> {
         enable_raw_mode()
-            .map_err(|e| CliError::Generic(format!("Failed to enable raw mode: {e}")))?;
+            .map_err(|e| CliError::generic(format!("Failed to enable raw mode: {e}")))?;
         let mut stdout = io::stdout();
         execute!(stdout, EnterAlternateScreen)
-            .map_err(|e| CliError::Generic(format!("Failed to enter alternate screen: {e}")))?;
+            .map_err(|e| CliError::generic(format!("Failed to enter alternate screen: {e}")))?;
         Ok(Self)
     }
 }
@@ -65,12 +68,13 @@ impl Drop for TerminalGuard {
 
 pub async fn handle(runtime: &Runtime, args: TuiArgs) -> Result<(), CliError> {
     if runtime.output.json {
-        return Err(CliError::Usage(
+        return Err(CliError::usage(
             "`--json` is not supported for `starbott tui`.".to_string(),
         ));
     }
 
-    let api = runtime.api_client()?;
+    // Verbose client logging goes to stderr, which would draw over the TUI.
+    let api = runtime.api_client()?.with_verbose(false);
     let api_url = runtime.resolved_api_url()?;
     let profile = runtime.active_profile();
     let config = runtime.config.clone();
@@ -84,13 +88,13 @@ pub async fn handle(runtime: &Runtime, args: TuiArgs) -> Result<(), CliError> {
 
     let backend = CrosstermBackend::new(io::stdout());
     let mut terminal = Terminal::new(backend)
-        .map_err(|e| CliError::Generic(format!("Failed to init terminal: {e}")))?;
+        .map_err(|e| CliError::generic(format!("Failed to init terminal: {e}")))?;
     terminal
         .clear()
-        .map_err(|e| CliError::Generic(format!("Failed to clear terminal: {e}")))?;
+        .map_err(|e| CliError::generic(format!("Failed to clear terminal: {e}")))?;
     terminal
         .hide_cursor()
-        .map_err(|e| CliError::Generic(format!("Failed to hide cursor: {e}")))?;
+        .map_err(|e| CliError::generic(format!("Failed to hide cursor: {e}")))?;
 
     let mut app = App {
         mode: Mode::Chat,
@@ -161,6 +165,8 @@ pub async fn handle(runtime: &Runtime, args: TuiArgs) -> Result<(), CliError> {
         show_debug: false,
         pending_tool: None,
         tool_approval_history: Vec::new(),
+        journal: Arc::new(Journal::new()),
+        shells: shell_manager(),
         // File browser state
         file_browser_path: "/".to_string(),
         file_browser_files: Vec::new(),
@@ -184,7 +190,7 @@ pub async fn handle(runtime: &Runtime, args: TuiArgs) -> Result<(), CliError> {
         update_spinner(&mut app);
         terminal
             .draw(|f| ui(f, &mut app))
-            .map_err(|e| CliError::Generic(format!("Failed to draw: {e}")))?;
+            .map_err(|e| CliError::generic(format!("Failed to draw: {e}")))?;
 
         if app.should_quit {
             break;
@@ -201,10 +207,10 @@ pub async fn handle(runtime: &Runtime, args: TuiArgs) -> Result<(), CliError> {
             120
         };
         if crossterm::event::poll(Duration::from_millis(poll_ms))
-            .map_err(|e| CliError::Generic(format!("Event poll failed: {e}")))?
+            .map_err(|e| CliError::generic(format!("Event poll failed: {e}")))?
         {
             let event = crossterm::event::read()
-                .map_err(|e| CliError::Generic(format!("Event read failed: {e}")))?;
+                .map_err(|e| CliError::generic(format!("Event read failed: {e}")))?;
             if let Err(err) = handle_event(&api, &tx, &mut app, event) {
                 app.messages.push(ChatMsg {
                     role: ChatRole::System,
@@ -218,7 +224,7 @@ pub async fn handle(runtime: &Runtime, args: TuiArgs) -> Result<(), CliError> {
 
     terminal
         .show_cursor()
-        .map_err(|e| CliError::Generic(format!("Failed to restore cursor: {e}")))?;
+        .map_err(|e| CliError::generic(format!("Failed to restore cursor: {e}")))?;
     drop(guard);
     Ok(())
 }
@@ -1684,6 +1690,9 @@ fn render_help(_app: &App) -> Paragraph<'static> {
         .wrap(Wrap { trim: false })
 }
 
+/// Preview lines shown on the tool card.
+const TOOL_PREVIEW_LINES: usize = 30;
+
 fn render_tool_card(app: &App) -> Paragraph<'static> {
     let mut lines = Vec::new();
 
@@ -1710,8 +1719,12 @@ fn render_tool_card(app: &App) -> Paragraph<'static> {
                 "Preview:",
                 Style::default().add_modifier(Modifier::BOLD),
             )]));
-            for preview_line in tool.preview.lines().take(15) {
-                let style = if preview_line.starts_with('+') {
+            for preview_line in tool.preview.lines().take(TOOL_PREVIEW_LINES) {
+                let style = if preview_line.starts_with("+++") || preview_line.starts_with("---") {
+                    Style::default().add_modifier(Modifier::BOLD)
+                } else if preview_line.starts_with("@@") {
+                    Style::default().fg(c_sparkle())
+                } else if preview_line.starts_with('+') {
                     Style::default().fg(c_ok())
                 } else if preview_line.starts_with('-') {
                     Style::default().fg(c_heart())
@@ -1723,6 +1736,13 @@ fn render_tool_card(app: &App) -> Paragraph<'static> {
                     style,
                 )]));
             }
+            let hidden = tool.preview.lines().count().saturating_sub(TOOL_PREVIEW_LINES);
+            if hidden > 0 {
+                lines.push(Line::from(vec![Span::styled(
+                    format!("  … {hidden} more lines"),
+                    Style::default().fg(c_muted()),
+                )]));
+            }
             lines.push(Line::from(""));
         }
 
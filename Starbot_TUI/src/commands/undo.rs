@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use clap::Args;
+use serde_json::{Value, json};
+
+use crate::app::Runtime;
+use crate::conversations::{age, now_secs};
+use crate::errors::CliError;
+use crate::journal::{self, Session};
+
+#[derive(Debug, Args)]
+pub struct UndoArgs {
+    /// Session to undo (ID or unique prefix); defaults to the newest one not yet undone
+    #[arg(long)]
+    pub session: Option<String>,
+    /// List journaled sessions instead of undoing one
+    #[arg(long, conflicts_with = "session")]
+    pub list: bool,
+    /// Also put back files edited since the agent wrote them
+    #[arg(long, conflicts_with = "list")]
+    pub force: bool,
+}
+
+pub async fn handle(runtime: &Runtime, args: UndoArgs) -> Result<(), CliError> {
+    let sessions = journal::sessions()?;
+    if args.list {
+        return list(runtime, &sessions);
+    }
+
+    let mut session = journal::find(sessions, args.session.as_deref())?;
+    if session.undone_at.is_some() {
+        return Err(CliError::usage(format!(
+            "Session {} was already undone.",
+            session.id
+        )));
+    }
+    let report = journal::undo(&mut session, args.force)?;
+
+    if runtime.output.json {
+        return runtime.output.print_json(&report);
+    }
+    for path in &report.restored {
+        runtime
+            .output
+            .print_human(&format!("Restored {}", display(path)));
+    }
+    for path in &report.removed {
+        runtime
+            .output
+            .print_human(&format!("Removed {} (created by the agent)", display(path)));
+    }
+    for skipped in &report.skipped {
+        runtime.output.print_stderr(&format!(
+            "Skipped {}: {}",
+            display(&skipped.path),
+            skipped.reason
+        ));
+    }
+    if !report.skipped.is_empty() {
+        runtime.output.print_stderr(&format!(
+            "Run `starbott undo --session {} --force` to put those back too.",
+            session.id
+        ));
+    }
+    Ok(())
+}
+
+fn list(runtime: &Runtime, sessions: &[Session]) -> Result<(), CliError> {
+    let now = now_secs();
+    let rows: Vec<Value> = sessions
+        .iter()
+        .map(|s| {
+            json!({
+                "id": s.id,
+                "created": age(s.created_at, now),
+                "files": s.files.len(),
+                "status": if s.undone_at.is_some() { "undone" } else { "active" },
+                "cwd": s.cwd,
+            })
+        })
+        .collect();
+    runtime.output.print_list(
+        &json!({ "sessions": sessions }),
+        &rows,
+        &[
+            ("ID", "id"),
+            ("CREATED", "created"),
+            ("FILES", "files"),
+            ("STATUS", "status"),
+            ("CWD", "cwd"),
+        ],
+        "No undo sessions. Files written by agent tool calls are journaled here.",
+    )
+}
+
+/// `path` relative to the current directory when it is inside it.
+fn display(path: &str) -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|cwd| {
+            let cwd = std::fs::canonicalize(&cwd).unwrap_or(cwd);
+            Path::new(path)
+                .strip_prefix(cwd)
+                .ok()
+                .map(|p| p.display().to_string())
+        })
+        .unwrap_or_else(|| path.to_string())
+}
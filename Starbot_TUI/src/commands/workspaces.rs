@@ -72,7 +72,7 @@ async fn create(runtime: &Runtime, name: Option<String>, root: Option<PathBuf>)
 
     let root = root.unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
     let resolved = std::fs::canonicalize(&root).map_err(|e| {
-        CliError::Usage(format!("Invalid workspace root {}: {e}", root.display()))
+        CliError::usage(format!("Invalid workspace root {}: {e}", root.display()))
     })?;
 
     let default_name = resolved
@@ -107,12 +107,6 @@ async fn create(runtime: &Runtime, name: Option<String>, root: Option<PathBuf>)
 async fn list(runtime: &Runtime) -> Result<(), CliError> {
     let api = runtime.api_client()?;
     let res = api.get_json("/v1/workspaces", None, true).await?;
-
-    if runtime.output.json {
-        runtime.output.print_json(&res.json)?;
-        return Ok(());
-    }
-
     let items = res
         .json
         .get("workspaces")
@@ -120,19 +114,12 @@ async fn list(runtime: &Runtime) -> Result<(), CliError> {
         .cloned()
         .unwrap_or_default();
 
-    if items.is_empty() {
-        runtime.output.print_human("No workspaces.");
-        return Ok(());
-    }
-
-    for w in items {
-        let id = w.get("id").and_then(|v| v.as_str()).unwrap_or("-");
-        let name = w.get("name").and_then(|v| v.as_str()).unwrap_or("-");
-        let root = w.get("rootPath").and_then(|v| v.as_str()).unwrap_or("-");
-        runtime.output.print_human(&format!("- {name}  ({id})  {root}"));
-    }
-
-    Ok(())
+    runtime.output.print_list(
+        &res.json,
+        &items,
+        &[("NAME", "name"), ("ID", "id"), ("ROOT", "rootPath")],
+        "No workspaces.",
+    )
 }
 
 async fn set_permissions(
@@ -160,7 +147,7 @@ async fn set_permissions(
     if let Some(v) = can_web_search { body["can_web_search"] = json!(v); }
 
     if body.as_object().map(|o| o.is_empty()).unwrap_or(true) {
-        return Err(CliError::Usage(
+        return Err(CliError::usage(
             "No permission fields provided. Pass e.g. --can-write-files true".to_string(),
         ));
     }
@@ -1,11 +1,13 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::credentials::{self, Secrets, StoreKind};
 use crate::errors::CliError;
+use crate::policy::ToolPolicy;
 
 pub const DEFAULT_API_URL: &str = "http://localhost:3737";
 
@@ -34,6 +36,17 @@ impl Default for ProfileConfig {
 pub struct CliConfig {
     pub profile: String,
     pub profiles: HashMap<String, ProfileConfig>,
+    /// Where tokens are kept. Unset means the best available store, except
+    /// for older configs that already hold plaintext tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_store: Option<StoreKind>,
+    /// Which local tool calls run, ask first, or are refused.
+    #[serde(default, skip_serializing_if = "is_default_policy")]
+    pub tool_policy: ToolPolicy,
+}
+
+fn is_default_policy(policy: &ToolPolicy) -> bool {
+    *policy == ToolPolicy::default()
 }
 
 impl Default for CliConfig {
@@ -43,17 +56,28 @@ impl Default for CliConfig {
         Self {
             profile: "default".to_string(),
             profiles,
+            credential_store: None,
+            tool_policy: ToolPolicy::default(),
         }
     }
 }
 
 pub fn config_path() -> Result<PathBuf, CliError> {
     let base = dirs::config_dir().ok_or_else(|| {
-        CliError::Generic("Could not resolve config directory for this OS.".to_string())
+        CliError::generic("Could not resolve config directory for this OS.".to_string())
     })?;
     Ok(base.join("starbott").join("config.json"))
 }
 
+/// Where the CLI keeps local state (conversation index, journals). Platforms
+/// without a state directory use the local data directory instead.
+pub fn state_dir() -> Result<PathBuf, CliError> {
+    let base = dirs::state_dir().or_else(dirs::data_local_dir).ok_or_else(|| {
+        CliError::generic("Could not resolve state directory for this OS.".to_string())
+    })?;
+    Ok(base.join("starbott"))
+}
+
 pub fn load_config() -> Result<CliConfig, CliError> {
     let path = config_path()?;
     if !path.exists() {
@@ -71,12 +95,34 @@ pub fn save_config(config: &CliConfig) -> Result<PathBuf, CliError> {
     let path = config_path()?;
     let parent = path
         .parent()
-        .ok_or_else(|| CliError::Generic("Invalid config path.".to_string()))?;
+        .ok_or_else(|| CliError::generic("Invalid config path.".to_string()))?;
     fs::create_dir_all(parent)?;
-    fs::write(&path, serde_json::to_string_pretty(config)?)?;
+    write_private(&path, &serde_json::to_string_pretty(config)?)?;
     Ok(path)
 }
 
+/// Write a file readable only by the current user (0600 on Unix). Existing
+/// files are tightened too, since earlier versions wrote config.json 0644.
+pub fn write_private(path: &Path, contents: &str) -> Result<(), CliError> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(contents.as_bytes())?;
+    }
+    #[cfg(not(unix))]
+    fs::write(path, contents)?;
+    Ok(())
+}
+
 pub fn active_profile_name(config: &CliConfig, profile_override: Option<&str>) -> String {
     profile_override
         .map(|s| s.to_string())
@@ -113,7 +159,7 @@ pub fn resolve_api_url(
     }
 
     let profile = profile_ref(config, profile_name)
-        .ok_or_else(|| CliError::Usage(format!("Profile '{profile_name}' does not exist.")))?;
+        .ok_or_else(|| CliError::usage(format!("Profile '{profile_name}' does not exist.")))?;
     validate_url(&profile.api_url)?;
     Ok(profile.api_url.clone())
 }
@@ -125,13 +171,116 @@ pub fn resolve_token(config: &CliConfig, profile_name: &str) -> Option<String> {
         }
     }
 
-    profile_ref(config, profile_name).and_then(|p| p.token.clone())
+    profile_ref(config, profile_name)
+        .and_then(|p| p.token.clone())
+        .or_else(|| stored_secrets(config, profile_name).access_token)
+}
+
+pub fn resolve_refresh_token(config: &CliConfig, profile_name: &str) -> Option<String> {
+    profile_ref(config, profile_name)
+        .and_then(|p| p.refresh_token.clone())
+        .or_else(|| stored_secrets(config, profile_name).refresh_token)
+}
+
+/// The store tokens are written to. Configs from before credential stores
+/// that still hold plaintext tokens keep using them until
+/// `config migrate-secrets` is run.
+pub fn credential_store(config: &CliConfig) -> StoreKind {
+    config.credential_store.unwrap_or_else(|| {
+        if has_plaintext_tokens(config) {
+            StoreKind::Plaintext
+        } else {
+            StoreKind::preferred()
+        }
+    })
+}
+
+pub fn has_plaintext_tokens(config: &CliConfig) -> bool {
+    config
+        .profiles
+        .values()
+        .any(|p| p.token.is_some() || p.refresh_token.is_some())
+}
+
+/// Tokens for `profile_name` from the configured store. Read failures are
+/// reported once and treated as "not logged in".
+fn stored_secrets(config: &CliConfig, profile_name: &str) -> Secrets {
+    let kind = credential_store(config);
+    if kind == StoreKind::Plaintext {
+        return Secrets::default();
+    }
+    credentials::load(kind, profile_name).unwrap_or_else(|err| {
+        eprintln!(
+            "Warning: could not read credentials from the {} store: {err}",
+            kind.as_str()
+        );
+        Secrets::default()
+    })
+}
+
+/// Store a token pair for `profile_name` in the configured credential store.
+/// A `None` refresh token keeps the existing one. The caller saves `config`.
+pub fn set_profile_tokens(
+    config: &mut CliConfig,
+    profile_name: &str,
+    access_token: String,
+    refresh_token: Option<String>,
+) -> Result<(), CliError> {
+    let kind = credential_store(config);
+    ensure_profile(config, profile_name);
+    let refresh_token = refresh_token.or_else(|| resolve_refresh_token(config, profile_name));
+
+    if kind != StoreKind::Plaintext {
+        let secrets = Secrets {
+            access_token: Some(access_token),
+            refresh_token,
+        };
+        credentials::save(kind, profile_name, &secrets)?;
+        // Pin the choice so tokens are looked up in the same place next time.
+        config.credential_store = Some(kind);
+        if let Some(profile) = profile_mut(config, profile_name) {
+            profile.token = None;
+            profile.refresh_token = None;
+        }
+        return Ok(());
+    }
+
+    let profile = profile_mut(config, profile_name)
+        .ok_or_else(|| CliError::generic("Failed to load active profile.".to_string()))?;
+    profile.token = Some(access_token);
+    profile.refresh_token = refresh_token;
+    Ok(())
+}
+
+/// Forget the tokens for `profile_name`, wherever they are stored.
+pub fn clear_profile_tokens(config: &mut CliConfig, profile_name: &str) -> Result<(), CliError> {
+    let kind = credential_store(config);
+    if kind != StoreKind::Plaintext {
+        credentials::delete(kind, profile_name)?;
+    }
+    if let Some(profile) = profile_mut(config, profile_name) {
+        profile.token = None;
+        profile.refresh_token = None;
+    }
+    Ok(())
+}
+
+/// Persist a rotated token pair into `profile_name`. The config is re-read
+/// from disk first so concurrent edits to other profiles are not clobbered.
+pub fn store_profile_tokens(
+    profile_name: &str,
+    access_token: String,
+    refresh_token: Option<String>,
+) -> Result<PathBuf, CliError> {
+    let mut config = load_config()?;
+    set_profile_tokens(&mut config, profile_name, access_token, refresh_token)?;
+    save_config(&config)
 }
 
 pub fn validate_url(value: &str) -> Result<(), CliError> {
     let parsed = Url::parse(value)?;
     if parsed.scheme() != "http" && parsed.scheme() != "https" {
-        return Err(CliError::Usage(
+        return Err(CliError::usage(
             "API URL must use http:// or https://.".to_string(),
         ));
     }
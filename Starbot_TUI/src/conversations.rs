@@ -0,0 +1,331 @@
+//! Local conversation index
+//!
+//! Remembers the chats each profile has used and the directory they were last
+//! used from, so `chat --continue` picks up where the previous invocation left
+//! off and `--conversation`/`--chat-id` accept a short ID prefix or a title
+//! instead of a full UUID. The index lives in the state directory as
+//! `conversations.json`; it holds IDs and titles only, nothing secret.
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::Runtime;
+use crate::config::state_dir;
+use crate::errors::CliError;
+
+const INDEX_VERSION: u32 = 1;
+/// Oldest entries are dropped beyond this many.
+const MAX_CONVERSATIONS: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub profile: String,
+    /// Working directory of the most recent use.
+    pub cwd: String,
+    /// Unix seconds.
+    pub created_at: u64,
+    /// Unix seconds.
+    pub last_used_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexFile {
+    version: u32,
+    conversations: Vec<Conversation>,
+}
+
+#[derive(Debug)]
+pub struct ConversationIndex {
+    path: PathBuf,
+    conversations: Vec<Conversation>,
+}
+
+impl ConversationIndex {
+    pub fn load() -> Result<Self, CliError> {
+        Self::load_from(&state_dir()?.join("conversations.json"))
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, CliError> {
+        let conversations = match fs::read_to_string(path) {
+            Ok(text) => {
+                let file: IndexFile = serde_json::from_str(&text).map_err(|err| {
+                    CliError::generic(format!(
+                        "Invalid conversation index {}: {err}",
+                        path.display()
+                    ))
+                    .with_hint("Delete the file to start a fresh index.")
+                })?;
+                file.conversations
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            conversations,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), CliError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = IndexFile {
+            version: INDEX_VERSION,
+            conversations: self.conversations.clone(),
+        };
+        fs::write(&self.path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+
+    /// A profile's conversations, most recently used first.
+    pub fn list(&self, profile: &str) -> Vec<&Conversation> {
+        let mut list: Vec<&Conversation> = self
+            .conversations
+            .iter()
+            .filter(|c| c.profile == profile)
+            .collect();
+        list.sort_by_key(|c| Reverse(c.last_used_at));
+        list
+    }
+
+    /// The conversation last used by `profile` from `cwd`.
+    pub fn last_for(&self, profile: &str, cwd: &str) -> Option<&Conversation> {
+        self.list(profile).into_iter().find(|c| c.cwd == cwd)
+    }
+
+    /// Find a conversation by exact ID, unique ID prefix, or exact title
+    /// (case-insensitive). `Ok(None)` means nothing matched; several matches
+    /// are a usage error.
+    pub fn resolve(&self, profile: &str, query: &str) -> Result<Option<&Conversation>, CliError> {
+        let list = self.list(profile);
+        if let Some(exact) = list.iter().find(|c| c.id == query) {
+            return Ok(Some(exact));
+        }
+
+        let by_prefix: Vec<&Conversation> = list
+            .iter()
+            .copied()
+            .filter(|c| c.id.starts_with(query))
+            .collect();
+        let matches = if by_prefix.is_empty() {
+            list.iter()
+                .copied()
+                .filter(|c| {
+                    c.title
+                        .as_deref()
+                        .is_some_and(|t| t.eq_ignore_ascii_case(query))
+                })
+                .collect()
+        } else {
+            by_prefix
+        };
+
+        match matches.as_slice() {
+            [] => Ok(None),
+            [only] => Ok(Some(only)),
+            several => Err(CliError::usage(format!(
+                "`{query}` matches {} conversations: {}.",
+                several.len(),
+                several
+                    .iter()
+                    .map(|c| c.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .with_hint("Use a longer ID prefix; `starbott chat sessions list` shows them all.")),
+        }
+    }
+
+    /// Record a use of `id` from `cwd`, creating the entry if needed. Known
+    /// project IDs and titles are kept when the new values are unknown.
+    pub fn touch(
+        &mut self,
+        profile: &str,
+        cwd: &str,
+        id: &str,
+        project_id: Option<&str>,
+        title: Option<&str>,
+        now: u64,
+    ) {
+        match self
+            .conversations
+            .iter_mut()
+            .find(|c| c.profile == profile && c.id == id)
+        {
+            Some(existing) => {
+                existing.cwd = cwd.to_string();
+                existing.last_used_at = now;
+                if let Some(project_id) = project_id {
+                    existing.project_id = Some(project_id.to_string());
+                }
+                if let Some(title) = title {
+                    existing.title = Some(title.to_string());
+                }
+            }
+            None => self.conversations.push(Conversation {
+                id: id.to_string(),
+                project_id: project_id.map(str::to_string),
+                title: title.map(str::to_string),
+                profile: profile.to_string(),
+                cwd: cwd.to_string(),
+                created_at: now,
+                last_used_at: now,
+            }),
+        }
+
+        if self.conversations.len() > MAX_CONVERSATIONS {
+            self.conversations.sort_by_key(|c| Reverse(c.last_used_at));
+            self.conversations.truncate(MAX_CONVERSATIONS);
+        }
+    }
+
+    pub fn remove(&mut self, profile: &str, id: &str) -> Option<Conversation> {
+        let idx = self
+            .conversations
+            .iter()
+            .position(|c| c.profile == profile && c.id == id)?;
+        Some(self.conversations.remove(idx))
+    }
+}
+
+/// The key conversations are filed under: the current directory, resolved.
+pub fn current_dir_key() -> String {
+    std::env::current_dir()
+        .map(|dir| fs::canonicalize(&dir).unwrap_or(dir))
+        .unwrap_or_else(|_| PathBuf::from("/"))
+        .display()
+        .to_string()
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Record that a chat was just used from the current directory. A broken
+/// index never fails the chat itself; it is only reported with `--verbose`.
+pub fn remember(runtime: &Runtime, id: &str, project_id: Option<&str>, title: Option<&str>) {
+    let result = ConversationIndex::load().and_then(|mut index| {
+        index.touch(
+            &runtime.active_profile(),
+            &current_dir_key(),
+            id,
+            project_id,
+            title,
+            now_secs(),
+        );
+        index.save()
+    });
+    if let Err(err) = result {
+        runtime
+            .output
+            .print_verbose(&format!("Could not update the conversation index: {err}"));
+    }
+}
+
+/// Resolve a user-supplied chat reference to `(chat id, project id)`.
+/// Unknown references are passed through as-is, since the chat may predate
+/// the index or come from another machine.
+pub fn resolve_chat(runtime: &Runtime, query: &str) -> Result<(String, Option<String>), CliError> {
+    let index = ConversationIndex::load()?;
+    Ok(match index.resolve(&runtime.active_profile(), query)? {
+        Some(found) => (found.id.clone(), found.project_id.clone()),
+        None => (query.to_string(), None),
+    })
+}
+
+/// Seconds since `then` as a short age: `just now`, `5m ago`, `3h ago`, `2d ago`.
+pub fn age(then: u64, now: u64) -> String {
+    let secs = now.saturating_sub(then);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> ConversationIndex {
+        let mut index = ConversationIndex {
+            path: PathBuf::from("unused.json"),
+            conversations: Vec::new(),
+        };
+        index.touch(
+            "default",
+            "/repo",
+            "c0ffee-1",
+            Some("p1"),
+            Some("Fix build"),
+            100,
+        );
+        index.touch("default", "/repo", "c0ffee-2", Some("p1"), None, 200);
+        index.touch("default", "/other", "beef-3", None, Some("Notes"), 300);
+        index.touch("work", "/repo", "c0ffee-9", None, None, 400);
+        index
+    }
+
+    #[test]
+    fn last_for_is_scoped_to_profile_and_directory() {
+        let index = index();
+        assert_eq!(index.last_for("default", "/repo").unwrap().id, "c0ffee-2");
+        assert_eq!(index.last_for("work", "/repo").unwrap().id, "c0ffee-9");
+        assert!(index.last_for("work", "/other").is_none());
+    }
+
+    #[test]
+    fn resolves_ids_prefixes_and_titles() {
+        let index = index();
+        assert_eq!(
+            index.resolve("default", "c0ffee-1").unwrap().unwrap().id,
+            "c0ffee-1"
+        );
+        assert_eq!(
+            index.resolve("default", "beef").unwrap().unwrap().id,
+            "beef-3"
+        );
+        assert_eq!(
+            index.resolve("default", "fix BUILD").unwrap().unwrap().id,
+            "c0ffee-1"
+        );
+        assert!(index.resolve("default", "c0ffee").is_err());
+        assert!(index.resolve("default", "nope").unwrap().is_none());
+        assert!(index.resolve("work", "beef").unwrap().is_none());
+    }
+
+    #[test]
+    fn touch_updates_in_place_and_keeps_known_fields() {
+        let mut index = index();
+        index.touch("default", "/elsewhere", "c0ffee-1", None, None, 500);
+        let entry = index.resolve("default", "c0ffee-1").unwrap().unwrap();
+        assert_eq!(entry.cwd, "/elsewhere");
+        assert_eq!(entry.project_id.as_deref(), Some("p1"));
+        assert_eq!(entry.title.as_deref(), Some("Fix build"));
+        assert_eq!((entry.created_at, entry.last_used_at), (100, 500));
+        assert_eq!(index.list("default").len(), 3);
+    }
+
+    #[test]
+    fn ages_are_short() {
+        assert_eq!(age(100, 130), "just now");
+        assert_eq!(age(0, 300), "5m ago");
+        assert_eq!(age(0, 7200), "2h ago");
+        assert_eq!(age(0, 200_000), "2d ago");
+    }
+}
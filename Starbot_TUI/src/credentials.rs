@@ -0,0 +1,652 @@
+//! Credential storage
+//!
+//! Access and refresh tokens are kept out of config.json by default: in the OS
+//! keyring when one is reachable (Secret Service on Linux, the login keychain
+//! on macOS), otherwise in a passphrase-encrypted file next to the config.
+//! Plaintext tokens in config.json remain available as an explicit opt-in and
+//! are handled by `config` directly.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::IsTerminal;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use clap::ValueEnum;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{config_path, write_private};
+use crate::errors::CliError;
+
+/// Keyring service name; the profile name is the account.
+const SERVICE: &str = "starbott";
+pub const PASSPHRASE_ENV: &str = "STARBOTT_PASSPHRASE";
+
+const SEALED_VERSION: u32 = 1;
+const KDF_NAME: &str = "pbkdf2-hmac-sha256";
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const AAD: &[u8] = b"starbott credentials v1";
+
+/// Where tokens are persisted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum StoreKind {
+    /// Secret Service (Linux) or the login keychain (macOS)
+    Keyring,
+    /// Passphrase-encrypted file in the config directory
+    EncryptedFile,
+    /// Tokens in config.json (opt-in)
+    Plaintext,
+}
+
+impl StoreKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StoreKind::Keyring => "keyring",
+            StoreKind::EncryptedFile => "encrypted-file",
+            StoreKind::Plaintext => "plaintext",
+        }
+    }
+
+    /// The store to use when none is configured.
+    pub fn preferred() -> Self {
+        if platform::available() {
+            StoreKind::Keyring
+        } else {
+            StoreKind::EncryptedFile
+        }
+    }
+}
+
+/// The token pair kept for one profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Secrets {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+/// A backend that keeps token pairs outside config.json.
+pub trait CredentialStore {
+    fn load(&self, profile: &str) -> Result<Secrets, CliError>;
+    fn save(&self, profile: &str, secrets: &Secrets) -> Result<(), CliError>;
+    fn delete(&self, profile: &str) -> Result<(), CliError>;
+}
+
+/// The backend for `kind`; plaintext has none because it lives in the config.
+pub fn open(kind: StoreKind) -> Result<Option<Box<dyn CredentialStore>>, CliError> {
+    Ok(match kind {
+        StoreKind::Keyring => Some(Box::new(KeyringStore)),
+        StoreKind::EncryptedFile => Some(Box::new(EncryptedFileStore::new(credentials_path()?))),
+        StoreKind::Plaintext => None,
+    })
+}
+
+pub fn credentials_path() -> Result<PathBuf, CliError> {
+    let config = config_path()?;
+    Ok(config.with_file_name("credentials.enc"))
+}
+
+/// Secrets loaded so far in this process, so each command unlocks a store at
+/// most once. A failed load is remembered as empty after it has been reported.
+type Loaded = Mutex<HashMap<(StoreKind, String), Secrets>>;
+
+fn loaded() -> &'static Loaded {
+    static LOADED: OnceLock<Loaded> = OnceLock::new();
+    LOADED.get_or_init(Default::default)
+}
+
+pub fn load(kind: StoreKind, profile: &str) -> Result<Secrets, CliError> {
+    let key = (kind, profile.to_string());
+    if let Some(secrets) = loaded().lock().ok().and_then(|m| m.get(&key).cloned()) {
+        return Ok(secrets);
+    }
+
+    let result = match open(kind)? {
+        Some(store) => store.load(profile),
+        None => Ok(Secrets::default()),
+    };
+    if let Ok(mut cache) = loaded().lock() {
+        cache.insert(key, result.as_ref().cloned().unwrap_or_default());
+    }
+    result
+}
+
+pub fn save(kind: StoreKind, profile: &str, secrets: &Secrets) -> Result<(), CliError> {
+    let store = open(kind)?.ok_or_else(|| {
+        CliError::generic("Plaintext tokens are stored in config.json.".to_string())
+    })?;
+    store.save(profile, secrets)?;
+    if let Ok(mut cache) = loaded().lock() {
+        cache.insert((kind, profile.to_string()), secrets.clone());
+    }
+    Ok(())
+}
+
+pub fn delete(kind: StoreKind, profile: &str) -> Result<(), CliError> {
+    if let Some(store) = open(kind)? {
+        store.delete(profile)?;
+    }
+    if let Ok(mut cache) = loaded().lock() {
+        cache.remove(&(kind, profile.to_string()));
+    }
+    Ok(())
+}
+
+/// One keyring item per profile holding the JSON-encoded token pair.
+struct KeyringStore;
+
+impl CredentialStore for KeyringStore {
+    fn load(&self, profile: &str) -> Result<Secrets, CliError> {
+        match platform::get(profile)? {
+            Some(text) => serde_json::from_str(&text).map_err(|err| {
+                CliError::generic(format!("Keyring entry for '{profile}' is not valid: {err}"))
+            }),
+            None => Ok(Secrets::default()),
+        }
+    }
+
+    fn save(&self, profile: &str, secrets: &Secrets) -> Result<(), CliError> {
+        platform::set(profile, &serde_json::to_string(secrets)?)
+    }
+
+    fn delete(&self, profile: &str) -> Result<(), CliError> {
+        platform::delete(profile)
+    }
+}
+
+/// Linux and the BSDs: `secret-tool` from libsecret. The secret is passed on
+/// stdin so it never shows up in the process list.
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    use super::SERVICE;
+    use crate::errors::CliError;
+
+    pub fn available() -> bool {
+        std::env::var_os("DBUS_SESSION_BUS_ADDRESS").is_some() && on_path("secret-tool")
+    }
+
+    pub fn get(profile: &str) -> Result<Option<String>, CliError> {
+        let output = run(&["lookup", "service", SERVICE, "account", profile], None)?;
+        // `lookup` exits 1 without output when nothing is stored.
+        if output.status.success() && !output.stdout.is_empty() {
+            return Ok(Some(
+                String::from_utf8_lossy(&output.stdout)
+                    .trim_end()
+                    .to_string(),
+            ));
+        }
+        if output.stderr.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        check(output, "lookup").map(|_| None)
+    }
+
+    pub fn set(profile: &str, secret: &str) -> Result<(), CliError> {
+        let label = format!("{SERVICE} ({profile})");
+        let args = [
+            "store", "--label", &label, "service", SERVICE, "account", profile,
+        ];
+        check(run(&args, Some(secret))?, "store")
+    }
+
+    pub fn delete(profile: &str) -> Result<(), CliError> {
+        check(
+            run(&["clear", "service", SERVICE, "account", profile], None)?,
+            "clear",
+        )
+    }
+
+    fn run(args: &[&str], stdin: Option<&str>) -> Result<Output, CliError> {
+        let mut child = Command::new("secret-tool")
+            .args(args)
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| CliError::generic(format!("Could not run secret-tool: {err}")))?;
+        if let (Some(secret), Some(mut pipe)) = (stdin, child.stdin.take()) {
+            pipe.write_all(secret.as_bytes())?;
+        }
+        Ok(child.wait_with_output()?)
+    }
+
+    fn check(output: Output, action: &str) -> Result<(), CliError> {
+        if output.status.success() {
+            return Ok(());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(CliError::generic(format!(
+            "secret-tool {action} failed ({}): {}",
+            output.status,
+            stderr.trim()
+        )))
+    }
+
+    fn on_path(program: &str) -> bool {
+        std::env::var_os("PATH")
+            .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+            .unwrap_or(false)
+    }
+}
+
+/// macOS: the login keychain via `security`. Items are written through
+/// `security -i` so the secret is not passed as an argument; it is stored
+/// base64-encoded to avoid quoting issues in that command language.
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+
+    use super::SERVICE;
+    use crate::errors::CliError;
+
+    /// `security` exit status for "The specified item could not be found".
+    const NOT_FOUND: i32 = 44;
+
+    pub fn available() -> bool {
+        std::path::Path::new("/usr/bin/security").exists()
+    }
+
+    pub fn get(profile: &str) -> Result<Option<String>, CliError> {
+        let output = Command::new("security")
+            .args(["find-generic-password", "-s", SERVICE, "-a", profile, "-w"])
+            .output()
+            .map_err(spawn_error)?;
+        if output.status.code() == Some(NOT_FOUND) {
+            return Ok(None);
+        }
+        if !output.status.success() {
+            return Err(failed("find-generic-password", &output.stderr));
+        }
+        let encoded = String::from_utf8_lossy(&output.stdout);
+        let bytes = BASE64.decode(encoded.trim()).map_err(|err| {
+            CliError::generic(format!(
+                "Keychain entry for '{profile}' is not valid: {err}"
+            ))
+        })?;
+        Ok(Some(String::from_utf8_lossy(&bytes).to_string()))
+    }
+
+    pub fn set(profile: &str, secret: &str) -> Result<(), CliError> {
+        let account = profile.replace('\\', "\\\\").replace('"', "\\\"");
+        let command = format!(
+            "add-generic-password -U -s {SERVICE} -a \"{account}\" -w {}\n",
+            BASE64.encode(secret)
+        );
+        let mut child = Command::new("security")
+            .arg("-i")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error)?;
+        if let Some(mut pipe) = child.stdin.take() {
+            pipe.write_all(command.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() || !output.stderr.is_empty() {
+            return Err(failed("add-generic-password", &output.stderr));
+        }
+        Ok(())
+    }
+
+    pub fn delete(profile: &str) -> Result<(), CliError> {
+        let output = Command::new("security")
+            .args(["delete-generic-password", "-s", SERVICE, "-a", profile])
+            .output()
+            .map_err(spawn_error)?;
+        if output.status.success() || output.status.code() == Some(NOT_FOUND) {
+            return Ok(());
+        }
+        Err(failed("delete-generic-password", &output.stderr))
+    }
+
+    fn spawn_error(err: std::io::Error) -> CliError {
+        CliError::generic(format!("Could not run security: {err}"))
+    }
+
+    fn failed(action: &str, stderr: &[u8]) -> CliError {
+        CliError::generic(format!(
+            "security {action} failed: {}",
+            String::from_utf8_lossy(stderr).trim()
+        ))
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use crate::errors::CliError;
+
+    pub fn available() -> bool {
+        false
+    }
+
+    pub fn get(_profile: &str) -> Result<Option<String>, CliError> {
+        Err(unsupported())
+    }
+
+    pub fn set(_profile: &str, _secret: &str) -> Result<(), CliError> {
+        Err(unsupported())
+    }
+
+    pub fn delete(_profile: &str) -> Result<(), CliError> {
+        Err(unsupported())
+    }
+
+    fn unsupported() -> CliError {
+        CliError::usage(
+            "No supported keyring on this platform; use the encrypted-file store.".to_string(),
+        )
+    }
+}
+
+/// On-disk layout of `credentials.enc`. The ciphertext is the JSON map of
+/// profile name to `Secrets`, sealed with AES-256-GCM under a PBKDF2 key.
+#[derive(Debug, Serialize, Deserialize)]
+struct SealedFile {
+    version: u32,
+    kdf: String,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+type SecretsByProfile = BTreeMap<String, Secrets>;
+/// Salt and iteration count of an existing file.
+type KdfParams = (Vec<u8>, u32);
+
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    /// Fixed passphrase; otherwise `STARBOTT_PASSPHRASE` or a prompt.
+    passphrase: Option<String>,
+}
+
+impl EncryptedFileStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            passphrase: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_passphrase(path: PathBuf, passphrase: &str) -> Self {
+        Self {
+            path,
+            passphrase: Some(passphrase.to_string()),
+        }
+    }
+
+    /// Decrypt the file. Returns the salt and iteration count so a rewrite can
+    /// reuse the derived key, or `None` when no file exists yet.
+    fn read(&self) -> Result<(Option<KdfParams>, SecretsByProfile), CliError> {
+        if !self.path.exists() {
+            return Ok((None, SecretsByProfile::new()));
+        }
+
+        let invalid = |what: &str| {
+            CliError::generic(format!(
+                "{} is not a valid credential file: {what}",
+                self.path.display()
+            ))
+        };
+        let sealed: SealedFile = serde_json::from_str(&fs::read_to_string(&self.path)?)
+            .map_err(|err| invalid(&err.to_string()))?;
+        if sealed.version != SEALED_VERSION || sealed.kdf != KDF_NAME {
+            return Err(invalid("unsupported version"));
+        }
+        let salt = BASE64
+            .decode(&sealed.salt)
+            .map_err(|_| invalid("bad salt"))?;
+        let nonce = BASE64
+            .decode(&sealed.nonce)
+            .map_err(|_| invalid("bad nonce"))?;
+        let mut data = BASE64
+            .decode(&sealed.ciphertext)
+            .map_err(|_| invalid("bad ciphertext"))?;
+        let nonce = Nonce::try_assume_unique_for_key(&nonce).map_err(|_| invalid("bad nonce"))?;
+
+        let passphrase = self.passphrase(false)?;
+        let key = derive_key(&passphrase, &salt, sealed.iterations)?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::from(AAD), &mut data)
+            .map_err(|_| {
+                CliError::auth(format!(
+                    "Could not decrypt {}: wrong passphrase or corrupted file.",
+                    self.path.display()
+                ))
+            })?;
+        let profiles =
+            serde_json::from_slice(plaintext).map_err(|err| invalid(&err.to_string()))?;
+        remember_passphrase(&passphrase);
+        Ok((Some((salt, sealed.iterations)), profiles))
+    }
+
+    fn write(
+        &self,
+        params: Option<KdfParams>,
+        profiles: &SecretsByProfile,
+    ) -> Result<(), CliError> {
+        let rng = SystemRandom::new();
+        let (salt, iterations) = match params {
+            Some(params) => params,
+            None => {
+                let mut salt = vec![0u8; SALT_LEN];
+                rng.fill(&mut salt).map_err(|_| random_error())?;
+                (salt, PBKDF2_ITERATIONS)
+            }
+        };
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill(&mut nonce).map_err(|_| random_error())?;
+
+        let passphrase = self.passphrase(!self.path.exists())?;
+        let key = derive_key(&passphrase, &salt, iterations)?;
+        let mut data = serde_json::to_vec(profiles)?;
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(AAD),
+            &mut data,
+        )
+        .map_err(|_| CliError::generic("Failed to encrypt credentials.".to_string()))?;
+
+        let sealed = SealedFile {
+            version: SEALED_VERSION,
+            kdf: KDF_NAME.to_string(),
+            iterations,
+            salt: BASE64.encode(&salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(&data),
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(&self.path, &serde_json::to_string_pretty(&sealed)?)?;
+        remember_passphrase(&passphrase);
+        Ok(())
+    }
+
+    /// The passphrase from, in order: this store, `STARBOTT_PASSPHRASE`, an
+    /// earlier unlock in this process, or a terminal prompt. A new file asks
+    /// for the passphrase twice.
+    fn passphrase(&self, creating: bool) -> Result<String, CliError> {
+        if let Some(passphrase) = &self.passphrase {
+            return Ok(passphrase.clone());
+        }
+        if let Ok(value) = std::env::var(PASSPHRASE_ENV)
+            && !value.is_empty()
+        {
+            return Ok(value);
+        }
+        if let Some(value) = remembered_passphrase() {
+            return Ok(value);
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(CliError::auth(format!(
+                "The encrypted credential store needs a passphrase. Set {PASSPHRASE_ENV}, or pick \
+                 another store with `starbott config set credentialStore <keyring|plaintext>`."
+            )));
+        }
+
+        let read = |prompt: &str| {
+            rpassword::prompt_password(prompt)
+                .map_err(|e| CliError::generic(format!("Failed reading passphrase: {e}")))
+        };
+        let passphrase = read("Credential store passphrase: ")?;
+        if passphrase.is_empty() {
+            return Err(CliError::usage("Passphrase must not be empty.".to_string()));
+        }
+        if creating && read("Repeat passphrase: ")? != passphrase {
+            return Err(CliError::usage("Passphrases do not match.".to_string()));
+        }
+        Ok(passphrase)
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn load(&self, profile: &str) -> Result<Secrets, CliError> {
+        let (_, mut profiles) = self.read()?;
+        Ok(profiles.remove(profile).unwrap_or_default())
+    }
+
+    fn save(&self, profile: &str, secrets: &Secrets) -> Result<(), CliError> {
+        let (params, mut profiles) = self.read()?;
+        profiles.insert(profile.to_string(), secrets.clone());
+        self.write(params, &profiles)
+    }
+
+    fn delete(&self, profile: &str) -> Result<(), CliError> {
+        let (params, mut profiles) = self.read()?;
+        if profiles.remove(profile).is_some() {
+            self.write(params, &profiles)?;
+        }
+        Ok(())
+    }
+}
+
+fn remembered() -> &'static Mutex<Option<String>> {
+    static PASSPHRASE: OnceLock<Mutex<Option<String>>> = OnceLock::new();
+    PASSPHRASE.get_or_init(Default::default)
+}
+
+fn remember_passphrase(passphrase: &str) {
+    if let Ok(mut slot) = remembered().lock() {
+        *slot = Some(passphrase.to_string());
+    }
+}
+
+fn remembered_passphrase() -> Option<String> {
+    remembered().lock().ok().and_then(|slot| slot.clone())
+}
+
+/// Key derivation is deliberately slow, so the last key is kept for the
+/// rest of the process.
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<LessSafeKey, CliError> {
+    type Derived = (String, Vec<u8>, u32, [u8; 32]);
+    static LAST: OnceLock<Mutex<Option<Derived>>> = OnceLock::new();
+    let last = LAST.get_or_init(Default::default);
+
+    let cached = last.lock().ok().and_then(|slot| {
+        slot.as_ref()
+            .filter(|(p, s, i, _)| p == passphrase && s == salt && *i == iterations)
+            .map(|(_, _, _, key)| *key)
+    });
+    let key = match cached {
+        Some(key) => key,
+        None => {
+            let rounds = NonZeroU32::new(iterations).ok_or_else(|| {
+                CliError::generic("Invalid credential file: zero iterations.".to_string())
+            })?;
+            let mut key = [0u8; 32];
+            pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                rounds,
+                salt,
+                passphrase.as_bytes(),
+                &mut key,
+            );
+            if let Ok(mut slot) = last.lock() {
+                *slot = Some((passphrase.to_string(), salt.to_vec(), iterations, key));
+            }
+            key
+        }
+    };
+
+    let unbound = UnboundKey::new(&AES_256_GCM, &key)
+        .map_err(|_| CliError::generic("Failed to initialise credential cipher.".to_string()))?;
+    Ok(LessSafeKey::new(unbound))
+}
+
+fn random_error() -> CliError {
+    CliError::generic("System random number generator failed.".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str, passphrase: &str) -> EncryptedFileStore {
+        let path = std::env::temp_dir().join(format!(
+            "starbott-credentials-{name}-{}.enc",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        EncryptedFileStore::with_passphrase(path, passphrase)
+    }
+
+    #[test]
+    fn encrypted_file_round_trips_per_profile() {
+        let store = temp_store("roundtrip", "correct horse");
+        let secrets = Secrets {
+            access_token: Some("access-123".to_string()),
+            refresh_token: Some("refresh-456".to_string()),
+        };
+        store.save("default", &secrets).unwrap();
+        store.save("work", &Secrets::default()).unwrap();
+
+        let text = fs::read_to_string(&store.path).unwrap();
+        assert!(!text.contains("access-123"));
+        assert_eq!(store.load("default").unwrap(), secrets);
+
+        store.delete("default").unwrap();
+        assert_eq!(store.load("default").unwrap(), Secrets::default());
+        let _ = fs::remove_file(&store.path);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_an_auth_error() {
+        let store = temp_store("wrong", "right");
+        store
+            .save(
+                "default",
+                &Secrets {
+                    access_token: Some("t".to_string()),
+                    refresh_token: None,
+                },
+            )
+            .unwrap();
+
+        let other = EncryptedFileStore::with_passphrase(store.path.clone(), "wrong");
+        assert!(matches!(other.load("default"), Err(CliError::Auth(_))));
+        let _ = fs::remove_file(&store.path);
+    }
+}
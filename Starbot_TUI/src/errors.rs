@@ -15,20 +15,76 @@ pub enum ExitCode {
 #[derive(Debug, Error)]
 pub enum CliError {
     #[error("{0}")]
-    Usage(String),
+    Usage(ErrorDetail),
     #[error("{0}")]
-    Auth(String),
+    Auth(ErrorDetail),
     #[error("{0}")]
-    Network(String),
+    Network(ErrorDetail),
     #[error("{0}")]
-    RateLimited(String),
+    RateLimited(ErrorDetail),
     #[error("{0}")]
-    Server(String),
+    Server(ErrorDetail),
     #[error("{0}")]
-    Generic(String),
+    Generic(ErrorDetail),
+}
+
+/// The message plus whatever else is known about a failure. Everything but
+/// `message` is optional and only shown when set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorDetail {
+    pub message: String,
+    /// HTTP status of the failed request.
+    pub status: Option<u16>,
+    pub request_id: Option<String>,
+    /// Machine-readable reason such as `token_expired` or `quota_exceeded`,
+    /// taken from the server when it sends one.
+    pub reason: Option<String>,
+    /// Overrides the per-variant default of `CliError::retryable`.
+    pub retryable: Option<bool>,
+    /// What the user can do about it, e.g. "Run `starbott auth login`.".
+    pub hint: Option<String>,
+}
+
+impl From<String> for ErrorDetail {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            ..Self::default()
+        }
+    }
+}
+
+impl fmt::Display for ErrorDetail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
 }
 
 impl CliError {
+    pub fn usage(message: impl Into<String>) -> Self {
+        CliError::Usage(ErrorDetail::from(message.into()))
+    }
+
+    pub fn auth(message: impl Into<String>) -> Self {
+        CliError::Auth(ErrorDetail::from(message.into()))
+    }
+
+    pub fn network(message: impl Into<String>) -> Self {
+        CliError::Network(ErrorDetail::from(message.into()))
+    }
+
+    pub fn rate_limited(message: impl Into<String>) -> Self {
+        CliError::RateLimited(ErrorDetail::from(message.into()))
+    }
+
+    pub fn server(message: impl Into<String>) -> Self {
+        CliError::Server(ErrorDetail::from(message.into()))
+    }
+
+    pub fn generic(message: impl Into<String>) -> Self {
+        CliError::Generic(ErrorDetail::from(message.into()))
+    }
+
     pub fn exit_code(&self) -> i32 {
         match self {
             CliError::Usage(_) => ExitCode::Usage as i32,
@@ -39,32 +95,103 @@ impl CliError {
             CliError::Generic(_) => ExitCode::Generic as i32,
         }
     }
+
+    pub fn detail(&self) -> &ErrorDetail {
+        match self {
+            CliError::Usage(d)
+            | CliError::Auth(d)
+            | CliError::Network(d)
+            | CliError::RateLimited(d)
+            | CliError::Server(d)
+            | CliError::Generic(d) => d,
+        }
+    }
+
+    fn detail_mut(&mut self) -> &mut ErrorDetail {
+        match self {
+            CliError::Usage(d)
+            | CliError::Auth(d)
+            | CliError::Network(d)
+            | CliError::RateLimited(d)
+            | CliError::Server(d)
+            | CliError::Generic(d) => d,
+        }
+    }
+
+    /// Whether running the same command again may succeed. Network, rate
+    /// limit, and server errors are retryable unless the detail says otherwise.
+    pub fn retryable(&self) -> bool {
+        self.detail().retryable.unwrap_or(matches!(
+            self,
+            CliError::Network(_) | CliError::RateLimited(_) | CliError::Server(_)
+        ))
+    }
+
+    /// The reason, falling back to one derived from the variant.
+    pub fn reason(&self) -> &str {
+        if let Some(reason) = &self.detail().reason {
+            return reason;
+        }
+        match self {
+            CliError::Usage(_) => "invalid_usage",
+            CliError::Auth(_) => "unauthorized",
+            CliError::Network(_) => "network_error",
+            CliError::RateLimited(_) => "rate_limited",
+            CliError::Server(_) => "server_error",
+            CliError::Generic(_) => "error",
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.detail_mut().status = Some(status);
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.detail_mut().request_id = request_id;
+        self
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.detail_mut().reason = Some(reason.into());
+        self
+    }
+
+    pub fn with_retryable(mut self, retryable: bool) -> Self {
+        self.detail_mut().retryable = Some(retryable);
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.detail_mut().hint = Some(hint.into());
+        self
+    }
 }
 
 impl From<std::io::Error> for CliError {
     fn from(value: std::io::Error) -> Self {
-        CliError::Generic(format!("I/O error: {value}"))
+        CliError::generic(format!("I/O error: {value}"))
     }
 }
 
 impl From<serde_json::Error> for CliError {
     fn from(value: serde_json::Error) -> Self {
-        CliError::Generic(format!("JSON error: {value}"))
+        CliError::generic(format!("JSON error: {value}"))
     }
 }
 
 impl From<url::ParseError> for CliError {
     fn from(value: url::ParseError) -> Self {
-        CliError::Usage(format!("Invalid URL: {value}"))
+        CliError::usage(format!("Invalid URL: {value}"))
     }
 }
 
 impl From<reqwest::Error> for CliError {
     fn from(value: reqwest::Error) -> Self {
         if value.is_timeout() {
-            return CliError::Network("Request timed out.".to_string());
+            return CliError::network("Request timed out.".to_string());
         }
-        CliError::Network(format!("Network request failed: {value}"))
+        CliError::network(format!("Network request failed: {value}"))
     }
 }
 
@@ -0,0 +1,499 @@
+//! Undo journal for local file tools
+//!
+//! Every file the agent writes goes through a temp file and a rename, so an
+//! interrupted write never leaves it half-written. Before a file is first
+//! changed in a session its previous contents are copied into the session's
+//! journal, `journal/<session>/` in the state directory, so
+//! `starbott undo` can put it back. Files the agent created are removed
+//! instead. A file edited again after the agent wrote it is left alone unless
+//! `--force` is given.
+
+use std::cmp::Reverse;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ring::digest::{SHA256, digest};
+use serde::{Deserialize, Serialize};
+
+use crate::config::state_dir;
+use crate::conversations::{current_dir_key, now_secs};
+use crate::errors::CliError;
+
+const SESSION_VERSION: u32 = 1;
+/// Oldest sessions are dropped beyond this many.
+const MAX_SESSIONS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileEntry {
+    /// Absolute path of the file.
+    pub path: String,
+    /// Name of the copy of the previous contents; `None` if the agent created
+    /// the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+    /// SHA-256 of what the agent last wrote, to spot later edits.
+    pub written: String,
+    /// Unix seconds.
+    pub changed_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub version: u32,
+    pub id: String,
+    /// Working directory the agent ran in.
+    pub cwd: String,
+    /// Unix seconds.
+    pub created_at: u64,
+    /// Unix seconds; set once every file has been put back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undone_at: Option<u64>,
+    pub files: Vec<FileEntry>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl Session {
+    fn load(dir: &Path) -> Result<Self, CliError> {
+        let path = dir.join("session.json");
+        let text = fs::read_to_string(&path)?;
+        let mut session: Session = serde_json::from_str(&text).map_err(|err| {
+            CliError::generic(format!("Invalid undo journal {}: {err}", path.display()))
+        })?;
+        session.dir = dir.to_path_buf();
+        Ok(session)
+    }
+
+    fn save(&self) -> Result<(), CliError> {
+        let json = serde_json::to_string_pretty(self)?;
+        write_atomic(&self.dir.join("session.json"), json.as_bytes())?;
+        Ok(())
+    }
+
+    /// A number no backup in the session is named after yet.
+    fn next_backup(&self) -> usize {
+        self.files
+            .iter()
+            .filter_map(|f| f.backup.as_deref()?.strip_suffix(".orig")?.parse::<usize>().ok())
+            .max()
+            .map_or(0, |n| n + 1)
+    }
+}
+
+/// What `undo` did with each file of a session.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoReport {
+    pub session: String,
+    pub restored: Vec<String>,
+    pub removed: Vec<String>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// A file `undo` left alone, and why.
+#[derive(Debug, Serialize)]
+pub struct Skipped {
+    pub path: String,
+    pub reason: String,
+}
+
+/// The journal of one agent run. Nothing is written to disk until the first
+/// file changes.
+#[derive(Debug)]
+pub struct Journal {
+    id: String,
+    root: Option<PathBuf>,
+    session: Mutex<Option<Session>>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::in_dir(journal_root().ok())
+    }
+
+    fn in_dir(root: Option<PathBuf>) -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string()[..12].to_string();
+        Self {
+            id,
+            root,
+            session: Mutex::new(None),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// How many files this run has changed.
+    pub fn changed(&self) -> usize {
+        self.session
+            .lock()
+            .ok()
+            .and_then(|session| session.as_ref().map(|s| s.files.len()))
+            .unwrap_or(0)
+    }
+
+    /// Write `contents` to `path` atomically, journaling what was there
+    /// before the first time this run touches it.
+    pub fn write(&self, path: &Path, contents: &[u8]) -> Result<(), CliError> {
+        let mut guard = self
+            .session
+            .lock()
+            .map_err(|_| CliError::generic("Undo journal lock poisoned.".to_string()))?;
+        let session = match guard.as_mut() {
+            Some(session) => session,
+            None => guard.insert(self.start()?),
+        };
+
+        let key = path.display().to_string();
+        let idx = match session.files.iter().position(|f| f.path == key) {
+            Some(idx) => idx,
+            None => {
+                let backup = match fs::read(path) {
+                    Ok(previous) => {
+                        let name = format!("{}.orig", session.next_backup());
+                        fs::write(session.dir.join(&name), previous)?;
+                        Some(name)
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                    Err(err) => return Err(err.into()),
+                };
+                session.files.push(FileEntry {
+                    path: key,
+                    backup,
+                    written: String::new(),
+                    changed_at: now_secs(),
+                });
+                // Saved before the write, so the original survives a crash.
+                session.save()?;
+                session.files.len() - 1
+            }
+        };
+
+        write_atomic(path, contents)?;
+        let entry = &mut session.files[idx];
+        entry.written = hash(contents);
+        entry.changed_at = now_secs();
+        session.save()
+    }
+
+    /// Delete `path` again after this run created it, and forget it, so
+    /// `undo` has nothing left to do for it.
+    pub fn remove(&self, path: &Path) -> Result<(), CliError> {
+        let mut guard = self
+            .session
+            .lock()
+            .map_err(|_| CliError::generic("Undo journal lock poisoned.".to_string()))?;
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        if let Some(session) = guard.as_mut() {
+            let key = path.display().to_string();
+            let before = session.files.len();
+            session.files.retain(|f| f.path != key || f.backup.is_some());
+            if session.files.len() != before {
+                session.save()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Result<Session, CliError> {
+        let root = self.root.as_ref().ok_or_else(|| {
+            CliError::generic("No state directory for the undo journal.".to_string())
+        })?;
+        let dir = root.join(&self.id);
+        fs::create_dir_all(&dir)?;
+        prune(root, &self.id);
+        let session = Session {
+            version: SESSION_VERSION,
+            id: self.id.clone(),
+            cwd: current_dir_key(),
+            created_at: now_secs(),
+            undone_at: None,
+            files: Vec::new(),
+            dir,
+        };
+        session.save()?;
+        Ok(session)
+    }
+}
+
+fn journal_root() -> Result<PathBuf, CliError> {
+    Ok(state_dir()?.join("journal"))
+}
+
+/// Journaled sessions, newest first. Unreadable ones are skipped.
+pub fn sessions() -> Result<Vec<Session>, CliError> {
+    sessions_in(&journal_root()?)
+}
+
+fn sessions_in(root: &Path) -> Result<Vec<Session>, CliError> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut sessions: Vec<Session> = entries
+        .flatten()
+        .filter_map(|entry| Session::load(&entry.path()).ok())
+        .collect();
+    sessions.sort_by_key(|s| Reverse(s.created_at));
+    Ok(sessions)
+}
+
+/// Drop the oldest sessions so at most `MAX_SESSIONS` remain, counting the
+/// one being started.
+fn prune(root: &Path, keep: &str) {
+    let Ok(sessions) = sessions_in(root) else {
+        return;
+    };
+    for old in sessions
+        .iter()
+        .filter(|s| s.id != keep)
+        .skip(MAX_SESSIONS - 1)
+    {
+        let _ = fs::remove_dir_all(&old.dir);
+    }
+}
+
+/// The session `query` names (exact ID or unique prefix), or the newest one
+/// not yet undone.
+pub fn find(sessions: Vec<Session>, query: Option<&str>) -> Result<Session, CliError> {
+    let Some(query) = query else {
+        return sessions
+            .into_iter()
+            .find(|s| s.undone_at.is_none() && !s.files.is_empty())
+            .ok_or_else(|| {
+                CliError::usage("Nothing to undo.".to_string())
+                    .with_hint("List sessions with `starbott undo --list`.")
+            });
+    };
+
+    let mut matches: Vec<Session> = sessions
+        .into_iter()
+        .filter(|s| s.id.starts_with(query))
+        .collect();
+    if let Some(idx) = matches.iter().position(|s| s.id == query) {
+        return Ok(matches.swap_remove(idx));
+    }
+    match matches.len() {
+        0 => Err(
+            CliError::usage(format!("No undo session matches `{query}`."))
+                .with_hint("List sessions with `starbott undo --list`."),
+        ),
+        1 => Ok(matches.remove(0)),
+        n => Err(CliError::usage(format!(
+            "`{query}` matches {n} undo sessions; use more of the ID."
+        ))),
+    }
+}
+
+/// Put back every file `session` changed, newest change first. Files edited
+/// since the agent wrote them are skipped unless `force` is set; the session
+/// is only marked undone once nothing was skipped.
+pub fn undo(session: &mut Session, force: bool) -> Result<UndoReport, CliError> {
+    let mut report = UndoReport {
+        session: session.id.clone(),
+        ..Default::default()
+    };
+
+    for entry in session.files.iter().rev() {
+        let path = Path::new(&entry.path);
+        let current = match fs::read(path) {
+            Ok(bytes) => Some(hash(&bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        if !force && current.as_deref() != Some(entry.written.as_str()) {
+            let reason = if current.is_some() {
+                "changed since the agent wrote it"
+            } else {
+                "deleted since the agent wrote it"
+            };
+            report.skipped.push(Skipped {
+                path: entry.path.clone(),
+                reason: reason.to_string(),
+            });
+            continue;
+        }
+
+        match &entry.backup {
+            Some(name) => {
+                let previous = fs::read(session.dir.join(name))?;
+                write_atomic(path, &previous)?;
+                report.restored.push(entry.path.clone());
+            }
+            None => {
+                if current.is_some() {
+                    fs::remove_file(path)?;
+                }
+                report.removed.push(entry.path.clone());
+            }
+        }
+    }
+
+    if report.skipped.is_empty() {
+        session.undone_at = Some(now_secs());
+        session.save()?;
+    }
+    Ok(report)
+}
+
+/// Replace `path` with `contents` through a temp file in the same directory
+/// and a rename, keeping the existing file's permissions.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file path", path.display()),
+        )
+    })?;
+    let tmp = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        name.to_string_lossy(),
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        if let Ok(meta) = fs::metadata(path) {
+            file.set_permissions(meta.permissions())?;
+        }
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn hash(bytes: &[u8]) -> String {
+    digest(&SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("starbott-journal-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn atomic_writes_leave_no_temp_files() {
+        let dir = scratch("atomic");
+        let path = dir.join("notes.txt");
+        write_atomic(&path, b"one").unwrap();
+        write_atomic(&path, b"two").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let missing = dir.join("no/such/dir.txt");
+        assert!(write_atomic(&missing, b"x").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undo_restores_and_removes_files() {
+        let dir = scratch("undo");
+        let root = dir.join("journal");
+        let edited = dir.join("edited.txt");
+        let created = dir.join("created.txt");
+        fs::write(&edited, "original").unwrap();
+
+        let journal = Journal::in_dir(Some(root.clone()));
+        journal.write(&edited, b"first").unwrap();
+        journal.write(&edited, b"second").unwrap();
+        journal.write(&created, b"new").unwrap();
+        assert_eq!(journal.changed(), 2);
+
+        let mut session = find(sessions_in(&root).unwrap(), None).unwrap();
+        assert_eq!(session.id, journal.id());
+        let report = undo(&mut session, false).unwrap();
+        assert_eq!(report.restored, vec![edited.display().to_string()]);
+        assert_eq!(report.removed, vec![created.display().to_string()]);
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "original");
+        assert!(!created.exists());
+
+        let err = find(sessions_in(&root).unwrap(), None).unwrap_err();
+        assert_eq!(err.to_string(), "Nothing to undo.");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn removed_files_are_forgotten() {
+        let dir = scratch("remove");
+        let root = dir.join("journal");
+        let created = dir.join("created.txt");
+        let (first, second) = (dir.join("first.txt"), dir.join("second.txt"));
+        fs::write(&first, "one").unwrap();
+        fs::write(&second, "two").unwrap();
+
+        let journal = Journal::in_dir(Some(root.clone()));
+        journal.write(&created, b"new").unwrap();
+        journal.write(&first, b"changed").unwrap();
+        journal.remove(&created).unwrap();
+        assert!(!created.exists());
+        assert_eq!(journal.changed(), 1);
+        // Backups keep distinct names once an entry is gone.
+        journal.write(&second, b"changed").unwrap();
+
+        let mut session = find(sessions_in(&root).unwrap(), None).unwrap();
+        let report = undo(&mut session, false).unwrap();
+        assert!(report.skipped.is_empty() && report.removed.is_empty());
+        assert_eq!(fs::read_to_string(&first).unwrap(), "one");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "two");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn later_edits_are_kept_unless_forced() {
+        let dir = scratch("edited");
+        let root = dir.join("journal");
+        let path = dir.join("main.rs");
+        fs::write(&path, "original").unwrap();
+
+        let journal = Journal::in_dir(Some(root.clone()));
+        journal.write(&path, b"agent").unwrap();
+        fs::write(&path, "user").unwrap();
+
+        let mut session = find(sessions_in(&root).unwrap(), Some(&journal.id()[..4])).unwrap();
+        let report = undo(&mut session, false).unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "user");
+        assert!(session.undone_at.is_none());
+
+        undo(&mut session, true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert!(session.undone_at.is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
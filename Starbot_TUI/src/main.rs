@@ -1,16 +1,33 @@
 mod api;
 mod app;
+mod attachments;
+mod cassette;
 mod commands;
 mod config;
+mod conversations;
+mod credentials;
 mod cute;
 mod errors;
+mod journal;
 mod output;
 mod parse;
+mod patch;
+mod policy;
+mod recording;
+mod retry;
+mod sandbox;
+mod sse;
+mod templates;
 mod tui;
 
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
 use clap::{Parser, Subcommand};
 
 use crate::app::Runtime;
+use crate::cassette::Cassette;
 use crate::commands::animate::AnimateArgs;
 use crate::commands::agent::CLIAgentCommands;
 use crate::commands::auth::AuthCommand;
@@ -18,11 +35,17 @@ use crate::commands::billing::BillingCommand;
 use crate::commands::chat::ChatArgs;
 use crate::commands::config::ConfigCommand;
 use crate::commands::tasks::TaskCommands;
+use crate::commands::sessions::SessionsCommand;
+use crate::commands::templates::TemplatesCommand;
 use crate::commands::tools::ToolsCommand;
 use crate::commands::tui::TuiArgs;
+use crate::commands::undo::UndoArgs;
 use crate::commands::usage::UsageArgs;
 use crate::errors::CliError;
+use crate::output::format::OutputFormat;
 use crate::output::{OutputMode, print_error};
+use crate::policy::Approval;
+use crate::parse::duration::parse_duration;
 use crate::commands::workspaces::WorkspaceCommand;
 use serde_json::json;
 
@@ -37,18 +60,34 @@ struct Cli {
     profile: Option<String>,
     #[arg(long = "api-url", global = true)]
     api_url: Option<String>,
-    #[arg(long, global = true)]
+    /// Shorthand for `--output json`
+    #[arg(long, global = true, conflicts_with = "output")]
     json: bool,
+    /// Output format
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+    /// Only show these fields (comma-separated, dotted paths allowed: id,owner.email)
+    #[arg(long, global = true, value_delimiter = ',', value_name = "FIELDS")]
+    fields: Option<Vec<String>>,
     #[arg(long, global = true)]
     quiet: bool,
     #[arg(long, global = true, default_value_t = 30_000)]
     timeout: u64,
     #[arg(long, global = true, default_value_t = 2)]
     retries: u32,
+    /// Maximum total time spent retrying one request, waits included (e.g. 30s, 2m)
+    #[arg(long = "retry-budget", global = true, value_name = "DURATION", default_value = "60s", value_parser = parse_duration)]
+    retry_budget: Duration,
     #[arg(long, global = true)]
     verbose: bool,
     #[arg(long, global = true)]
     debug: bool,
+    /// Write every API request and response to a redacted cassette file
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Serve API responses from a cassette file instead of the network
+    #[arg(long, global = true, value_name = "FILE")]
+    replay: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -89,6 +128,18 @@ enum Commands {
         #[command(subcommand)]
         command: AgentCommand,
     },
+    /// Prompt templates for `chat --template`
+    Templates {
+        #[command(subcommand)]
+        command: TemplatesCommand,
+    },
+    /// Restore files changed by agent tool calls
+    Undo(UndoArgs),
+    /// Recordings of shell sessions the agent ran
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -106,9 +157,6 @@ struct InitArgs {
     /// Model to use
     #[arg(long, default_value = "gpt-4")]
     model: String,
-    /// Enable task management
-    #[arg(long)]
-    enable_tasks: bool,
 }
 
 #[derive(Debug, clap::Args)]
@@ -119,6 +167,9 @@ struct ProcessArgs {
     /// Model to use
     #[arg(long, default_value = "gpt-4")]
     model: String,
+    /// Run tool calls that the tool policy would ask about without asking (deny rules still apply)
+    #[arg(long)]
+    yolo: bool,
 }
 
 #[derive(Debug, clap::Args)]
@@ -128,22 +179,46 @@ struct RunArgs {
     /// Project ID to scope the chat
     #[arg(long)]
     pub project_id: Option<String>,
-    /// Existing chat ID to continue
+    /// Chat to continue: an ID, a unique ID prefix, or a title from `chat sessions list`
     #[arg(long)]
     pub chat_id: Option<String>,
     /// Model preference (e.g. "azure:gpt-4o")
     #[arg(short = 'm', long)]
     pub model: Option<String>,
+    /// Attach a file (repeatable; `-` reads stdin). Text over 100KB is truncated
+    #[arg(long = "file", value_name = "PATH")]
+    pub files: Vec<PathBuf>,
+    /// Attach every file matching a glob such as `src/**/*.rs` (repeatable)
+    #[arg(long = "glob", value_name = "PATTERN")]
+    pub globs: Vec<String>,
+    /// System prompt to run with (front-matter and {{variables}} supported)
+    #[arg(long, value_name = "PATH")]
+    pub system_prompt_file: Option<PathBuf>,
+    /// Template variable (repeatable): --var file=src/api.rs
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = templates::parse_var)]
+    pub vars: Vec<(String, String)>,
+    /// Let the model call local tools (files, shell, tasks) on this machine
+    #[arg(long)]
+    pub local_tools: bool,
+    /// Model turns allowed with --local-tools before giving up (default 50)
+    #[arg(long, value_name = "N", requires = "local_tools", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_iterations: Option<u32>,
+    /// Run tool calls that the tool policy would ask about without asking (deny rules still apply)
+    #[arg(long, requires = "local_tools")]
+    pub yolo: bool,
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    let format = if cli.json { OutputFormat::Json } else { cli.output };
     let output = OutputMode {
-        json: cli.json,
+        json: format != OutputFormat::Table,
         quiet: cli.quiet,
         verbose: cli.verbose,
         debug: cli.debug,
+        format,
+        fields: cli.fields.clone(),
     };
 
     cute::print_banner(&output);
@@ -158,6 +233,11 @@ async fn main() {
 async fn run(cli: Cli, output: OutputMode) -> Result<(), CliError> {
     let config = config::load_config()?;
     let config_path = config::config_path()?;
+    let cassette = match (&cli.record, &cli.replay) {
+        (Some(path), _) => Some(Arc::new(Cassette::record(path)?)),
+        (None, Some(path)) => Some(Arc::new(Cassette::replay(path)?)),
+        (None, None) => None,
+    };
 
     let mut runtime = Runtime {
         output,
@@ -167,6 +247,8 @@ async fn run(cli: Cli, output: OutputMode) -> Result<(), CliError> {
         api_url_override: cli.api_url,
         timeout_ms: cli.timeout,
         retries: cli.retries,
+        retry_budget: cli.retry_budget,
+        cassette,
     };
 
     match cli.command {
@@ -183,6 +265,9 @@ async fn run(cli: Cli, output: OutputMode) -> Result<(), CliError> {
         Commands::Health => commands::health::handle(&runtime).await,
         Commands::Tasks { command } => commands::tasks::handle_tasks(&runtime, command).await,
         Commands::Agent { command } => handle_agent_command(&mut runtime, command).await,
+        Commands::Templates { command } => commands::templates::handle(&runtime, command).await,
+        Commands::Undo(args) => commands::undo::handle(&runtime, args).await,
+        Commands::Sessions { command } => commands::sessions::handle(&runtime, command).await,
     }
 }
 
@@ -193,9 +278,6 @@ async fn handle_agent_command(runtime: &mut Runtime, command: AgentCommand) -> R
         AgentCommand::Init(args) => {
             let config = crate::commands::agent::AgentConfig {
                 model: args.model,
-                max_tokens: 4096,
-                temperature: 0.7,
-                enable_tasks: args.enable_tasks,
                 ..Default::default()
             };
 
@@ -211,20 +293,35 @@ async fn handle_agent_command(runtime: &mut Runtime, command: AgentCommand) -> R
             }
         }
         AgentCommand::Process(args) => {
+            runtime.config.tool_policy.validate()?;
             let config = crate::commands::agent::AgentConfig {
                 model: args.model,
+                tool_policy: runtime.config.tool_policy.clone(),
+                approval: if args.yolo { Approval::Granted } else { Approval::Prompt },
+                workspace_id: runtime.workspace_id(),
                 ..Default::default()
             };
 
             match CLIAgentCommands::create(config, api).await {
                 Ok(mut agent) => {
-                    match CLIAgentCommands::process_task(&mut agent, &args.task_id).await {
+                    let processed = CLIAgentCommands::process_task(&mut agent, &args.task_id).await;
+                    agent.close_shells().await;
+                    match processed {
                         Ok(_) => {
+                            let stats = agent.stats();
                             if runtime.output.json {
-                                runtime.output.print_json(&json!({ "success": true, "message": "Task processed" }))?;
+                                let mut out = json!({ "success": true, "message": "Task processed", "stats": stats });
+                                if agent.journal().changed() > 0 {
+                                    out["undoSession"] = json!(agent.journal().id());
+                                }
+                                runtime.output.print_json(&out)?;
                             } else {
-                                runtime.output.print_human("✓ Task processed successfully");
+                                runtime.output.print_human(&format!(
+                                    "✓ Task processed successfully ({} model turns, {} tool calls, {} failed)",
+                                    stats.total_requests, stats.total_tool_calls, stats.failed_tool_calls
+                                ));
                             }
+                            crate::commands::agent::report_changes(runtime, agent.journal());
                         }
                         Err(e) => return Err(e),
                     }
@@ -233,14 +330,35 @@ async fn handle_agent_command(runtime: &mut Runtime, command: AgentCommand) -> R
             }
         }
         AgentCommand::Run(args) => {
-            crate::commands::agent::handle_run(
-                &api,
-                &runtime,
-                args.prompt,
-                args.project_id,
-                args.chat_id,
-                args.model,
-            ).await?;
+            let (chat_id, project_id) = match &args.chat_id {
+                Some(query) => {
+                    let (id, indexed_project) = conversations::resolve_chat(runtime, query)?;
+                    (Some(id), args.project_id.or(indexed_project))
+                }
+                None => (None, args.project_id),
+            };
+            let attachments = attachments::load(runtime, &args.files, &args.globs)?;
+            let prepared = templates::prepare(
+                Some(args.prompt),
+                None,
+                args.system_prompt_file.as_deref(),
+                &args.vars,
+            )?;
+            if let Some(notice) = prepared.temperature_notice() {
+                runtime.output.print_stderr(&notice);
+            }
+            let options = crate::commands::agent::RunOptions {
+                project_id,
+                chat_id,
+                model_prefs: args.model.or(prepared.front.model),
+                attachments,
+                system_prompt: prepared.system,
+                lane: prepared.front.lane,
+                local_tools: args.local_tools,
+                max_iterations: args.max_iterations,
+                yolo: args.yolo,
+            };
+            crate::commands::agent::handle_run(&api, &runtime, prepared.prompt, options).await?;
         }
     }
 
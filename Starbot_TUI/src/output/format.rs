@@ -0,0 +1,257 @@
+//! Renderers behind `--output`: aligned tables, CSV, YAML, and `--fields`
+//! selection. Everything works on `serde_json::Value` so list commands can feed
+//! API payloads in directly.
+
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text; lists are aligned tables
+    #[default]
+    Table,
+    /// One JSON document
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    Yaml,
+    /// Header row plus one row per item
+    Csv,
+}
+
+/// Follow a dotted path such as `owner.email` or `items.0.id`.
+pub fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |current, key| match current {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+/// Keep only `fields` of an object (or of each object in an array). Selected
+/// values are keyed by their path; missing ones become `null`.
+pub fn select_fields(value: &Value, fields: &[String]) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.iter().map(|v| select_fields(v, fields)).collect()),
+        Value::Object(_) => Value::Object(
+            fields
+                .iter()
+                .map(|f| (f.clone(), lookup(value, f).cloned().unwrap_or(Value::Null)))
+                .collect::<Map<_, _>>(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// A value as one line of text: strings unquoted, `null` empty, and nested
+/// values as compact JSON.
+pub fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Left-aligned columns separated by two spaces, padded by display width so
+/// CJK and emoji line up.
+pub fn render_table(headers: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    let clean = |cell: &str| cell.replace(['\n', '\r', '\t'], " ");
+    let rows: Vec<Vec<String>> = rows.iter().map(|r| r.iter().map(|c| clean(c)).collect()).collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.width()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+
+    std::iter::once(headers.to_vec())
+        .chain(rows)
+        .map(|row| {
+            let mut line = String::new();
+            for (idx, (cell, width)) in row.iter().zip(&widths).enumerate() {
+                if idx > 0 {
+                    line.push_str("  ");
+                }
+                line.push_str(cell);
+                line.push_str(&" ".repeat(width.saturating_sub(cell.width())));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// RFC 4180 CSV with a header row.
+pub fn render_csv(headers: &[String], rows: &[Vec<String>]) -> String {
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .map(|cell| {
+                if cell.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    let mut out = line(headers);
+    out.push('\n');
+    for row in rows {
+        out.push_str(&line(row));
+        out.push('\n');
+    }
+    out
+}
+
+/// Block-style YAML. Strings that YAML would read as something else are
+/// double-quoted (JSON string syntax is valid YAML).
+pub fn render_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => write_map(&mut out, map, 0),
+        Value::Array(items) if !items.is_empty() => write_seq(&mut out, items, 0),
+        other => {
+            out.push_str(&yaml_scalar(other));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn write_map(out: &mut String, map: &Map<String, Value>, indent: usize) {
+    for (key, value) in map {
+        out.push_str(&" ".repeat(indent));
+        out.push_str(&yaml_string(key));
+        out.push(':');
+        write_nested(out, value, indent + 2);
+    }
+}
+
+fn write_seq(out: &mut String, items: &[Value], indent: usize) {
+    for item in items {
+        out.push_str(&" ".repeat(indent));
+        out.push('-');
+        match item {
+            // The first entry shares the dash's line.
+            Value::Object(map) if !map.is_empty() => {
+                let mut nested = String::new();
+                write_map(&mut nested, map, indent + 2);
+                out.push(' ');
+                out.push_str(&nested[indent + 2..]);
+            }
+            Value::Array(inner) if !inner.is_empty() => {
+                let mut nested = String::new();
+                write_seq(&mut nested, inner, indent + 2);
+                out.push(' ');
+                out.push_str(&nested[indent + 2..]);
+            }
+            scalar => {
+                out.push(' ');
+                out.push_str(&yaml_scalar(scalar));
+                out.push('\n');
+            }
+        }
+    }
+}
+
+fn write_nested(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            write_map(out, map, indent);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_seq(out, items, indent);
+        }
+        scalar => {
+            out.push(' ');
+            out.push_str(&yaml_scalar(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::String(s) => yaml_string(s),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn yaml_string(s: &str) -> String {
+    const RESERVED: [&str; 11] = [
+        "null", "~", "true", "false", "yes", "no", "on", "off", "y", "n", "<<",
+    ];
+    let needs_quotes = s.is_empty()
+        || s != s.trim()
+        || RESERVED.contains(&s.to_ascii_lowercase().as_str())
+        || s.starts_with(|c: char| c.is_ascii_digit() || "-+.?:,[]{}#&*!|>'\"%@`".contains(c))
+        || s.contains(": ")
+        || s.contains(" #")
+        || s.ends_with(':')
+        || s.chars().any(char::is_control);
+    if needs_quotes {
+        serde_json::to_string(s).unwrap_or_default()
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn table_pads_by_display_width() {
+        let headers = vec!["NAME".to_string(), "ID".to_string()];
+        let rows = vec![
+            vec!["日本語".to_string(), "ws-1".to_string()],
+            vec!["a".to_string(), "ws-22".to_string()],
+        ];
+        assert_eq!(
+            render_table(&headers, &rows),
+            vec!["NAME    ID", "日本語  ws-1", "a       ws-22"]
+        );
+    }
+
+    #[test]
+    fn csv_quotes_only_when_needed() {
+        let headers = vec!["id".to_string(), "note".to_string()];
+        let rows = vec![vec!["1".to_string(), "say \"hi\", then\nleave".to_string()]];
+        assert_eq!(render_csv(&headers, &rows), "id,note\n1,\"say \"\"hi\"\", then\nleave\"\n");
+    }
+
+    #[test]
+    fn yaml_nests_and_quotes_ambiguous_strings() {
+        let value = json!({
+            "tasks": [{ "id": "t1", "done": false, "tags": ["a", "yes"] }],
+            "count": 1,
+            "when": "2024-01-01",
+            "empty": "",
+        });
+        assert_eq!(
+            render_yaml(&value),
+            "count: 1\nempty: \"\"\ntasks:\n  - done: false\n    id: t1\n    tags:\n      - a\n      - \"yes\"\nwhen: \"2024-01-01\"\n"
+        );
+    }
+
+    #[test]
+    fn fields_follow_dotted_paths() {
+        let rows = json!([{ "id": "w1", "owner": { "email": "a@b.c" }, "secret": 1 }]);
+        let fields = vec!["id".to_string(), "owner.email".to_string(), "missing".to_string()];
+        assert_eq!(
+            select_fields(&rows, &fields),
+            json!([{ "id": "w1", "owner.email": "a@b.c", "missing": null }])
+        );
+    }
+}
@@ -0,0 +1,193 @@
+pub mod format;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::errors::CliError;
+use format::{OutputFormat, lookup, render_csv, render_table, render_yaml, scalar_text, select_fields};
+
+#[derive(Debug, Clone)]
+pub struct OutputMode {
+    /// Machine-readable output: any `--output` other than `table`.
+    pub json: bool,
+    pub quiet: bool,
+    pub verbose: bool,
+    pub debug: bool,
+    pub format: OutputFormat,
+    /// `--fields`: paths to keep, in order.
+    pub fields: Option<Vec<String>>,
+}
+
+/// A list column: table header and the field path it shows.
+pub type Column = (&'static str, &'static str);
+
+impl OutputMode {
+    /// Print a structured result in the selected format, narrowed to `--fields`.
+    pub fn print_json<T: Serialize>(&self, value: &T) -> Result<(), CliError> {
+        let mut value = serde_json::to_value(value)?;
+        if let Some(fields) = &self.fields {
+            value = select_fields(&value, fields);
+        }
+
+        match self.format {
+            OutputFormat::Yaml => print!("{}", render_yaml(&value)),
+            OutputFormat::Csv => {
+                let rows: Vec<Value> = match value {
+                    Value::Array(items) => items,
+                    other => vec![other],
+                }
+                .into_iter()
+                .map(|row| match row {
+                    Value::Object(_) => row,
+                    scalar => serde_json::json!({ "value": scalar }),
+                })
+                .collect();
+                let headers = self.fields.clone().unwrap_or_else(|| csv_headers(&rows));
+                self.print_csv(&headers, &rows);
+            }
+            _ => println!("{}", serde_json::to_string(&value)?),
+        }
+        Ok(())
+    }
+
+    /// Print one event of a stream as a compact JSON line (NDJSON), narrowed
+    /// to `--fields`, whatever the output format.
+    pub fn print_event<T: Serialize>(&self, event: &T) -> Result<(), CliError> {
+        let mut value = serde_json::to_value(event)?;
+        if let Some(fields) = &self.fields {
+            value = select_fields(&value, fields);
+        }
+        println!("{}", serde_json::to_string(&value)?);
+        Ok(())
+    }
+
+    /// Print a list. Tables show `columns` (or `--fields`); `json` and `yaml`
+    /// print the untouched API payload `raw` unless `--fields` narrows the rows;
+    /// `ndjson` and `csv` print one line per row.
+    pub fn print_list(
+        &self,
+        raw: &Value,
+        rows: &[Value],
+        columns: &[Column],
+        empty_message: &str,
+    ) -> Result<(), CliError> {
+        let paths: Vec<String> = match &self.fields {
+            Some(fields) => fields.clone(),
+            None => columns.iter().map(|(_, path)| path.to_string()).collect(),
+        };
+
+        match self.format {
+            OutputFormat::Table => {
+                if rows.is_empty() {
+                    self.print_human(empty_message);
+                    return Ok(());
+                }
+                let headers: Vec<String> = match &self.fields {
+                    Some(fields) => fields.iter().map(|f| f.to_uppercase()).collect(),
+                    None => columns.iter().map(|(header, _)| header.to_string()).collect(),
+                };
+                let cells: Vec<Vec<String>> = rows
+                    .iter()
+                    .map(|row| {
+                        paths
+                            .iter()
+                            .map(|path| match lookup(row, path) {
+                                None | Some(Value::Null) => "-".to_string(),
+                                Some(value) => scalar_text(value),
+                            })
+                            .collect()
+                    })
+                    .collect();
+                for line in render_table(&headers, &cells) {
+                    self.print_human(&line);
+                }
+            }
+            OutputFormat::Ndjson => {
+                for row in rows {
+                    let row = match &self.fields {
+                        Some(fields) => select_fields(row, fields),
+                        None => row.clone(),
+                    };
+                    println!("{}", serde_json::to_string(&row)?);
+                }
+            }
+            OutputFormat::Csv => self.print_csv(&paths, rows),
+            OutputFormat::Json | OutputFormat::Yaml => match &self.fields {
+                Some(_) => self.print_json(&rows)?,
+                None => self.print_json(raw)?,
+            },
+        }
+        Ok(())
+    }
+
+    fn print_csv(&self, headers: &[String], rows: &[Value]) {
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                headers
+                    .iter()
+                    .map(|path| lookup(row, path).map(scalar_text).unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+        print!("{}", render_csv(headers, &cells));
+    }
+
+    pub fn print_human(&self, message: &str) {
+        if self.json || self.quiet {
+            return;
+        }
+        println!("{message}");
+    }
+
+    pub fn print_stderr(&self, message: &str) {
+        if self.json || self.quiet {
+            return;
+        }
+        eprintln!("{message}");
+    }
+
+    pub fn print_verbose(&self, message: &str) {
+        if !self.verbose || self.json || self.quiet {
+            return;
+        }
+        eprintln!("{message}");
+    }
+}
+
+/// Top-level keys of the first row, for CSV without `--fields`.
+fn csv_headers(rows: &[Value]) -> Vec<String> {
+    match rows.first() {
+        Some(Value::Object(map)) => map.keys().cloned().collect(),
+        _ => vec!["value".to_string()],
+    }
+}
+
+pub fn print_error(error: &CliError, mode: &OutputMode) {
+    let detail = error.detail();
+    if mode.json {
+        let payload = serde_json::json!({
+            "error": error.to_string(),
+            "code": error.exit_code(),
+            "reason": error.reason(),
+            "retryable": error.retryable(),
+            "status": detail.status,
+            "requestId": detail.request_id,
+            "hint": detail.hint,
+        });
+        println!(
+            "{}",
+            serde_json::to_string(&payload)
+                .unwrap_or_else(|_| "{\"error\":\"unknown\"}".to_string())
+        );
+        return;
+    }
+
+    match &detail.request_id {
+        Some(id) => eprintln!("Error: {error} (request_id: {id})"),
+        None => eprintln!("Error: {error}"),
+    }
+    if let Some(hint) = &detail.hint {
+        eprintln!("Hint: {hint}");
+    }
+}
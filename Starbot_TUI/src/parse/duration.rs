@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+/// Parse a human duration for CLI flags: `1500ms`, `30s`, `2m`, `1h`.
+/// A bare number is taken as seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{input}` (examples: 500ms, 30s, 2m)"))?;
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        other => return Err(format!("unknown duration unit `{other}` (use ms, s, m, or h)")),
+    };
+
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("duration `{input}` is out of range"))
+}
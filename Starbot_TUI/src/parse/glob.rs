@@ -0,0 +1,143 @@
+//! Shell-style globs: `*`, `?`, `**`, `[abc]`/`[!abc]` and `{a,b}`.
+//! Paths are matched with `/` separators; `*` never crosses one, `**` does.
+
+use regex::Regex;
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let regex = Regex::new(&to_regex(pattern)?)
+            .map_err(|err| format!("invalid glob `{pattern}`: {err}"))?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.regex.is_match(&path.replace('\\', "/"))
+    }
+
+    /// The leading directories that contain no wildcards, i.e. where a walk
+    /// for matches can start. Empty for patterns like `*.rs`.
+    pub fn base_dir(&self) -> &str {
+        let wild = self
+            .pattern
+            .find(['*', '?', '[', '{'])
+            .unwrap_or(self.pattern.len());
+        match self.pattern[..wild].rfind('/') {
+            Some(0) => "/",
+            Some(slash) => &self.pattern[..slash],
+            None if wild == self.pattern.len() => &self.pattern,
+            None => "",
+        }
+    }
+}
+
+fn to_regex(pattern: &str) -> Result<String, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut out = String::from("^");
+    let mut braces = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_segment_start = i == 0 || chars[i - 1] == '/';
+                if at_segment_start && chars.get(i + 2) == Some(&'/') {
+                    out.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    out.push_str(".*");
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => {
+                let close = chars[i + 1..]
+                    .iter()
+                    .skip(1)
+                    .position(|&c| c == ']')
+                    .map(|p| i + 2 + p)
+                    .ok_or_else(|| format!("invalid glob `{pattern}`: unclosed `[`"))?;
+                out.push('[');
+                let mut body = &chars[i + 1..close];
+                if let Some(('!' | '^', rest)) = body.split_first() {
+                    out.push('^');
+                    body = rest;
+                }
+                for &c in body {
+                    if c == '\\' || c == '[' {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                out.push(']');
+                i = close + 1;
+                continue;
+            }
+            '{' => {
+                braces += 1;
+                out.push_str("(?:");
+            }
+            '}' if braces > 0 => {
+                braces -= 1;
+                out.push(')');
+            }
+            ',' if braces > 0 => out.push('|'),
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    if braces > 0 {
+        return Err(format!("invalid glob `{pattern}`: unclosed `{{`"));
+    }
+    out.push('$');
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn stars_stay_within_a_segment() {
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/commands/chat.rs"));
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/commands/chat.rs"));
+        assert!(matches("**/.env*", ".env.local"));
+        assert!(matches("**/.env*", "deploy/.env"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(!matches("file?.txt", "file10.txt"));
+    }
+
+    #[test]
+    fn classes_and_alternatives() {
+        assert!(matches("log[0-9].txt", "log3.txt"));
+        assert!(!matches("log[!0-9].txt", "log3.txt"));
+        assert!(matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(!matches("*.{rs,toml}", "Cargo.lock"));
+        assert!(matches("a+b(1).txt", "a+b(1).txt"));
+        assert!(Glob::new("src/[abc").is_err());
+        assert!(Glob::new("src/{a,b").is_err());
+    }
+
+    #[test]
+    fn base_dir_is_the_literal_prefix() {
+        assert_eq!(Glob::new("src/**/*.rs").unwrap().base_dir(), "src");
+        assert_eq!(Glob::new("*.rs").unwrap().base_dir(), "");
+        assert_eq!(Glob::new("/var/log/*.log").unwrap().base_dir(), "/var/log");
+        assert_eq!(Glob::new("/*.log").unwrap().base_dir(), "/");
+    }
+}
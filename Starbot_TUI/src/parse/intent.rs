@@ -0,0 +1,193 @@
+//! Task intents in chat prompts
+//!
+//! `chat --task` parses its prompt with [`parse_task`]. Without `--task`, only
+//! prompts that *open* with an explicit command (`create task: ...`,
+//! `make a task called ...`, `add task "..."`) are offered as a task, and the
+//! user still confirms before anything is created. Questions that merely
+//! mention tasks are left alone.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// What `chat` would create, shown to the user before confirming.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskDraft {
+    pub title: String,
+    pub description: Option<String>,
+    /// 0-10, as `/v1/tasks` expects; 0 means unset.
+    pub priority: i32,
+}
+
+/// An explicit command at the very start of the prompt, followed by `:`,
+/// `called`/`named`/`titled`, or a quoted title.
+static COMMAND: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)^\s*(?:please\s+)?(?:create|make|add)\s+(?:a\s+)?(?:new\s+)?task\s*(?::|(?:called|named|titled)\s|")"#)
+        .expect("valid regex")
+});
+
+/// The same command, optional tail included, for stripping before parsing.
+static COMMAND_PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^\s*(?:please\s+)?(?:create|make|add)\s+(?:a\s+)?(?:new\s+)?task\b\s*(?::|(?:called|named|titled)\b)?\s*")
+        .expect("valid regex")
+});
+
+static FIELD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(description|priority)\s*:").expect("valid regex"));
+
+static PRIORITY_PHRASE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(high|medium|low)[- ]priority\b").expect("valid regex"));
+
+/// A task the prompt explicitly asks for, or `None` for anything else.
+pub fn detect_task_intent(prompt: &str) -> Option<TaskDraft> {
+    if !COMMAND.is_match(prompt) {
+        return None;
+    }
+    let draft = parse_task(prompt);
+    (draft.title != UNTITLED).then_some(draft)
+}
+
+const UNTITLED: &str = "Untitled Task";
+
+/// Parse `<title> [description: ...] [priority: high|medium|low|0-10]`. The
+/// title may be quoted, in which case the rest of the sentence becomes the
+/// description; `high priority` and friends work anywhere.
+pub fn parse_task(text: &str) -> TaskDraft {
+    let text = COMMAND_PREFIX.replace(text, "");
+
+    let fields: Vec<_> = FIELD.captures_iter(&text).collect();
+    let head_end = fields
+        .first()
+        .map_or(text.len(), |c| c.get(0).map_or(0, |m| m.start()));
+    let mut description = None;
+    let mut priority = None;
+    for (idx, captures) in fields.iter().enumerate() {
+        let (Some(whole), Some(name)) = (captures.get(0), captures.get(1)) else {
+            continue;
+        };
+        let end = fields
+            .get(idx + 1)
+            .and_then(|next| next.get(0))
+            .map_or(text.len(), |m| m.start());
+        let value = clean(&text[whole.end()..end]);
+        if value.is_empty() {
+            continue;
+        }
+        if name.as_str().eq_ignore_ascii_case("description") {
+            description = Some(value);
+        } else {
+            priority = priority_value(&value);
+        }
+    }
+
+    let mut head = text[..head_end].to_string();
+    if let Some(found) = PRIORITY_PHRASE.captures(&head) {
+        priority = priority.or_else(|| priority_value(&found[1]));
+        head = PRIORITY_PHRASE.replace(&head, "").to_string();
+    }
+
+    let title = match quoted(&head) {
+        Some((title, rest)) => {
+            let rest = clean(rest.trim_start_matches(['-', ',', ':', ' ']));
+            if description.is_none() && !rest.is_empty() {
+                description = Some(rest);
+            }
+            title
+        }
+        None => clean(&head),
+    };
+
+    TaskDraft {
+        title: if title.is_empty() {
+            UNTITLED.to_string()
+        } else {
+            title
+        },
+        description,
+        priority: priority.unwrap_or(0),
+    }
+}
+
+/// The first `"quoted"` span and the text around it.
+fn quoted(text: &str) -> Option<(String, String)> {
+    let start = text.find('"')?;
+    let len = text[start + 1..].find('"')?;
+    let title = clean(&text[start + 1..start + 1 + len]);
+    let rest = format!("{} {}", &text[..start], &text[start + 2 + len..]);
+    Some((title, rest))
+}
+
+fn priority_value(value: &str) -> Option<i32> {
+    match value.to_ascii_lowercase().as_str() {
+        "high" | "urgent" => Some(8),
+        "medium" | "normal" => Some(5),
+        "low" => Some(2),
+        other => other.parse::<i32>().ok().filter(|n| (0..=10).contains(n)),
+    }
+}
+
+fn clean(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['.', ',', ';'])
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn questions_about_tasks_are_not_intents() {
+        for prompt in [
+            "how do I create task queues in Celery?",
+            "Can you make task lists more readable?",
+            "I want to create tasks in Jira from a script",
+            "create task queues in Celery",
+            "Why does `make task` fail in CI?",
+            "Explain: create task vs. spawn task in tokio",
+            "add task",
+            "create task:",
+        ] {
+            assert_eq!(detect_task_intent(prompt), None, "{prompt}");
+        }
+    }
+
+    #[test]
+    fn explicit_commands_are_intents() {
+        let draft = detect_task_intent("create task: Write docs priority: high").unwrap();
+        assert_eq!(
+            draft,
+            TaskDraft {
+                title: "Write docs".to_string(),
+                description: None,
+                priority: 8
+            }
+        );
+
+        let draft = detect_task_intent(
+            "Please make a new task called \"Ship v2\" for the release description: cut the tag priority: 7",
+        )
+        .unwrap();
+        assert_eq!(draft.title, "Ship v2");
+        assert_eq!(draft.description.as_deref(), Some("cut the tag"));
+        assert_eq!(draft.priority, 7);
+
+        let draft = detect_task_intent("add task \"Fix login\" before Friday.").unwrap();
+        assert_eq!(draft.title, "Fix login");
+        assert_eq!(draft.description.as_deref(), Some("before Friday"));
+    }
+
+    #[test]
+    fn parse_task_takes_the_whole_prompt_as_a_task() {
+        let draft = parse_task("Refactor the parser, high priority");
+        assert_eq!(draft.title, "Refactor the parser");
+        assert_eq!(draft.priority, 8);
+
+        let draft = parse_task("Rotate keys description: all staging hosts priority: eleven");
+        assert_eq!(draft.title, "Rotate keys");
+        assert_eq!(draft.description.as_deref(), Some("all staging hosts"));
+        assert_eq!(draft.priority, 0);
+    }
+}
@@ -1 +1,4 @@
+pub mod duration;
+pub mod glob;
+pub mod intent;
 pub mod response;
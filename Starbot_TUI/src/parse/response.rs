@@ -48,11 +48,13 @@ pub fn extract_usage_line(payload: &Value) -> String {
         let input = usage
             .get("inputTokens")
             .or_else(|| usage.get("input_tokens"))
+            .or_else(|| usage.get("prompt_tokens"))
             .and_then(|v| v.as_u64())
             .unwrap_or(0);
         let output = usage
             .get("outputTokens")
             .or_else(|| usage.get("output_tokens"))
+            .or_else(|| usage.get("completion_tokens"))
             .and_then(|v| v.as_u64())
             .unwrap_or(0);
         let total = usage
@@ -0,0 +1,646 @@
+//! Patches for the `apply_patch` tool
+//!
+//! Two formats are accepted. Unified diffs, as `git diff` prints them:
+//!
+//! ```text
+//! --- a/src/main.rs
+//! +++ b/src/main.rs
+//! @@ -1,3 +1,3 @@
+//!  fn main() {
+//! -    println!("hello");
+//! +    println!("hello, world");
+//!  }
+//! ```
+//!
+//! and search/replace blocks, preceded by the file they apply to (or taking
+//! it from the tool's `path` argument):
+//!
+//! ```text
+//! src/main.rs
+//! <<<<<<< SEARCH
+//!     println!("hello");
+//! =======
+//!     println!("hello, world");
+//! >>>>>>> REPLACE
+//! ```
+//!
+//! Hunks are placed with fuzz: the line numbers in `@@` headers are only a
+//! hint (the nearest match wins), lines may differ in whitespace, and up to
+//! `MAX_FUZZ` lines of context at either end may be dropped. Search/replace
+//! blocks and hunks without line numbers must match exactly one place.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::Value;
+
+/// Context lines a hunk may lose at each end and still apply.
+const MAX_FUZZ: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+impl Op {
+    /// The line this op expects in the original file.
+    fn old_line(&self) -> Option<&str> {
+        match self {
+            Op::Context(line) | Op::Remove(line) => Some(line),
+            Op::Add(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The `@@` line; `None` for search/replace blocks.
+    header: Option<String>,
+    /// 1-based line the hunk starts at in the original, when the header says.
+    old_start: Option<usize>,
+    ops: Vec<Op>,
+}
+
+impl Hunk {
+    fn label(&self, idx: usize) -> String {
+        match &self.header {
+            Some(header) => format!("hunk {} ({header})", idx + 1),
+            None => format!("block {}", idx + 1),
+        }
+    }
+
+    fn old_len(&self) -> usize {
+        self.ops.iter().filter_map(Op::old_line).count()
+    }
+
+    fn leading_context(&self) -> usize {
+        self.ops
+            .iter()
+            .take_while(|op| matches!(op, Op::Context(_)))
+            .count()
+    }
+
+    fn trailing_context(&self) -> usize {
+        let lead = self.leading_context();
+        self.ops[lead..]
+            .iter()
+            .rev()
+            .take_while(|op| matches!(op, Op::Context(_)))
+            .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    pub path: String,
+    /// `--- /dev/null`: the file must not exist yet.
+    pub create: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// Whether the patch can apply to a file that does not exist.
+    pub fn creates(&self) -> bool {
+        self.create || self.hunks.iter().all(|h| h.old_len() == 0)
+    }
+}
+
+/// Where a hunk went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// 1-based line of the first line the hunk touched.
+    pub line: usize,
+    /// Lines away from where the header said, for hunks with line numbers.
+    pub offset: Option<isize>,
+    /// Context lines dropped at each end.
+    pub fuzz: usize,
+    /// Matched only after ignoring whitespace.
+    pub loose: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HunkOutcome {
+    pub label: String,
+    pub result: Result<Placement, String>,
+}
+
+impl fmt::Display for HunkOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let placed = match &self.result {
+            Ok(placed) => placed,
+            Err(reason) => return write!(f, "{}: failed, {reason}", self.label),
+        };
+        write!(f, "{}: applied at line {}", self.label, placed.line)?;
+        let mut notes = Vec::new();
+        if let Some(offset) = placed.offset.filter(|o| *o != 0) {
+            notes.push(format!("offset {offset:+}"));
+        }
+        if placed.fuzz > 0 {
+            notes.push(format!("fuzz {}", placed.fuzz));
+        }
+        if placed.loose {
+            notes.push("ignoring whitespace".to_string());
+        }
+        if !notes.is_empty() {
+            write!(f, " ({})", notes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The patched file, or `None` when any hunk failed, plus what happened to
+/// each hunk.
+#[derive(Debug)]
+pub struct FileOutcome {
+    pub contents: Option<String>,
+    pub hunks: Vec<HunkOutcome>,
+}
+
+/// Parse a patch in either format. `default_path` names the file for
+/// search/replace blocks and headerless hunks that do not name one.
+pub fn parse(text: &str, default_path: Option<&str>) -> Result<Vec<FilePatch>, String> {
+    let files = if text.lines().any(|line| line.starts_with("<<<<<<< SEARCH")) {
+        parse_search_replace(text, default_path)?
+    } else {
+        parse_unified(text, default_path)?
+    };
+    if files.iter().all(|f| f.hunks.is_empty()) {
+        return Err("The patch has no hunks or search/replace blocks.".to_string());
+    }
+    Ok(files)
+}
+
+fn parse_unified(text: &str, default_path: Option<&str>) -> Result<Vec<FilePatch>, String> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some(old) = line.strip_prefix("--- ")
+            && let Some(new) = lines.peek().and_then(|l| l.strip_prefix("+++ "))
+        {
+            let (old, new) = (diff_path(old), diff_path(new));
+            lines.next();
+            if new == "/dev/null" {
+                return Err(format!(
+                    "Deleting {old} is not supported; only edits and new files are."
+                ));
+            }
+            files.push(FilePatch {
+                path: new,
+                create: old == "/dev/null",
+                hunks: Vec::new(),
+            });
+            continue;
+        }
+        if !line.starts_with("@@") {
+            continue;
+        }
+
+        if files.is_empty() {
+            let path = default_path.ok_or_else(|| {
+                "The patch has a hunk before any `--- a/<path>` / `+++ b/<path>` header."
+                    .to_string()
+            })?;
+            files.push(FilePatch {
+                path: path.to_string(),
+                create: false,
+                hunks: Vec::new(),
+            });
+        }
+        let mut ops = Vec::new();
+        while let Some(next) = lines.peek() {
+            if next.starts_with("@@") || next.starts_with("diff --git ") {
+                break;
+            }
+            if next.starts_with("--- ")
+                && lines.clone().nth(1).is_some_and(|l| l.starts_with("+++ "))
+            {
+                break;
+            }
+            let next = lines.next().unwrap_or_default();
+            match next.chars().next() {
+                Some('+') => ops.push(Op::Add(next[1..].to_string())),
+                Some('-') => ops.push(Op::Remove(next[1..].to_string())),
+                Some(' ') => ops.push(Op::Context(next[1..].to_string())),
+                // Editors and models often strip the space off blank context lines.
+                None => ops.push(Op::Context(String::new())),
+                // `\ No newline at end of file`, or prose after the hunk.
+                _ => {}
+            }
+        }
+        while ops.last() == Some(&Op::Context(String::new())) {
+            ops.pop();
+        }
+        if let Some(file) = files.last_mut() {
+            file.hunks.push(Hunk {
+                old_start: old_start(line),
+                header: Some(hunk_header(line)),
+                ops,
+            });
+        }
+    }
+    Ok(files)
+}
+
+fn parse_search_replace(text: &str, default_path: Option<&str>) -> Result<Vec<FilePatch>, String> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut named: Option<String> = None;
+    let mut lines = text.lines();
+    let mut block = 0;
+    while let Some(line) = lines.next() {
+        if !line.starts_with("<<<<<<< SEARCH") {
+            let trimmed = line.trim();
+            if !trimmed.is_empty() && !trimmed.starts_with("```") {
+                // Only a bare path on its own line names the file.
+                named = (!trimmed.contains(char::is_whitespace)).then(|| trimmed.to_string());
+            }
+            continue;
+        }
+
+        block += 1;
+        let unclosed =
+            || format!("Search/replace block {block} is not closed with >>>>>>> REPLACE.");
+        let mut ops = Vec::new();
+        loop {
+            let line = lines.next().ok_or_else(unclosed)?;
+            if line.starts_with("=======") {
+                break;
+            }
+            ops.push(Op::Remove(line.to_string()));
+        }
+        loop {
+            let line = lines.next().ok_or_else(unclosed)?;
+            if line.starts_with(">>>>>>> REPLACE") {
+                break;
+            }
+            ops.push(Op::Add(line.to_string()));
+        }
+
+        let path = named
+            .take()
+            .or_else(|| default_path.map(str::to_string))
+            .or_else(|| files.last().map(|f| f.path.clone()))
+            .ok_or_else(|| {
+                format!("Search/replace block {block} does not say which file it applies to.")
+            })?;
+        let hunk = Hunk {
+            header: None,
+            old_start: None,
+            ops,
+        };
+        match files.iter_mut().find(|f| f.path == path) {
+            Some(file) => file.hunks.push(hunk),
+            None => files.push(FilePatch {
+                path,
+                create: false,
+                hunks: vec![hunk],
+            }),
+        }
+    }
+    Ok(files)
+}
+
+/// `a/src/main.rs\t2024-01-01 ...` -> `src/main.rs`.
+fn diff_path(raw: &str) -> String {
+    let path = raw.split('\t').next().unwrap_or(raw).trim();
+    path.strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// `@@ -12,5 +12,6 @@ fn main()` -> `@@ -12,5 +12,6 @@`.
+fn hunk_header(line: &str) -> String {
+    match line[2..].find("@@") {
+        Some(end) => line[..end + 4].to_string(),
+        None => line.trim_end().to_string(),
+    }
+}
+
+/// The old start line of an `@@ -12,5 +12,6 @@` header; `None` for a bare `@@`.
+fn old_start(line: &str) -> Option<usize> {
+    let range = line
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix('-'))?;
+    range.split(',').next()?.parse().ok()
+}
+
+/// Apply one file's hunks, in order, to its current contents (`None` when it
+/// does not exist yet). Line endings and the final newline are kept.
+pub fn apply(original: Option<&str>, file: &FilePatch) -> FileOutcome {
+    let text = original.unwrap_or_default();
+    let crlf = text.contains("\r\n");
+    let final_newline = text.is_empty() || text.ends_with('\n');
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+
+    let mut delta = 0isize;
+    let mut failed = false;
+    let mut hunks = Vec::new();
+    for (idx, hunk) in file.hunks.iter().enumerate() {
+        let result = place(&lines, hunk, delta).map(|placed| {
+            let ops = &hunk.ops[placed.cut_lead..hunk.ops.len() - placed.cut_trail];
+            let mut replacement = Vec::new();
+            let mut end = placed.start;
+            for op in ops {
+                match op {
+                    // Keep the file's own context lines, whitespace and all.
+                    Op::Context(_) => {
+                        replacement.push(lines[end].clone());
+                        end += 1;
+                    }
+                    Op::Remove(_) => end += 1,
+                    Op::Add(line) => replacement.push(line.clone()),
+                }
+            }
+            delta += replacement.len() as isize - (end - placed.start) as isize;
+            lines.splice(placed.start..end, replacement);
+            placed.placement
+        });
+        failed |= result.is_err();
+        hunks.push(HunkOutcome {
+            label: hunk.label(idx),
+            result,
+        });
+    }
+
+    let contents = (!failed).then(|| {
+        let mut out = lines.join(if crlf { "\r\n" } else { "\n" });
+        if final_newline && !lines.is_empty() {
+            out.push_str(if crlf { "\r\n" } else { "\n" });
+        }
+        out
+    });
+    FileOutcome { contents, hunks }
+}
+
+struct Placed {
+    start: usize,
+    cut_lead: usize,
+    cut_trail: usize,
+    placement: Placement,
+}
+
+fn place(lines: &[String], hunk: &Hunk, delta: isize) -> Result<Placed, String> {
+    let lead = hunk.leading_context();
+    let trail = hunk.trailing_context();
+    let expected = hunk
+        .old_start
+        .map(|start| (start.saturating_sub(1) as isize + delta).max(0) as usize);
+
+    if hunk.old_len() == 0 {
+        // Pure insertion: `@@ -5,0 +6,2 @@` adds after line 5.
+        let at = match (hunk.old_start, expected) {
+            (Some(start), Some(_)) => ((start as isize + delta).max(0) as usize).min(lines.len()),
+            _ if lines.is_empty() => 0,
+            _ => return Err("the search text is empty; include the lines to replace".to_string()),
+        };
+        return Ok(Placed {
+            start: at,
+            cut_lead: 0,
+            cut_trail: 0,
+            placement: Placement {
+                line: at + 1,
+                offset: expected.map(|_| 0),
+                fuzz: 0,
+                loose: false,
+            },
+        });
+    }
+
+    for fuzz in 0..=MAX_FUZZ.min(lead.max(trail)) {
+        let (cut_lead, cut_trail) = (fuzz.min(lead), fuzz.min(trail));
+        let old: Vec<&str> = hunk.ops[cut_lead..hunk.ops.len() - cut_trail]
+            .iter()
+            .filter_map(Op::old_line)
+            .collect();
+        if old.is_empty() || old.len() > lines.len() {
+            continue;
+        }
+        for loose in [false, true] {
+            let fits = |start: usize| {
+                old.iter()
+                    .zip(&lines[start..])
+                    .all(|(want, have)| same_line(want, have, loose))
+            };
+            let last = lines.len() - old.len();
+            let start = match expected {
+                Some(expected) => {
+                    let expected = (expected + cut_lead).min(last);
+                    nearest(expected, last, fits)
+                }
+                None => {
+                    let found: Vec<usize> = (0..=last).filter(|&s| fits(s)).collect();
+                    if found.len() > 1 {
+                        return Err(format!(
+                            "the text matches {} places; include more lines to pick one",
+                            found.len()
+                        ));
+                    }
+                    found.first().copied()
+                }
+            };
+            if let Some(start) = start {
+                return Ok(Placed {
+                    start,
+                    cut_lead,
+                    cut_trail,
+                    placement: Placement {
+                        line: start + 1,
+                        offset: expected
+                            .map(|expected| start as isize - (expected + cut_lead) as isize),
+                        fuzz,
+                        loose,
+                    },
+                });
+            }
+        }
+    }
+
+    Err(match hunk.old_start {
+        Some(start) => format!("its context was not found near line {start}"),
+        None => "the search text was not found".to_string(),
+    })
+}
+
+/// The match closest to `expected`, looking both ways.
+fn nearest(expected: usize, last: usize, fits: impl Fn(usize) -> bool) -> Option<usize> {
+    (0..=last.max(expected)).find_map(|distance| {
+        [expected.checked_sub(distance), Some(expected + distance)]
+            .into_iter()
+            .flatten()
+            .find(|&start| start <= last && fits(start))
+    })
+}
+
+fn same_line(want: &str, have: &str, loose: bool) -> bool {
+    if loose {
+        want.split_whitespace().eq(have.split_whitespace())
+    } else {
+        want == have
+    }
+}
+
+/// The patch as a unified diff, for approval prompts and tool cards.
+pub fn render(files: &[FilePatch]) -> String {
+    let mut out = String::new();
+    for file in files {
+        let old = if file.create {
+            "/dev/null".to_string()
+        } else {
+            format!("a/{}", file.path)
+        };
+        out.push_str(&format!("--- {old}\n+++ b/{}\n", file.path));
+        for hunk in &file.hunks {
+            out.push_str(hunk.header.as_deref().unwrap_or("@@ search/replace @@"));
+            out.push('\n');
+            for op in &hunk.ops {
+                let (sign, line) = match op {
+                    Op::Context(line) => (' ', line),
+                    Op::Remove(line) => ('-', line),
+                    Op::Add(line) => ('+', line),
+                };
+                out.push(sign);
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// `render`'s output with ANSI colors, for the terminal.
+pub fn colorize(diff: &str) -> String {
+    diff.lines()
+        .map(|line| {
+            let color = if line.starts_with("+++") || line.starts_with("---") {
+                "1"
+            } else if line.starts_with("@@") {
+                "36"
+            } else if line.starts_with('+') {
+                "32"
+            } else if line.starts_with('-') {
+                "31"
+            } else {
+                return line.to_string();
+            };
+            format!("\x1b[{color}m{line}\x1b[0m")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The patch in an `apply_patch` call, parsed.
+pub fn from_args(args: &HashMap<String, Value>) -> Option<Result<Vec<FilePatch>, String>> {
+    let text = args.get("patch").and_then(Value::as_str)?;
+    Some(parse(text, args.get("path").and_then(Value::as_str)))
+}
+
+/// The rendered diff of an `apply_patch` call, if it parses.
+pub fn preview(args: &HashMap<String, Value>) -> Option<String> {
+    from_args(args)?.ok().map(|files| render(&files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str =
+        "fn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n";
+
+    fn applied(original: Option<&str>, patch: &str) -> (Option<String>, Vec<String>) {
+        let files = parse(patch, Some("src/main.rs")).unwrap();
+        let outcome = apply(original, &files[0]);
+        let report = outcome.hunks.iter().map(ToString::to_string).collect();
+        (outcome.contents, report)
+    }
+
+    #[test]
+    fn parses_unified_diffs() {
+        let files = parse(
+            "diff --git a/src/a.rs b/src/a.rs\n--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1,2 +1,2 @@ fn a()\n one\n-two\n+three\n--- /dev/null\n+++ b/src/new.rs\n@@ -0,0 +1 @@\n+fresh\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "src/a.rs");
+        assert_eq!(files[0].hunks[0].header.as_deref(), Some("@@ -1,2 +1,2 @@"));
+        assert_eq!(files[0].hunks[0].old_start, Some(1));
+        assert!(files[1].create && files[1].creates());
+
+        let err = parse("--- a/x.rs\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\n", None).unwrap_err();
+        assert!(err.starts_with("Deleting x.rs"), "{err}");
+        assert!(parse("@@ -1 +1 @@\n-a\n+b\n", None).is_err());
+    }
+
+    #[test]
+    fn hunks_apply_with_offset_and_fuzz() {
+        // The header is two lines off and the first context line is stale.
+        let patch =
+            "@@ -3,3 +3,3 @@\n fn main() {\n-    let a = 1;\n+    let a = 10;\n     let b = 2;\n";
+        let (contents, report) = applied(Some(FILE), patch);
+        assert_eq!(contents.unwrap(), FILE.replace("a = 1", "a = 10"));
+        assert_eq!(
+            report,
+            ["hunk 1 (@@ -3,3 +3,3 @@): applied at line 1 (offset -2)"]
+        );
+
+        let patch = "@@ -1,4 +1,4 @@\n fn start() {\n     let a = 1;\n-    let b = 2;\n+    let b = 3;\n     println!(\"{}\", a + b);\n";
+        let (contents, report) = applied(Some(FILE), patch);
+        assert_eq!(contents.unwrap(), FILE.replace("b = 2", "b = 3"));
+        assert_eq!(
+            report,
+            ["hunk 1 (@@ -1,4 +1,4 @@): applied at line 2 (fuzz 1)"]
+        );
+
+        let loose = "@@ -2,1 +2,1 @@\n-let a   =  1;\n+    let a = 5;\n";
+        let (contents, report) = applied(Some(FILE), loose);
+        assert_eq!(contents.unwrap(), FILE.replace("a = 1", "a = 5"));
+        assert!(report[0].ends_with("(ignoring whitespace)"), "{report:?}");
+    }
+
+    #[test]
+    fn failed_hunks_are_reported_and_nothing_is_written() {
+        let patch = "@@ -2 +2 @@\n-    let a = 1;\n+    let a = 2;\n@@ -9 +9 @@\n-    missing();\n+    found();\n";
+        let (contents, report) = applied(Some(FILE), patch);
+        assert!(contents.is_none());
+        assert_eq!(report[0], "hunk 1 (@@ -2 +2 @@): applied at line 2");
+        assert_eq!(
+            report[1],
+            "hunk 2 (@@ -9 +9 @@): failed, its context was not found near line 9"
+        );
+    }
+
+    #[test]
+    fn search_replace_blocks_must_match_once() {
+        let patch = "src/lib.rs\n```rust\n<<<<<<< SEARCH\n    let b = 2;\n=======\n    let b = 4;\n>>>>>>> REPLACE\n```\n";
+        let files = parse(patch, None).unwrap();
+        assert_eq!(files[0].path, "src/lib.rs");
+        let outcome = apply(Some(FILE), &files[0]);
+        assert_eq!(outcome.contents.unwrap(), FILE.replace("b = 2", "b = 4"));
+
+        let twice = "<<<<<<< SEARCH\n    let\n=======\n    let mut\n>>>>>>> REPLACE\n";
+        let (contents, report) = applied(Some("    let\n    let\n"), twice);
+        assert!(contents.is_none());
+        assert!(report[0].contains("matches 2 places"), "{report:?}");
+
+        let create = "<<<<<<< SEARCH\n=======\nhello\n>>>>>>> REPLACE\n";
+        assert_eq!(applied(None, create).0.unwrap(), "hello\n");
+
+        let crlf = "<<<<<<< SEARCH\nb\n=======\nc\n>>>>>>> REPLACE\n";
+        assert_eq!(applied(Some("a\r\nb\r\n"), crlf).0.unwrap(), "a\r\nc\r\n");
+    }
+
+    #[test]
+    fn renders_search_replace_as_a_diff() {
+        let files = parse(
+            "<<<<<<< SEARCH\nold\n=======\nnew\n>>>>>>> REPLACE\n",
+            Some("a.txt"),
+        )
+        .unwrap();
+        assert_eq!(
+            render(&files),
+            "--- a/a.txt\n+++ b/a.txt\n@@ search/replace @@\n-old\n+new\n"
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Approval policy for local tool calls
+//!
+//! Every tool call the agent runs on this machine is checked against the
+//! `tool_policy` section of config.json first:
+//!
+//! ```json
+//! "tool_policy": {
+//!   "mode": "ask-writes",
+//!   "rules": [
+//!     { "tool": "write_file", "path": "src/**", "action": "allow" },
+//!     { "tool": "execute_command", "command": "^cargo (build|test)( [-\\w./=]+)*$", "action": "allow" },
+//!     { "command": "\\brm\\s+-rf\\b", "action": "deny" }
+//!   ]
+//! }
+//! ```
+//!
+//! A rule applies when all of its conditions hold: `tool` is a glob over the
+//! tool name, `path` a glob over the call's path arguments or the files its
+//! patch touches (relative to the working directory), `command` a regex over
+//! its command, or over the line it types into a shell. A deny or ask rule's
+//! `path` needs to match one of the call's paths, an allow rule's all of
+//! them; an allow rule's `command` never matches a command that chains,
+//! pipes, redirects or substitutes (`;`, `&`, `|`, `<`, `>`, backticks,
+//! `$(`, newlines), since commands run through `sh -c`. When several rules
+//! apply the strictest action wins. Calls no rule covers fall back to the
+//! mode.
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::errors::CliError;
+use crate::parse::glob::Glob;
+use crate::patch;
+
+/// What happens to a call, in increasing order of strictness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Allow,
+    Ask,
+    Deny,
+}
+
+/// The fallback for calls no rule covers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Run read-only tools, ask before anything else.
+    #[default]
+    AskWrites,
+    /// Ask before every call.
+    AskAll,
+    /// Run read-only tools, refuse anything else.
+    ReadOnly,
+    /// Run everything.
+    AllowAll,
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::AskWrites => "ask-writes",
+            Mode::AskAll => "ask-all",
+            Mode::ReadOnly => "read-only",
+            Mode::AllowAll => "allow-all",
+        }
+    }
+
+    fn action(self, read_only: bool) -> Action {
+        match (self, read_only) {
+            (Mode::AllowAll, _) | (Mode::AskWrites | Mode::ReadOnly, true) => Action::Allow,
+            (Mode::AskAll, _) | (Mode::AskWrites, false) => Action::Ask,
+            (Mode::ReadOnly, false) => Action::Deny,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolPolicy {
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+}
+
+/// The policy's answer for one call, with the rule (or mode) behind it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub action: Action,
+    pub reason: String,
+}
+
+/// What to do with calls the policy says to ask about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Approval {
+    /// Ask on the terminal; refuse when there is none.
+    #[default]
+    Prompt,
+    /// Approve them: `--yolo`, or a caller that already asked (the TUI card).
+    Granted,
+}
+
+impl ToolPolicy {
+    /// Compile every pattern, so a typo fails the command instead of
+    /// silently never matching.
+    pub fn validate(&self) -> Result<(), CliError> {
+        for (idx, rule) in self.rules.iter().enumerate() {
+            compile(rule).map_err(|err| {
+                CliError::usage(format!("tool_policy rule {}: {err}", idx + 1))
+                    .with_hint("Fix the rule in config.json.")
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn decide(&self, tool: &str, read_only: bool, args: &HashMap<String, Value>) -> Verdict {
+        let paths = call_paths(args);
+        let command = args
+            .get("command")
+            .or_else(|| args.get("input"))
+            .and_then(Value::as_str);
+
+        let mut verdict: Option<Verdict> = None;
+        for (idx, rule) in self.rules.iter().enumerate() {
+            let Ok(matcher) = compile(rule) else {
+                continue;
+            };
+            if !matcher.matches(rule.action, tool, &paths, command) {
+                continue;
+            }
+            if verdict.as_ref().is_none_or(|v| rule.action > v.action) {
+                verdict = Some(Verdict {
+                    action: rule.action,
+                    reason: format!("tool_policy rule {}", idx + 1),
+                });
+            }
+        }
+
+        verdict.unwrap_or_else(|| Verdict {
+            action: self.mode.action(read_only),
+            reason: format!("{} mode", self.mode.as_str()),
+        })
+    }
+}
+
+struct Matcher {
+    tool: Option<Glob>,
+    path: Option<Glob>,
+    command: Option<Regex>,
+}
+
+impl Matcher {
+    /// Allow rules must cover the whole call; deny and ask rules fire on
+    /// any part of it.
+    fn matches(&self, action: Action, tool: &str, paths: &[String], command: Option<&str>) -> bool {
+        if self.tool.as_ref().is_some_and(|glob| !glob.is_match(tool)) {
+            return false;
+        }
+        if let Some(glob) = &self.path {
+            let matched = if action == Action::Allow {
+                !paths.is_empty() && paths.iter().all(|p| glob.is_match(p))
+            } else {
+                paths.iter().any(|p| glob.is_match(p))
+            };
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.command
+            && !command.is_some_and(|c| {
+                regex.is_match(c) && (action != Action::Allow || !is_compound(c))
+            })
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Whether a command does more than run one program: `sh -c` would chain,
+/// pipe, redirect or substitute it.
+fn is_compound(command: &str) -> bool {
+    command.contains([';', '&', '|', '<', '>', '`', '\n', '\r']) || command.contains("$(")
+}
+
+fn compile(rule: &Rule) -> Result<Matcher, String> {
+    Ok(Matcher {
+        tool: rule.tool.as_deref().map(Glob::new).transpose()?,
+        path: rule.path.as_deref().map(Glob::new).transpose()?,
+        command: rule
+            .command
+            .as_deref()
+            .map(|c| Regex::new(c).map_err(|err| format!("invalid command regex `{c}`: {err}")))
+            .transpose()?,
+    })
+}
+
+/// The `path`/`paths` arguments and the files a `patch` touches, relative
+/// to the working directory where possible, so `src/**` also covers
+/// `./src/main.rs` and absolute paths.
+pub fn call_paths(args: &HashMap<String, Value>) -> Vec<String> {
+    let cwd = std::env::current_dir().ok();
+    let mut raw: Vec<String> = match patch::from_args(args) {
+        Some(Ok(files)) => files.into_iter().map(|f| f.path).collect(),
+        _ => args.get("path").and_then(Value::as_str).map(str::to_string).into_iter().collect(),
+    };
+    if let Some(list) = args.get("paths").and_then(Value::as_array) {
+        raw.extend(list.iter().filter_map(Value::as_str).map(str::to_string));
+    }
+    raw.into_iter()
+        .map(|path| {
+            let relative = cwd
+                .as_deref()
+                .and_then(|cwd| Path::new(&path).strip_prefix(cwd).ok())
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or(path);
+            relative
+                .trim_start_matches("./")
+                .replace('\\', "/")
+        })
+        .collect()
+}
+
+/// A one-line description of a call for approval prompts.
+pub fn describe(tool: &str, args: &HashMap<String, Value>) -> String {
+    if let Some(command) = args.get("command").and_then(Value::as_str) {
+        return format!("{tool}: {command}");
+    }
+    let paths = call_paths(args);
+    if !paths.is_empty() {
+        return format!("{tool}: {}", paths.join(", "));
+    }
+    match serde_json::to_string(args) {
+        Ok(json) if json != "{}" => format!("{tool} {json}"),
+        _ => tool.to_string(),
+    }
+}
+
+/// Ask on the terminal whether a call may run. `Err` carries the reason it
+/// cannot be asked, for the tool result.
+pub fn ask(tool: &str, args: &HashMap<String, Value>, verdict: &Verdict) -> Result<bool, String> {
+    if !io::stdin().is_terminal() {
+        return Err(format!(
+            "{tool} needs approval ({}), but there is no terminal to ask on. \
+             Run with --yolo, or add an allow rule to tool_policy.",
+            verdict.reason
+        ));
+    }
+    eprintln!("\x1b[33m[approve] {}\x1b[0m", describe(tool, args));
+    if let Some(diff) = patch::preview(args) {
+        eprintln!("{}", patch::colorize(&diff));
+    }
+    crate::commands::chat::confirm("Run it?").map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn args(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn policy(value: Value) -> ToolPolicy {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn modes_split_reads_from_writes() {
+        let none = HashMap::new();
+        let default = ToolPolicy::default();
+        assert_eq!(default.decide("read_file", true, &none).action, Action::Allow);
+        assert_eq!(default.decide("write_file", false, &none).action, Action::Ask);
+        assert_eq!(default.decide("write_file", false, &none).reason, "ask-writes mode");
+
+        let read_only = policy(json!({ "mode": "read-only" }));
+        assert_eq!(read_only.decide("execute_command", false, &none).action, Action::Deny);
+        let ask_all = policy(json!({ "mode": "ask-all" }));
+        assert_eq!(ask_all.decide("read_file", true, &none).action, Action::Ask);
+        let allow_all = policy(json!({ "mode": "allow-all" }));
+        assert_eq!(allow_all.decide("execute_command", false, &none).action, Action::Allow);
+    }
+
+    #[test]
+    fn rules_match_tools_paths_and_commands() {
+        let policy = policy(json!({
+            "rules": [
+                { "tool": "write_file", "path": "src/**", "action": "allow" },
+                { "tool": "execute_command", "command": "^cargo (build|test)( [-\\w./=]+)*$", "action": "allow" },
+                { "command": "\\brm\\s+-rf\\b", "action": "deny" },
+                { "tool": "*_task", "action": "deny" },
+            ]
+        }));
+
+        let write = |path: &str| policy.decide("write_file", false, &args(json!({ "path": path })));
+        assert_eq!(write("src/main.rs").action, Action::Allow);
+        assert_eq!(write("./src/commands/chat.rs").action, Action::Allow);
+        assert_eq!(write("Cargo.toml").action, Action::Ask);
+
+        let run = |command: &str| {
+            policy.decide("execute_command", false, &args(json!({ "command": command })))
+        };
+        assert_eq!(run("cargo test --workspace").action, Action::Allow);
+        assert_eq!(run("cargo publish").action, Action::Ask);
+        // The strictest matching rule wins.
+        let verdict = run("cargo build && rm -rf target");
+        assert_eq!(verdict.action, Action::Deny);
+        assert_eq!(verdict.reason, "tool_policy rule 3");
+        // Lines typed into a shell count as commands too.
+        let typed = args(json!({ "session": "s1", "input": "rm -rf build" }));
+        assert_eq!(policy.decide("shell_send_input", false, &typed).action, Action::Deny);
+
+        assert_eq!(policy.decide("create_task", false, &HashMap::new()).action, Action::Deny);
+    }
+
+    #[test]
+    fn deny_rules_fire_on_any_path_allow_rules_need_all() {
+        let policy = policy(json!({
+            "rules": [
+                { "path": "src/**", "action": "allow" },
+                { "path": "secrets/**", "action": "deny" },
+            ]
+        }));
+
+        let patch = "--- a/src/ok.rs\n+++ b/src/ok.rs\n@@ -1 +1 @@\n-a\n+b\n\
+                     --- a/secrets/key\n+++ b/secrets/key\n@@ -1 +1 @@\n-a\n+b\n";
+        let verdict = policy.decide("apply_patch", false, &args(json!({ "patch": patch })));
+        assert_eq!(verdict.action, Action::Deny);
+        assert_eq!(verdict.reason, "tool_policy rule 2");
+
+        let mixed = args(json!({ "paths": ["src/a.rs", "docs/b.md"] }));
+        assert_eq!(policy.decide("read_many", false, &mixed).action, Action::Ask);
+    }
+
+    #[test]
+    fn allow_rules_never_match_compound_commands() {
+        // Even an unanchored regex cannot be chained past.
+        let policy = policy(json!({
+            "rules": [{ "tool": "execute_command", "command": "^cargo (build|test)\\b", "action": "allow" }]
+        }));
+        let run = |command: &str| {
+            policy.decide("execute_command", false, &args(json!({ "command": command })))
+        };
+        assert_eq!(run("cargo test --workspace").action, Action::Allow);
+        for command in [
+            "cargo test && rm -rf ~",
+            "cargo test; rm -rf ~",
+            "cargo build || true",
+            "cargo test | sh",
+            "cargo test $(cat args)",
+            "cargo test `id`",
+            "cargo test > ~/.bashrc",
+            "cargo test\nrm -rf ~",
+        ] {
+            assert_eq!(run(command).action, Action::Ask, "{command}");
+        }
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        let bad = policy(json!({ "rules": [{ "command": "(", "action": "deny" }] }));
+        let err = bad.validate().unwrap_err();
+        assert!(err.to_string().starts_with("tool_policy rule 1: invalid command regex"));
+        assert!(ToolPolicy::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,273 @@
+//! Recordings of agent shell sessions
+//!
+//! A PTY session started with `record` set writes everything it reads and
+//! sends to `recordings/<id>.cast` in the state directory, in asciinema's
+//! asciicast v2 format: a JSON header line, then one `[seconds, code, data]`
+//! line per event (`o` output, `i` input, `r` resize). `starbott sessions
+//! replay` plays them back; `asciinema play` works too.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::state_dir;
+use crate::conversations::{current_dir_key, now_secs};
+use crate::errors::CliError;
+
+const CAST_VERSION: u32 = 2;
+/// Oldest recordings are dropped beyond this many.
+const MAX_RECORDINGS: usize = 50;
+
+/// The first line of a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    /// Unix seconds.
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+/// One event: seconds since the start, `o`/`i`/`r`, and its data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event(pub f64, pub String, pub String);
+
+/// A recording on disk.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    pub id: String,
+    #[serde(flatten)]
+    pub header: Header,
+    /// Seconds from the start to the last event.
+    pub duration: f64,
+    pub path: PathBuf,
+}
+
+impl Recording {
+    fn load(path: &Path) -> Result<Self, CliError> {
+        let invalid = |err: &dyn std::fmt::Display| {
+            CliError::generic(format!("Invalid recording {}: {err}", path.display()))
+        };
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let first = lines.next().transpose()?.unwrap_or_default();
+        let header: Header = serde_json::from_str(&first).map_err(|err| invalid(&err))?;
+        if header.version != CAST_VERSION {
+            return Err(invalid(&format!(
+                "version {} is not supported",
+                header.version
+            )));
+        }
+        // A session killed mid-write may leave a partial last line.
+        let duration = lines
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<Event>(&line).ok())
+            .last()
+            .map_or(0.0, |event| event.0);
+        Ok(Self {
+            id: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            header,
+            duration,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Every complete event, in order.
+    pub fn events(&self) -> Result<Vec<Event>, CliError> {
+        let file = File::open(&self.path)?;
+        Ok(BufReader::new(file)
+            .lines()
+            .skip(1)
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect())
+    }
+}
+
+/// Writes one session's recording as it happens. Write errors are ignored:
+/// a full disk should not take the shell down with it.
+#[derive(Debug)]
+pub struct Recorder {
+    id: String,
+    file: File,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn start(width: u16, height: u16, env: BTreeMap<String, String>) -> Result<Self, CliError> {
+        Self::start_in(&recordings_root()?, width, height, env)
+    }
+
+    fn start_in(
+        root: &Path,
+        width: u16,
+        height: u16,
+        env: BTreeMap<String, String>,
+    ) -> Result<Self, CliError> {
+        fs::create_dir_all(root)?;
+        let id = uuid::Uuid::new_v4().simple().to_string()[..12].to_string();
+        prune(root);
+        let header = Header {
+            version: CAST_VERSION,
+            width,
+            height,
+            timestamp: now_secs(),
+            title: Some(current_dir_key()),
+            env,
+        };
+        let mut file = File::create(root.join(format!("{id}.cast")))?;
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        Ok(Self {
+            id,
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn output(&mut self, data: &str) {
+        self.event("o", data);
+    }
+
+    pub fn input(&mut self, data: &str) {
+        self.event("i", data);
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.event("r", &format!("{width}x{height}"));
+    }
+
+    fn event(&mut self, code: &str, data: &str) {
+        let event = Event(
+            self.started.elapsed().as_secs_f64(),
+            code.to_string(),
+            data.to_string(),
+        );
+        if let Ok(line) = serde_json::to_string(&event) {
+            let _ = writeln!(self.file, "{line}");
+        }
+    }
+}
+
+fn recordings_root() -> Result<PathBuf, CliError> {
+    Ok(state_dir()?.join("recordings"))
+}
+
+/// Recordings, newest first. Unreadable ones are skipped.
+pub fn recordings() -> Result<Vec<Recording>, CliError> {
+    recordings_in(&recordings_root()?)
+}
+
+fn recordings_in(root: &Path) -> Result<Vec<Recording>, CliError> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut recordings: Vec<Recording> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "cast"))
+        .filter_map(|path| Recording::load(&path).ok())
+        .collect();
+    recordings.sort_by_key(|r| Reverse(r.header.timestamp));
+    Ok(recordings)
+}
+
+/// Drop the oldest recordings so at most `MAX_RECORDINGS` remain, counting
+/// the one being started.
+fn prune(root: &Path) {
+    let Ok(recordings) = recordings_in(root) else {
+        return;
+    };
+    for old in recordings.iter().skip(MAX_RECORDINGS - 1) {
+        let _ = fs::remove_file(&old.path);
+    }
+}
+
+/// The recording `query` names: an exact ID or a unique prefix.
+pub fn find(recordings: Vec<Recording>, query: &str) -> Result<Recording, CliError> {
+    let mut matches: Vec<Recording> = recordings
+        .into_iter()
+        .filter(|r| r.id.starts_with(query))
+        .collect();
+    if let Some(idx) = matches.iter().position(|r| r.id == query) {
+        return Ok(matches.swap_remove(idx));
+    }
+    match matches.len() {
+        0 => Err(CliError::usage(format!("No recording matches `{query}`."))
+            .with_hint("List recordings with `starbott sessions list`.")),
+        1 => Ok(matches.remove(0)),
+        n => Err(CliError::usage(format!(
+            "`{query}` matches {n} recordings; use more of the ID."
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("starbott-recording-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn recordings_are_asciicast_v2() {
+        let root = scratch("cast");
+        let env = BTreeMap::from([("TERM".to_string(), "xterm-256color".to_string())]);
+        let mut recorder = Recorder::start_in(&root, 80, 24, env).unwrap();
+        recorder.input("echo hi\n");
+        recorder.output("hi\r\n");
+        recorder.resize(120, 40);
+
+        let path = root.join(format!("{}.cast", recorder.id()));
+        let text = fs::read_to_string(&path).unwrap();
+        let header: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(header["version"], 2);
+        assert_eq!(
+            (header["width"].as_u64(), header["height"].as_u64()),
+            (Some(80), Some(24))
+        );
+        assert_eq!(header["env"]["TERM"], "xterm-256color");
+
+        let recording = find(recordings_in(&root).unwrap(), &recorder.id()[..6]).unwrap();
+        let events = recording.events().unwrap();
+        let kinds: Vec<(&str, &str)> = events
+            .iter()
+            .map(|e| (e.1.as_str(), e.2.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [("i", "echo hi\n"), ("o", "hi\r\n"), ("r", "120x40")]
+        );
+        assert!(events.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert_eq!(recording.duration, events[2].0);
+
+        // A half-written last line does not spoil the rest.
+        fs::write(&path, format!("{text}[1.5, \"o\", \"trunc")).unwrap();
+        assert_eq!(recording.events().unwrap().len(), 3);
+
+        let err = find(recordings_in(&root).unwrap(), "zzz").unwrap_err();
+        assert_eq!(err.to_string(), "No recording matches `zzz`.");
+        fs::remove_dir_all(root).unwrap();
+    }
+}
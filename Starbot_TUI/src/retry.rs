@@ -0,0 +1,128 @@
+//! Retry timing
+//!
+//! Backoff between attempts uses "full jitter" (a random delay between zero
+//! and the exponential ceiling) so clients that failed together do not retry
+//! together. A server-provided `Retry-After` always wins over the computed
+//! backoff.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+
+const BACKOFF_BASE_MS: u64 = 200;
+const BACKOFF_CAP_MS: u64 = 10_000;
+
+/// Total wall time a request may spend retrying unless `--retry-budget` says otherwise.
+pub const DEFAULT_RETRY_BUDGET: Duration = Duration::from_secs(60);
+
+/// Random delay in `[0, min(cap, base * 2^attempt)]`.
+pub fn jittered_backoff(attempt: u32) -> Duration {
+    let ceiling = BACKOFF_BASE_MS
+        .saturating_mul(1u64 << attempt.min(16))
+        .min(BACKOFF_CAP_MS);
+    Duration::from_millis(rand::rng().random_range(0..=ceiling))
+}
+
+/// Parse a `Retry-After` header: either delay-seconds or an HTTP-date.
+/// Dates in the past yield a zero delay.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = parse_http_date(value)?;
+    Some(at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// Parse the three date formats HTTP/1.1 allows (RFC 9110 §5.6.7):
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, `Sunday, 06-Nov-94 08:49:37 GMT`, and
+/// asctime's `Sun Nov  6 08:49:37 1994`.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let normalized = value.replace([',', '-'], " ");
+    let parts: Vec<&str> = normalized.split_whitespace().collect();
+
+    let (day, month, year, time) = match parts.as_slice() {
+        [_, day, month, year, time, "GMT"] => {
+            let year: i64 = year.parse().ok()?;
+            // RFC 850 two-digit years: interpret 70-99 as 19xx.
+            let year = match year {
+                0..=69 => year + 2000,
+                70..=99 => year + 1900,
+                _ => year,
+            };
+            (day.parse().ok()?, *month, year, *time)
+        }
+        [_, month, day, time, year] => (day.parse().ok()?, *month, year.parse().ok()?, *time),
+        _ => return None,
+    };
+
+    let month = match month {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+
+    let mut clock = time.split(':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if clock.next().is_some() || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second;
+    let secs = u64::try_from(secs).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_accepts_seconds_and_all_date_forms() {
+        let now = UNIX_EPOCH + Duration::from_secs(784_111_767); // Sun, 06 Nov 1994 08:49:27 GMT
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        for date in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ] {
+            assert_eq!(parse_retry_after(date, now), Some(Duration::from_secs(10)), "{date}");
+        }
+        assert_eq!(
+            parse_retry_after("Sat, 05 Nov 1994 08:49:37 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-5", now), None);
+    }
+
+    #[test]
+    fn backoff_stays_under_the_cap() {
+        for attempt in 0..40 {
+            assert!(jittered_backoff(attempt) <= Duration::from_millis(BACKOFF_CAP_MS));
+        }
+        assert!(jittered_backoff(0) <= Duration::from_millis(BACKOFF_BASE_MS));
+    }
+}
@@ -0,0 +1,233 @@
+//! Workspace confinement for local file tools
+//!
+//! The agent's file tools only touch paths inside the workspace root (the
+//! current directory unless configured otherwise). Paths are resolved against
+//! the root, normalised, and canonicalised, so neither `..` nor a symlink can
+//! lead outside it. Secrets (`.env` files, private keys, `~/.ssh`, the
+//! starbott config and state) are refused even inside the root.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::attachments;
+use crate::config;
+use crate::parse::glob::Glob;
+
+/// Largest file `read_file` returns, matching the server's `file.read` cap.
+pub const MAX_READ_BYTES: u64 = attachments::MAX_FILE_BYTES as u64;
+
+/// Secret files, matched against the path relative to the root.
+const SECRET_FILES: [&str; 12] = [
+    "**/.env",
+    "**/.env.*",
+    "**/*.pem",
+    "**/*.key",
+    "**/id_rsa*",
+    "**/id_ecdsa*",
+    "**/id_ed25519*",
+    "**/.netrc",
+    "**/.npmrc",
+    "**/.pypirc",
+    "**/.git-credentials",
+    "**/{.ssh,.aws,.gnupg}/**",
+];
+
+/// Templates that look like secrets but are meant to be shared.
+const SECRET_EXCEPTIONS: [&str; 1] = ["**/.env.{example,sample,template}"];
+
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    root: PathBuf,
+    secrets: Vec<Glob>,
+    exceptions: Vec<Glob>,
+    /// Directories refused wherever the root is: ~/.ssh, the config dir, ...
+    denied_dirs: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    pub fn new(root: &Path) -> Result<Self, String> {
+        let root = fs::canonicalize(root)
+            .map_err(|err| format!("Workspace root {} is not usable: {err}", root.display()))?;
+        let globs = |patterns: &[&str]| {
+            patterns
+                .iter()
+                .map(|p| Glob::new(p).expect("valid secret glob"))
+                .collect()
+        };
+
+        let mut denied_dirs = Vec::new();
+        if let Some(home) = dirs::home_dir() {
+            denied_dirs.extend([".ssh", ".aws", ".gnupg"].map(|dir| home.join(dir)));
+        }
+        if let Some(dir) = config::config_path()
+            .ok()
+            .and_then(|p| p.parent().map(Path::to_path_buf))
+        {
+            denied_dirs.push(dir);
+        }
+        if let Ok(dir) = config::state_dir() {
+            denied_dirs.push(dir);
+        }
+        let denied_dirs = denied_dirs
+            .into_iter()
+            .map(|dir| fs::canonicalize(&dir).unwrap_or(dir))
+            .collect();
+
+        Ok(Self {
+            root,
+            secrets: globs(&SECRET_FILES),
+            exceptions: globs(&SECRET_EXCEPTIONS),
+            denied_dirs,
+        })
+    }
+
+    /// An existing file or directory inside the workspace.
+    pub fn resolve_existing(&self, path: &str) -> Result<PathBuf, String> {
+        let lexical = self.lexical(path)?;
+        let real = fs::canonicalize(&lexical).map_err(|err| format!("{path}: {err}"))?;
+        self.check(path, &real)?;
+        Ok(real)
+    }
+
+    /// A file that may not exist yet; its nearest existing ancestor must be
+    /// inside the workspace, and so must the file if it is a symlink.
+    pub fn resolve_for_write(&self, path: &str) -> Result<PathBuf, String> {
+        let lexical = self.lexical(path)?;
+        if fs::symlink_metadata(&lexical).is_ok() {
+            return self.resolve_existing(path);
+        }
+
+        let mut existing = lexical.as_path();
+        let mut missing = Vec::new();
+        while fs::symlink_metadata(existing).is_err() {
+            let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+                break;
+            };
+            missing.push(name.to_os_string());
+            existing = parent;
+        }
+        let mut real = fs::canonicalize(existing).map_err(|err| format!("{path}: {err}"))?;
+        real.extend(missing.iter().rev());
+        self.check(path, &real)?;
+        Ok(real)
+    }
+
+    /// `path` joined to the root with `.` and `..` folded away, refusing
+    /// anything that climbs out of it.
+    fn lexical(&self, path: &str) -> Result<PathBuf, String> {
+        if path.trim().is_empty() {
+            return Err("Path is empty.".to_string());
+        }
+        let mut out = PathBuf::new();
+        for component in self.root.join(path).components() {
+            match component {
+                Component::ParentDir => {
+                    out.pop();
+                }
+                Component::CurDir => {}
+                other => out.push(other),
+            }
+        }
+        if !out.starts_with(&self.root) {
+            return Err(self.outside(path));
+        }
+        Ok(out)
+    }
+
+    fn check(&self, path: &str, real: &Path) -> Result<(), String> {
+        let Ok(relative) = real.strip_prefix(&self.root) else {
+            return Err(format!(
+                "{path} resolves to {} through a symlink, outside the workspace ({}).",
+                real.display(),
+                self.root.display()
+            ));
+        };
+        let relative = relative.to_string_lossy();
+        let secret = self.secrets.iter().any(|glob| glob.is_match(&relative))
+            && !self.exceptions.iter().any(|glob| glob.is_match(&relative));
+        if secret || self.denied_dirs.iter().any(|dir| real.starts_with(dir)) {
+            return Err(format!("{path} may hold secrets; file tools cannot access it."));
+        }
+        Ok(())
+    }
+
+    fn outside(&self, path: &str) -> String {
+        format!(
+            "{path} is outside the workspace ({}); file tools only work inside it.",
+            self.root.display()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("starbott-sandbox-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        dir
+    }
+
+    #[test]
+    fn paths_stay_inside_the_root() {
+        let root = scratch("inside");
+        let sandbox = Sandbox::new(&root).unwrap();
+        let real_root = sandbox.root.clone();
+
+        assert_eq!(
+            sandbox.resolve_existing("src/../src/./main.rs").unwrap(),
+            real_root.join("src/main.rs")
+        );
+        let absolute = real_root.join("src/main.rs");
+        assert!(sandbox.resolve_existing(absolute.to_str().unwrap()).is_ok());
+        assert_eq!(
+            sandbox.resolve_for_write("src/new/mod.rs").unwrap(),
+            real_root.join("src/new/mod.rs")
+        );
+
+        for path in ["../outside.txt", "src/../../outside.txt", "/etc/passwd"] {
+            let err = sandbox.resolve_for_write(path).unwrap_err();
+            assert!(err.contains("is outside the workspace"), "{path}: {err}");
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn secrets_are_refused() {
+        let root = scratch("secrets");
+        let sandbox = Sandbox::new(&root).unwrap();
+        for path in [
+            ".env",
+            "deploy/.env.production",
+            "certs/server.pem",
+            ".ssh/id_ed25519",
+        ] {
+            let err = sandbox.resolve_for_write(path).unwrap_err();
+            assert!(err.contains("may hold secrets"), "{path}: {err}");
+        }
+        assert!(sandbox.resolve_for_write(".env.example").is_ok());
+        assert!(sandbox.resolve_for_write("src/keys.rs").is_ok());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_cannot_escape() {
+        let root = scratch("symlink");
+        let outside = scratch("symlink-target");
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("src/main.rs"), root.join("file.rs")).unwrap();
+        let sandbox = Sandbox::new(&root).unwrap();
+
+        for path in ["link/src/main.rs", "link/new.txt", "file.rs"] {
+            let err = sandbox.resolve_for_write(path).unwrap_err();
+            assert!(err.contains("through a symlink"), "{path}: {err}");
+        }
+        fs::remove_dir_all(root).unwrap();
+        fs::remove_dir_all(outside).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! Server-Sent Events decoder
+//!
+//! Incremental parser for the `text/event-stream` format. Bytes are buffered
+//! until a full line is available, so multibyte characters split across
+//! network chunks are never lost. Supports every field in the spec: `event`,
+//! `data`, `id`, `retry`, and `:` comment lines (used by servers as
+//! heartbeats).
+
+use std::time::Duration;
+
+/// A dispatched SSE event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Event type (`message` when the server did not send `event:`).
+    pub event_type: String,
+    /// Data lines joined with `\n`.
+    pub data: String,
+    /// Last event ID in effect when this event was dispatched.
+    pub id: Option<String>,
+}
+
+/// Incremental SSE decoder.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    /// The previous chunk ended in `\r`; a leading `\n` belongs to that line.
+    pending_cr: bool,
+    event_type: String,
+    data: String,
+    has_data: bool,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a chunk of bytes and return every event completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        let mut bytes = chunk;
+
+        if self.pending_cr {
+            self.pending_cr = false;
+            if let Some(rest) = bytes.strip_prefix(b"\n") {
+                bytes = rest;
+            }
+        }
+
+        self.buffer.extend_from_slice(bytes);
+
+        let mut start = 0;
+        let mut idx = 0;
+        while idx < self.buffer.len() {
+            let b = self.buffer[idx];
+            if b == b'\n' || b == b'\r' {
+                let line = String::from_utf8_lossy(&self.buffer[start..idx]).into_owned();
+                if b == b'\r' {
+                    match self.buffer.get(idx + 1) {
+                        Some(b'\n') => idx += 1,
+                        Some(_) => {}
+                        None => self.pending_cr = true,
+                    }
+                }
+                if let Some(event) = self.process_line(&line) {
+                    events.push(event);
+                }
+                start = idx + 1;
+            }
+            idx += 1;
+        }
+
+        self.buffer.drain(..start);
+        events
+    }
+
+    /// Flush whatever is buffered when the connection closes. Servers that end
+    /// the stream without a trailing blank line still get their last event
+    /// delivered.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&self.buffer).into_owned();
+            self.buffer.clear();
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    /// The most recent `id:` seen, for use as `Last-Event-ID` on reconnect.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Reconnection delay requested by the server via `retry:`.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Drop any partially received event, keeping `id` and `retry` state.
+    /// Used before resuming on a new connection.
+    pub fn reset_pending(&mut self) {
+        self.buffer.clear();
+        self.pending_cr = false;
+        self.event_type.clear();
+        self.data.clear();
+        self.has_data = false;
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // Comment lines are heartbeats; receiving them keeps the read timeout alive.
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event_type = value.to_string(),
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            }
+            "id" if !value.contains('\0') => {
+                self.last_event_id = if value.is_empty() {
+                    None
+                } else {
+                    Some(value.to_string())
+                };
+            }
+            "retry" if value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(ms) = value.parse::<u64>() {
+                    self.retry = Some(Duration::from_millis(ms));
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event_type = std::mem::take(&mut self.event_type);
+        if !self.has_data {
+            return None;
+        }
+
+        self.has_data = false;
+        Some(SseEvent {
+            event_type: if event_type.is_empty() {
+                "message".to_string()
+            } else {
+                event_type
+            },
+            data: std::mem::take(&mut self.data),
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_event_and_data_fields() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b"event: token.delta\ndata: {\"text\":\"hi\"}\n\n");
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event_type: "token.delta".to_string(),
+                data: "{\"text\":\"hi\"}".to_string(),
+                id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn joins_multiline_data_and_defaults_type() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b"data: one\ndata:two\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "message");
+        assert_eq!(events[0].data, "one\ntwo");
+    }
+
+    #[test]
+    fn keeps_multibyte_chars_split_across_chunks() {
+        let payload = "data: ★ star\n\n".as_bytes();
+        let split = payload.iter().position(|&b| b == 0xE2).unwrap() + 1;
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(&payload[..split]).is_empty());
+        let events = decoder.push(&payload[split..]);
+        assert_eq!(events[0].data, "★ star");
+    }
+
+    #[test]
+    fn handles_crlf_split_between_chunks() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: a\r").is_empty());
+        assert!(decoder.push(b"\n\r").len() == 1);
+        assert!(decoder.push(b"\ndata: b\r\n\r\n").len() == 1);
+    }
+
+    #[test]
+    fn tracks_id_and_retry_and_skips_heartbeats() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b": ping\nretry: 2500\nid: 7\ndata: x\n\n: ping\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(decoder.last_event_id(), Some("7"));
+        assert_eq!(decoder.retry(), Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn ignores_events_without_data() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"event: status\n\n").is_empty());
+        let events = decoder.push(b"data: y\n\n");
+        assert_eq!(events[0].event_type, "message");
+    }
+
+    #[test]
+    fn finish_flushes_unterminated_event() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"event: message.final\ndata: {}").is_empty());
+        let event = decoder.finish().unwrap();
+        assert_eq!(event.event_type, "message.final");
+        assert_eq!(event.data, "{}");
+    }
+}
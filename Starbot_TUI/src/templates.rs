@@ -0,0 +1,434 @@
+//! Prompt templates
+//!
+//! Named Markdown files with optional front-matter and `{{variables}}`:
+//!
+//! ```text
+//! ---
+//! description: Review a file for bugs
+//! model: azure:gpt-4o
+//! lane: deep
+//! temperature: 0.2
+//! role: user
+//! ---
+//! Review {{file}} for correctness. {{prompt}}
+//! ```
+//!
+//! Templates are looked up in `.starbott/templates/` of the current directory
+//! or any parent (so a team can commit them), then in `templates/` next to
+//! the config file. `role: system` makes the rendered text the system prompt
+//! instead of the message. `{{prompt}}` is the text typed on the command line.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config::config_path;
+use crate::errors::CliError;
+
+pub const LANES: [&str; 3] = ["quick", "standard", "deep"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    System,
+}
+
+/// Defaults a template carries for the request it starts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FrontMatter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lane: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Project,
+    User,
+    File,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Template {
+    pub name: String,
+    pub path: PathBuf,
+    pub source: Source,
+    #[serde(flatten)]
+    pub front: FrontMatter,
+    pub variables: Vec<String>,
+    pub body: String,
+}
+
+impl Template {
+    pub fn parse(name: &str, path: &Path, source: Source, text: &str) -> Result<Self, CliError> {
+        let invalid = |message: String| {
+            CliError::usage(format!("Invalid template {}: {message}", path.display()))
+        };
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+        let (front, body) = match text
+            .strip_prefix("---\n")
+            .or_else(|| text.strip_prefix("---\r\n"))
+        {
+            Some(rest) => {
+                // Search from a newline so an empty front-matter block works too.
+                let end = format!("\n{rest}")
+                    .find("\n---")
+                    .ok_or_else(|| invalid("front-matter has no closing `---`".to_string()))?;
+                let front = &rest[..end.saturating_sub(1)];
+                let body = rest[end + 3..].trim_start_matches(['\r', '\n']);
+                (parse_front_matter(front).map_err(invalid)?, body)
+            }
+            None => (FrontMatter::default(), text),
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            source,
+            front,
+            variables: variables(body),
+            body: body.to_string(),
+        })
+    }
+
+    /// A template given by path, e.g. `--system-prompt-file`.
+    pub fn load_file(path: &Path) -> Result<Self, CliError> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| CliError::usage(format!("Could not read {}: {err}", path.display())))?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self::parse(&name, path, Source::File, &text)
+    }
+
+    /// Substitute `{{name}}` placeholders. Every placeholder needs a value.
+    pub fn render(&self, vars: &BTreeMap<String, String>) -> Result<String, CliError> {
+        let missing: Vec<&str> = self
+            .variables
+            .iter()
+            .filter(|v| !vars.contains_key(*v))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(CliError::usage(format!(
+                "Template `{}` needs {}.",
+                self.name,
+                missing
+                    .iter()
+                    .map(|v| format!("--var {v}=..."))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+
+        let mut out = String::with_capacity(self.body.len());
+        let mut rest = self.body.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else {
+                break;
+            };
+            let key = rest[start + 2..start + len].trim();
+            out.push_str(&rest[..start]);
+            match vars.get(key) {
+                Some(value) if is_variable(key) => out.push_str(value),
+                _ => out.push_str(&rest[start..start + len + 2]),
+            }
+            rest = &rest[start + len + 2..];
+        }
+        out.push_str(rest);
+        Ok(out.trim_end().to_string())
+    }
+}
+
+fn parse_front_matter(text: &str) -> Result<FrontMatter, String> {
+    let mut front = FrontMatter::default();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| format!("expected `key: value`, got `{line}`"))?;
+        let value = value
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'')
+            .to_string();
+        match key.trim() {
+            "description" => front.description = Some(value),
+            "model" => front.model = Some(value),
+            "lane" => {
+                if !LANES.contains(&value.as_str()) {
+                    return Err(format!("lane must be one of {}", LANES.join(", ")));
+                }
+                front.lane = Some(value);
+            }
+            "temperature" => {
+                let temperature = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|t| (0.0..=2.0).contains(t))
+                    .ok_or_else(|| {
+                        format!("temperature must be a number from 0 to 2, got `{value}`")
+                    })?;
+                front.temperature = Some(temperature);
+            }
+            "role" => {
+                front.role = match value.as_str() {
+                    "user" => Role::User,
+                    "system" => Role::System,
+                    other => return Err(format!("role must be user or system, got `{other}`")),
+                }
+            }
+            other => return Err(format!("unknown front-matter key `{other}`")),
+        }
+    }
+    Ok(front)
+}
+
+fn is_variable(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// Placeholder names in order of first use.
+fn variables(body: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + len].trim();
+        if is_variable(name) && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+        rest = &rest[start + len + 2..];
+    }
+    names
+}
+
+/// Where templates live, highest precedence first.
+pub fn template_dirs() -> Result<Vec<(Source, PathBuf)>, CliError> {
+    let mut dirs = Vec::new();
+    if let Ok(cwd) = std::env::current_dir()
+        && let Some(dir) = cwd
+            .ancestors()
+            .map(|dir| dir.join(".starbott").join("templates"))
+            .find(|dir| dir.is_dir())
+    {
+        dirs.push((Source::Project, dir));
+    }
+    dirs.push((Source::User, user_template_dir()?));
+    Ok(dirs)
+}
+
+pub fn user_template_dir() -> Result<PathBuf, CliError> {
+    Ok(config_path()?.with_file_name("templates"))
+}
+
+/// Every template, sorted by name. Project templates shadow user ones.
+pub fn list() -> Result<Vec<Template>, CliError> {
+    let mut found: BTreeMap<String, Template> = BTreeMap::new();
+    for (source, dir) in template_dirs()? {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("md") {
+                continue;
+            }
+            let Some(name) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+                continue;
+            };
+            if found.contains_key(&name) {
+                continue;
+            }
+            let text = fs::read_to_string(&path)?;
+            found.insert(name.clone(), Template::parse(&name, &path, source, &text)?);
+        }
+    }
+    Ok(found.into_values().collect())
+}
+
+pub fn find(name: &str) -> Result<Template, CliError> {
+    for (source, dir) in template_dirs()? {
+        let path = dir.join(format!("{name}.md"));
+        if path.is_file() {
+            let text = fs::read_to_string(&path)?;
+            return Template::parse(name, &path, source, &text);
+        }
+    }
+    Err(CliError::usage(format!("No template named `{name}`."))
+        .with_hint("`starbott templates list` shows the available templates."))
+}
+
+/// Parse a `--var key=value` argument.
+pub fn parse_var(input: &str) -> Result<(String, String), String> {
+    let (key, value) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{input}`"))?;
+    let key = key.trim();
+    if !is_variable(key) {
+        return Err(format!("invalid variable name `{key}`"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// The request a command sends after templates are applied.
+#[derive(Debug, Default)]
+pub struct Prepared {
+    pub prompt: String,
+    pub system: Option<String>,
+    pub front: FrontMatter,
+}
+
+impl Prepared {
+    /// Warning for a `temperature:` the request can't carry: neither the
+    /// chat run nor the inference API takes one, so the server's default is used.
+    pub fn temperature_notice(&self) -> Option<String> {
+        self.front.temperature.map(|t| {
+            format!("Ignoring the template's temperature ({t}): the Starbot API does not accept one yet.")
+        })
+    }
+}
+
+/// Combine the typed prompt with `--template`, `--system-prompt-file` and
+/// `--var`. A user-role template becomes the message (typed text is appended
+/// unless the template places it with `{{prompt}}`); system-role templates
+/// and the system prompt file become the system prompt. The template's
+/// front-matter wins over the file's.
+pub fn prepare(
+    typed: Option<String>,
+    template: Option<&str>,
+    system_file: Option<&Path>,
+    vars: &[(String, String)],
+) -> Result<Prepared, CliError> {
+    let mut values: BTreeMap<String, String> = vars.iter().cloned().collect();
+    if let Some(typed) = &typed {
+        values
+            .entry("prompt".to_string())
+            .or_insert_with(|| typed.clone());
+    }
+
+    let mut prepared = Prepared::default();
+    let mut message = None;
+
+    if let Some(name) = template {
+        let template = find(name)?;
+        let rendered = template.render(&values)?;
+        match template.front.role {
+            Role::User => {
+                let places_prompt = template.variables.iter().any(|v| v == "prompt");
+                message = Some(match &typed {
+                    Some(typed) if !places_prompt => format!("{rendered}\n\n{typed}"),
+                    _ => rendered,
+                });
+            }
+            Role::System => {
+                if system_file.is_some() {
+                    return Err(CliError::usage(format!(
+                        "Template `{name}` is a system prompt; it can't be combined with --system-prompt-file."
+                    )));
+                }
+                prepared.system = Some(rendered);
+            }
+        }
+        prepared.front = template.front;
+    }
+
+    if let Some(path) = system_file {
+        let file = Template::load_file(path)?;
+        prepared.system = Some(file.render(&values)?);
+        let front = &mut prepared.front;
+        front.model = front.model.take().or(file.front.model);
+        front.lane = front.lane.take().or(file.front.lane);
+        front.temperature = front.temperature.or(file.front.temperature);
+    }
+
+    prepared.prompt = message.or(typed).ok_or_else(|| {
+        CliError::usage(
+            "Missing prompt. Use `starbott chat \"...\"` or pass `--stdin`.".to_string(),
+        )
+    })?;
+    Ok(prepared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(text: &str) -> Template {
+        Template::parse("review", Path::new("review.md"), Source::User, text).unwrap()
+    }
+
+    #[test]
+    fn parses_front_matter_and_variables() {
+        let t = template(
+            "---\ndescription: Review a file\nmodel: azure:gpt-4o\nlane: deep\ntemperature: 0.2\n---\n\nReview {{ file }} for {{focus}}. {{file}} {{ not a var }}\n",
+        );
+        assert_eq!(t.front.description.as_deref(), Some("Review a file"));
+        assert_eq!(t.front.model.as_deref(), Some("azure:gpt-4o"));
+        assert_eq!(t.front.lane.as_deref(), Some("deep"));
+        assert_eq!(t.front.temperature, Some(0.2));
+        let prepared = Prepared { front: t.front.clone(), ..Default::default() };
+        assert!(prepared.temperature_notice().unwrap().contains("0.2"));
+        assert!(Prepared::default().temperature_notice().is_none());
+        assert_eq!(t.front.role, Role::User);
+        assert_eq!(t.variables, vec!["file", "focus"]);
+        assert!(t.body.starts_with("Review"));
+    }
+
+    #[test]
+    fn rejects_bad_front_matter() {
+        for text in [
+            "---\nlane: turbo\n---\nx",
+            "---\ntemperature: hot\n---\nx",
+            "---\ncolour: blue\n---\nx",
+            "---\nmodel: x\n",
+        ] {
+            assert!(
+                Template::parse("t", Path::new("t.md"), Source::User, text).is_err(),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn renders_and_reports_missing_variables() {
+        let t = template("Review {{ file }} ({{file}}), keep {{ not a var }}.");
+        let mut vars = BTreeMap::new();
+        assert!(t.render(&vars).is_err());
+        vars.insert("file".to_string(), "src/api.rs".to_string());
+        assert_eq!(
+            t.render(&vars).unwrap(),
+            "Review src/api.rs (src/api.rs), keep {{ not a var }}."
+        );
+    }
+
+    #[test]
+    fn parses_vars() {
+        assert_eq!(
+            parse_var("file=src/a=b.rs").unwrap(),
+            ("file".to_string(), "src/a=b.rs".to_string())
+        );
+        assert!(parse_var("file").is_err());
+        assert!(parse_var("a b=c").is_err());
+    }
+}
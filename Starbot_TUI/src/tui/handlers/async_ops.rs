@@ -4,7 +4,9 @@
 use serde_json::{Value, json};
 use tokio::sync::mpsc;
 
-use crate::api::{ApiClient, ApiResponse};
+use crate::api::{ApiClient, ApiResponse, GenerationEvent, StreamEvent};
+use crate::commands::agent::{ToolCall, execute_tool_call};
+use crate::commands::enhanced_tools::EnhancedToolExecutor;
 use crate::tui::types::{ChatMsg, ChatRole, TuiMsg, Completion};
 
 pub fn spawn_health_fetch(api: ApiClient, tx: mpsc::UnboundedSender<TuiMsg>) {
@@ -89,40 +91,22 @@ pub fn spawn_chat_request_stream(
         match api.post_stream(&path, Some(body), false).await {
             Ok(mut rx) => {
                 while let Some(event) = rx.recv().await {
-                    match event.event_type.as_str() {
-                        "status" => {
-                            // Status update (routing, thinking, etc.)
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                if let Some(msg) = data.get("message").and_then(|v| v.as_str()) {
-                                    let _ = tx.send(TuiMsg::StreamStatus(msg.to_string()));
-                                }
-                            }
-                        }
-                        "token.delta" => {
-                            // Streaming token
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                if let Some(text) = data.get("text").and_then(|v| v.as_str()) {
-                                    let _ = tx.send(TuiMsg::StreamToken(text.to_string()));
-                                }
-                            }
-                        }
-                        "message.final" => {
-                            // Final assistant message
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                let _ = tx.send(TuiMsg::StreamDone(data));
-                            }
-                        }
-                        "error" => {
-                            // Error during generation
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                if let Some(msg) = data.get("message").and_then(|v| v.as_str()) {
-                                    let _ = tx.send(TuiMsg::StreamError(msg.to_string()));
-                                }
-                            }
+                    let event = match event {
+                        StreamEvent::Event(event) => event,
+                        StreamEvent::Reconnecting { attempt, .. } => {
+                            let _ = tx.send(TuiMsg::StreamStatus(format!(
+                                "Connection lost, resuming (attempt {attempt})…"
+                            )));
+                            continue;
                         }
-                        _ => {
-                            // Ignore unknown events for now (tool.start, tool.end, etc.)
+                        StreamEvent::Disconnected { error } => {
+                            let _ = tx.send(TuiMsg::StreamError(error));
+                            return;
                         }
+                    };
+
+                    if let Some(msg) = generation_msg(event) {
+                        let _ = tx.send(msg);
                     }
                 }
             }
@@ -347,6 +331,28 @@ pub fn spawn_tool_propose(
     });
 }
 
+/// Run a local tool that the tool policy or the tool card already approved;
+/// deny rules are still enforced.
+pub fn spawn_local_tool(
+    tx: mpsc::UnboundedSender<TuiMsg>,
+    executor: EnhancedToolExecutor,
+    tool_name: String,
+    input: Value,
+) {
+    tokio::spawn(async move {
+        let call = ToolCall {
+            id: "tui".to_string(),
+            name: tool_name.clone(),
+            arguments: input
+                .as_object()
+                .map(|map| map.clone().into_iter().collect())
+                .unwrap_or_default(),
+        };
+        let result = execute_tool_call(&executor, &call).await;
+        let _ = tx.send(TuiMsg::LocalTool(tool_name, result));
+    });
+}
+
 pub fn spawn_chat_request(
     api: ApiClient,
     tx: mpsc::UnboundedSender<TuiMsg>,
@@ -552,44 +558,42 @@ pub fn spawn_chat_request_stream_legacy(
                 let mut chat_update: Option<Value> = None;
 
                 while let Some(event) = rx.recv().await {
-                    match event.event_type.as_str() {
-                        "status" => {
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                if let Some(msg) = data.get("message").and_then(|v| v.as_str()) {
-                                    let _ = tx.send(TuiMsg::StreamStatus(msg.to_string()));
-                                }
-                            }
+                    let event = match event {
+                        StreamEvent::Event(event) => event,
+                        StreamEvent::Reconnecting { attempt, .. } => {
+                            let _ = tx.send(TuiMsg::StreamStatus(format!(
+                                "Connection lost, resuming (attempt {attempt})…"
+                            )));
+                            continue;
                         }
-                        "token.delta" => {
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                if let Some(text) = data.get("text").and_then(|v| v.as_str()) {
-                                    let _ = tx.send(TuiMsg::StreamToken(text.to_string()));
-                                }
-                            }
+                        StreamEvent::Disconnected { error } => {
+                            let _ = tx.send(TuiMsg::StreamError(format!(
+                                "Connection lost: {error}"
+                            )));
+                            return;
                         }
-                        "message.final" => {
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                final_payload = Some(data);
-                            }
+                    };
+
+                    match event {
+                        GenerationEvent::MessageFinal(message) => {
+                            final_payload = Some(message.raw);
                         }
-                        "chat.updated" => {
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                chat_update = Some(data);
-                            }
+                        GenerationEvent::ChatUpdated { id, title, updated_at } => {
+                            chat_update = Some(json!({
+                                "id": id,
+                                "title": title,
+                                "updatedAt": updated_at,
+                            }));
                         }
-                        "error" => {
-                            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
-                                if let Some(msg) = data.get("message").and_then(|v| v.as_str()) {
-                                    let _ = tx.send(TuiMsg::StreamError(msg.to_string()));
-                                } else {
-                                    let _ = tx.send(TuiMsg::StreamError(event.data));
-                                }
-                            } else {
-                                let _ = tx.send(TuiMsg::StreamError(event.data));
-                            }
+                        GenerationEvent::Error { message, fatal: true, .. } => {
+                            let _ = tx.send(TuiMsg::StreamError(message));
                             return;
                         }
-                        _ => {}
+                        event => {
+                            if let Some(msg) = generation_msg(event) {
+                                let _ = tx.send(msg);
+                            }
+                        }
                     }
                 }
 
@@ -629,6 +633,27 @@ pub fn spawn_chat_request_stream_legacy(
     });
 }
 
+/// Map a generation event to the TUI message shown while a reply streams.
+/// Events with no visible effect map to `None`.
+fn generation_msg(event: GenerationEvent) -> Option<TuiMsg> {
+    match event {
+        GenerationEvent::Status { message } => Some(TuiMsg::StreamStatus(message)),
+        GenerationEvent::TokenDelta { text } => Some(TuiMsg::StreamToken(text)),
+        GenerationEvent::ToolStart { tool_name, .. } => {
+            Some(TuiMsg::StreamStatus(format!("Running tool: {tool_name}")))
+        }
+        GenerationEvent::ToolEnd { tool_name, success: false, error, .. } => Some(TuiMsg::StreamStatus(
+            format!("Tool {tool_name} failed: {}", error.as_deref().unwrap_or("unknown error")),
+        )),
+        GenerationEvent::MessageFinal(message) => Some(TuiMsg::StreamDone(message.raw)),
+        GenerationEvent::Error { message, fatal: true, .. } => Some(TuiMsg::StreamError(message)),
+        GenerationEvent::Error { message, fatal: false, provider } => Some(TuiMsg::StreamStatus(
+            format!("{} failed, trying fallback: {message}", provider.as_deref().unwrap_or("Provider")),
+        )),
+        _ => None,
+    }
+}
+
 fn trim_non_empty(value: Option<String>) -> Option<String> {
     value
         .and_then(|raw| {
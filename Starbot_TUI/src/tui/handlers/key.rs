@@ -3,6 +3,8 @@
 
 use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
 use ratatui::widgets::ListState;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use std::time::Instant;
@@ -441,14 +443,19 @@ fn handle_local_access_prompt(app: &mut App, prompt: &str) -> bool {
 }
 use crate::cute::CuteMode;
 use crate::errors::CliError;
+use crate::commands::agent::local_executor;
+use crate::patch;
+use crate::policy::{self, Action, Approval};
 use crate::tui::types::{
-    App, ChatMsg, ChatRole, ChoiceAction, Mode, TextPromptState, ToolApprovalEntry, TuiMsg,
+    App, ChatMsg, ChatRole, ChoiceAction, Mode, PendingToolCard, TextPromptState,
+    ToolApprovalEntry, TuiMsg,
 };
 
 use super::async_ops::{
     spawn_chat_request_stream_legacy, spawn_completion_request, spawn_file_list_fetch,
     spawn_health_fetch, spawn_memory_fetch, spawn_memory_settings_fetch, spawn_memory_toggle,
-    spawn_models_fetch, spawn_threads_fetch, spawn_tool_propose, spawn_workspaces_fetch,
+    spawn_local_tool, spawn_models_fetch, spawn_threads_fetch, spawn_tool_propose,
+    spawn_workspaces_fetch,
 };
 
 // Import helper functions from parent tui module
@@ -510,6 +517,7 @@ pub fn handle_key(
                             sendable: false,
                         });
                         app.status = format!("Tool approved: {}", tool.tool_name);
+                        run_local_tool(api, tx, app, tool.tool_name, tool.input);
                     }
                     app.mode = Mode::Chat;
                 }
@@ -747,6 +755,45 @@ pub fn handle_key(
                             }
                         }
                         ChoiceAction::Tool { tool_name, input } => {
+                            // Tools that run on this machine go through the tool policy.
+                            let args: HashMap<String, Value> = input
+                                .as_object()
+                                .map(|map| map.clone().into_iter().collect())
+                                .unwrap_or_default();
+                            let executor = local_executor(
+                                api,
+                                app.config.tool_policy.clone(),
+                                Approval::Granted,
+                            );
+                            if executor.has_tool(&tool_name) {
+                                let verdict = executor.verdict(&tool_name, &args);
+                                match verdict.action {
+                                    Action::Allow => run_local_tool(api, tx, app, tool_name, input),
+                                    Action::Deny => {
+                                        app.messages.push(ChatMsg {
+                                            role: ChatRole::System,
+                                            content: format!(
+                                                "{tool_name} is not allowed ({}).",
+                                                verdict.reason
+                                            ),
+                                            sendable: false,
+                                        });
+                                        app.status = format!("Tool denied: {tool_name}");
+                                    }
+                                    Action::Ask => {
+                                        app.pending_tool = Some(PendingToolCard {
+                                            preview: tool_preview(&args),
+                                            target_files: policy::call_paths(&args),
+                                            tool_name,
+                                            requires_confirmation: true,
+                                            input,
+                                        });
+                                        app.mode = Mode::ToolCard;
+                                    }
+                                }
+                                return Ok(());
+                            }
+
                             let ws = match app.selected_workspace_id.clone() {
                                 Some(v) if !v.trim().is_empty() => v,
                                 _ => {
@@ -1308,6 +1355,44 @@ fn trigger_completion(api: &ApiClient, tx: &mpsc::UnboundedSender<TuiMsg>, app:
     spawn_completion_request(api.clone(), tx.clone(), file_path, content, cursor_pos);
 }
 
+/// Start an approved local tool call.
+fn run_local_tool(
+    api: &ApiClient,
+    tx: &mpsc::UnboundedSender<TuiMsg>,
+    app: &mut App,
+    tool_name: String,
+    input: Value,
+) {
+    app.messages.push(ChatMsg {
+        role: ChatRole::System,
+        content: format!("Running tool: {tool_name}"),
+        sendable: false,
+    });
+    app.waiting = true;
+    app.status = format!("Running tool: {tool_name}...");
+    app.bg_tasks = app.bg_tasks.saturating_add(1);
+    let executor = local_executor(api, app.config.tool_policy.clone(), Approval::Granted)
+        .with_journal(app.journal.clone())
+        .with_shells(app.shells.clone())
+        .with_workspace(app.selected_workspace_id.clone());
+    spawn_local_tool(tx.clone(), executor, tool_name, input);
+}
+
+/// What the tool card shows: the command, the diff of a patch, the content
+/// to be written, or the raw arguments.
+fn tool_preview(args: &HashMap<String, Value>) -> String {
+    if let Some(diff) = patch::preview(args) {
+        return diff;
+    }
+    if let Some(command) = args.get("command").and_then(Value::as_str) {
+        return format!("$ {command}");
+    }
+    if let Some(content) = args.get("content").and_then(Value::as_str) {
+        return content.lines().map(|line| format!("+{line}")).collect::<Vec<_>>().join("\n");
+    }
+    serde_json::to_string_pretty(args).unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::{is_local_list_request, is_local_pwd_request, parse_local_list_target};
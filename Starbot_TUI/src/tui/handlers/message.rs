@@ -331,6 +331,30 @@ pub fn handle_tui_msg(api: &ApiClient, tx: &mpsc::UnboundedSender<TuiMsg>, app:
                 }
             }
         }
+        TuiMsg::LocalTool(tool_name, result) => {
+            app.bg_tasks = app.bg_tasks.saturating_sub(1);
+            app.waiting = false;
+            let content = match (&result.error, result.success) {
+                (_, true) => format!("Tool {tool_name} finished:\n{}", result.output),
+                (Some(error), false) if !result.output.is_empty() => {
+                    format!("Tool {tool_name} failed: {error}\n{}", result.output)
+                }
+                (error, false) => format!(
+                    "Tool {tool_name} failed: {}",
+                    error.as_deref().unwrap_or(&result.output)
+                ),
+            };
+            app.messages.push(ChatMsg {
+                role: ChatRole::System,
+                content,
+                sendable: result.success,
+            });
+            app.status = if result.success {
+                format!("Tool completed: {tool_name}")
+            } else {
+                format!("Tool failed: {tool_name}")
+            };
+        }
         TuiMsg::StreamToken(token) => {
             // Append token to the last assistant message (streaming)
             if let Some(last_msg) = app.messages.last_mut() {
@@ -478,7 +502,7 @@ pub fn handle_tui_msg(api: &ApiClient, tx: &mpsc::UnboundedSender<TuiMsg>, app:
                 app.cute,
                 &mut app.rng,
                 &mut app.last_phrase,
-                &crate::errors::CliError::Generic(error),
+                &crate::errors::CliError::generic(error),
             );
         }
         // New Starbot_API message handlers
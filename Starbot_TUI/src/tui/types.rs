@@ -1,6 +1,7 @@
 // PHASE 3: TUI type definitions extracted from tui.rs
 // Contains all structs, enums, and their implementations
 
+use std::sync::Arc;
 use std::time::Instant;
 use ratatui::widgets::ListState;
 use serde_json::Value;
@@ -9,6 +10,8 @@ use crate::config::CliConfig;
 use crate::cute::CuteMode;
 use crate::api::ApiResponse;
 use crate::errors::CliError;
+use crate::commands::pty::PtyManager;
+use crate::journal::Journal;
 
 // ============================================================================
 // Request routing types
@@ -218,6 +221,8 @@ pub struct PendingToolCard {
     pub target_files: Vec<String>,
     pub preview: String,
     pub requires_confirmation: bool,
+    /// Arguments to run the tool with once approved.
+    pub input: serde_json::Value,
 }
 
 #[derive(Debug, Clone)]
@@ -306,6 +311,10 @@ pub struct App {
     // Tool card state
     pub pending_tool: Option<PendingToolCard>,
     pub tool_approval_history: Vec<ToolApprovalEntry>,
+    /// Undo journal shared by every local tool run this session.
+    pub journal: Arc<Journal>,
+    /// Shells the agent opened with `shell_open`; closed when the TUI exits.
+    pub shells: Arc<PtyManager>,
 
     // File browser state
     pub file_browser_path: String,
@@ -347,6 +356,8 @@ pub enum TuiMsg {
     MemorySettings(Result<ApiResponse, CliError>),
     Chat(Result<ApiResponse, CliError>),
     Tool(String, Result<ApiResponse, CliError>),
+    /// A local tool finished (see `spawn_local_tool`).
+    LocalTool(String, crate::commands::agent::ToolResult),
     // New Starbot_API messages
     Projects(Result<ApiResponse, CliError>),
     Chats(Result<ApiResponse, CliError>),
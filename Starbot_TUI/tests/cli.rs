@@ -0,0 +1,1318 @@
+//! End-to-end tests: run the `starbott` binary against the offline mock API.
+
+mod support;
+
+use serde_json::{Value, json};
+
+use support::{MockResponse, MockServer, REFRESH_TOKEN, TOKEN, TestHome, stdout_json};
+
+#[test]
+fn health_prints_server_status() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home.command(&server).args(["--json", "health"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let body = stdout_json(&output);
+    assert_eq!(body["ok"], true);
+    assert_eq!(body["version"], "mock");
+
+    let output = home.command(&server).args(["--quiet", "health"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn missing_token_exits_with_auth_code() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home.command(&server).args(["--json", "whoami"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let error = stdout_json(&output);
+    assert_eq!(error["code"], 2);
+    assert_eq!(error["reason"], "not_logged_in");
+    assert!(error["hint"].as_str().unwrap().contains("starbott auth login"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn rejected_token_exits_with_auth_code() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", "wrong-token")
+        .args(["--json", "whoami"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let error = stdout_json(&output);
+    assert_eq!(error["code"], 2);
+    assert!(!error["error"].as_str().unwrap().contains("wrong-token"));
+}
+
+#[test]
+fn whoami_sends_bearer_token() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "whoami"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout_json(&output)["email"], "dev@starbot.test");
+
+    let requests = server.requests_to("GET", "/v1/auth/me");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].header("authorization"),
+        Some(format!("Bearer {TOKEN}").as_str())
+    );
+}
+
+#[test]
+fn http_errors_map_to_exit_codes() {
+    let cases = [
+        (400, 3), // CliError::Usage
+        (403, 2), // CliError::Auth
+        (429, 5), // CliError::RateLimited
+        (500, 6), // CliError::Server
+        (404, 1), // CliError::Generic
+    ];
+
+    for (status, exit_code) in cases {
+        let server = MockServer::start();
+        let home = TestHome::new();
+        server.enqueue("GET", "/v1/usage/current", MockResponse::error(status, "scripted failure"));
+
+        let output = home
+            .command(&server)
+            .env("STARBOTT_TOKEN", TOKEN)
+            .args(["--json", "--retries", "0", "usage"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(exit_code), "HTTP {status}");
+        assert_eq!(stdout_json(&output)["code"], exit_code, "HTTP {status}");
+    }
+}
+
+#[test]
+fn http_errors_carry_structured_details() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    server.enqueue(
+        "GET",
+        "/v1/usage/current",
+        MockResponse::json(
+            429,
+            json!({ "error": "quota_exceeded", "message": "Monthly quota used up." }),
+        ),
+    );
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "--retries", "0", "usage"])
+        .output()
+        .unwrap();
+    let error = stdout_json(&output);
+    assert_eq!(error["code"], 5);
+    assert_eq!(error["reason"], "quota_exceeded");
+    assert_eq!(error["status"], 429);
+    assert_eq!(error["requestId"], "req-mock");
+    assert_eq!(error["retryable"], true);
+    assert!(error["error"].as_str().unwrap().starts_with("Monthly quota used up."));
+    assert!(error["hint"].as_str().unwrap().contains("--retry-budget"));
+
+    server.enqueue(
+        "GET",
+        "/v1/usage/current",
+        MockResponse::error(500, "Database unavailable"),
+    );
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--retries", "0", "usage"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: Database unavailable"), "{stderr}");
+    assert!(stderr.contains("(request_id: req-mock)"), "{stderr}");
+    assert!(stderr.contains("Hint: Try again later"), "{stderr}");
+}
+
+#[test]
+fn retries_transient_failures() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    server.enqueue("GET", "/v1/usage/current", MockResponse::error(503, "warming up"));
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "--retries", "1", "usage"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout_json(&output)["totalTokens"], 1200);
+    assert_eq!(server.requests_to("GET", "/v1/usage/current").len(), 2);
+}
+
+#[test]
+fn unreachable_server_exits_with_network_code() {
+    let home = TestHome::new();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_starbott"))
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.config_dir())
+        .args(["--json", "--retries", "0", "--api-url", &url, "health"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(stdout_json(&output)["code"], 4);
+}
+
+#[test]
+fn tasks_round_trip() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "tasks", "create", "Write tests", "--priority", "3"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let created = stdout_json(&output);
+    assert_eq!(created["task"]["title"], "Write tests");
+    let task_id = created["task"]["id"].as_str().unwrap().to_string();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "tasks", "list"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let listed = stdout_json(&output);
+    assert_eq!(listed["tasks"][0]["id"], task_id.as_str());
+    assert_eq!(listed["tasks"][0]["priority"], 3);
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "tasks", "get", "task-missing"])
+        .output()
+        .unwrap();
+    assert_ne!(output.status.code(), Some(0));
+}
+
+#[test]
+fn workspaces_list_json() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "workspaces", "list"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout_json(&output)["workspaces"][0]["id"], "ws-1");
+}
+
+#[test]
+fn workspaces_list_in_every_output_format() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let run = |args: &[&str]| {
+        let output = home
+            .command(&server)
+            .env("STARBOTT_TOKEN", TOKEN)
+            .args(args)
+            .args(["workspaces", "list"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let table = run(&[]);
+    assert_eq!(table, "NAME  ID    ROOT\nmock  ws-1  /tmp/mock\n");
+    assert_eq!(run(&["--output", "csv"]), "name,id,rootPath\nmock,ws-1,/tmp/mock\n");
+    assert_eq!(run(&["--output", "csv", "--fields", "id,name"]), "id,name\nws-1,mock\n");
+    assert_eq!(
+        run(&["--output", "ndjson", "--fields", "id"]),
+        "{\"id\":\"ws-1\"}\n"
+    );
+    assert_eq!(
+        run(&["--output", "yaml"]),
+        "workspaces:\n  - id: ws-1\n    name: mock\n    rootPath: /tmp/mock\n"
+    );
+    assert_eq!(run(&["--json", "--fields", "name"]), "[{\"name\":\"mock\"}]\n");
+}
+
+#[test]
+fn agent_run_streams_generation() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["agent", "run", "say hello", "--project-id", "proj-1"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello world"));
+
+    let messages = server.requests_to("POST", "/v1/chats/chat-1/messages");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].body.as_ref().unwrap()["content"], "say hello");
+    let runs = server.requests_to("POST", "/v1/chats/chat-1/run");
+    assert_eq!(runs[0].header("accept"), Some("text/event-stream"));
+}
+
+#[test]
+fn agent_run_uses_scripted_stream() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    server.enqueue(
+        "POST",
+        "/v1/chats/chat-9/run",
+        MockResponse::sse(vec![
+            ("codex.classification", json!({ "intent": "chat" })),
+            ("message.final", json!({ "content": "Clarify please?" })),
+        ]),
+    );
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["agent", "run", "hm", "--project-id", "proj-1", "--chat-id", "chat-9"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Clarify please?"));
+}
+
+#[test]
+fn agent_run_executes_local_tools_until_a_final_answer() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    std::fs::write(home.path().join("notes.txt"), "remember the milk\n").unwrap();
+    let ask_for_file = || {
+        MockResponse::sse(vec![(
+            "message.final",
+            json!({ "content": "<tool>read_file</tool>\n<args>{\"path\": \"notes.txt\"}</args>" }),
+        )])
+    };
+    server.enqueue("POST", "/v1/chats/chat-1/run", ask_for_file());
+
+    let output = home
+        .command(&server)
+        .current_dir(home.path())
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["agent", "run", "what is in notes.txt?", "--project-id", "proj-1", "--local-tools"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello world"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[tool] executing locally: read_file"));
+
+    // The tool protocol goes out with the prompt; the result comes back as a second message.
+    let messages = server.requests_to("POST", "/v1/chats/chat-1/messages");
+    assert_eq!(messages.len(), 2);
+    let first = messages[0].body.as_ref().unwrap()["content"].as_str().unwrap().to_string();
+    assert!(first.starts_with("<instructions>\n# Starbot CLI Agent"));
+    assert!(first.ends_with("what is in notes.txt?"));
+    assert_eq!(
+        messages[1].body.as_ref().unwrap()["content"],
+        "<tool_result id=\"call-1-1\" tool=\"read_file\" success=\"true\">\nremember the milk\n</tool_result>"
+    );
+    assert_eq!(server.requests_to("POST", "/v1/chats/chat-1/run").len(), 2);
+
+    // A model that keeps asking for tools is cut off.
+    server.enqueue("POST", "/v1/chats/chat-9/run", ask_for_file());
+    server.enqueue("POST", "/v1/chats/chat-9/run", ask_for_file());
+    let output = home
+        .command(&server)
+        .current_dir(home.path())
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["agent", "run", "loop", "--chat-id", "chat-9", "--local-tools", "--max-iterations", "2"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Agent stopped after 2 iterations"));
+    assert_eq!(server.requests_to("POST", "/v1/chats/chat-9/run").len(), 2);
+}
+
+#[test]
+fn local_tools_follow_the_tool_policy() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let write = |chat: &str, path: &str| {
+        server.enqueue(
+            "POST",
+            &format!("/v1/chats/{chat}/run"),
+            MockResponse::sse(vec![(
+                "message.final",
+                json!({ "content": format!("<tool>write_file</tool><args>{{\"path\": \"{path}\", \"content\": \"hi\"}}</args>") }),
+            )]),
+        );
+    };
+    let run = |chat: &str, extra: &[&str]| {
+        let output = home
+            .command(&server)
+            .current_dir(home.path())
+            .env("STARBOTT_TOKEN", TOKEN)
+            .args(["agent", "run", "write it", "--chat-id", chat, "--local-tools"])
+            .args(extra)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+        let messages = server.requests_to("POST", &format!("/v1/chats/{chat}/messages"));
+        messages[1].body.as_ref().unwrap()["content"].as_str().unwrap().to_string()
+    };
+
+    // Writes ask by default, and there is no terminal to ask on.
+    write("chat-a", "a.txt");
+    let result = run("chat-a", &[]);
+    assert!(result.contains("success=\"false\""), "{result}");
+    assert!(result.contains("no terminal to ask on"), "{result}");
+    assert!(!home.path().join("a.txt").exists());
+
+    // --yolo approves what would be asked.
+    write("chat-b", "b.txt");
+    assert!(run("chat-b", &["--yolo"]).contains("success=\"true\""));
+    assert_eq!(std::fs::read_to_string(home.path().join("b.txt")).unwrap(), "hi");
+
+    // Deny rules hold even with --yolo; allow rules need no approval.
+    home.write_config(&json!({
+        "profile": "default",
+        "profiles": { "default": { "api_url": server.url(), "token": null } },
+        "tool_policy": {
+            "rules": [
+                { "tool": "write_file", "path": "secrets/**", "action": "deny" },
+                { "tool": "write_file", "path": "docs/*.md", "action": "allow" },
+            ]
+        }
+    }));
+    std::fs::create_dir_all(home.path().join("secrets")).unwrap();
+    std::fs::create_dir_all(home.path().join("docs")).unwrap();
+    write("chat-c", "secrets/key");
+    let result = run("chat-c", &["--yolo"]);
+    assert!(result.contains("write_file is not allowed (tool_policy rule 1)"), "{result}");
+    assert!(!home.path().join("secrets/key").exists());
+    write("chat-d", "docs/notes.md");
+    assert!(run("chat-d", &[]).contains("success=\"true\""));
+    assert!(home.path().join("docs/notes.md").exists());
+}
+
+#[test]
+fn local_file_tools_stay_inside_the_workspace() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let workspace = home.path().join("project");
+    std::fs::create_dir_all(&workspace).unwrap();
+    std::fs::write(workspace.join(".env"), "TOKEN=hunter2\n").unwrap();
+    std::fs::write(home.path().join("outside.txt"), "not yours\n").unwrap();
+    server.enqueue(
+        "POST",
+        "/v1/chats/chat-1/run",
+        MockResponse::sse(vec![(
+            "message.final",
+            json!({ "content": "<tool>read_file</tool><args>{\"path\": \"../outside.txt\"}</args>\n<tool>read_file</tool><args>{\"path\": \".env\"}</args>" }),
+        )]),
+    );
+
+    let output = home
+        .command(&server)
+        .current_dir(&workspace)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["agent", "run", "read them", "--project-id", "proj-1", "--local-tools"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let messages = server.requests_to("POST", "/v1/chats/chat-1/messages");
+    let results = messages[1].body.as_ref().unwrap()["content"].as_str().unwrap().to_string();
+    assert!(results.contains("../outside.txt is outside the workspace"), "{results}");
+    assert!(results.contains(".env may hold secrets"), "{results}");
+    assert!(!results.contains("not yours") && !results.contains("hunter2"), "{results}");
+}
+
+#[test]
+fn local_file_tools_use_the_selected_workspace_root() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let workspace = home.path().join("project");
+    std::fs::create_dir_all(&workspace).unwrap();
+    std::fs::write(workspace.join("notes.txt"), "in the workspace\n").unwrap();
+    let select = |workspace_id: &str| {
+        home.write_config(&json!({
+            "profile": "default",
+            "profiles": {
+                "default": { "api_url": server.url(), "token": null, "workspace_id": workspace_id }
+            }
+        }));
+    };
+    let read_notes = |chat: &str| {
+        server.enqueue(
+            "POST",
+            &format!("/v1/chats/{chat}/run"),
+            MockResponse::sse(vec![(
+                "message.final",
+                json!({ "content": "<tool>read_file</tool><args>{\"path\": \"notes.txt\"}</args>" }),
+            )]),
+        );
+        let output = home
+            .command(&server)
+            .current_dir(home.path())
+            .env("STARBOTT_TOKEN", TOKEN)
+            .args(["agent", "run", "read it", "--chat-id", chat, "--local-tools"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+        let messages = server.requests_to("POST", &format!("/v1/chats/{chat}/messages"));
+        messages[1].body.as_ref().unwrap()["content"].as_str().unwrap().to_string()
+    };
+
+    // Paths resolve against the workspace's root, not the current directory.
+    select("ws-9");
+    server.enqueue(
+        "GET",
+        "/v1/workspaces/ws-9",
+        MockResponse::json(200, json!({ "workspace": { "id": "ws-9", "rootPath": workspace } })),
+    );
+    let result = read_notes("chat-a");
+    assert!(result.contains("success=\"true\"") && result.contains("in the workspace"), "{result}");
+
+    // A workspace that cannot be found is an error, not a fallback to the cwd.
+    std::fs::write(home.path().join("notes.txt"), "in the cwd\n").unwrap();
+    select("ws-gone");
+    let result = read_notes("chat-b");
+    assert!(result.contains("Could not find the root of workspace ws-gone"), "{result}");
+    assert!(!result.contains("in the cwd"), "{result}");
+}
+
+#[test]
+fn undo_restores_files_written_by_local_tools() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let workspace = home.path().join("project");
+    std::fs::create_dir_all(&workspace).unwrap();
+    std::fs::write(workspace.join("notes.txt"), "before\n").unwrap();
+    server.enqueue(
+        "POST",
+        "/v1/chats/chat-1/run",
+        MockResponse::sse(vec![(
+            "message.final",
+            json!({ "content": "<tool>write_file</tool><args>{\"path\": \"notes.txt\", \"content\": \"after\\n\"}</args>\n<tool>write_file</tool><args>{\"path\": \"new.txt\", \"content\": \"fresh\\n\"}</args>" }),
+        )]),
+    );
+
+    let output = home
+        .command(&server)
+        .current_dir(&workspace)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["agent", "run", "edit them", "--project-id", "proj-1", "--local-tools", "--yolo"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Changed 2 files; undo with `starbott undo --session "));
+    assert_eq!(std::fs::read_to_string(workspace.join("notes.txt")).unwrap(), "after\n");
+
+    let output = home
+        .command(&server)
+        .args(["--json", "undo", "--list"])
+        .output()
+        .unwrap();
+    let listed: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listed["sessions"].as_array().unwrap().len(), 1);
+    assert_eq!(listed["sessions"][0]["files"].as_array().unwrap().len(), 2);
+
+    let output = home.command(&server).current_dir(&workspace).arg("undo").output().unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Restored notes.txt"), "{stdout}");
+    assert!(stdout.contains("Removed new.txt (created by the agent)"), "{stdout}");
+    assert_eq!(std::fs::read_to_string(workspace.join("notes.txt")).unwrap(), "before\n");
+    assert!(!workspace.join("new.txt").exists());
+
+    let output = home.command(&server).arg("undo").output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Nothing to undo."));
+}
+
+#[cfg(unix)]
+#[test]
+fn interactive_shell_sessions_are_recorded_and_replayed() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    server.enqueue(
+        "POST",
+        "/v1/chats/chat-1/run",
+        MockResponse::sse(vec![(
+            "message.final",
+            json!({ "content": "<tool>interactive_shell</tool><args>{\"command\": \"echo rec-$((6*7)); false\", \"timeout\": 10}</args>" }),
+        )]),
+    );
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["agent", "run", "run it", "--project-id", "proj-1", "--local-tools", "--yolo"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let messages = server.requests_to("POST", "/v1/chats/chat-1/messages");
+    let result = messages[1].body.as_ref().unwrap()["content"].as_str().unwrap().to_string();
+    assert!(result.contains("success=\"false\""), "{result}");
+    assert!(result.contains("\"exit_code\": 1"), "{result}");
+    assert!(result.contains("rec-42"), "{result}");
+
+    let output = home
+        .command(&server)
+        .args(["--json", "sessions", "list"])
+        .output()
+        .unwrap();
+    let listed: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let recordings = listed["recordings"].as_array().unwrap();
+    assert_eq!(recordings.len(), 1);
+    let id = recordings[0]["id"].as_str().unwrap();
+    assert!(result.contains(&format!("\"recording\": \"{id}\"")), "{result}");
+
+    let output = home
+        .command(&server)
+        .args(["sessions", "replay", &id[..8], "--speed", "50"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("rec-42"));
+
+    let output = home
+        .command(&server)
+        .args(["sessions", "replay", id, "--speed", "0"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn apply_patch_edits_files_or_reports_failed_hunks() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let workspace = home.path().join("project");
+    std::fs::create_dir_all(workspace.join("src")).unwrap();
+    let original = "fn main() {\n    println!(\"hello\");\n}\n";
+    std::fs::write(workspace.join("src/main.rs"), original).unwrap();
+
+    let call = |patch: &str| {
+        let args = serde_json::to_string(&json!({ "patch": patch })).unwrap();
+        json!({ "content": format!("<tool>apply_patch</tool><args>{args}</args>") })
+    };
+    let broken = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -2 +2 @@\n-    println!(\"hello\");\n+    println!(\"hi\");\n@@ -7 +7 @@\n-    missing();\n+    found();\n";
+    let good = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,3 +1,3 @@\n fn main() {\n-    println!(\"hello\");\n+    println!(\"hello, world\");\n }\n";
+    // Two sections for one file both land.
+    let twice = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1 +1 @@\n-fn main() {\n+fn main() -> () {\n\
+                 --- a/src/main.rs\n+++ b/src/main.rs\n@@ -3 +3 @@\n-}\n+} // end\n";
+    // docs/ does not exist, so the last write fails after the others.
+    let partial = "--- /dev/null\n+++ b/notes.md\n@@ -0,0 +1 @@\n+notes\n\
+                   --- a/src/main.rs\n+++ b/src/main.rs\n@@ -2 +2 @@\n-    println!(\"hello, world\");\n+    println!(\"bye\");\n\
+                   --- /dev/null\n+++ b/docs/new.md\n@@ -0,0 +1 @@\n+new\n";
+    for patch in [broken, good, twice, partial] {
+        server.enqueue(
+            "POST",
+            "/v1/chats/chat-1/run",
+            MockResponse::sse(vec![("message.final", call(patch))]),
+        );
+    }
+
+    let output = home
+        .command(&server)
+        .current_dir(&workspace)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["agent", "run", "patch it", "--project-id", "proj-1", "--local-tools", "--yolo"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let messages = server.requests_to("POST", "/v1/chats/chat-1/messages");
+    let first = messages[1].body.as_ref().unwrap()["content"].as_str().unwrap().to_string();
+    assert!(first.contains("success=\"false\""), "{first}");
+    assert!(first.contains("Patch not applied; no files were changed."), "{first}");
+    assert!(first.contains("src/main.rs: hunk 1 (@@ -2 +2 @@): applied at line 2"), "{first}");
+    assert!(
+        first.contains("src/main.rs: hunk 2 (@@ -7 +7 @@): failed, its context was not found near line 7"),
+        "{first}"
+    );
+    let second = messages[2].body.as_ref().unwrap()["content"].as_str().unwrap().to_string();
+    assert!(second.contains("Patched 1 file(s)."), "{second}");
+    let third = messages[3].body.as_ref().unwrap()["content"].as_str().unwrap().to_string();
+    assert!(third.contains("Patched 1 file(s)."), "{third}");
+    let patched = "fn main() -> () {\n    println!(\"hello, world\");\n} // end\n";
+    assert_eq!(std::fs::read_to_string(workspace.join("src/main.rs")).unwrap(), patched);
+
+    let fourth = messages[4].body.as_ref().unwrap()["content"].as_str().unwrap().to_string();
+    assert!(fourth.contains("success=\"false\""), "{fourth}");
+    assert!(fourth.contains("Patch not applied; no files were changed.\ndocs/new.md:"), "{fourth}");
+    assert_eq!(std::fs::read_to_string(workspace.join("src/main.rs")).unwrap(), patched);
+    assert!(!workspace.join("notes.md").exists());
+
+    // Undo takes the file back to before the run, past the rolled-back patch.
+    let output = home.command(&server).args(["--json", "undo"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(std::fs::read_to_string(workspace.join("src/main.rs")).unwrap(), original);
+}
+
+#[test]
+fn chat_stream_prints_tokens_progressively() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["chat", "say hello", "--stream"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello world"));
+
+    // Streaming goes through the project/chat/run pipeline, not /v1/inference/chat.
+    assert_eq!(server.requests_to("POST", "/v1/chats/chat-1/run").len(), 1);
+    assert!(server.requests_to("POST", "/v1/inference/chat").is_empty());
+}
+
+#[test]
+fn chat_stream_json_emits_ndjson_events() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "chat", "say hello", "--stream"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let events: Vec<Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line is one JSON event"))
+        .collect();
+    let types: Vec<&str> = events.iter().filter_map(|e| e["type"].as_str()).collect();
+    assert_eq!(&types[..4], ["status", "token.delta", "token.delta", "message.final"]);
+    assert_eq!(events[1]["text"], "Hello");
+    assert_eq!(events[3]["content"], "Hello world");
+}
+
+#[test]
+fn chat_stream_fails_on_fatal_error_event() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    server.enqueue(
+        "POST",
+        "/v1/chats/chat-1/run",
+        MockResponse::sse(vec![
+            ("token.delta", json!({ "text": "Hel" })),
+            ("error", json!({ "message": "provider exploded", "fatal": true })),
+        ]),
+    );
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["chat", "say hello", "--stream"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("provider exploded"));
+}
+
+#[test]
+fn chat_only_creates_tasks_when_asked() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let chat = |args: &[&str]| {
+        home.command(&server)
+            .env("STARBOTT_TOKEN", TOKEN)
+            .arg("chat")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // Questions that mention tasks, and commands that can't be confirmed, are chat.
+    for prompt in ["how do I create task queues in Celery?", "create task: Write docs"] {
+        let output = chat(&[prompt]);
+        assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Hello world"));
+    }
+    assert_eq!(server.requests_to("POST", "/v1/inference/chat").len(), 2);
+
+    let unconfirmed = chat(&["--task", "Write docs"]);
+    assert_eq!(unconfirmed.status.code(), Some(3));
+    assert!(server.requests_to("POST", "/v1/tasks").is_empty());
+
+    let created = chat(&["--task", "--yes", "Write docs description: for the CLI priority: high"]);
+    assert_eq!(created.status.code(), Some(0), "{}", String::from_utf8_lossy(&created.stderr));
+    let tasks = server.requests_to("POST", "/v1/tasks");
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(
+        tasks[0].body.as_ref().unwrap(),
+        &json!({ "title": "Write docs", "description": "for the CLI", "priority": 8 })
+    );
+}
+
+#[test]
+fn chat_attaches_files_and_globs() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let dir = home.path();
+    std::fs::create_dir_all(dir.join("src/nested")).unwrap();
+    std::fs::write(dir.join("trace.log"), "thread 'main' panicked at src/main.rs:3").unwrap();
+    std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+    std::fs::write(dir.join("src/nested/lib.rs"), "pub fn lib() {}\n").unwrap();
+    std::fs::write(dir.join("logo.png"), [0x89, b'P', b'N', b'G', 0]).unwrap();
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .current_dir(dir)
+        .args(["chat", "explain this", "--file", "trace.log", "--file", "logo.png"])
+        .args(["--glob", "src/**/*.rs", "--file", "src/main.rs"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let body = server.requests_to("POST", "/v1/inference/chat")[0].body.clone().unwrap();
+    let content = body["messages"][0]["content"].as_str().unwrap();
+    assert!(content.starts_with("explain this\n\n<file path=\"trace.log\">\nthread 'main' panicked"));
+    // src/main.rs was named twice but is attached once.
+    assert_eq!(content.matches("<file path=\"src/main.rs\">").count(), 1);
+    assert!(content.contains("<file path=\"src/nested/lib.rs\">\npub fn lib() {}\n</file>"));
+    assert_eq!(body["messages"][0]["attachments"][0]["name"], "logo.png");
+    assert_eq!(body["messages"][0]["attachments"][0]["data"], "iVBORwA=");
+
+    let missing = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .current_dir(dir)
+        .args(["agent", "run", "explain", "--glob", "docs/*.md"])
+        .output()
+        .unwrap();
+    assert_eq!(missing.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("No files match `docs/*.md`"));
+}
+
+#[test]
+fn chat_and_agent_run_use_templates() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let dir = home.path();
+    std::fs::create_dir_all(dir.join(".starbott/templates")).unwrap();
+    std::fs::write(
+        dir.join(".starbott/templates/review.md"),
+        "---\ndescription: Review a file\nmodel: azure:gpt-4o\ntemperature: 0.2\n---\nReview {{file}}.\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("system.md"),
+        "---\nlane: deep\n---\nYou help the {{team}} team.\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        home.command(&server)
+            .env("STARBOTT_TOKEN", TOKEN)
+            .current_dir(dir)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["chat", "--template", "review", "--var", "file=src/api.rs", "focus on errors"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let body = server.requests_to("POST", "/v1/inference/chat")[0].body.clone().unwrap();
+    assert_eq!(body["messages"], json!([{ "role": "user", "content": "Review src/api.rs.\n\nfocus on errors" }]));
+    assert_eq!((body["provider"].as_str(), body["model"].as_str()), (Some("azure"), Some("gpt-4o")));
+    // The API has no temperature parameter, so it is reported rather than sent.
+    assert!(body.get("temperature").is_none(), "{body}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Ignoring the template's temperature (0.2)"));
+
+    let missing = run(&["chat", "--template", "review"]);
+    assert_eq!(missing.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("--var file=..."));
+
+    let listed = stdout_json(&run(&["--json", "templates", "list"]));
+    assert_eq!(listed["templates"][0]["name"], "review");
+    assert_eq!(listed["templates"][0]["source"], "project");
+    assert_eq!(listed["templates"][0]["variables"], "file");
+
+    let output = run(&[
+        "agent", "run", "hello", "--project-id", "proj-1",
+        "--system-prompt-file", "system.md", "--var", "team=core",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    // The earlier chat used id 1 of the mock's counter.
+    let message = server.requests_to("POST", "/v1/chats/chat-2/messages")[0].body.clone().unwrap();
+    assert_eq!(message["content"], "<instructions>\nYou help the core team.\n</instructions>\n\nhello");
+    let run_body = server.requests_to("POST", "/v1/chats/chat-2/run")[0].body.clone().unwrap();
+    assert_eq!(run_body["mode"], "deep");
+}
+
+#[cfg(unix)]
+#[test]
+fn templates_edit_creates_from_a_skeleton() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .env("EDITOR", "true")
+        .env_remove("VISUAL")
+        .current_dir(home.path())
+        .args(["templates", "edit", "standup"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let created = home.config_dir().join("starbott/templates/standup.md");
+    assert!(std::fs::read_to_string(created).unwrap().ends_with("---\n{{prompt}}\n"));
+}
+
+#[test]
+fn chat_continue_reuses_the_last_conversation_here() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let elsewhere = home.path().join("elsewhere");
+    std::fs::create_dir_all(&elsewhere).unwrap();
+    let chat = |dir: &std::path::Path, args: &[&str]| {
+        home.command(&server)
+            .env("STARBOTT_TOKEN", TOKEN)
+            .current_dir(dir)
+            .arg("chat")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let first = chat(home.path(), &["--verbose", "hello"]);
+    assert_eq!(first.status.code(), Some(0), "{}", String::from_utf8_lossy(&first.stderr));
+    let logged = String::from_utf8_lossy(&first.stderr);
+    assert!(logged.contains("conversation=conv-1"), "{logged}");
+    assert!(logged.contains("usage(input=3, output=2, total=5)"), "{logged}");
+    let second = chat(home.path(), &["again", "--continue"]);
+    assert_eq!(second.status.code(), Some(0), "{}", String::from_utf8_lossy(&second.stderr));
+
+    let bodies: Vec<Value> = server
+        .requests_to("POST", "/v1/inference/chat")
+        .into_iter()
+        .map(|r| r.body.unwrap())
+        .collect();
+    assert!(bodies[0].get("conversationId").is_none());
+    assert_eq!(bodies[1]["conversationId"], "conv-1");
+
+    // Nothing was started from the other directory yet.
+    let other = chat(&elsewhere, &["hi", "--continue"]);
+    assert_eq!(other.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&other.stderr).contains("No earlier conversation"));
+
+    let list = stdout_json(&chat(&elsewhere, &["--json", "sessions", "list"]));
+    assert_eq!(list["conversations"][0]["id"], "conv-1");
+    let here = stdout_json(&chat(&elsewhere, &["--json", "sessions", "list", "--here"]));
+    assert_eq!(here["conversations"], json!([]));
+
+    let removed = chat(home.path(), &["sessions", "rm", "conv"]);
+    assert_eq!(removed.status.code(), Some(0), "{}", String::from_utf8_lossy(&removed.stderr));
+    let list = stdout_json(&chat(home.path(), &["--json", "sessions", "list"]));
+    assert_eq!(list["conversations"], json!([]));
+}
+
+#[test]
+fn agent_run_resolves_chat_id_through_the_index() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let run = |args: &[&str]| {
+        home.command(&server)
+            .env("STARBOTT_TOKEN", TOKEN)
+            .current_dir(home.path())
+            .args(["agent", "run"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let first = run(&["say hello"]);
+    assert_eq!(first.status.code(), Some(0), "{}", String::from_utf8_lossy(&first.stderr));
+
+    // The title came from the stream's chat.updated event.
+    let second = run(&["and again", "--chat-id", "mock chat"]);
+    assert_eq!(second.status.code(), Some(0), "{}", String::from_utf8_lossy(&second.stderr));
+    assert_eq!(server.requests_to("POST", "/v1/chats/chat-1/messages").len(), 2);
+    assert_eq!(server.requests_to("POST", "/v1/projects/proj-1/chats").len(), 1);
+
+    let shown = home
+        .command(&server)
+        .args(["--json", "chat", "sessions", "show", "chat-1"])
+        .output()
+        .unwrap();
+    let shown = stdout_json(&shown);
+    assert_eq!(shown["projectId"], "proj-1");
+    assert_eq!(shown["title"], "Mock chat");
+}
+
+// The config directory can only be redirected through the environment on
+// Unix-like systems.
+#[cfg(unix)]
+#[test]
+fn expired_token_is_refreshed_and_saved() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    home.write_config(&json!({
+        "profile": "default",
+        "profiles": {
+            "default": {
+                "api_url": server.url(),
+                "token": "expired",
+                "refresh_token": REFRESH_TOKEN,
+            }
+        }
+    }));
+
+    let output = home.command(&server).args(["--json", "whoami"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(server.requests_to("POST", "/v1/auth/refresh").len(), 1);
+    assert_eq!(home.read_config()["profiles"]["default"]["token"], TOKEN);
+}
+
+// The Starbot API only issues access tokens from the device poll, so this is
+// what a real login leads to once the token stops working.
+#[cfg(unix)]
+#[test]
+fn expired_token_without_refresh_token_asks_to_log_in() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    home.write_config(&json!({
+        "profile": "default",
+        "profiles": { "default": { "api_url": server.url(), "token": "expired" } }
+    }));
+
+    let output = home.command(&server).args(["--json", "whoami"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let error = stdout_json(&output);
+    assert_eq!(error["reason"], "token_invalid");
+    assert!(error["hint"].as_str().unwrap().contains("starbott auth login"));
+    assert!(server.requests_to("POST", "/v1/auth/refresh").is_empty());
+    assert_eq!(home.read_config()["profiles"]["default"]["token"], "expired");
+}
+
+#[cfg(unix)]
+#[test]
+fn device_login_saves_tokens() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    home.write_config(&json!({
+        "profile": "default",
+        "profiles": { "default": { "api_url": server.url(), "token": null } },
+        "credential_store": "encrypted-file",
+    }));
+
+    let output = home
+        .command(&server)
+        .env_remove("CI")
+        .env("STARBOTT_PASSPHRASE", "hunter2")
+        .args(["--json", "auth", "login"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout_json(&output)["ok"], true);
+
+    let profile = &home.read_config()["profiles"]["default"];
+    assert!(profile["token"].is_null());
+    assert!(profile["refresh_token"].is_null());
+    let sealed = std::fs::read_to_string(home.credentials_file()).unwrap();
+    assert!(!sealed.contains(TOKEN) && !sealed.contains(REFRESH_TOKEN));
+
+    let whoami = home
+        .command(&server)
+        .env("STARBOTT_PASSPHRASE", "hunter2")
+        .args(["--json", "whoami"])
+        .output()
+        .unwrap();
+    assert_eq!(whoami.status.code(), Some(0), "{}", String::from_utf8_lossy(&whoami.stderr));
+
+    let locked = home.command(&server).args(["--json", "whoami"]).output().unwrap();
+    assert_eq!(locked.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&locked.stderr).contains("STARBOTT_PASSPHRASE"));
+}
+
+#[test]
+fn migrate_secrets_moves_plaintext_tokens() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    home.write_config(&json!({
+        "profile": "default",
+        "profiles": {
+            "default": { "api_url": server.url(), "token": TOKEN, "refresh_token": REFRESH_TOKEN },
+            "empty": { "api_url": server.url(), "token": null },
+        }
+    }));
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_PASSPHRASE", "hunter2")
+        .args(["--json", "config", "migrate-secrets", "--to", "encrypted-file"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout_json(&output)["profiles"], json!(["default"]));
+
+    let config = home.read_config();
+    assert_eq!(config["credential_store"], "encrypted-file");
+    assert!(config["profiles"]["default"]["token"].is_null());
+    assert!(!std::fs::read_to_string(home.config_file()).unwrap().contains(TOKEN));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        for file in [home.config_file(), home.credentials_file()] {
+            let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", file.display());
+        }
+    }
+
+    let whoami = home
+        .command(&server)
+        .env("STARBOTT_PASSPHRASE", "hunter2")
+        .args(["--json", "whoami"])
+        .output()
+        .unwrap();
+    assert_eq!(whoami.status.code(), Some(0), "{}", String::from_utf8_lossy(&whoami.stderr));
+}
+
+#[test]
+fn login_in_ci_requires_explicit_token() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    let output = home
+        .command(&server)
+        .args(["--json", "auth", "login"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn recorded_session_replays_offline() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let cassette = home.path().join("session.json");
+    let cassette_arg = cassette.to_str().unwrap();
+
+    let recorded = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--record", cassette_arg])
+        .args(["agent", "run", "say hello", "--project-id", "proj-1"])
+        .output()
+        .unwrap();
+    assert_eq!(recorded.status.code(), Some(0));
+
+    let text = std::fs::read_to_string(&cassette).unwrap();
+    assert!(!text.contains(TOKEN), "token leaked into cassette");
+    let file: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let interactions = file["interactions"].as_array().unwrap();
+    assert_eq!(interactions.len(), 3);
+    assert_eq!(interactions[2]["path"], "/v1/chats/chat-1/run");
+    assert_eq!(interactions[2]["response"]["events"][1]["event"], "token.delta");
+
+    // No server and no token: every answer comes from the cassette.
+    let requests_before = server.requests().len();
+    let replayed = std::process::Command::new(env!("CARGO_BIN_EXE_starbott"))
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.config_dir())
+        .env_remove("STARBOTT_TOKEN")
+        .args(["--replay", cassette_arg])
+        .args(["agent", "run", "say hello", "--project-id", "proj-1"])
+        .output()
+        .unwrap();
+    assert_eq!(replayed.status.code(), Some(0), "{}", String::from_utf8_lossy(&replayed.stderr));
+    assert_eq!(replayed.stdout, recorded.stdout);
+    assert_eq!(server.requests().len(), requests_before);
+}
+
+#[test]
+fn replay_reports_missing_interactions() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    let cassette = home.path().join("health.json");
+    let cassette_arg = cassette.to_str().unwrap();
+
+    let output = home
+        .command(&server)
+        .args(["--json", "--record", cassette_arg, "health"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = home
+        .command(&server)
+        .args(["--json", "--replay", cassette_arg, "--retries", "0", "usage"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stdout_json(&output)["error"].as_str().unwrap().contains("GET /v1/usage/current"));
+}
+
+#[test]
+fn honours_retry_after_within_budget() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    server.enqueue(
+        "GET",
+        "/v1/usage/current",
+        MockResponse::error(429, "slow down").with_header("Retry-After", "1"),
+    );
+
+    let started = std::time::Instant::now();
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "usage"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+    assert_eq!(server.requests_to("GET", "/v1/usage/current").len(), 2);
+}
+
+#[test]
+fn rate_limit_beyond_budget_reports_wait() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+    server.enqueue(
+        "GET",
+        "/v1/usage/current",
+        MockResponse::error(429, "slow down").with_header("Retry-After", "150"),
+    );
+
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "--retry-budget", "5s", "usage"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let error = stdout_json(&output);
+    assert!(error["error"].as_str().unwrap().contains("retry after 2m30s"), "{error}");
+    assert_eq!(server.requests_to("GET", "/v1/usage/current").len(), 1);
+}
+
+#[test]
+fn mutating_calls_retry_with_a_stable_idempotency_key() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    server.enqueue(
+        "POST",
+        "/v1/tasks",
+        MockResponse::error(503, "unavailable").with_header("Retry-After", "0"),
+    );
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--verbose", "tasks", "create", "Once"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let creates = server.requests_to("POST", "/v1/tasks");
+    assert_eq!(creates.len(), 2);
+    let key = creates[0].header("idempotency-key").expect("idempotency key");
+    assert!(uuid_like(key), "{key}");
+    assert_eq!(creates[1].header("idempotency-key"), Some(key));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("idempotency_key={key}")), "{stderr}");
+
+    // Each logical call gets its own key.
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "tasks", "create", "Twice"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let creates = server.requests_to("POST", "/v1/tasks");
+    assert_ne!(creates[2].header("idempotency-key"), Some(key));
+
+    // Reads carry no key.
+    assert!(server.requests_to("GET", "/v1/tasks").iter().all(|r| r.header("idempotency-key").is_none()));
+}
+
+#[test]
+fn mutating_calls_are_not_retried_when_the_server_may_have_acted() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    // A bare 503 could come from a handler that already wrote; only a
+    // Retry-After rejection is known to be unhandled.
+    server.enqueue("POST", "/v1/tasks", MockResponse::error(503, "unavailable"));
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "tasks", "create", "Once"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(6));
+    assert_eq!(server.requests_to("POST", "/v1/tasks").len(), 1);
+
+    // Reads are still retried.
+    server.enqueue("GET", "/v1/tasks", MockResponse::error(503, "unavailable"));
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "tasks", "list"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(server.requests_to("GET", "/v1/tasks").len(), 2);
+}
+
+#[test]
+fn tools_commit_key_is_derived_from_the_proposal() {
+    let server = MockServer::start();
+    let home = TestHome::new();
+
+    server.enqueue(
+        "POST",
+        "/v1/tools/commit",
+        MockResponse::error(503, "unavailable").with_header("Retry-After", "0"),
+    );
+    let output = home
+        .command(&server)
+        .env("STARBOTT_TOKEN", TOKEN)
+        .args(["--json", "tools", "commit", "--proposal-id", "prop-1"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let commits = server.requests_to("POST", "/v1/tools/commit");
+    assert_eq!(commits.len(), 2);
+    assert_eq!(commits[0].header("idempotency-key"), Some("tools-commit-prop-1"));
+    assert_eq!(commits[1].header("idempotency-key"), Some("tools-commit-prop-1"));
+}
+
+fn uuid_like(value: &str) -> bool {
+    value.len() == 36 && value.chars().filter(|&c| c == '-').count() == 4
+}
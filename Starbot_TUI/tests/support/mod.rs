@@ -0,0 +1,579 @@
+//! Offline stand-in for the Starbot `/v1` API
+//!
+//! A small HTTP/1.1 server on a loopback port, implementing the routes the CLI
+//! calls with in-memory state. Tests can queue one-off responses (errors, rate
+//! limits, custom SSE scripts) ahead of the built-in routes, and inspect every
+//! request the CLI sent.
+
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{Value, json};
+
+/// Bearer token accepted by authenticated routes.
+pub const TOKEN: &str = "test-token";
+/// Refresh token accepted by `/v1/auth/refresh`.
+pub const REFRESH_TOKEN: &str = "test-refresh";
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Value>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Body of a scripted response.
+#[derive(Debug, Clone)]
+pub enum MockBody {
+    Json(Value),
+    /// `(event, data)` pairs written as a `text/event-stream`.
+    Sse(Vec<(String, Value)>),
+    Empty,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: MockBody,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: MockBody::Json(body),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "error": message, "message": message }))
+    }
+
+    pub fn sse(events: Vec<(&str, Value)>) -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: MockBody::Sse(
+                events
+                    .into_iter()
+                    .map(|(event, data)| (event.to_string(), data))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+#[derive(Default)]
+struct State {
+    requests: Vec<RecordedRequest>,
+    queued: VecDeque<(String, String, MockResponse)>,
+    tasks: Vec<Value>,
+    device_polls: u32,
+    next_id: u64,
+}
+
+impl State {
+    fn id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{prefix}-{}", self.next_id)
+    }
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock server");
+        let addr = listener.local_addr().expect("mock server address");
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let shared = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { break };
+                let state = shared.clone();
+                thread::spawn(move || {
+                    let _ = handle_connection(stream, &state);
+                });
+            }
+        });
+
+        Self { addr, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Serve `response` for the next `method path` request instead of the
+    /// built-in route. Queued responses are consumed in order.
+    pub fn enqueue(&self, method: &str, path: &str, response: MockResponse) {
+        self.state
+            .lock()
+            .unwrap()
+            .queued
+            .push_back((method.to_string(), path.to_string(), response));
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn requests_to(&self, method: &str, path: &str) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == method && r.path == path)
+            .collect()
+    }
+}
+
+/// The events a generation run streams when no script is queued.
+pub fn default_generation(chat_id: &str) -> Vec<(&'static str, Value)> {
+    vec![
+        ("status", json!({ "message": "Routing auto..." })),
+        ("token.delta", json!({ "text": "Hello" })),
+        ("token.delta", json!({ "text": " world" })),
+        (
+            "message.final",
+            json!({
+                "id": "msg-1",
+                "role": "assistant",
+                "content": "Hello world",
+                "provider": "mock",
+                "model": "mock-1",
+                "modelDisplayName": "Mock",
+                "usage": { "promptTokens": 3, "completionTokens": 2, "totalTokens": 5 },
+            }),
+        ),
+        (
+            "chat.updated",
+            json!({ "id": chat_id, "title": "Mock chat", "updatedAt": "2026-01-01T00:00:00Z" }),
+        ),
+    ]
+}
+
+fn handle_connection(mut stream: TcpStream, state: &Mutex<State>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut raw_body = vec![0; length];
+    reader.read_exact(&mut raw_body)?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.clone(), None),
+    };
+
+    let request = RecordedRequest {
+        method,
+        path,
+        query,
+        headers,
+        body: serde_json::from_slice(&raw_body).ok(),
+    };
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+        let queued = state
+            .queued
+            .iter()
+            .position(|(method, path, _)| *method == request.method && *path == request.path);
+        match queued {
+            Some(index) => state.queued.remove(index).unwrap().2,
+            None => route(&mut state, &request),
+        }
+    };
+
+    write_response(&mut stream, &response)
+}
+
+fn write_response(stream: &mut TcpStream, response: &MockResponse) -> std::io::Result<()> {
+    let (content_type, body) = match &response.body {
+        MockBody::Json(value) => ("application/json", value.to_string()),
+        MockBody::Sse(events) => (
+            "text/event-stream",
+            events
+                .iter()
+                .map(|(event, data)| format!("event: {event}\ndata: {data}\n\n"))
+                .collect(),
+        ),
+        MockBody::Empty => ("application/json", String::new()),
+    };
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\nx-request-id: req-mock\r\n",
+        response.status,
+        reason(response.status),
+        body.len(),
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn route(state: &mut State, req: &RecordedRequest) -> MockResponse {
+    let segments: Vec<&str> = req.path.trim_matches('/').split('/').collect();
+    let body = req.body.clone().unwrap_or(Value::Null);
+    let authorized = req.header("authorization") == Some(&format!("Bearer {TOKEN}"));
+
+    // Unauthenticated routes
+    match (req.method.as_str(), segments.as_slice()) {
+        ("GET", ["v1", "health"]) => {
+            return MockResponse::json(
+                200,
+                json!({
+                    "ok": true,
+                    "version": "mock",
+                    "inference": "ready",
+                    "providers": { "mock": "up" },
+                }),
+            );
+        }
+        ("POST", ["v1", "auth", "device", "start"]) => {
+            return MockResponse::json(
+                200,
+                json!({
+                    "device_code": "device-1",
+                    "user_code": "ABCD-1234",
+                    "verification_url": "https://starbot.test/device",
+                    "expires_in": 600,
+                    "interval": 1,
+                }),
+            );
+        }
+        ("POST", ["v1", "auth", "device", "poll"]) => {
+            state.device_polls += 1;
+            return MockResponse::json(
+                200,
+                json!({
+                    "status": "authorized",
+                    "access_token": TOKEN,
+                    "refresh_token": REFRESH_TOKEN,
+                }),
+            );
+        }
+        ("POST", ["v1", "auth", "refresh"]) => {
+            return if body.get("refresh_token").and_then(|v| v.as_str()) == Some(REFRESH_TOKEN) {
+                MockResponse::json(200, json!({ "access_token": TOKEN }))
+            } else {
+                MockResponse::error(401, "Invalid refresh token")
+            };
+        }
+        _ => {}
+    }
+
+    if !authorized {
+        return MockResponse::error(401, "Unauthorized");
+    }
+
+    match (req.method.as_str(), segments.as_slice()) {
+        ("GET", ["v1", "auth", "me"]) => MockResponse::json(
+            200,
+            json!({
+                "id": "user-1",
+                "email": "dev@starbot.test",
+                "planStatus": "active",
+                "currentPeriodEnd": "2026-12-31",
+            }),
+        ),
+        ("GET", ["v1", "usage", "current"]) => MockResponse::json(
+            200,
+            json!({
+                "totalTokens": 1200,
+                "tokenLimit": 100000,
+                "periodStart": "2026-01-01",
+                "periodEnd": "2026-01-31",
+            }),
+        ),
+        ("GET", ["v1", "models"]) => MockResponse::json(
+            200,
+            json!({ "models": [{ "id": "mock-1", "provider": "mock", "displayName": "Mock" }] }),
+        ),
+
+        ("GET", ["v1", "projects"]) => MockResponse::json(
+            200,
+            json!({ "projects": [{ "id": "proj-1", "name": "Mock project" }] }),
+        ),
+        ("POST", ["v1", "projects"]) => {
+            let id = state.id("proj");
+            MockResponse::json(201, json!({ "project": { "id": id, "name": body.get("name") } }))
+        }
+        ("GET", ["v1", "projects", _, "chats"]) => MockResponse::json(
+            200,
+            json!({ "chats": [{ "id": "chat-1", "title": "Mock chat", "updatedAt": "2026-01-01T00:00:00Z" }] }),
+        ),
+        ("POST", ["v1", "projects", project_id, "chats"]) => {
+            let id = state.id("chat");
+            MockResponse::json(
+                201,
+                json!({ "chat": { "id": id, "projectId": project_id, "title": body.get("title") } }),
+            )
+        }
+        ("POST", ["v1", "chats", _, "messages"]) => {
+            let id = state.id("msg");
+            MockResponse::json(201, json!({ "message": { "id": id, "role": body.get("role"), "content": body.get("content") } }))
+        }
+        ("POST", ["v1", "chats", chat_id, "run"]) => MockResponse::sse(default_generation(chat_id)),
+        ("POST", ["v1", "chats", _, "cancel"]) => MockResponse::json(200, json!({ "ok": true })),
+        ("POST", ["v1", "inference", "chat"]) => MockResponse::json(
+            200,
+            json!({
+                "conversation_id": body
+                    .get("conversationId")
+                    .cloned()
+                    .unwrap_or_else(|| json!(state.id("conv"))),
+                "reply": "Hello world",
+                "provider": "mock",
+                "model": "mock-1",
+                "usage": { "prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5 },
+            }),
+        ),
+
+        ("GET", ["v1", "tasks"]) => MockResponse::json(
+            200,
+            json!({ "data": state.tasks, "total": state.tasks.len() }),
+        ),
+        ("POST", ["v1", "tasks"]) => {
+            let Some(title) = body.get("title").and_then(|v| v.as_str()) else {
+                return MockResponse::error(400, "title is required");
+            };
+            let task = json!({
+                "id": state.id("task"),
+                "title": title,
+                "description": body.get("description"),
+                "status": "pending",
+                "priority": body.get("priority").and_then(|v| v.as_i64()).unwrap_or(0),
+                "created_at": "2026-01-01T00:00:00Z",
+                "updated_at": "2026-01-01T00:00:00Z",
+            });
+            state.tasks.push(task.clone());
+            MockResponse::json(201, json!({ "task": task }))
+        }
+        (method, ["v1", "tasks", task_id, rest @ ..]) => {
+            let Some(index) = state.tasks.iter().position(|t| t["id"] == *task_id) else {
+                return MockResponse::error(404, "Task not found");
+            };
+            match (method, rest) {
+                ("GET", []) => MockResponse::json(200, json!({ "task": state.tasks[index] })),
+                ("PATCH", []) => {
+                    if let (Some(task), Some(patch)) = (state.tasks[index].as_object_mut(), body.as_object()) {
+                        for (key, value) in patch {
+                            if !value.is_null() {
+                                task.insert(key.clone(), value.clone());
+                            }
+                        }
+                    }
+                    MockResponse::json(200, json!({ "task": state.tasks[index] }))
+                }
+                ("DELETE", []) => {
+                    state.tasks.remove(index);
+                    MockResponse::json(200, json!({ "ok": true }))
+                }
+                ("POST", [action @ ("start" | "complete" | "cancel")]) => {
+                    let status = match *action {
+                        "start" => "in_progress",
+                        "complete" => "completed",
+                        _ => "cancelled",
+                    };
+                    state.tasks[index]["status"] = json!(status);
+                    MockResponse::json(200, json!({ "task": state.tasks[index] }))
+                }
+                _ => MockResponse::error(404, "Not found"),
+            }
+        }
+
+        ("GET", ["v1", "workspaces"]) => MockResponse::json(
+            200,
+            json!({ "workspaces": [{ "id": "ws-1", "name": "mock", "rootPath": "/tmp/mock" }] }),
+        ),
+        ("POST", ["v1", "workspaces"]) => {
+            let id = state.id("ws");
+            MockResponse::json(
+                201,
+                json!({ "workspace": { "id": id, "name": body.get("name"), "rootPath": body.get("rootPath") } }),
+            )
+        }
+        ("POST", ["v1", "workspaces", workspace_id, "permissions"]) => MockResponse::json(
+            200,
+            json!({ "permission": { "workspaceId": workspace_id, "userId": body.get("userId").cloned().unwrap_or(json!("user-1")) } }),
+        ),
+
+        ("POST", ["v1", "tools", "propose"]) => {
+            let id = state.id("run");
+            MockResponse::json(
+                200,
+                json!({ "requiresConfirmation": false, "runId": id, "result": { "ok": true } }),
+            )
+        }
+        ("POST", ["v1", "tools", "commit" | "deny"]) => {
+            let id = state.id("run");
+            MockResponse::json(200, json!({ "runId": id, "result": { "ok": true } }))
+        }
+        ("GET", ["v1", "tools", "runs"]) => MockResponse::json(
+            200,
+            json!({ "runs": [{ "id": "run-1", "toolName": "file.read", "status": "succeeded", "createdAt": "2026-01-01T00:00:00Z" }] }),
+        ),
+
+        _ => MockResponse::error(404, "Not found"),
+    }
+}
+
+/// A scratch directory used as `HOME`/config root so tests never touch the
+/// developer's real profile.
+pub struct TestHome {
+    path: PathBuf,
+}
+
+impl TestHome {
+    pub fn new() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "starbott-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&path).expect("create test home");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where `dirs::config_dir()` resolves inside this home.
+    pub fn config_dir(&self) -> PathBuf {
+        if cfg!(target_os = "macos") {
+            self.path.join("Library").join("Application Support")
+        } else {
+            self.path.join(".config")
+        }
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config_dir().join("starbott").join("config.json")
+    }
+
+    pub fn credentials_file(&self) -> PathBuf {
+        self.config_dir().join("starbott").join("credentials.enc")
+    }
+
+    pub fn write_config(&self, config: &Value) {
+        let file = self.config_file();
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, serde_json::to_string_pretty(config).unwrap()).unwrap();
+    }
+
+    pub fn read_config(&self) -> Value {
+        let text = std::fs::read_to_string(self.config_file()).expect("config file");
+        serde_json::from_str(&text).expect("config json")
+    }
+
+    /// A `starbott` command isolated to this home and pointed at `server`.
+    pub fn command(&self, server: &MockServer) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_starbott"));
+        cmd.env("HOME", &self.path)
+            .env("XDG_CONFIG_HOME", self.config_dir())
+            .env("XDG_STATE_HOME", self.path.join(".local").join("state"))
+            .env("XDG_DATA_HOME", self.path.join(".local").join("share"))
+            .env("CI", "1")
+            .env_remove("STARBOTT_TOKEN")
+            .env_remove("STARBOTT_PASSPHRASE")
+            // Keep the developer's Secret Service out of reach.
+            .env_remove("DBUS_SESSION_BUS_ADDRESS")
+            .arg("--api-url")
+            .arg(server.url());
+        cmd
+    }
+}
+
+impl Drop for TestHome {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Parse the first JSON document on stdout.
+pub fn stdout_json(output: &Output) -> Value {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout
+        .lines()
+        .find(|line| line.trim_start().starts_with('{'))
+        .unwrap_or_else(|| panic!("no JSON on stdout: {stdout}"));
+    serde_json::from_str(line).unwrap_or_else(|e| panic!("invalid JSON ({e}): {line}"))
+}